@@ -0,0 +1,247 @@
+//! Expanding zip archives so that each of the files in them is classified by its own name.
+
+use std::fs;
+use std::io;
+use std::path;
+
+use crate::audit;
+use crate::bucket::Layout;
+use crate::date::Extractor;
+use crate::manifest;
+use crate::plan::{self, Move, Outcome, PlaceError};
+use crate::report;
+
+/// Checks if the file is an archive that `expand` can look inside.
+pub fn is_archive(path: &path::Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Extracts each of the files in the zip archive into its bucket in the base directory, using the
+/// date in the file's own name. Files without a date are left in the archive. If `delete` is set,
+/// the archive is deleted once all of its files have been extracted.
+pub fn expand(
+    path: &path::Path,
+    base: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    delete: bool,
+) -> Outcome {
+    let mut outcome = Outcome::default();
+    let mut archive = match fs::File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| zip::ZipArchive::new(file).map_err(|e| e.to_string()))
+    {
+        Ok(archive) => archive,
+        Err(e) => {
+            report::note!("Could not open archive {}: {}", path.display(), e);
+            outcome.fail(path, e);
+            return outcome;
+        }
+    };
+    report::note!("Expanding archive {}", path.display());
+    for index in 0..archive.len() {
+        let mut member = match archive.by_index(index) {
+            Ok(member) => member,
+            Err(e) => {
+                report::note!("Could not read file {} in {}: {}", index, path.display(), e);
+                outcome.fail(path, e.to_string());
+                continue;
+            }
+        };
+        if member.is_dir() {
+            continue;
+        }
+        let name = match member.enclosed_name() {
+            Some(name) => name,
+            None => {
+                report::note!(
+                    "Leaving {} in {} as its name is not safe to extract",
+                    member.name(),
+                    path.display()
+                );
+                outcome.unclassified += 1;
+                continue;
+            }
+        };
+        let member_path = path.join(&name);
+        let bucket = extractor.read_name(&name).and_then(|dated| {
+            if let Some(ambiguity) = &dated.ambiguity {
+                report::ambiguous(&member_path, ambiguity);
+            }
+            Ok((dated.date, layout.existing_bucket(base, dated.date)?))
+        });
+        let (date, bucket) = match bucket {
+            Ok(bucket) => bucket,
+            Err(reason) => {
+                report::note!(
+                    "Could not get FY for {}. Leaving it in the archive: {}",
+                    member_path.display(),
+                    reason
+                );
+                outcome.unclassified += 1;
+                continue;
+            }
+        };
+        let file_name = name.file_name().expect("member does not have a name");
+        let file_move = Move {
+            source: member_path,
+            destination: base.join(&bucket).join(file_name),
+            date: Some(date),
+        };
+        let year = bucket
+            .iter()
+            .next()
+            .map(|year| year.to_string_lossy().into_owned());
+        let size = member.size();
+        match extract(&mut member, &file_move) {
+            Ok(plan::Placement::UpToDate) => outcome.up_to_date += 1,
+            Ok(_) => outcome.place(year, size),
+            Err(e) => {
+                report::note!("Could not extract {}: {}", file_move.source.display(), e);
+                outcome.fail(&file_move.source, e);
+            }
+        }
+    }
+    if delete && outcome.unclassified == 0 && outcome.failures.is_empty() {
+        let sha256 = audit::is_enabled()
+            .then(|| manifest::checksum(path).ok())
+            .flatten();
+        match fs::remove_file(path) {
+            Ok(()) => {
+                report::note!("Deleted archive {}", path.display());
+                audit::record("remove", path, None, sha256.as_deref());
+            }
+            Err(e) => {
+                report::note!("Could not delete archive {}: {}", path.display(), e);
+                outcome.fail(path, PlaceError::io("could not delete archive", e));
+            }
+        }
+    }
+    outcome
+}
+
+/// Writes the file from the archive to its destination, creating the directory it goes in if
+/// needed. Nothing is done if the destination already has the same contents.
+fn extract(
+    member: &mut zip::read::ZipFile<'_>,
+    file_move: &Move,
+) -> Result<plan::Placement, String> {
+    let dest_dir = file_move
+        .destination
+        .parent()
+        .ok_or("destination has no parent")?;
+    let dest_dir = plan::existing_dir(dest_dir);
+    let dest = dest_dir.join(
+        file_move
+            .destination
+            .file_name()
+            .ok_or("destination has no file name")?,
+    );
+    if dest.exists() {
+        let existing = manifest::checksum(&dest)?;
+        let extracted =
+            manifest::checksum_of(member).map_err(|e| format!("could not read file: {}", e))?;
+        if existing == extracted {
+            report::note!(
+                "{} is up to date in {}",
+                file_move.source.display(),
+                dest_dir.display()
+            );
+            return Ok(plan::Placement::UpToDate);
+        }
+        return Err(format!("{:?} already exists", dest));
+    }
+    report::note!(
+        "Extracting {} to {}",
+        file_move.source.display(),
+        dest_dir.display()
+    );
+    fs::create_dir_all(&dest_dir).map_err(|e| format!("could not create directory: {}", e))?;
+
+    let mut file = fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(&dest)
+        .map_err(|e| format!("could not create file: {}", e))?;
+    if let Err(e) = io::copy(member, &mut file) {
+        let _ = fs::remove_file(&dest);
+        return Err(format!("could not extract file: {}", e));
+    }
+    report::placed(&dest);
+    audit::record("extract", &file_move.source, Some(&dest), None);
+
+    let placed = Move {
+        destination: dest.clone(),
+        ..file_move.clone()
+    };
+    if let Err(e) =
+        manifest::checksum(&dest).and_then(|checksum| manifest::record(&placed, &checksum))
+    {
+        report::note!("Could not record {} in the manifest: {}", dest.display(), e);
+    }
+    Ok(plan::Placement::Moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::{expand, is_archive};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+
+    #[test]
+    fn test_expand() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        let archive = base.join("statements.ZIP");
+        assert!(is_archive(&archive));
+
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("2022/", options).unwrap();
+        writer
+            .start_file("2022/statement_10JUL2022.pdf", options)
+            .unwrap();
+        writer.write_all(b"july").unwrap();
+        writer
+            .start_file("statement_10JUN2022.pdf", options)
+            .unwrap();
+        writer.write_all(b"june").unwrap();
+        writer.start_file("notes.txt", options).unwrap();
+        writer.finish().unwrap();
+
+        let outcome = expand(
+            &archive,
+            base,
+            &Layout::default(),
+            &Extractor::default(),
+            true,
+        );
+        assert_eq!(
+            (outcome.placed, outcome.unclassified, outcome.failures.len()),
+            (2, 1, 0)
+        );
+        assert_eq!(
+            fs::read(base.join("2023FY").join("statement_10JUL2022.pdf")).unwrap(),
+            b"july"
+        );
+        assert!(base
+            .join("2022FY")
+            .join("statement_10JUN2022.pdf")
+            .is_file());
+        // Not everything could be extracted, so the archive is kept.
+        assert!(archive.is_file());
+
+        let outcome = expand(
+            &archive,
+            base,
+            &Layout::default(),
+            &Extractor::default(),
+            false,
+        );
+        assert_eq!((outcome.placed, outcome.up_to_date), (0, 2));
+    }
+}
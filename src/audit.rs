@@ -0,0 +1,144 @@
+//! An append-only record of every change made to the files, across all runs, for those who need
+//! an audit trail of how their documents were handled. Unlike the manifests, nothing is ever
+//! removed from it, so rolling back or undoing a run adds to it rather than erasing what was done.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::iter;
+use std::path;
+use std::process;
+use std::sync::RwLock;
+
+use serde::Serialize;
+
+use crate::manifest;
+use crate::report;
+
+/// The audit log that the changes are appended to, if there is one.
+static LOG: RwLock<Option<path::PathBuf>> = RwLock::new(None);
+
+/// A line in the audit log, describing a change made to a file.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    at: String,
+    user: String,
+    pid: u32,
+    /// What was done, such as "move", "copy" or "remove".
+    action: &'a str,
+    source: &'a path::Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination: Option<&'a path::Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// Appends the changes to the file from now on, or stops recording them if there is none.
+pub fn set_log(log: Option<path::PathBuf>) {
+    *LOG.write().unwrap_or_else(|e| e.into_inner()) = log;
+}
+
+/// Checks if the changes are being recorded, for those that need to read the file before it is
+/// changed.
+pub fn is_enabled() -> bool {
+    LOG.read().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Records the change in the audit log, if there is one. Without a checksum, it is taken from the
+/// source if it is still there, or else the destination.
+pub fn record(
+    action: &str,
+    source: &path::Path,
+    destination: Option<&path::Path>,
+    sha256: Option<&str>,
+) {
+    let log = LOG.read().unwrap_or_else(|e| e.into_inner());
+    let Some(log) = log.as_deref() else {
+        return;
+    };
+    let sha256 = sha256.map(str::to_string).or_else(|| {
+        iter::once(source)
+            .chain(destination)
+            .find(|path| path.is_file())
+            .and_then(|path| manifest::checksum(path).ok())
+    });
+    // The paths are recorded in full, as the log is kept across runs from different directories.
+    let source = path::absolute(source).unwrap_or_else(|_| source.to_path_buf());
+    let destination =
+        destination.map(|dest| path::absolute(dest).unwrap_or_else(|_| dest.to_path_buf()));
+    let entry = Entry {
+        at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        user: user(),
+        pid: process::id(),
+        action,
+        source: &source,
+        destination: destination.as_deref(),
+        sha256,
+    };
+    if let Err(e) = append(log, &entry) {
+        report::note!(
+            "Could not record {} in the audit log: {}",
+            source.display(),
+            e
+        );
+    }
+}
+
+/// Gets the name of the user running classfy.
+fn user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// Appends the entry to the log as a single write, creating the log and its directory if needed.
+fn append(log: &path::Path, entry: &Entry) -> Result<(), String> {
+    if let Some(dir) = log.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create directory {}: {}", dir.display(), e))?;
+    }
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())? + "\n";
+    let mut file = fs::File::options()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|e| format!("Could not open {}: {}", log.display(), e))?;
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("Could not write to {}: {}", log.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{append, Entry};
+
+    #[test]
+    fn test_append() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let log = tempdir.path().join("logs").join("audit.jsonl");
+        let entry = |action| Entry {
+            at: String::from("2024-07-01T00:00:00Z"),
+            user: String::from("alice"),
+            pid: 42,
+            action,
+            source: path::Path::new("/scans/a.pdf"),
+            destination: (action == "move").then_some(path::Path::new("/scans/2025FY/a.pdf")),
+            sha256: Some(String::from("abc")),
+        };
+        append(&log, &entry("move")).unwrap();
+        append(&log, &entry("roll-back")).unwrap();
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "move");
+        assert_eq!(lines[0]["destination"], "/scans/2025FY/a.pdf");
+        assert_eq!(lines[0]["user"], "alice");
+        assert_eq!(lines[1]["action"], "roll-back");
+        assert!(lines[1].get("destination").is_none());
+    }
+}
@@ -0,0 +1,576 @@
+use std::iter;
+use std::ops;
+use std::path;
+
+use chrono::Datelike;
+
+use crate::date::FileDate;
+use crate::fiscal::FiscalYear;
+use crate::template::DirTemplate;
+
+/// The abbreviated names of the months, used to name the month directories.
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// The years that are recognised in the names of existing directories.
+const RECOGNISED_YEARS: ops::RangeInclusive<u16> = 1900..=2199;
+
+/// Other names for financial year directories that are recognised by default, such as "FY2022"
+/// and "2021-2022". These include the names given by each of the styles in `FY_STYLES`.
+const DEFAULT_RECOGNISED: [&str; 4] = [
+    "FY{year}",
+    "FY{year_short_prev}-{year_short}",
+    "{year_prev}-{year}",
+    "{year_prev}-{year_short}",
+];
+
+/// How finely the files are divided within each year.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Granularity {
+    /// One directory per year.
+    #[default]
+    Year,
+    /// A directory per half of the year.
+    HalfYear,
+    /// A directory per quarter of the year.
+    Quarter,
+    /// A directory per month of the year.
+    Month,
+    /// A directory per ISO 8601 week, such as "W27". In calendar years, the weeks are placed in
+    /// the year that they are numbered in.
+    IsoWeek,
+}
+
+impl Granularity {
+    /// Parses the granularity from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "year" | "fy" => Ok(Granularity::Year),
+            "half-year" => Ok(Granularity::HalfYear),
+            "quarter" => Ok(Granularity::Quarter),
+            "month" => Ok(Granularity::Month),
+            "iso-week" => Ok(Granularity::IsoWeek),
+            _ => Err(format!("Unknown granularity {:?}", name)),
+        }
+    }
+}
+
+/// Describes the directories that the classified files are placed in.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// The template used to name the financial year directories.
+    pub dir_template: DirTemplate,
+    /// Other names for financial year directories that may already exist. When the directory
+    /// named by `dir_template` does not exist, an existing one named by any of these is used.
+    pub recognised: Vec<DirTemplate>,
+    /// How finely the files are divided within each year.
+    pub granularity: Granularity,
+    /// Whether to divide the files by calendar year instead of financial year.
+    pub calendar: bool,
+    /// When the financial year starts and how it is named.
+    pub fiscal: FiscalYear,
+    /// Whether to rename the files to their stem followed by their date, such as
+    /// "bill_2022-07-10.pdf", as they are placed.
+    pub normalize_names: bool,
+    /// Whether to add the date to the names of the files that have none in them, such as
+    /// "notes_14SEP2022.txt", dating those whose date is not found by when they were last
+    /// modified.
+    pub stamp_dates: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            dir_template: DirTemplate::default(),
+            recognised: DEFAULT_RECOGNISED
+                .iter()
+                .map(|template| DirTemplate::parse(template).expect("template should be valid"))
+                .collect(),
+            granularity: Granularity::default(),
+            calendar: false,
+            fiscal: FiscalYear::default(),
+            normalize_names: false,
+            stamp_dates: false,
+        }
+    }
+}
+
+impl Layout {
+    /// Sets the kind of year and the granularity from a layout description such as "fy/quarter"
+    /// or "year/month". The first level is either "fy" or "year" (for calendar years) and the
+    /// optional second level is the granularity within the year.
+    pub fn set_levels(&mut self, levels: &str) -> Result<(), String> {
+        let (year, within) = match levels.split_once('/') {
+            Some((year, within)) => (year, Granularity::parse(within)?),
+            None => (levels, Granularity::Year),
+        };
+        self.calendar = match year {
+            "fy" => false,
+            "year" => true,
+            _ => {
+                return Err(format!(
+                    "Layout {:?} does not start with fy or year",
+                    levels
+                ))
+            }
+        };
+        self.granularity = within;
+        Ok(())
+    }
+
+    /// If the directory is one that files are placed in, or is directly within one, gets the
+    /// directory that contains it. Files in such a directory have already been classified.
+    pub fn classified_root(&self, dir: &path::Path) -> Option<path::PathBuf> {
+        let dir = dir.canonicalize().ok()?;
+        dir.ancestors()
+            .take(2)
+            .find(|ancestor| {
+                ancestor
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| self.is_year_dir(name))
+            })
+            .and_then(|year_dir| year_dir.parent())
+            .map(path::Path::to_path_buf)
+    }
+
+    /// Checks if the name is one that this layout gives to the top level directories, or is one of
+    /// the recognised names, ignoring case. Directories named like "2022FY" are always recognised.
+    pub fn is_year_dir(&self, name: &str) -> bool {
+        let is_year = |year: &str| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit());
+        if self.calendar {
+            match (name.split_once("-Q"), name.split_once("-H")) {
+                (Some((year, quarter)), _) => {
+                    is_year(year) && matches!(quarter, "1" | "2" | "3" | "4")
+                }
+                (_, Some((year, half))) => is_year(year) && matches!(half, "1" | "2"),
+                _ => is_year(name),
+            }
+        } else {
+            name.to_uppercase().strip_suffix("FY").is_some_and(is_year)
+                || self.year_of_dir(name).is_some()
+        }
+    }
+
+    /// Gets the name that this layout gives to the financial year directory that the existing
+    /// directory is for, if it is one. For example, "FY2022" is "2022FY" by default.
+    pub fn canonical_year_dir(&self, name: &str) -> Option<String> {
+        if self.calendar {
+            return None;
+        }
+        let fy = self.year_of_dir(name).or_else(|| {
+            name.to_uppercase()
+                .strip_suffix("FY")
+                .filter(|year| year.len() == 4)
+                .and_then(|year| year.parse().ok())
+        })?;
+        self.dir_template.render(fy).ok()
+    }
+
+    /// Gets the financial year that the directory is named for by this layout's template or one of
+    /// the recognised ones, trying this layout's own template first.
+    fn year_of_dir(&self, name: &str) -> Option<u16> {
+        iter::once(&self.dir_template)
+            .chain(&self.recognised)
+            .find_map(|template| template.year_of(name, RECOGNISED_YEARS))
+    }
+
+    /// Gets the names that a directory for the financial year could have, starting with the one
+    /// that is created when there is none.
+    fn fy_dir_names(&self, fy: u16) -> impl Iterator<Item = String> + '_ {
+        iter::once(&self.dir_template)
+            .chain(&self.recognised)
+            .filter_map(move |template| template.render(fy).ok())
+    }
+
+    /// Gets the directory, relative to `base`, that a file with the given date belongs in. If the
+    /// financial year directory does not exist in `base` but one with a recognised name does, that
+    /// one is used instead.
+    pub fn existing_bucket(
+        &self,
+        base: &path::Path,
+        date: FileDate,
+    ) -> Result<path::PathBuf, String> {
+        let bucket = self.bucket(date)?;
+        if self.calendar {
+            return Ok(bucket);
+        }
+        let mut components = bucket.components();
+        let fy_dir = components.next().expect("bucket should not be empty");
+        let within = components.as_path();
+        if base.join(fy_dir).is_dir() {
+            return Ok(bucket);
+        }
+        Ok(self
+            .fy_dir_names(self.fiscal.fy(date)?)
+            .skip(1)
+            .find(|name| base.join(name).is_dir())
+            .map(|name| {
+                path::Path::new(&name)
+                    .components()
+                    .chain(within.components())
+                    .collect()
+            })
+            .unwrap_or(bucket))
+    }
+
+    /// Checks if a file with the given date belongs in the directory, relative to the base
+    /// directory. The directory may be anywhere within the file's bucket, and the year directory
+    /// may have any of its recognised names. The names are compared ignoring case.
+    pub fn belongs_in(&self, dir: &path::Path, date: FileDate) -> Result<bool, String> {
+        let bucket = self.bucket(date)?;
+        let mut expected = bucket.components();
+        let mut actual = dir.components();
+        let year_dir = expected.next().expect("bucket should not be empty");
+        let actual_year = match actual.next() {
+            Some(year) => year.as_os_str().to_string_lossy().to_uppercase(),
+            None => return Ok(false),
+        };
+        let same_year = if self.calendar {
+            year_dir.as_os_str().to_string_lossy().to_uppercase() == actual_year
+        } else {
+            self.fy_dir_names(self.fiscal.fy(date)?)
+                .any(|name| name.to_uppercase() == actual_year)
+        };
+        Ok(same_year
+            && expected.all(|within| {
+                actual.next().is_some_and(|component| {
+                    component
+                        .as_os_str()
+                        .eq_ignore_ascii_case(within.as_os_str())
+                })
+            }))
+    }
+
+    /// Gets the directory, relative to the directory being classified, that a file with the given
+    /// date belongs in.
+    pub fn bucket(&self, date: FileDate) -> Result<path::PathBuf, String> {
+        if self.calendar {
+            self.calendar_bucket(date)
+        } else {
+            self.fiscal_bucket(date)
+        }
+    }
+
+    fn fiscal_bucket(&self, date: FileDate) -> Result<path::PathBuf, String> {
+        let fy_dir = path::PathBuf::from(self.dir_template.render(self.fiscal.fy(date)?)?);
+        let index = match (self.granularity, self.fiscal.month_of(date)) {
+            (Granularity::Year, _) | (_, None) => return Ok(fy_dir),
+            (_, Some(index)) => index,
+        };
+        Ok(match self.granularity {
+            Granularity::Year => fy_dir,
+            Granularity::HalfYear => fy_dir.join(format!("H{}", index / 6 + 1)),
+            Granularity::Quarter => fy_dir.join(format!("Q{}", index / 3 + 1)),
+            Granularity::Month => fy_dir.join(format!(
+                "{:02}-{}",
+                index + 1,
+                MONTH_NAMES[(usize::from(self.fiscal.start_month) - 1 + usize::from(index)) % 12]
+            )),
+            Granularity::IsoWeek => match iso_week(date)? {
+                Some(week) => fy_dir.join(format!("W{:02}", week.week())),
+                None => fy_dir,
+            },
+        })
+    }
+
+    fn calendar_bucket(&self, date: FileDate) -> Result<path::PathBuf, String> {
+        let (year, month) = date
+            .year_month()
+            .ok_or_else(|| format!("{} does not fall in a single calendar year", date))?;
+        Ok(match self.granularity {
+            Granularity::Year => path::PathBuf::from(year.to_string()),
+            Granularity::HalfYear => {
+                path::PathBuf::from(format!("{}-H{}", year, (month - 1) / 6 + 1))
+            }
+            Granularity::Quarter => {
+                path::PathBuf::from(format!("{}-Q{}", year, (month - 1) / 3 + 1))
+            }
+            Granularity::Month => {
+                path::PathBuf::from(year.to_string()).join(format!("{:02}", month))
+            }
+            Granularity::IsoWeek => match iso_week(date)? {
+                Some(week) => path::PathBuf::from(week.year().to_string())
+                    .join(format!("W{:02}", week.week())),
+                None => path::PathBuf::from(year.to_string()),
+            },
+        })
+    }
+}
+
+/// Gets the ISO 8601 week that the date falls in, if it is a single day.
+fn iso_week(date: FileDate) -> Result<Option<chrono::IsoWeek>, String> {
+    match date {
+        FileDate::Day { year, month, day } => {
+            chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), day.into())
+                .map(|day| Some(day.iso_week()))
+                .ok_or_else(|| format!("{} is not a valid date", date))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{Granularity, Layout};
+    use crate::date::FileDate;
+    use crate::template::DirTemplate;
+
+    #[test]
+    fn test_quarters() {
+        let layout = Layout {
+            granularity: Granularity::Quarter,
+            ..Layout::default()
+        };
+        let jul = FileDate::Month {
+            year: 2022,
+            month: 7,
+        };
+        let mar = FileDate::Month {
+            year: 2023,
+            month: 3,
+        };
+        assert_eq!(layout.bucket(jul), Ok(path::PathBuf::from("2023FY/Q1")));
+        assert_eq!(layout.bucket(mar), Ok(path::PathBuf::from("2023FY/Q3")));
+        assert_eq!(
+            layout.bucket(FileDate::FinancialYear(2023)),
+            Ok(path::PathBuf::from("2023FY"))
+        );
+
+        let calendar = Layout {
+            calendar: true,
+            ..layout
+        };
+        assert_eq!(calendar.bucket(jul), Ok(path::PathBuf::from("2022-Q3")));
+        assert_eq!(calendar.bucket(mar), Ok(path::PathBuf::from("2023-Q1")));
+        assert!(calendar.bucket(FileDate::FinancialYear(2023)).is_err());
+    }
+
+    #[test]
+    fn test_iso_weeks() {
+        let layout = Layout {
+            granularity: Granularity::IsoWeek,
+            calendar: true,
+            ..Layout::default()
+        };
+        let day = |year, month, day| FileDate::Day { year, month, day };
+        assert_eq!(
+            layout.bucket(day(2022, 7, 10)),
+            Ok(path::PathBuf::from("2022/W27"))
+        );
+        assert_eq!(
+            layout.bucket(day(2021, 1, 1)),
+            Ok(path::PathBuf::from("2020/W53"))
+        );
+        assert!(layout.bucket(day(2022, 2, 31)).is_err());
+        assert_eq!(
+            layout.bucket(FileDate::Month {
+                year: 2022,
+                month: 7
+            }),
+            Ok(path::PathBuf::from("2022"))
+        );
+
+        let fiscal = Layout {
+            calendar: false,
+            ..layout
+        };
+        assert_eq!(
+            fiscal.bucket(day(2022, 7, 10)),
+            Ok(path::PathBuf::from("2023FY/W27"))
+        );
+    }
+
+    #[test]
+    fn test_half_years() {
+        let layout = Layout {
+            granularity: Granularity::HalfYear,
+            ..Layout::default()
+        };
+        let dec = FileDate::Month {
+            year: 2022,
+            month: 12,
+        };
+        let jan = FileDate::Month {
+            year: 2023,
+            month: 1,
+        };
+        assert_eq!(layout.bucket(dec), Ok(path::PathBuf::from("2023FY/H1")));
+        assert_eq!(layout.bucket(jan), Ok(path::PathBuf::from("2023FY/H2")));
+
+        let calendar = Layout {
+            calendar: true,
+            ..layout
+        };
+        assert_eq!(calendar.bucket(dec), Ok(path::PathBuf::from("2022-H2")));
+        assert_eq!(calendar.bucket(jan), Ok(path::PathBuf::from("2023-H1")));
+    }
+
+    #[test]
+    fn test_set_levels() {
+        let mut layout = Layout::default();
+        assert!(layout.set_levels("year/month").is_ok());
+        assert!(layout.calendar);
+        assert_eq!(layout.granularity, Granularity::Month);
+
+        assert!(layout.set_levels("fy").is_ok());
+        assert!(!layout.calendar);
+        assert_eq!(layout.granularity, Granularity::Year);
+
+        assert!(layout.set_levels("month/year").is_err());
+        assert!(layout.set_levels("fy/week").is_err());
+    }
+
+    #[test]
+    fn test_classified_root() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path().canonicalize().unwrap();
+        for dir in ["2023FY/Q1", "2021fy", "FY2022", "2022", "other"] {
+            fs::create_dir_all(base_path.join(dir)).unwrap();
+        }
+
+        let layout = Layout::default();
+        assert_eq!(
+            layout.classified_root(&base_path.join("2023FY")),
+            Some(base_path.clone())
+        );
+        assert_eq!(
+            layout.classified_root(&base_path.join("2023FY/Q1")),
+            Some(base_path.clone())
+        );
+        assert_eq!(
+            layout.classified_root(&base_path.join("2021fy")),
+            Some(base_path.clone())
+        );
+        assert_eq!(
+            layout.classified_root(&base_path.join("FY2022")),
+            Some(base_path.clone())
+        );
+        assert_eq!(layout.classified_root(&base_path.join("other")), None);
+
+        let templated = Layout {
+            dir_template: DirTemplate::parse("FY{year}").unwrap(),
+            recognised: Vec::new(),
+            ..Layout::default()
+        };
+        assert_eq!(
+            templated.classified_root(&base_path.join("FY2022")),
+            Some(base_path.clone())
+        );
+
+        let calendar = Layout {
+            calendar: true,
+            ..Layout::default()
+        };
+        assert_eq!(
+            calendar.classified_root(&base_path.join("2022")),
+            Some(base_path.clone())
+        );
+        assert_eq!(calendar.classified_root(&base_path.join("2023FY")), None);
+    }
+
+    #[test]
+    fn test_existing_bucket() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir(base_path.join("FY2022")).unwrap();
+        fs::create_dir(base_path.join("2022-2023")).unwrap();
+        fs::create_dir(base_path.join("2024FY")).unwrap();
+        fs::create_dir(base_path.join("FY2024")).unwrap();
+
+        let layout = Layout {
+            granularity: Granularity::Quarter,
+            ..Layout::default()
+        };
+        let bucket =
+            |year, month| layout.existing_bucket(base_path, FileDate::Month { year, month });
+        assert_eq!(bucket(2021, 8), Ok(path::PathBuf::from("FY2022/Q1")));
+        assert_eq!(bucket(2023, 1), Ok(path::PathBuf::from("2022-2023/Q3")));
+        assert_eq!(bucket(2023, 8), Ok(path::PathBuf::from("2024FY/Q1")));
+        assert_eq!(bucket(2024, 8), Ok(path::PathBuf::from("2025FY/Q1")));
+
+        let canonical_base = base_path.canonicalize().unwrap();
+        assert_eq!(
+            layout.classified_root(&base_path.join("2022-2023")),
+            Some(canonical_base)
+        );
+    }
+
+    #[test]
+    fn test_belongs_in() {
+        let layout = Layout {
+            granularity: Granularity::Quarter,
+            ..Layout::default()
+        };
+        let jul = FileDate::Month {
+            year: 2022,
+            month: 7,
+        };
+        let belongs_in = |dir: &str| layout.belongs_in(path::Path::new(dir), jul);
+        assert_eq!(belongs_in("2023FY/Q1"), Ok(true));
+        assert_eq!(belongs_in("2023fy/q1/receipts"), Ok(true));
+        assert_eq!(belongs_in("FY2023/Q1"), Ok(true));
+        assert_eq!(belongs_in("2023FY/Q2"), Ok(false));
+        assert_eq!(belongs_in("2023FY"), Ok(false));
+        assert_eq!(belongs_in("2022FY/Q1"), Ok(false));
+    }
+
+    #[test]
+    fn test_canonical_year_dir() {
+        let layout = Layout::default();
+        assert_eq!(
+            layout.canonical_year_dir("FY2022").as_deref(),
+            Some("2022FY")
+        );
+        assert_eq!(
+            layout.canonical_year_dir("2021-22").as_deref(),
+            Some("2022FY")
+        );
+        assert_eq!(
+            layout.canonical_year_dir("2022fy").as_deref(),
+            Some("2022FY")
+        );
+        assert_eq!(layout.canonical_year_dir("receipts"), None);
+
+        let layout = Layout {
+            dir_template: DirTemplate::parse("FY{year}").unwrap(),
+            ..Layout::default()
+        };
+        assert_eq!(
+            layout.canonical_year_dir("2022FY").as_deref(),
+            Some("FY2022")
+        );
+    }
+
+    #[test]
+    fn test_months() {
+        let layout = Layout {
+            granularity: Granularity::Month,
+            ..Layout::default()
+        };
+        let jul = FileDate::Month {
+            year: 2022,
+            month: 7,
+        };
+        let jun = FileDate::Month {
+            year: 2023,
+            month: 6,
+        };
+        assert_eq!(layout.bucket(jul), Ok(path::PathBuf::from("2023FY/01-JUL")));
+        assert_eq!(layout.bucket(jun), Ok(path::PathBuf::from("2023FY/12-JUN")));
+        assert_eq!(
+            layout.bucket(FileDate::FinancialYear(2023)),
+            Ok(path::PathBuf::from("2023FY"))
+        );
+
+        let calendar = Layout {
+            calendar: true,
+            ..layout
+        };
+        assert_eq!(calendar.bucket(jul), Ok(path::PathBuf::from("2022/07")));
+    }
+}
@@ -0,0 +1,1115 @@
+use std::path;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+
+use classfy::bucket::{Granularity, Layout};
+use classfy::config::Config;
+use classfy::date::{DateOrder, DatePick, Extractor, Lang};
+use classfy::dedup::DuplicatePolicy;
+use classfy::exclude;
+use classfy::extract;
+use classfy::fiscal::{FiscalYear, YearLabel, YearStart, CONVENTIONS};
+use classfy::input::{Input, Source};
+use classfy::inventory;
+use classfy::journal;
+use classfy::pack;
+use classfy::plan::{Collision, Mode, Overwrite, Settings, Transfer};
+use classfy::remote::Destination;
+use classfy::report::{ColorChoice, LogTarget, OutputFormat};
+use classfy::template::{DirTemplate, FY_STYLES};
+
+/// The names accepted by `--by`.
+const GRANULARITIES: [&str; 6] = ["year", "fy", "half-year", "quarter", "month", "iso-week"];
+
+/// The layouts accepted by `--layout`.
+const LAYOUTS: [&str; 10] = [
+    "fy",
+    "fy/half-year",
+    "fy/quarter",
+    "fy/month",
+    "fy/iso-week",
+    "year",
+    "year/half-year",
+    "year/quarter",
+    "year/month",
+    "year/iso-week",
+];
+
+/// The languages accepted by `--lang`, where "auto" recognises all of them.
+const LANGS: [&str; 7] = ["auto", "en", "de", "es", "fr", "it", "nl"];
+
+/// The labels accepted by `--fy-label`.
+const YEAR_LABELS: [&str; 2] = ["start", "end"];
+
+/// The directory, in the base directory, that files replaced by `--overwrite` are kept in.
+const BACKUP_DIR: &str = ".classfy-backup";
+
+/// The archive formats accepted by `--archive`.
+const ARCHIVE_FORMATS: [&str; 2] = ["zip", "tar.zst"];
+
+/// The policies accepted by `--duplicates`.
+const DUPLICATE_POLICIES: [&str; 3] = ["skip-duplicate", "keep-both", "move-to-duplicates"];
+
+/// The modes accepted by `--mode`.
+const MODES: [&str; 2] = ["place", "tag"];
+
+/// The strategies accepted by `--on-collision`.
+const COLLISIONS: [&str; 3] = ["number", "hash", "fail"];
+
+/// The conditions accepted by `--overwrite`.
+const OVERWRITES: [&str; 2] = ["newer", "always"];
+
+/// The targets accepted by `--log-target`.
+const LOG_TARGETS: [&str; 2] = ["stdout", "syslog"];
+
+/// The formats accepted by `classfy report --format`.
+const REPORT_FORMATS: [&str; 3] = ["table", "csv", "json"];
+
+/// The formats accepted by `--output`.
+const OUTPUT_FORMATS: [&str; 2] = ["text", "ndjson"];
+
+/// The choices accepted by `--color`.
+const COLOR_CHOICES: [&str; 3] = ["auto", "always", "never"];
+
+/// The orders accepted by `--date-order`.
+const DATE_ORDERS: [&str; 3] = ["dmy", "mdy", "ymd"];
+
+/// The policies accepted by `--date-pick`.
+const DATE_PICKS: [&str; 4] = ["first", "last", "latest", "earliest"];
+
+/// Describes the exit codes and how the options can be given in the environment, to be shown with
+/// the help.
+const EXIT_STATUS_HELP: &str = "Exit status:
+  0  Every file was classified
+  1  Some files could not be classified and were left in place
+  2  Files or directories could not be read, written or moved
+  3  The command line or configuration is invalid
+  4  classfy verify found files that are not in the directory their date belongs in
+
+Environment:
+  Many options can also be given by the CLASSFY_* variable shown with them, such as CLASSFY_DEST or
+  CLASSFY_DRY_RUN=1. A variable takes the place of the setting in the configuration file, and the
+  option on the command line takes the place of the variable.";
+
+/// Describes the command line interface.
+pub fn command() -> Command {
+    Command::new("classfy")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Moves files into directories by financial year, using the date in their names")
+        .after_help(EXIT_STATUS_HELP)
+        .args(input_args())
+        .args(layout_args())
+        .args(extractor_args())
+        .arg(
+            Arg::new("expand-archives")
+                .long("expand-archives")
+                .action(ArgAction::SetTrue)
+                .help("Extract the files in zip archives into their buckets by their own dates"),
+        )
+        .arg(
+            Arg::new("delete-archives")
+                .long("delete-archives")
+                .action(ArgAction::SetTrue)
+                .requires("expand-archives")
+                .help("Delete each archive once all of its files have been extracted"),
+        )
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .value_name("FORMAT")
+                .value_parser(
+                    PossibleValuesParser::new(ARCHIVE_FORMATS)
+                        .try_map(|name| pack::Format::parse(&name)),
+                )
+                .conflicts_with_all(["copy", "hardlink", "leave-symlink", "tag"])
+                .help("Add the files to an archive for each year, such as 2022FY.zip, instead of moving them into directories"),
+        )
+        .arg(
+            Arg::new("dest")
+                .long("dest")
+                .env("CLASSFY_DEST")
+                .value_name("URL")
+                .value_parser(Destination::parse)
+                .conflicts_with_all(["archive", "expand-archives", "hardlink", "leave-symlink", "tag"])
+                .help("Upload the files into a directory for each year at the URL, such as s3://bucket/prefix, webdav://host/path, sftp://user@host/path, gdrive://root/path or dropbox:///path, instead of moving them"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .env("CLASSFY_DRY_RUN")
+                .action(ArgAction::SetTrue)
+                .value_parser(clap::builder::FalseyValueParser::new())
+                .help("Show where each file would be placed, without moving anything"),
+        )
+        .arg(
+            Arg::new("copy")
+                .long("copy")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Copy the files into their directories, leaving the originals in place"),
+        )
+        .arg(
+            Arg::new("hardlink")
+                .long("hardlink")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .conflicts_with("copy")
+                .help("Hard link the files into their directories, leaving the originals in place without using more space"),
+        )
+        .arg(
+            Arg::new("leave-symlink")
+                .long("leave-symlink")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .conflicts_with_all(["copy", "hardlink"])
+                .help("Leave a relative symbolic link at the original location of each moved file"),
+        )
+        .arg(
+            Arg::new("mode")
+                .long("mode")
+                .value_name("MODE")
+                .global(true)
+                .value_parser(PossibleValuesParser::new(MODES).try_map(|name| Mode::parse(&name)))
+                .help("Whether to place the files in their directories, or leave them where they are and record their year in the user.classfy.fy attribute for classfy query (defaults to place)"),
+        )
+        .arg(
+            Arg::new("tag")
+                .long("tag")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Tag each placed file with its year, such as 2023FY, as a Finder tag on macOS or in user.xdg.tags elsewhere"),
+        )
+        .arg(
+            Arg::new("transactional")
+                .long("transactional")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("If any file cannot be placed, put back all of the files placed in this run"),
+        )
+        .arg(
+            Arg::new("duplicates")
+                .long("duplicates")
+                .env("CLASSFY_DUPLICATES")
+                .value_name("POLICY")
+                .value_parser(
+                    PossibleValuesParser::new(DUPLICATE_POLICIES)
+                        .try_map(|name| DuplicatePolicy::parse(&name)),
+                )
+                .global(true)
+                .help("Look for files whose contents are already in their year's directory, and what to do with them"),
+        )
+        .arg(
+            Arg::new("on-collision")
+                .long("on-collision")
+                .value_name("STRATEGY")
+                .value_parser(
+                    PossibleValuesParser::new(COLLISIONS).try_map(|name| Collision::parse(&name)),
+                )
+                .global(true)
+                .help("What to do when a different file already has the name, such as adding a number (the default)"),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .value_name("WHEN")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("newer")
+                .value_parser(
+                    PossibleValuesParser::new(OVERWRITES).try_map(|name| Overwrite::parse(&name)),
+                )
+                .conflicts_with("on-collision")
+                .global(true)
+                .help("Replace a different file that already has the name, if the file being placed is newer or always"),
+        )
+        .arg(
+            Arg::new("no-backup")
+                .long("no-backup")
+                .action(ArgAction::SetTrue)
+                .requires("overwrite")
+                // A replaced file that was deleted could not be put back by --transactional.
+                .conflicts_with("transactional")
+                .global(true)
+                .help("Delete the files replaced by --overwrite instead of keeping them in .classfy-backup"),
+        )
+        .arg(
+            Arg::new("skip-existing")
+                .long("skip-existing")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["overwrite", "on-collision"])
+                .global(true)
+                .help("Leave files whose destination already exists in place, as already archived"),
+        )
+        .arg(
+            Arg::new("remove-archived")
+                .long("remove-archived")
+                .action(ArgAction::SetTrue)
+                .requires("skip-existing")
+                .global(true)
+                .help("Remove the files that are already archived with the same contents"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .env("CLASSFY_CONFIG")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(path::PathBuf))
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Read the settings from FILE instead of classfy.toml"),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(path::PathBuf))
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Ask fn classify(name, mtime, size) in the Rhai script FILE for the year of each file first (needs the script feature)"),
+        )
+        .arg(
+            Arg::new("plugin")
+                .long("plugin")
+                .value_name("FILE")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(path::PathBuf))
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Ask the WebAssembly plugin FILE for the date of each file, before looking in its name (needs the plugin feature)"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .env("CLASSFY_PROFILE")
+                .value_name("NAME")
+                .add(ArgValueCandidates::new(profile_names))
+                .global(true)
+                .help("Use the settings in the [profile.NAME] section of the configuration file"),
+        )
+        .arg(
+            Arg::new("print0")
+                .long("print0")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help(
+                    "Print the new path of each placed file followed by a NUL, instead of messages",
+                ),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Explain how each file was classified"),
+        )
+        .arg(
+            Arg::new("log-target")
+                .long("log-target")
+                .value_name("TARGET")
+                .value_parser(
+                    PossibleValuesParser::new(LOG_TARGETS).try_map(|name| LogTarget::parse(&name)),
+                )
+                .global(true)
+                .help("Where to write the messages, such as the system journal with syslog, which records the file, year and action of each"),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output")
+                .value_name("FORMAT")
+                .default_value("text")
+                .value_parser(
+                    PossibleValuesParser::new(OUTPUT_FORMATS)
+                        .try_map(|name| OutputFormat::parse(&name)),
+                )
+                .conflicts_with("print0")
+                .help("How to write the progress, where ndjson writes a line of JSON for each file and step as it happens, with the messages written to standard error"),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .default_value("auto")
+                .value_parser(
+                    PossibleValuesParser::new(COLOR_CHOICES)
+                        .try_map(|name| ColorChoice::parse(&name)),
+                )
+                .global(true)
+                .help("When to colour the status of each file, where auto colours it on a terminal unless NO_COLOR is set"),
+        )
+        .arg(
+            Arg::new("stats-file")
+                .long("stats-file")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(path::PathBuf))
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Save the statistics of each run to FILE as JSON, for monitoring to collect"),
+        )
+        .arg(
+            Arg::new("notify")
+                .long("notify")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Show a desktop notification with a summary once the files are placed (needs the notify feature)"),
+        )
+        .subcommand(
+            Command::new("plan")
+                .about("Work out where the files belong and save it as a plan, without moving them")
+                .args(input_args())
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("The file to save the plan in"),
+                )
+                .arg(
+                    Arg::new("diff")
+                        .long("diff")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the plan as a tree of the directories it changes, instead of a list of the files"),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Move the files as described by a previously saved plan")
+                .arg(
+                    Arg::new("plan")
+                        .value_name("PLAN")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("The plan saved by classfy plan"),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Review and adjust where the files will be placed before moving them")
+                .args(input_args()),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Classify the directories listed with [[watch]] in the configuration")
+                .arg(
+                    Arg::new("scheduled")
+                        .long("scheduled")
+                        .action(ArgAction::SetTrue)
+                        .help("Keep running, classifying the directories at the times given by schedule in the configuration"),
+                ),
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Keep classifying the files that arrive in the directories listed with [[watch]] in the configuration (needs the daemon feature)")
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("SECONDS")
+                        .default_value("10")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("How often to check the directories for new files"),
+                )
+                .arg(
+                    Arg::new("socket")
+                        .long("socket")
+                        .value_name("PATH")
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("The control socket, which defaults to classfy.sock in the runtime directory"),
+                )
+                .arg(
+                    Arg::new("status")
+                        .long("status")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("reload")
+                        .help("Print the status of the running daemon"),
+                )
+                .arg(
+                    Arg::new("reload")
+                        .long("reload")
+                        .action(ArgAction::SetTrue)
+                        .help("Ask the running daemon to reload the configuration, as SIGHUP does"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check that the files in an existing archive are in the directories their dates belong in, without moving them")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                ),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Count the files and their total size in each year directory of an existing archive")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                )
+                .arg(
+                    Arg::new("by-extension")
+                        .long("by-extension")
+                        .action(ArgAction::SetTrue)
+                        .help("Count the files of each extension separately"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .default_value("table")
+                        .value_parser(
+                            PossibleValuesParser::new(REPORT_FORMATS)
+                                .try_map(|name| inventory::Format::parse(&name)),
+                        )
+                        .help("How to write the counts"),
+                ),
+        )
+        .subcommand(
+            Command::new("reclassify")
+                .about("Find the dates of the files in an existing archive again, and move those that belong in another year directory under the current settings")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                )
+                .arg(
+                    Arg::new("apply")
+                        .long("apply")
+                        .action(ArgAction::SetTrue)
+                        .help("Move the files, rather than only showing where they would be moved"),
+                ),
+        )
+        .subcommand(
+            Command::new("flatten")
+                .about("Move the files in the year directories of an existing archive back into its root, and remove the year directories")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                ),
+        )
+        .subcommand(
+            Command::new("merge-dirs")
+                .about("Merge the year directories of an existing archive that are for the same year but named differently, such as FY2022 and 2022FY, into the one named by --dir-template or --fy-style")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate-layout")
+                .about("Rename the year directories of an existing archive to the names given by --dir-template or --fy-style")
+                .arg(
+                    Arg::new("root")
+                        .value_name("ARCHIVE_ROOT")
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory that contains the year directories"),
+                )
+                .arg(
+                    Arg::new("nested")
+                        .long("nested")
+                        .action(ArgAction::SetTrue)
+                        .help("Also move the files within each year into the directories given by --layout, such as quarters"),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the configuration and the directories for problems, and say how to fix them")
+                .arg(
+                    Arg::new("dirs")
+                        .value_name("DIR")
+                        .num_args(0..)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directories to check (defaults to the watched directories, or the current directory)"),
+                ),
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Show each of the ways the date of a file is looked for, what each found and where the file is placed")
+                .arg(
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .num_args(1..)
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("The files to explain"),
+                ),
+        )
+        .subcommand(
+            Command::new("override")
+                .about("Give files a date by hand, which is used instead of looking for their dates in later runs")
+                .arg(
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .num_args(1..)
+                        .required(true)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("The files to date"),
+                )
+                .arg(
+                    Arg::new("date")
+                        .long("date")
+                        .value_name("DATE")
+                        .required_unless_present("forget")
+                        .conflicts_with("forget")
+                        .help("The date to give the files, such as 2022-09-14, 2022-09 or 2023FY"),
+                )
+                .arg(
+                    Arg::new("forget")
+                        .long("forget")
+                        .action(ArgAction::SetTrue)
+                        .help("Forget the dates given to the files, so that they are looked for again"),
+                ),
+        )
+        .subcommand(
+            Command::new("test-rules")
+                .about("Show which rule applies to each file name, the date found and where the file is placed, without needing the files")
+                .arg(
+                    Arg::new("names")
+                        .value_name("NAME")
+                        .num_args(0..)
+                        .help("The file names to try (defaults to reading them, one per line, from standard input)"),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("List the files tagged with their year by --mode tag, by year")
+                .arg(
+                    Arg::new("dirs")
+                        .value_name("DIR")
+                        .num_args(0..)
+                        .value_parser(clap::value_parser!(path::PathBuf))
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directories to look in (defaults to the current directory)"),
+                )
+                .arg(
+                    Arg::new("fy")
+                        .long("fy")
+                        .value_name("YEAR")
+                        .help("Only list the paths of the files tagged with YEAR, such as 2023FY"),
+                ),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Put back the files placed by the last run, so that it can be redone with classfy redo"),
+        )
+        .subcommand(
+            Command::new("redo")
+                .about("Place the files put back by classfy undo again, where the run placed them, without looking for their dates again"),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print the script that completes the arguments in a shell, which asks classfy for the names of the profiles in the configuration file as they are completed")
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(clap::value_parser!(clap_complete::Shell)),
+                ),
+        )
+}
+
+/// Gets where to find the files to classify, which defaults to the current directory.
+pub fn input(matches: &ArgMatches) -> Input {
+    let source = if let Some(list) = matches.get_one::<path::PathBuf>("files-from") {
+        let delimiter = if matches.get_flag("null") {
+            b'\0'
+        } else {
+            b'\n'
+        };
+        Source::FileList(list.clone(), delimiter)
+    } else if let Some(dirs) = matches.get_many::<path::PathBuf>("dirs") {
+        Source::Dirs(dirs.cloned().collect())
+    } else {
+        Source::Dirs(vec![path::PathBuf::from(".")])
+    };
+    Input {
+        source,
+        reclassify: matches.get_flag("reclassify"),
+        excludes: matches
+            .get_many::<String>("exclude")
+            .map(|excludes| excludes.cloned().collect())
+            .unwrap_or_default(),
+        incremental: matches.get_flag("incremental"),
+    }
+}
+
+/// Gets the layout of the directories to place the files in, from the command line and then the
+/// configuration.
+pub fn layout(matches: &ArgMatches, config: &Config) -> Layout {
+    let mut layout = Layout::default();
+    if let Some(template) = matches.get_one::<DirTemplate>("dir-template") {
+        layout.dir_template = template.clone();
+    }
+    if let Some(template) = matches.get_one::<DirTemplate>("fy-style") {
+        layout.dir_template = template.clone();
+    }
+    if let Some(recognised) = matches.get_many::<DirTemplate>("recognise") {
+        layout.recognised.extend(recognised.cloned());
+    }
+    if let Some(levels) = matches.get_one::<String>("layout") {
+        layout
+            .set_levels(levels)
+            .expect("layout should be one of the possible values");
+    }
+    if let Some(granularity) = matches.get_one::<Granularity>("by") {
+        layout.granularity = *granularity;
+    }
+    if matches.get_flag("calendar") {
+        layout.calendar = true;
+    }
+    if matches.get_flag("normalize-names") {
+        layout.normalize_names = true;
+    }
+    if matches.get_flag("stamp-dates") {
+        layout.stamp_dates = true;
+    }
+    layout.fiscal = fiscal(matches, config);
+    layout
+}
+
+/// Gets when the financial year starts and how it is named.
+fn fiscal(matches: &ArgMatches, config: &Config) -> FiscalYear {
+    let mut fiscal = config.fiscal;
+    if let Some(convention) = matches.get_one::<FiscalYear>("fy-convention") {
+        fiscal = *convention;
+    }
+    if let Some(month) = matches.get_one::<u8>("fy-start") {
+        fiscal.start_month = *month;
+    }
+    if let Some(start) = matches.get_one::<YearStart>("fy-start-day") {
+        fiscal.start = *start;
+    }
+    if let Some(label) = matches.get_one::<YearLabel>("fy-label") {
+        fiscal.label = *label;
+    }
+    fiscal
+}
+
+/// Gets how the dates are found in the file names, from the command line and then the
+/// configuration.
+pub fn extractor(matches: &ArgMatches, config: &Config) -> Extractor {
+    let mut extractor = Extractor::default();
+    if let Some(langs) = matches.get_many::<String>("lang") {
+        extractor.languages = Vec::new();
+        for lang in langs {
+            match lang.as_str() {
+                "auto" => extractor.languages.extend(Lang::ALL),
+                code => extractor.languages.push(
+                    Lang::parse(code).expect("language should be one of the possible values"),
+                ),
+            }
+        }
+    }
+    if let Some(separators) = matches.get_one::<String>("separators") {
+        extractor.separators = separators.chars().collect();
+    }
+    if matches.get_flag("epoch") {
+        extractor.epoch = true;
+    }
+    if let Some(pick) = matches.get_one::<DatePick>("date-pick") {
+        extractor.pick = *pick;
+    }
+    extractor.order = match matches.get_one::<DateOrder>("date-order") {
+        Some(order) => *order,
+        None => match matches
+            .get_raw("fy-convention")
+            .and_then(|mut names| names.next())
+        {
+            Some(name) => DateOrder::convention(&name.to_string_lossy()),
+            None => config.date_order,
+        },
+    };
+    extractor.fiscal = fiscal(matches, config);
+    extractor.timezone = matches.get_one::<chrono_tz::Tz>("timezone").copied();
+    extractor.csv_rules = config.csv_rules.clone();
+    extractor.rules = config.rules.clone();
+    if matches.get_flag("scan-content") {
+        let limit = matches
+            .get_one::<u64>("scan-limit")
+            .expect("scan-limit has a default");
+        extractor.scan_limit = Some(limit * 1024);
+    }
+    extractor
+}
+
+/// Gets how the files are to be placed from the command line.
+pub fn settings(matches: &ArgMatches) -> Settings {
+    Settings {
+        transfer: if matches.get_flag("copy") {
+            Transfer::Copy
+        } else if matches.get_flag("hardlink") {
+            Transfer::Hardlink
+        } else {
+            Transfer::Move
+        },
+        transactional: matches.get_flag("transactional"),
+        duplicates: matches.get_one::<DuplicatePolicy>("duplicates").copied(),
+        collision: matches
+            .get_one::<Collision>("on-collision")
+            .copied()
+            .unwrap_or_default(),
+        overwrite: matches.get_one::<Overwrite>("overwrite").copied(),
+        skip_existing: matches.get_flag("skip-existing"),
+        remove_archived: matches.get_flag("remove-archived"),
+        leave_symlink: matches.get_flag("leave-symlink"),
+        tag: matches.get_flag("tag"),
+        mode: matches.get_one::<Mode>("mode").copied().unwrap_or_default(),
+        backup: (!matches.get_flag("no-backup")).then(|| {
+            let started = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+            path::Path::new(BACKUP_DIR).join(started.to_string())
+        }),
+        history: journal::history_path(),
+    }
+}
+
+fn input_args() -> [Arg; 6] {
+    [
+        Arg::new("dirs")
+            .value_name("DIR")
+            .num_args(0..)
+            .value_hint(ValueHint::DirPath)
+            .value_parser(existing_dir)
+            .help("The directories to classify (defaults to the current directory)"),
+        Arg::new("files-from")
+            .long("files-from")
+            .value_name("FILE")
+            .value_parser(clap::value_parser!(path::PathBuf))
+            .value_hint(ValueHint::FilePath)
+            .conflicts_with("dirs")
+            .help("Classify the files listed, one per line, in FILE (or standard input if -)"),
+        Arg::new("null")
+            .short('0')
+            .long("null")
+            .action(ArgAction::SetTrue)
+            .requires("files-from")
+            .help("The files listed by --files-from are separated by NUL instead of new lines"),
+        Arg::new("exclude")
+            .long("exclude")
+            .value_name("PATTERN")
+            .action(ArgAction::Append)
+            .value_parser(exclude::parse_pattern)
+            .help("Leave out the files matching PATTERN, as in a .gitignore file, taking precedence over .classfyignore"),
+        Arg::new("reclassify")
+            .long("reclassify")
+            .action(ArgAction::SetTrue)
+            .help(
+                "Classify files in directories that have already been classified, such as 2022FY",
+            ),
+        Arg::new("incremental")
+            .long("incremental")
+            .action(ArgAction::SetTrue)
+            .help("Only look at the files that are new or have changed since the last run, recording the decisions in each directory"),
+    ]
+}
+
+fn layout_args() -> [Arg; 12] {
+    [
+        Arg::new("dir-template")
+            .long("dir-template")
+            .value_name("TEMPLATE")
+            .value_parser(DirTemplate::parse)
+            .global(true)
+            .help("The template for naming the financial year directories, such as FY{year}"),
+        Arg::new("fy-style")
+            .long("fy-style")
+            .value_name("STYLE")
+            .value_parser(
+                PossibleValuesParser::new(FY_STYLES.map(|(style, _)| style))
+                    .try_map(|style| DirTemplate::style(&style)),
+            )
+            .conflicts_with("dir-template")
+            .global(true)
+            .help("Name the financial year directories in one of the built-in styles"),
+        Arg::new("recognise")
+            .long("recognise")
+            .visible_alias("recognize")
+            .value_name("TEMPLATE")
+            .action(ArgAction::Append)
+            .value_parser(DirTemplate::parse)
+            .global(true)
+            .help(
+                "Reuse existing financial year directories named by TEMPLATE, in addition to \
+                 those named in any of the FY styles or like 2021-2022",
+            ),
+        Arg::new("by")
+            .long("by")
+            .value_name("GRANULARITY")
+            .value_parser(
+                PossibleValuesParser::new(GRANULARITIES).try_map(|name| Granularity::parse(&name)),
+            )
+            .global(true)
+            .help("How finely to divide the files within each year"),
+        Arg::new("calendar")
+            .long("calendar")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Divide the files by calendar year instead of financial year"),
+        Arg::new("normalize-names")
+            .long("normalize-names")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Rename the files to their name followed by their date, such as bill_2022-07-10.pdf, as they are placed"),
+        Arg::new("stamp-dates")
+            .long("stamp-dates")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Add the date to the names of files that have none, such as notes_14SEP2022.txt, dating them by when they were last modified if need be"),
+        Arg::new("layout")
+            .long("layout")
+            .value_name("LEVELS")
+            .value_parser(LAYOUTS)
+            .conflicts_with_all(["by", "calendar"])
+            .global(true)
+            .help("The levels of directories to create, such as fy/quarter or year/month"),
+        Arg::new("fy-convention")
+            .long("fy-convention")
+            .env("CLASSFY_FY_CONVENTION")
+            .value_name("COUNTRY")
+            .value_parser(
+                PossibleValuesParser::new(CONVENTIONS)
+                    .try_map(|name| FiscalYear::convention(&name)),
+            )
+            .global(true)
+            .help("Use the financial year of a country, such as uk for April to March (defaults to au)"),
+        Arg::new("fy-start")
+            .long("fy-start")
+            .env("CLASSFY_FY_START")
+            .value_name("MONTH")
+            .value_parser(clap::value_parser!(u8).range(1..=12))
+            .global(true)
+            .help("The month (1 to 12) in which the financial year starts"),
+        Arg::new("fy-start-day")
+            .long("fy-start-day")
+            .env("CLASSFY_FY_START_DAY")
+            .value_name("DAY")
+            .value_parser(YearStart::parse)
+            .global(true)
+            .help("The day on which the financial year starts: a day of its first month, such as 6, or last-sat or nearest-sat for a 52/53-week year that ends on the last Saturday of the month before or the Saturday nearest its end (defaults to 1)"),
+        Arg::new("fy-label")
+            .long("fy-label")
+            .env("CLASSFY_FY_LABEL")
+            .value_name("YEAR")
+            .value_parser(
+                PossibleValuesParser::new(YEAR_LABELS).try_map(|name| YearLabel::parse(&name)),
+            )
+            .global(true)
+            .help("Name each financial year by the calendar year it starts or ends in"),
+    ]
+}
+
+fn extractor_args() -> [Arg; 8] {
+    [
+        Arg::new("lang")
+            .long("lang")
+            .env("CLASSFY_LANG")
+            .value_name("LANG")
+            .value_parser(LANGS)
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .global(true)
+            .help(
+                "The languages of the month names in the file names, such as de for OKT, or auto \
+             for any of them (defaults to en)",
+            ),
+        Arg::new("date-pick")
+            .long("date-pick")
+            .env("CLASSFY_DATE_PICK")
+            .value_name("POLICY")
+            .value_parser(
+                PossibleValuesParser::new(DATE_PICKS).try_map(|name| DatePick::parse(&name)),
+            )
+            .global(true)
+            .help("Which date to use when a file name has more than one (defaults to last)"),
+        Arg::new("date-order")
+            .long("date-order")
+            .env("CLASSFY_DATE_ORDER")
+            .value_name("ORDER")
+            .value_parser(
+                PossibleValuesParser::new(DATE_ORDERS).try_map(|name| DateOrder::parse(&name)),
+            )
+            .global(true)
+            .help("The order of the day, month and year in dates written only in numbers, such as 01-02-2022 (defaults to mdy with --fy-convention us, ymd with jp and dmy otherwise)"),
+        Arg::new("timezone")
+            .long("timezone")
+            .env("CLASSFY_TIMEZONE")
+            .value_name("ZONE")
+            .value_parser(extract::parse_timezone)
+            .global(true)
+            .help("The time zone to read the times of files in, by its IANA name such as Australia/Perth, so that a file modified late on 30 June is placed in the year it was in that zone (defaults to the system's)"),
+        Arg::new("separators")
+            .long("separators")
+            .value_name("CHARS")
+            .value_parser(clap::builder::NonEmptyStringValueParser::new())
+            .global(true)
+            .help(
+                "The characters that separate the date from the rest of the file name \
+                 (defaults to underscore, dash, dot and space)",
+            ),
+        Arg::new("epoch")
+            .long("epoch")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Also recognise Unix timestamps in seconds or milliseconds, such as 1657843200"),
+        Arg::new("scan-content")
+            .long("scan-content")
+            .action(ArgAction::SetTrue)
+            .global(true)
+            .help("Look for a date in the contents of text files whose names do not have one"),
+        Arg::new("scan-limit")
+            .long("scan-limit")
+            .value_name("KIB")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .default_value("64")
+            .global(true)
+            .help("How much of the start of each text file to look in with --scan-content"),
+    ]
+}
+
+/// Gets the names of the profiles to complete --profile with, from the configuration file given by
+/// CLASSFY_CONFIG or otherwise found as it is for a run.
+fn profile_names() -> Vec<CompletionCandidate> {
+    let path = std::env::var_os("CLASSFY_CONFIG").map(path::PathBuf::from);
+    Config::load(path.as_deref(), None)
+        .map(|config| {
+            config
+                .profiles
+                .into_iter()
+                .map(CompletionCandidate::new)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn existing_dir(value: &str) -> Result<path::PathBuf, String> {
+    let path = path::PathBuf::from(value);
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(format!("{} is not a directory", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::command;
+    use classfy::config::Config;
+    use classfy::date::DateOrder;
+    use classfy::fiscal::{YearLabel, YearStart};
+
+    #[test]
+    fn test_command() {
+        command().debug_assert();
+    }
+
+    #[test]
+    fn test_no_backup() {
+        assert!(command()
+            .try_get_matches_from(["classfy", "--overwrite", "--no-backup", "."])
+            .is_ok());
+        assert!(command()
+            .try_get_matches_from(["classfy", "--transactional", "--overwrite", "--no-backup"])
+            .is_err());
+        assert!(command()
+            .try_get_matches_from(["classfy", "tui", "--no-backup", "--transactional"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        let matches = command()
+            .try_get_matches_from(["classfy", "tui", "--by", "quarter", "--calendar", "."])
+            .unwrap();
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        let layout = super::layout(sub_matches, &Config::default());
+        assert!(layout.calendar);
+        assert_eq!(layout.granularity, classfy::bucket::Granularity::Quarter);
+
+        assert!(command()
+            .try_get_matches_from(["classfy", "--layout", "year", "--by", "month"])
+            .is_err());
+        let matches = command()
+            .try_get_matches_from(["classfy", "--fy-style", "FY21-22"])
+            .unwrap();
+        assert_eq!(
+            super::layout(&matches, &Config::default())
+                .dir_template
+                .render(2022)
+                .unwrap(),
+            "FY21-22"
+        );
+
+        let matches = command()
+            .try_get_matches_from(["classfy", "--fy-convention", "uk", "--fy-start", "5"])
+            .unwrap();
+        let fiscal = super::layout(&matches, &Config::default()).fiscal;
+        assert_eq!(fiscal.start_month, 5);
+        assert_eq!(fiscal.label, YearLabel::Start);
+
+        let matches = command()
+            .try_get_matches_from(["classfy", "--fy-start-day", "nearest-sat"])
+            .unwrap();
+        assert_eq!(
+            super::layout(&matches, &Config::default()).fiscal.start,
+            YearStart::AfterNearest(chrono::Weekday::Sat)
+        );
+        assert!(command()
+            .try_get_matches_from(["classfy", "--fy-start-day", "0"])
+            .is_err());
+
+        let matches = command()
+            .try_get_matches_from(["classfy", "--lang", "de,en"])
+            .unwrap();
+        assert_eq!(
+            super::extractor(&matches, &Config::default()).languages,
+            [classfy::date::Lang::De, classfy::date::Lang::En]
+        );
+
+        let matches = command()
+            .try_get_matches_from(["classfy", "--fy-convention", "us"])
+            .unwrap();
+        assert_eq!(
+            super::extractor(&matches, &Config::default()).order,
+            DateOrder::Mdy
+        );
+        let matches = command()
+            .try_get_matches_from(["classfy", "--fy-convention", "us", "--date-order", "dmy"])
+            .unwrap();
+        assert_eq!(
+            super::extractor(&matches, &Config::default()).order,
+            DateOrder::Dmy
+        );
+
+        assert!(command()
+            .try_get_matches_from(["classfy", "--unknown"])
+            .is_err());
+    }
+}
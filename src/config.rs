@@ -0,0 +1,434 @@
+//! Settings read from the configuration file, which the command line can override.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::content::CsvRule;
+use crate::date::DateOrder;
+use crate::fiscal::{FiscalYear, YearLabel, YearStart};
+use crate::remote::{Credentials, OAuthClient};
+use crate::rules::Rule;
+use crate::schedule::{Schedule, DEFAULT_JITTER};
+
+/// The name of the configuration file.
+pub const CONFIG_NAME: &str = "classfy.toml";
+
+/// Settings read from the configuration file.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// When the financial year starts and how it is named.
+    pub fiscal: FiscalYear,
+    /// The order that dates written only in numbers are read in, which is that of the country of
+    /// the financial year convention.
+    pub date_order: DateOrder,
+    /// Rules for finding the date in a column of CSV files.
+    pub csv_rules: Vec<CsvRule>,
+    /// Rules for where to find the date of particular files and where to place them, in the
+    /// order that they are tried.
+    pub rules: Vec<Rule>,
+    /// The URL to post a summary of each run to.
+    pub webhook: Option<String>,
+    /// The credentials for uploading to a WebDAV server.
+    pub webdav: Credentials,
+    /// The credentials for uploading to a server with SFTP.
+    pub sftp: Credentials,
+    /// The application that classfy is authorised as to upload to Google Drive.
+    pub gdrive: OAuthClient,
+    /// The credentials for uploading to Dropbox.
+    pub dropbox: Credentials,
+    /// The directories that the daemon watches, which are also those classified by `classfy run`.
+    pub watch: Vec<Watch>,
+    /// When `classfy run --scheduled` classifies the directories.
+    pub schedule: Option<Schedule>,
+    /// The most that each scheduled run is delayed by.
+    pub schedule_jitter: Duration,
+    /// The file that every change made to the files is appended to, in all runs.
+    pub audit_log: Option<path::PathBuf>,
+    /// The names of the profiles in the file, which can be selected with `--profile`.
+    pub profiles: Vec<String>,
+}
+
+/// A directory that the daemon classifies the files in as they arrive.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Watch {
+    pub dir: path::PathBuf,
+    /// The profile with the settings to classify the files in the directory with.
+    pub profile: Option<String>,
+}
+
+/// The configuration file as it is written. Each profile has the same settings, which override
+/// those at the top of the file when the profile is selected.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    fy_convention: Option<String>,
+    fy_start: Option<u8>,
+    fy_start_day: Option<String>,
+    fy_label: Option<String>,
+    csv_rules: Option<Vec<CsvRuleFile>>,
+    rules: Option<Vec<RuleFile>>,
+    webhook: Option<String>,
+    webdav: Option<Credentials>,
+    sftp: Option<Credentials>,
+    gdrive: Option<OAuthClient>,
+    dropbox: Option<Credentials>,
+    watch: Option<Vec<Watch>>,
+    schedule: Option<String>,
+    /// In seconds.
+    schedule_jitter: Option<u64>,
+    audit_log: Option<path::PathBuf>,
+    profile: BTreeMap<String, ConfigFile>,
+}
+
+impl ConfigFile {
+    /// Gets the settings with those that are set in the profile replacing these.
+    fn with_profile(self, profile: ConfigFile) -> ConfigFile {
+        ConfigFile {
+            fy_convention: profile.fy_convention.or(self.fy_convention),
+            fy_start: profile.fy_start.or(self.fy_start),
+            fy_start_day: profile.fy_start_day.or(self.fy_start_day),
+            fy_label: profile.fy_label.or(self.fy_label),
+            csv_rules: profile.csv_rules.or(self.csv_rules),
+            rules: profile.rules.or(self.rules),
+            webhook: profile.webhook.or(self.webhook),
+            webdav: profile.webdav.or(self.webdav),
+            sftp: profile.sftp.or(self.sftp),
+            gdrive: profile.gdrive.or(self.gdrive),
+            dropbox: profile.dropbox.or(self.dropbox),
+            watch: self.watch,
+            schedule: profile.schedule.or(self.schedule),
+            schedule_jitter: profile.schedule_jitter.or(self.schedule_jitter),
+            audit_log: self.audit_log,
+            profile: BTreeMap::new(),
+        }
+    }
+}
+
+/// A rule for where to find the date of particular files, as it is written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    glob: Option<String>,
+    regex: Option<String>,
+    extractor: String,
+    format: Option<String>,
+    #[serde(default)]
+    command: Vec<String>,
+    destination: Option<String>,
+}
+
+/// A rule for finding the date in a column of CSV files, as it is written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsvRuleFile {
+    glob: String,
+    column: String,
+}
+
+impl Config {
+    /// Loads the configuration from the given file, with the settings of the profile if one is
+    /// given. Without a file, `classfy.toml` is looked for in the current directory and then in the
+    /// user's configuration directory. If there is none, the defaults are used.
+    pub fn load(path: Option<&path::Path>, profile: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match (default_path(), profile) {
+                (Some(path), _) => path,
+                (None, Some(profile)) => {
+                    return Err(format!(
+                        "There is no {} to find the profile {:?} in",
+                        CONFIG_NAME, profile
+                    ))
+                }
+                (None, None) => return Ok(Config::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Config::parse(&contents, profile).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Parses the contents of a configuration file, using the settings of the profile if one is
+    /// given.
+    fn parse(contents: &str, profile: Option<&str>) -> Result<Self, String> {
+        let mut file: ConfigFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+        if let Some((name, _)) = file
+            .profile
+            .iter()
+            .find(|(_, profile)| !profile.profile.is_empty())
+        {
+            return Err(format!(
+                "profile {:?} cannot have profiles of its own",
+                name
+            ));
+        }
+        if let Some((name, _)) = file
+            .profile
+            .iter()
+            .find(|(_, profile)| profile.watch.is_some())
+        {
+            return Err(format!(
+                "profile {:?} cannot have directories to watch, give the profile in [[watch]] instead",
+                name
+            ));
+        }
+        if let Some((name, _)) = file
+            .profile
+            .iter()
+            .find(|(_, profile)| profile.audit_log.is_some())
+        {
+            return Err(format!(
+                "profile {:?} cannot have an audit log of its own, as it records every run",
+                name
+            ));
+        }
+        let profiles = file.profile.keys().cloned().collect();
+        if let Some(name) = profile {
+            let selected = file.profile.remove(name).ok_or_else(|| {
+                let names: Vec<&str> = file.profile.keys().map(String::as_str).collect();
+                format!(
+                    "there is no profile {:?}, the profiles are: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+            file = file.with_profile(selected);
+        }
+        let mut fiscal = match &file.fy_convention {
+            Some(convention) => FiscalYear::convention(convention)?,
+            None => FiscalYear::default(),
+        };
+        if let Some(month) = file.fy_start {
+            if !(1..=12).contains(&month) {
+                return Err(format!("fy-start must be from 1 to 12, not {}", month));
+            }
+            fiscal.start_month = month;
+        }
+        if let Some(start) = &file.fy_start_day {
+            fiscal.start = YearStart::parse(start)?;
+        }
+        if let Some(label) = &file.fy_label {
+            fiscal.label = YearLabel::parse(label)?;
+        }
+        let csv_rules = file
+            .csv_rules
+            .unwrap_or_default()
+            .iter()
+            .map(|rule| CsvRule::new(&rule.glob, &rule.column))
+            .collect::<Result<_, _>>()?;
+        let rules = file
+            .rules
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                Rule::new(
+                    rule.glob.as_deref(),
+                    rule.regex.as_deref(),
+                    &rule.extractor,
+                    rule.format.as_deref(),
+                    &rule.command,
+                    rule.destination.as_deref(),
+                )
+                .map_err(|e| format!("rule {}: {}", index + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Config {
+            fiscal,
+            date_order: file
+                .fy_convention
+                .as_deref()
+                .map_or_else(DateOrder::default, DateOrder::convention),
+            csv_rules,
+            rules,
+            webhook: file.webhook,
+            webdav: file.webdav.unwrap_or_default(),
+            sftp: file.sftp.unwrap_or_default(),
+            gdrive: file.gdrive.unwrap_or_default(),
+            dropbox: file.dropbox.unwrap_or_default(),
+            watch: file.watch.unwrap_or_default(),
+            schedule: file.schedule.as_deref().map(Schedule::parse).transpose()?,
+            schedule_jitter: file
+                .schedule_jitter
+                .map_or(DEFAULT_JITTER, Duration::from_secs),
+            audit_log: file.audit_log,
+            profiles,
+        })
+    }
+}
+
+/// Gets the configuration file to use when none is given, if there is one.
+fn default_path() -> Option<path::PathBuf> {
+    let local = path::PathBuf::from(CONFIG_NAME);
+    if local.is_file() {
+        return Some(local);
+    }
+    dirs::config_dir()
+        .map(|dir| dir.join("classfy").join(CONFIG_NAME))
+        .filter(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+    use std::time::Duration;
+
+    use super::{Config, Watch};
+    use crate::date::DateOrder;
+    use crate::fiscal::{YearLabel, YearStart};
+    use crate::remote::Credentials;
+    use crate::schedule::{Schedule, DEFAULT_JITTER};
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse("fy-convention = \"us\"\nfy-label = \"start\"\n", None).unwrap();
+        assert_eq!(config.fiscal.start_month, 10);
+        assert_eq!(config.fiscal.label, YearLabel::Start);
+        assert_eq!(config.date_order, DateOrder::Mdy);
+
+        assert_eq!(Config::parse("", None).unwrap().fiscal.start_month, 7);
+        assert!(Config::parse("fy-start = 13", None).is_err());
+        assert!(Config::parse("fy-label = \"middle\"", None).is_err());
+        assert_eq!(
+            Config::parse("fy-start-day = \"last-sat\"", None)
+                .unwrap()
+                .fiscal
+                .start,
+            YearStart::AfterLast(chrono::Weekday::Sat)
+        );
+        assert!(Config::parse("fy-start-day = \"first-sat\"", None).is_err());
+        assert!(Config::parse("unknown = 1", None).is_err());
+
+        let config = Config::parse(
+            "[[csv-rules]]\nglob = \"transactions*.csv\"\ncolumn = \"Transaction Date\"\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.csv_rules[0].column, "Transaction Date");
+        assert!(Config::parse("[[csv-rules]]\nglob = \"*.csv\"\n", None).is_err());
+
+        let config =
+            Config::parse("[webdav]\nuser = \"alex\"\npassword = \"secret\"\n", None).unwrap();
+        assert_eq!(config.webdav.user.as_deref(), Some("alex"));
+        assert_eq!(config.webdav.token, None);
+        assert!(Config::parse("[webdav]\npasswd = \"secret\"\n", None).is_err());
+        assert_eq!(config.sftp, Credentials::default());
+
+        let config = Config::parse("[gdrive]\nclient-id = \"classfy.apps\"\n", None).unwrap();
+        assert_eq!(config.gdrive.client_id.as_deref(), Some("classfy.apps"));
+        assert_eq!(config.gdrive.client_secret, None);
+        assert_eq!(config.dropbox, Credentials::default());
+        assert_eq!(config.webhook, None);
+
+        let config = Config::parse("webhook = \"https://ntfy.example/classfy\"\n", None).unwrap();
+        assert_eq!(
+            config.webhook.as_deref(),
+            Some("https://ntfy.example/classfy")
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let contents = "fy-start = 4\nfy-label = \"start\"\n\n\
+                        [profile.work]\nfy-convention = \"uk\"\n\n\
+                        [profile.personal]\nfy-start = 7\n";
+        let base = Config::parse(contents, None).unwrap();
+        assert_eq!(
+            (base.fiscal.start_month, base.fiscal.label),
+            (4, YearLabel::Start)
+        );
+        assert_eq!(base.profiles, ["personal", "work"]);
+        let personal = Config::parse(contents, Some("personal")).unwrap();
+        assert_eq!(
+            (personal.fiscal.start_month, personal.fiscal.label),
+            (7, YearLabel::Start)
+        );
+        let error = Config::parse(contents, Some("home")).unwrap_err();
+        assert!(error.contains("personal, work"));
+
+        assert!(Config::parse("[profile.work]\nunknown = 1\n", None).is_err());
+        assert!(Config::parse("[profile.work.profile.home]\n", None).is_err());
+    }
+
+    #[test]
+    fn test_rules() {
+        let config = Config::parse(
+            "[[rules]]\nglob = \"INV-*.pdf\"\nextractor = \"mtime\"\n\n\
+             [[rules]]\nregex = \"^scan\"\nextractor = \"metadata\"\ndestination = \"{bucket}/Scans\"\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(
+            config.rules[1].destination.as_deref(),
+            Some("{bucket}/Scans")
+        );
+
+        let error = Config::parse(
+            "[[rules]]\nglob = \"*\"\nextractor = \"mtime\"\n\n[[rules]]\nextractor = \"mtime\"\n",
+            None,
+        )
+        .unwrap_err();
+        assert!(error.starts_with("rule 2:"));
+    }
+
+    #[test]
+    fn test_watch() {
+        let contents = "[[watch]]\ndir = \"/scans\"\n\n\
+                        [[watch]]\ndir = \"/work/scans\"\nprofile = \"work\"\n\n\
+                        [profile.work]\nfy-convention = \"uk\"\n";
+        let config = Config::parse(contents, None).unwrap();
+        assert_eq!(
+            config.watch,
+            vec![
+                Watch {
+                    dir: path::PathBuf::from("/scans"),
+                    profile: None,
+                },
+                Watch {
+                    dir: path::PathBuf::from("/work/scans"),
+                    profile: Some(String::from("work")),
+                },
+            ]
+        );
+        assert_eq!(
+            Config::parse(contents, Some("work")).unwrap().watch.len(),
+            2
+        );
+
+        assert!(Config::parse("[[watch]]\nprofile = \"work\"\n", None).is_err());
+        assert!(Config::parse(
+            "[profile.work]\n[[profile.work.watch]]\ndir = \"/scans\"\n",
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let contents = "audit-log = \"/var/log/classfy/audit.jsonl\"\n[profile.work]\n";
+        assert_eq!(
+            Config::parse(contents, Some("work")).unwrap().audit_log,
+            Some(path::PathBuf::from("/var/log/classfy/audit.jsonl"))
+        );
+        assert!(Config::parse("[profile.work]\naudit-log = \"audit.jsonl\"\n", None).is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let config =
+            Config::parse("schedule = \"hourly :30\"\nschedule-jitter = 0\n", None).unwrap();
+        assert_eq!(config.schedule, Some(Schedule::Hourly(30)));
+        assert_eq!(config.schedule_jitter, Duration::ZERO);
+
+        let config = Config::parse("", None).unwrap();
+        assert_eq!(config.schedule, None);
+        assert_eq!(config.schedule_jitter, DEFAULT_JITTER);
+        assert!(Config::parse("schedule = \"fortnightly\"\n", None).is_err());
+    }
+}
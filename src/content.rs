@@ -0,0 +1,435 @@
+//! Finding the date in the contents of a file, for files whose names do not have one.
+
+use std::fs;
+use std::io::Read;
+use std::path;
+
+use chrono::Datelike;
+
+use crate::date::{self, DateOrder, Extractor, FileDate};
+
+/// The most bytes read from the start of an OFX statement when looking for its end date.
+const OFX_LIMIT: u64 = 1024 * 1024;
+
+/// The most bytes read from the start of an email when looking for its Date header.
+const EMAIL_LIMIT: u64 = 64 * 1024;
+
+/// The most bytes read from the start of a text file by a rule that looks in the contents, unless
+/// `--scan-limit` is given.
+pub const DEFAULT_SCAN_LIMIT: u64 = 64 * 1024;
+
+/// The most bytes read from the properties of an Office document.
+const CORE_PROPERTIES_LIMIT: u64 = 1024 * 1024;
+
+/// Classifies the CSV files whose names match the glob by the date in a column of their first row.
+#[derive(Clone, Debug)]
+pub struct CsvRule {
+    /// Matches the names of the files that the rule applies to.
+    pub glob: globset::GlobMatcher,
+    /// The heading of the column with the date.
+    pub column: String,
+}
+
+impl CsvRule {
+    /// Creates the rule from the glob, such as "transactions*.csv", and the column heading.
+    pub fn new(glob: &str, column: &str) -> Result<Self, String> {
+        let glob = globset::Glob::new(glob)
+            .map_err(|e| format!("Invalid glob {:?}: {}", glob, e))?
+            .compile_matcher();
+        Ok(CsvRule {
+            glob,
+            column: String::from(column),
+        })
+    }
+
+    /// Gets the date from the column in the first row after the headings.
+    fn get_date(&self, path: &path::Path, extractor: &Extractor) -> Result<FileDate, String> {
+        let read_error = |e: csv::Error| format!("Could not read {}: {}", path.display(), e);
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(path)
+            .map_err(read_error)?;
+        let index = reader
+            .headers()
+            .map_err(read_error)?
+            .iter()
+            .position(|heading| heading.trim() == self.column)
+            .ok_or_else(|| format!("No column {:?} in {}", self.column, path.display()))?;
+        let record = match reader.records().next() {
+            Some(record) => record.map_err(read_error)?,
+            None => return Err(format!("No rows in {}", path.display())),
+        };
+        let value = record.get(index).unwrap_or("").trim();
+        numeric_date(value, extractor.order)
+            .or_else(|| find_text_date(value, extractor))
+            .ok_or_else(|| format!("{:?} in column {:?} is not a date", value, self.column))
+    }
+}
+
+/// Gets the date from the contents of the file, if there is one and the file is of a kind that is
+/// looked in.
+pub fn get_date(path: &path::Path, extractor: &Extractor) -> Result<Option<FileDate>, String> {
+    let name = path.file_name().unwrap_or_default();
+    if let Some(rule) = extractor
+        .csv_rules
+        .iter()
+        .find(|rule| rule.glob.is_match(name))
+    {
+        return rule.get_date(path, extractor).map(Some);
+    }
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match (extension.as_deref(), extractor.scan_limit) {
+        (Some("ofx" | "qfx"), _) => read_start(path, OFX_LIMIT).map(|text| ofx_end_date(&text)),
+        (Some("eml"), _) => read_start(path, EMAIL_LIMIT).map(|text| email_date(&text)),
+        (Some("docx" | "docm" | "xlsx" | "xlsm" | "pptx" | "pptm"), _) => ooxml_created(path),
+        (_, Some(limit)) => scan_text(path, limit, extractor),
+        (_, None) => Ok(None),
+    }
+}
+
+/// Gets the date that the file was created from its properties, if it is an Office document that
+/// has them.
+pub fn document_date(path: &path::Path) -> Result<Option<FileDate>, String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("docx" | "docm" | "xlsx" | "xlsm" | "pptx" | "pptm") => ooxml_created(path),
+        _ => Ok(None),
+    }
+}
+
+/// Reads up to `limit` bytes from the start of the file.
+fn read_start(path: &path::Path, limit: u64) -> Result<String, String> {
+    let mut contents = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(limit).read_to_end(&mut contents))
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(String::from_utf8_lossy(&contents).into_owned())
+}
+
+/// Gets the end date of the statement in an OFX (or Quicken QFX) file, from its `<DTEND>` tag.
+/// The value starts with the date as YYYYMMDD, and may be followed by the time and time zone.
+fn ofx_end_date(text: &str) -> Option<FileDate> {
+    let start = text.to_ascii_uppercase().find("<DTEND>")? + "<DTEND>".len();
+    let value = text[start..].trim_start();
+    let date = value
+        .get(..8)
+        .filter(|date| date.bytes().all(|b| b.is_ascii_digit()))?;
+    date::parse_day(date, "%Y%m%d").ok()
+}
+
+/// Looks for a date in the first `limit` bytes of a text file, such as "Statement date: 14 Sep
+/// 2022". Files that appear to be binary are skipped.
+fn scan_text(
+    path: &path::Path,
+    limit: u64,
+    extractor: &Extractor,
+) -> Result<Option<FileDate>, String> {
+    let text = read_start(path, limit)?;
+    if text.contains('\0') {
+        return Ok(None);
+    }
+    Ok(find_text_date(&text, extractor))
+}
+
+/// Gets the date that an email was sent, from its RFC 2822 `Date:` header. The date is as it was
+/// where the email was sent from.
+fn email_date(text: &str) -> Option<FileDate> {
+    let mut lines = text.lines().take_while(|line| !line.is_empty()).peekable();
+    while let Some(line) = lines.next() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !name.eq_ignore_ascii_case("date") {
+            continue;
+        }
+        let mut value = String::from(value.trim());
+        while let Some(folded) = lines.next_if(|line| line.starts_with([' ', '\t'])) {
+            value.push(' ');
+            value.push_str(folded.trim());
+        }
+        let sent = chrono::DateTime::parse_from_rfc2822(&value).ok()?;
+        return Some(FileDate::Day {
+            year: u16::try_from(sent.year()).ok()?,
+            month: sent.month() as u8,
+            day: sent.day() as u8,
+        });
+    }
+    None
+}
+
+/// Gets the date that an Office Open XML document, such as a .docx or .xlsx file, was created from
+/// the properties in its docProps/core.xml. If it does not say when it was created, the date it
+/// was last modified is used.
+fn ooxml_created(path: &path::Path) -> Result<Option<FileDate>, String> {
+    let read_error = |e: zip::result::ZipError| format!("Could not read {}: {}", path.display(), e);
+    let file =
+        fs::File::open(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(read_error)?;
+    let core = match archive.by_name("docProps/core.xml") {
+        Ok(core) => core,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(read_error(e)),
+    };
+    let mut xml = String::new();
+    core.take(CORE_PROPERTIES_LIMIT)
+        .read_to_string(&mut xml)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(core_property_date(&xml, b"created").or_else(|| core_property_date(&xml, b"modified")))
+}
+
+/// Gets the date from a property, such as `<dcterms:created>2022-09-14T03:21:00Z</...>`, in the
+/// core properties of an Office document.
+fn core_property_date(xml: &str, property: &[u8]) -> Option<FileDate> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    loop {
+        match reader.read_event().ok()? {
+            quick_xml::events::Event::Start(start) if start.local_name().as_ref() == property => {
+                let text = reader.read_text(start.name()).ok()?;
+                let date =
+                    chrono::NaiveDate::parse_from_str(text.trim().get(..10)?, "%Y-%m-%d").ok()?;
+                return Some(FileDate::Day {
+                    year: u16::try_from(date.year()).ok()?,
+                    month: date.month() as u8,
+                    day: date.day() as u8,
+                });
+            }
+            quick_xml::events::Event::Eof => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Gets the date written as numbers in the order, such as "14/09/2022" when the day is first.
+fn numeric_date(text: &str, order: DateOrder) -> Option<FileDate> {
+    let parts: Vec<&str> = text.split('/').collect();
+    let year = match order {
+        DateOrder::Dmy | DateOrder::Mdy => 2,
+        DateOrder::Ymd => 0,
+    };
+    let digits = parts.len() == 3
+        && parts.iter().enumerate().all(|(i, part)| {
+            let lengths: &[usize] = if i == year { &[4] } else { &[1, 2] };
+            lengths.contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit())
+        });
+    if !digits {
+        return None;
+    }
+    date::parse_day(text, &order.format('/')).ok()
+}
+
+/// Finds the first date in the text. Besides the forms recognised in file names, dates may be
+/// written as separate words, such as "14 Sep 2022" or "14 September 2022".
+fn find_text_date(text: &str, extractor: &Extractor) -> Option<FileDate> {
+    let text = text.to_uppercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '-'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.iter().enumerate().find_map(|(i, word)| {
+        words_date(&words[i..], extractor).or_else(|| extractor.parse_token(word).ok())
+    })
+}
+
+/// Gets the date written as the day, month and year in the first three words.
+fn words_date(words: &[&str], extractor: &Extractor) -> Option<FileDate> {
+    let [day, month, year, ..] = words else {
+        return None;
+    };
+    let digits = |word: &str, lengths: &[usize]| {
+        lengths.contains(&word.len()) && word.bytes().all(|b| b.is_ascii_digit())
+    };
+    if !digits(day, &[1, 2]) || !digits(year, &[4]) || !month.chars().all(char::is_alphabetic) {
+        return None;
+    }
+    let day = day.parse().ok()?;
+    // Months are abbreviated with four letters in some languages, such as "JUIL" in French.
+    let abbreviation = |length| {
+        month
+            .char_indices()
+            .nth(length)
+            .map_or(*month, |(i, _)| &month[..i])
+    };
+    let month = [4, 3]
+        .into_iter()
+        .find_map(|length| extractor.get_month(abbreviation(length)).ok())?;
+    FileDate::day(year.parse().ok()?, month, day).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use std::io::Write;
+
+    use super::{email_date, find_text_date, get_date, ofx_end_date, CsvRule};
+    use crate::date::{Extractor, FileDate};
+
+    #[test]
+    fn test_find_text_date() {
+        let extractor = Extractor::default();
+        let day = |year, month, day| Some(FileDate::Day { year, month, day });
+        assert_eq!(
+            find_text_date("Account 1234\nStatement date: 14 Sep 2022\n", &extractor),
+            day(2022, 9, 14)
+        );
+        assert_eq!(
+            find_text_date("Date,Amount\n2022-07-01,10.00\n", &extractor),
+            day(2022, 7, 1)
+        );
+        assert_eq!(
+            find_text_date("Issued 3 September 2021.", &extractor),
+            day(2021, 9, 3)
+        );
+        assert_eq!(find_text_date("Page 1 of 2022 pages", &extractor), None);
+        assert_eq!(
+            find_text_date("Due 30 Feb 2022, paid 31/04/2022 or 1 Mar 2022", &extractor),
+            day(2022, 3, 1)
+        );
+        let french = Extractor {
+            languages: vec![crate::date::Lang::Fr],
+            ..Extractor::default()
+        };
+        assert_eq!(
+            find_text_date("Facture du 10 juillet 2023", &french),
+            day(2023, 7, 10)
+        );
+    }
+
+    #[test]
+    fn test_csv_rule() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("transactions.csv");
+        fs::write(
+            &path,
+            "Description,Transaction Date,Amount\n\"Coffee, large\",14/09/2022,4.50\n\
+             Rent,2022-10-01,100\n",
+        )
+        .unwrap();
+
+        let extractor = Extractor {
+            csv_rules: vec![CsvRule::new("transactions*.csv", "Transaction Date").unwrap()],
+            ..Extractor::default()
+        };
+        assert_eq!(
+            get_date(&path, &extractor),
+            Ok(Some(FileDate::Day {
+                year: 2022,
+                month: 9,
+                day: 14
+            }))
+        );
+
+        let missing = Extractor {
+            csv_rules: vec![CsvRule::new("*.csv", "Date").unwrap()],
+            ..Extractor::default()
+        };
+        assert!(get_date(&path, &missing).is_err());
+        assert!(CsvRule::new("[", "Date").is_err());
+    }
+
+    #[test]
+    fn test_email_date() {
+        let email = "Received: from mail.example.com\r\n\tby mx.example.com\r\n\
+                     From: Supplier <accounts@example.com>\r\n\
+                     DATE: Thu, 30 Jun 2022\r\n 23:30:00 -0700\r\n\
+                     Subject: Invoice\r\n\r\n\
+                     Date: Fri, 1 Jul 2022 10:00:00 +1000\r\n";
+        assert_eq!(
+            email_date(email),
+            Some(FileDate::Day {
+                year: 2022,
+                month: 6,
+                day: 30
+            })
+        );
+        assert_eq!(email_date("Subject: No date\r\n\r\nDate: 1 Jul 2022"), None);
+        assert_eq!(email_date("Date: yesterday\r\n"), None);
+    }
+
+    #[test]
+    fn test_ooxml_created() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("Budget.xlsx");
+        let mut archive = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        archive
+            .start_file(
+                "docProps/core.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        archive
+            .write_all(
+                br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+<dcterms:modified xsi:type="dcterms:W3CDTF">2023-01-05T09:00:00Z</dcterms:modified>
+<dcterms:created xsi:type="dcterms:W3CDTF">2022-09-14T03:21:00Z</dcterms:created>
+</cp:coreProperties>"#,
+            )
+            .unwrap();
+        archive.finish().unwrap();
+
+        assert_eq!(
+            get_date(&path, &Extractor::default()),
+            Ok(Some(FileDate::Day {
+                year: 2022,
+                month: 9,
+                day: 14
+            }))
+        );
+
+        let not_zip = tempdir.path().join("Notes.docx");
+        fs::write(&not_zip, "not a zip file").unwrap();
+        assert!(get_date(&not_zip, &Extractor::default()).is_err());
+    }
+
+    #[test]
+    fn test_ofx_end_date() {
+        let sgml = "OFXHEADER:100\n<OFX><BANKTRANLIST>\n<DTSTART>20220601\n\
+                    <dtend>20220630120000.000[-5:EST]\n<STMTTRN>";
+        assert_eq!(
+            ofx_end_date(sgml),
+            Some(FileDate::Day {
+                year: 2022,
+                month: 6,
+                day: 30
+            })
+        );
+        let xml = "<BANKTRANLIST><DTSTART>20220701</DTSTART><DTEND>20220731</DTEND>";
+        assert_eq!(
+            ofx_end_date(xml),
+            Some(FileDate::Day {
+                year: 2022,
+                month: 7,
+                day: 31
+            })
+        );
+        assert_eq!(ofx_end_date("<DTEND>2022-07-31</DTEND>"), None);
+        assert_eq!(ofx_end_date("<DTSTART>20220701"), None);
+    }
+
+    #[test]
+    fn test_scan_limit() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("statement.txt");
+        fs::write(&path, "Opening balance\nStatement date: 14 Sep 2022\n").unwrap();
+
+        let extractor = Extractor::default();
+        assert_eq!(get_date(&path, &extractor), Ok(None));
+
+        let limited = Extractor {
+            scan_limit: Some(16),
+            ..Extractor::default()
+        };
+        assert_eq!(get_date(&path, &limited), Ok(None));
+
+        let scanning = Extractor {
+            scan_limit: Some(1024),
+            ..Extractor::default()
+        };
+        assert!(get_date(&path, &scanning).unwrap().is_some());
+    }
+}
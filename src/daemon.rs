@@ -0,0 +1,344 @@
+//! Running in the background, classifying the files that arrive in each of the directories listed
+//! in the configuration. SIGHUP reloads the configuration, and the control socket answers `status`
+//! with what has been done so far and `reload` by reloading the configuration.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+use classfy::config::Watch;
+use classfy::{plan, report};
+
+use crate::watch::{load_watches, scan, Watched};
+use crate::{fail, load_config, EXIT_IO_ERROR, EXIT_USAGE};
+
+/// The name of the control socket.
+const SOCKET_NAME: &str = "classfy.sock";
+
+/// How often to check whether the daemon has been asked to stop or reload while waiting.
+const POLL_TIME: Duration = Duration::from_millis(200);
+
+/// What the daemon has done, as it is reported through the control socket.
+#[derive(Debug, Serialize)]
+struct Status {
+    pid: u32,
+    started_at: String,
+    /// The number of times the directories have been checked.
+    scans: u64,
+    last_scan: Option<String>,
+    watches: Vec<WatchStatus>,
+}
+
+/// What has been done in one of the watched directories.
+#[derive(Debug, Serialize)]
+struct WatchStatus {
+    dir: path::PathBuf,
+    profile: Option<String>,
+    placed: usize,
+    unclassified: usize,
+    failed: usize,
+    /// When files were last placed.
+    last_placed: Option<String>,
+    /// Why the directory could not be checked the last time it was tried.
+    error: Option<String>,
+}
+
+impl WatchStatus {
+    fn new(watch: &Watch) -> Self {
+        WatchStatus {
+            dir: watch.dir.clone(),
+            profile: watch.profile.clone(),
+            placed: 0,
+            unclassified: 0,
+            failed: 0,
+            last_placed: None,
+            error: None,
+        }
+    }
+
+    /// Adds the outcome of classifying a batch of files.
+    fn record(&mut self, outcome: &plan::Outcome, now: &str) {
+        self.placed += outcome.placed;
+        self.unclassified += outcome.unclassified;
+        self.failed += outcome.failures.len();
+        if outcome.placed > 0 {
+            self.last_placed = Some(now.to_string());
+        }
+        self.error = None;
+    }
+}
+
+impl Status {
+    fn new() -> Self {
+        Status {
+            pid: process::id(),
+            started_at: now(),
+            scans: 0,
+            last_scan: None,
+            watches: Vec::new(),
+        }
+    }
+
+    /// Sets the directories being watched, keeping what was done in those that were already
+    /// watched with the same profile.
+    fn watch(&mut self, watches: &[Watch]) {
+        let mut previous = std::mem::take(&mut self.watches);
+        self.watches = watches
+            .iter()
+            .map(|watch| {
+                match previous
+                    .iter()
+                    .position(|status| status.dir == watch.dir && status.profile == watch.profile)
+                {
+                    Some(index) => previous.swap_remove(index),
+                    None => WatchStatus::new(watch),
+                }
+            })
+            .collect();
+    }
+}
+
+/// Runs the daemon until it is stopped, or asks the running daemon for its status or to reload
+/// with --status or --reload.
+pub fn run(matches: &clap::ArgMatches, sub_matches: &clap::ArgMatches) -> i32 {
+    let socket = sub_matches
+        .get_one::<path::PathBuf>("socket")
+        .cloned()
+        .unwrap_or_else(default_socket);
+    if sub_matches.get_flag("status") {
+        return request(&socket, "status");
+    }
+    if sub_matches.get_flag("reload") {
+        return request(&socket, "reload");
+    }
+    let interval = Duration::from_secs(
+        *sub_matches
+            .get_one::<u64>("interval")
+            .expect("interval has a default"),
+    );
+
+    let mut watched =
+        match load_config(matches, None).and_then(|config| load_watches(&config, matches)) {
+            Ok(watched) => watched,
+            Err(e) => return fail(EXIT_USAGE, &e),
+        };
+    let reload = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let registered = signal_hook::flag::register(SIGHUP, Arc::clone(&reload))
+        .and_then(|_| signal_hook::flag::register(SIGTERM, Arc::clone(&stop)))
+        .and_then(|_| signal_hook::flag::register(SIGINT, Arc::clone(&stop)));
+    if let Err(e) = registered {
+        return fail(EXIT_IO_ERROR, &format!("Could not handle signals: {}", e));
+    }
+    let listener = match bind(&socket) {
+        Ok(listener) => listener,
+        Err(e) => return fail(EXIT_IO_ERROR, &e),
+    };
+    let status = Arc::new(Mutex::new(Status::new()));
+    lock(&status).watch(&watch_list(&watched));
+    {
+        let status = Arc::clone(&status);
+        let reload = Arc::clone(&reload);
+        thread::spawn(move || serve(listener, &status, &reload));
+    }
+    report::note!(
+        "Watching {} directories, with the control socket at {}",
+        watched.len(),
+        socket.display()
+    );
+
+    while !stop.load(Ordering::Relaxed) {
+        if reload.swap(false, Ordering::Relaxed) {
+            match load_config(matches, None).and_then(|config| load_watches(&config, matches)) {
+                Ok(reloaded) => {
+                    watched = reloaded;
+                    lock(&status).watch(&watch_list(&watched));
+                    report::note!("Reloaded the configuration");
+                }
+                Err(e) => report::note!("Could not reload the configuration: {}", e),
+            }
+        }
+        for (index, watched) in watched.iter_mut().enumerate() {
+            let result = scan(watched, matches);
+            let mut status = lock(&status);
+            let watch_status = &mut status.watches[index];
+            match result {
+                Ok(Some((outcome, _))) => watch_status.record(&outcome, &now()),
+                Ok(None) => watch_status.error = None,
+                Err(e) => {
+                    report::detail!("Could not check {}: {}", watched.watch.dir.display(), e);
+                    watch_status.error = Some(e);
+                }
+            }
+        }
+        {
+            let mut status = lock(&status);
+            status.scans += 1;
+            status.last_scan = Some(now());
+        }
+        let started = Instant::now();
+        while started.elapsed() < interval
+            && !stop.load(Ordering::Relaxed)
+            && !reload.load(Ordering::Relaxed)
+        {
+            thread::sleep(POLL_TIME);
+        }
+    }
+    report::note!("Stopping");
+    let _ = fs::remove_file(&socket);
+    0
+}
+
+/// Gets the control socket to use when none is given, in the user's runtime directory.
+pub fn default_socket() -> path::PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(SOCKET_NAME)
+}
+
+/// Gets the directories that are being watched.
+fn watch_list(watched: &[Watched]) -> Vec<Watch> {
+    watched
+        .iter()
+        .map(|watched| watched.watch.clone())
+        .collect()
+}
+
+/// Listens on the control socket, replacing the socket of a daemon that is no longer running.
+fn bind(socket: &path::Path) -> Result<UnixListener, String> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!(
+                "classfy is already running with the control socket {}",
+                socket.display()
+            ));
+        }
+        fs::remove_file(socket)
+            .map_err(|e| format!("Could not remove {}: {}", socket.display(), e))?;
+    }
+    UnixListener::bind(socket)
+        .map_err(|e| format!("Could not listen on {}: {}", socket.display(), e))
+}
+
+/// Answers the commands sent to the control socket, one for each connection.
+fn serve(listener: UnixListener, status: &Mutex<Status>, reload: &AtomicBool) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let mut command = String::new();
+        if io::BufReader::new(&stream).read_line(&mut command).is_err() {
+            continue;
+        }
+        let response = respond(command.trim(), status, reload);
+        let _ = writeln!(&stream, "{}", response);
+    }
+}
+
+/// Gets the response to a command sent to the control socket.
+fn respond(command: &str, status: &Mutex<Status>, reload: &AtomicBool) -> String {
+    match command {
+        "status" => {
+            serde_json::to_string_pretty(&*lock(status)).expect("status could not be serialised")
+        }
+        "reload" => {
+            reload.store(true, Ordering::Relaxed);
+            String::from("reloading")
+        }
+        command => format!(
+            "unknown command {:?}, the commands are: status, reload",
+            command
+        ),
+    }
+}
+
+/// Sends the command to the running daemon and prints its response.
+fn request(socket: &path::Path, command: &str) -> i32 {
+    let response = UnixStream::connect(socket).and_then(|mut stream| {
+        writeln!(stream, "{}", command)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    });
+    match response {
+        Ok(response) => {
+            print!("{}", response);
+            0
+        }
+        Err(e) => fail(
+            EXIT_IO_ERROR,
+            &format!("Could not reach classfy at {}: {}", socket.display(), e),
+        ),
+    }
+}
+
+fn lock(status: &Mutex<Status>) -> std::sync::MutexGuard<'_, Status> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use classfy::config::Watch;
+    use classfy::plan::Outcome;
+
+    use super::{respond, Status};
+
+    fn watch(dir: &str, profile: Option<&str>) -> Watch {
+        Watch {
+            dir: path::PathBuf::from(dir),
+            profile: profile.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let mut status = Status::new();
+        status.watch(&[watch("/scans", None), watch("/work", Some("work"))]);
+        let outcome = Outcome {
+            placed: 2,
+            unclassified: 1,
+            ..Outcome::default()
+        };
+        status.watches[1].record(&outcome, "2024-07-01T09:00:00+10:00");
+        assert_eq!(status.watches[1].placed, 2);
+        assert_eq!(
+            status.watches[1].last_placed.as_deref(),
+            Some("2024-07-01T09:00:00+10:00")
+        );
+
+        status.watch(&[watch("/work", Some("work")), watch("/home", None)]);
+        assert_eq!(status.watches.len(), 2);
+        assert_eq!(status.watches[0].placed, 2);
+        assert_eq!(status.watches[1].placed, 0);
+    }
+
+    #[test]
+    fn test_respond() {
+        let status = Mutex::new(Status::new());
+        let reload = AtomicBool::new(false);
+        let response: serde_json::Value =
+            serde_json::from_str(&respond("status", &status, &reload)).unwrap();
+        assert_eq!(response["scans"], 0);
+        assert!(!reload.load(Ordering::Relaxed));
+
+        assert_eq!(respond("reload", &status, &reload), "reloading");
+        assert!(reload.load(Ordering::Relaxed));
+        assert!(respond("stop", &status, &reload).starts_with("unknown command"));
+    }
+}
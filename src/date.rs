@@ -0,0 +1,1489 @@
+use std::fmt;
+use std::iter;
+use std::ops;
+use std::path;
+use std::str;
+use std::sync;
+
+use chrono::Datelike;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::content::{self, CsvRule};
+use crate::extract::DateExtractor;
+use crate::fiscal::FiscalYear;
+use crate::overrides;
+#[cfg(feature = "plugin")]
+use crate::plugin::Plugin;
+use crate::report;
+use crate::rules::Rule;
+#[cfg(feature = "script")]
+use crate::script::Script;
+use crate::unicode;
+
+/// The date found in a file name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileDate {
+    /// A day of a month (1 for January) of a calendar year, such as from "10JUL2022" or
+    /// "2022-07-10".
+    Day { year: u16, month: u8, day: u8 },
+    /// A month (1 for January) of a calendar year, such as from "JUL2022".
+    Month { year: u16, month: u8 },
+    /// A financial year, such as from "2022FY".
+    FinancialYear(u16),
+}
+
+impl FileDate {
+    /// Gets the year and month of the date, unless it is a whole financial year.
+    pub fn year_month(&self) -> Option<(u16, u8)> {
+        match *self {
+            FileDate::Day { year, month, .. } | FileDate::Month { year, month } => {
+                Some((year, month))
+            }
+            FileDate::FinancialYear(_) => None,
+        }
+    }
+
+    /// Gets the date as a day, taking a month as its first day. A whole financial year has none.
+    pub fn naive_date(&self) -> Option<chrono::NaiveDate> {
+        match *self {
+            FileDate::Day { year, month, day } => {
+                chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), day.into())
+            }
+            FileDate::Month { year, month } => {
+                chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), 1)
+            }
+            FileDate::FinancialYear(_) => None,
+        }
+    }
+
+    /// Gets the day of a month (1 for January) of a calendar year, if the month has that day.
+    pub fn day(year: u16, month: u8, day: u8) -> Result<Self, String> {
+        let name = chrono::Month::try_from(month)
+            .map_err(|_| format!("Invalid month {}", month))?
+            .name();
+        match chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), day.into()) {
+            Some(_) => Ok(FileDate::Day { year, month, day }),
+            None => Err(format!("{} {} has no day {}", name, year, day)),
+        }
+    }
+
+    /// Gets the day of the date.
+    pub fn from_naive(date: chrono::NaiveDate) -> Result<Self, String> {
+        Ok(FileDate::Day {
+            year: u16::try_from(date.year()).map_err(|_| format!("{} is out of range", date))?,
+            month: date.month() as u8,
+            day: date.day() as u8,
+        })
+    }
+}
+
+impl fmt::Display for FileDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileDate::Day { year, month, day } => write!(f, "{}-{:02}-{:02}", year, month, day),
+            FileDate::Month { year, month } => write!(f, "{}-{:02}", year, month),
+            FileDate::FinancialYear(fy) => write!(f, "{}FY", fy),
+        }
+    }
+}
+
+impl str::FromStr for FileDate {
+    type Err = String;
+
+    /// Parses a date in the form it is displayed, such as "2022-07-10", "2022-07" or "2022FY".
+    fn from_str(date: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid date {:?}", date);
+        if let Some(fy) = date.strip_suffix("FY") {
+            return fy
+                .parse()
+                .map(FileDate::FinancialYear)
+                .map_err(|_| invalid());
+        }
+        if date.len() == 10 {
+            return get_iso_date(date);
+        }
+        let (year, month) = date.split_once('-').ok_or_else(invalid)?;
+        match (year.parse(), month.parse()) {
+            (Ok(year), Ok(month)) if (1..=12).contains(&month) => {
+                Ok(FileDate::Month { year, month })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for FileDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The characters that separate a date from the rest of the file name, unless others are given.
+const DEFAULT_SEPARATORS: [char; 4] = ['_', '-', '.', ' '];
+
+/// The lengths, in characters, of the forms that a date can take, longest first.
+const DATE_LENGTHS: [usize; 6] = [13, 10, 9, 8, 7, 6];
+
+/// A language whose abbreviated month names are recognised in file names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+}
+
+impl Lang {
+    /// Every language that month names are known for.
+    pub const ALL: [Lang; 6] = [Lang::En, Lang::De, Lang::Es, Lang::Fr, Lang::It, Lang::Nl];
+
+    /// Parses the language from its two letter code, such as "de".
+    pub fn parse(code: &str) -> Result<Self, String> {
+        match code {
+            "en" => Ok(Lang::En),
+            "de" => Ok(Lang::De),
+            "es" => Ok(Lang::Es),
+            "fr" => Ok(Lang::Fr),
+            "it" => Ok(Lang::It),
+            "nl" => Ok(Lang::Nl),
+            _ => Err(format!("Unknown language {:?}", code)),
+        }
+    }
+
+    /// Gets the abbreviations used for each month, from January to December, of three or four
+    /// letters. Some months have more than one, such as "MÄR" and "MRZ" for March in German. The
+    /// first is the one that dates are written with.
+    fn month_names(self) -> [&'static [&'static str]; 12] {
+        match self {
+            Lang::En => [
+                &["JAN"],
+                &["FEB"],
+                &["MAR"],
+                &["APR"],
+                &["MAY"],
+                &["JUN"],
+                &["JUL"],
+                &["AUG"],
+                &["SEP"],
+                &["OCT"],
+                &["NOV"],
+                &["DEC"],
+            ],
+            Lang::De => [
+                &["JAN"],
+                &["FEB"],
+                &["MÄR", "MRZ"],
+                &["APR"],
+                &["MAI"],
+                &["JUN"],
+                &["JUL"],
+                &["AUG"],
+                &["SEP"],
+                &["OKT"],
+                &["NOV"],
+                &["DEZ"],
+            ],
+            Lang::Es => [
+                &["ENE"],
+                &["FEB"],
+                &["MAR"],
+                &["ABR"],
+                &["MAY"],
+                &["JUN"],
+                &["JUL"],
+                &["AGO"],
+                &["SEP"],
+                &["OCT"],
+                &["NOV"],
+                &["DIC"],
+            ],
+            Lang::Fr => [
+                &["JANV", "JAN"],
+                &["FÉVR", "FÉV", "FEVR", "FEV"],
+                &["MARS", "MAR"],
+                &["AVR"],
+                &["MAI"],
+                &["JUIN"],
+                &["JUIL"],
+                &["AOÛT", "AOÛ", "AOUT", "AOU"],
+                &["SEPT", "SEP"],
+                &["OCT"],
+                &["NOV"],
+                &["DÉC", "DEC"],
+            ],
+            Lang::It => [
+                &["GEN"],
+                &["FEB"],
+                &["MAR"],
+                &["APR"],
+                &["MAG"],
+                &["GIU"],
+                &["LUG"],
+                &["AGO"],
+                &["SET"],
+                &["OTT"],
+                &["NOV"],
+                &["DIC"],
+            ],
+            Lang::Nl => [
+                &["JAN"],
+                &["FEB"],
+                &["MRT"],
+                &["APR"],
+                &["MEI"],
+                &["JUN"],
+                &["JUL"],
+                &["AUG"],
+                &["SEP"],
+                &["OKT"],
+                &["NOV"],
+                &["DEC"],
+            ],
+        }
+    }
+}
+
+/// The order that the day, month and year of a date written only in numbers, such as
+/// "01-02-2022", are read in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateOrder {
+    /// The day, then the month and then the year, as in "31-01-2022".
+    #[default]
+    Dmy,
+    /// The month, then the day and then the year, as in "01-31-2022".
+    Mdy,
+    /// The year, then the month and then the day, as in "2022.01.31".
+    Ymd,
+}
+
+impl DateOrder {
+    /// Parses the order from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "dmy" => Ok(DateOrder::Dmy),
+            "mdy" => Ok(DateOrder::Mdy),
+            "ymd" => Ok(DateOrder::Ymd),
+            _ => Err(format!("Unknown date order {:?}", name)),
+        }
+    }
+
+    /// Gets the order that dates are written in by the country of one of the financial year
+    /// conventions in `fiscal::CONVENTIONS`, such as month first for "us".
+    pub fn convention(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "us" => DateOrder::Mdy,
+            "jp" => DateOrder::Ymd,
+            _ => DateOrder::Dmy,
+        }
+    }
+
+    /// Gets the name of the order on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            DateOrder::Dmy => "dmy",
+            DateOrder::Mdy => "mdy",
+            DateOrder::Ymd => "ymd",
+        }
+    }
+
+    /// Gets the format, for `parse_day`, of a date written in this order with the separator
+    /// between its numbers.
+    pub fn format(self, separator: char) -> String {
+        let fields = match self {
+            DateOrder::Dmy => ["%d", "%m", "%Y"],
+            DateOrder::Mdy => ["%m", "%d", "%Y"],
+            DateOrder::Ymd => ["%Y", "%m", "%d"],
+        };
+        fields.join(&separator.to_string())
+    }
+}
+
+/// Which date to use when a file name has more than one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DatePick {
+    /// The one that appears first in the name.
+    First,
+    /// The one that appears last in the name.
+    #[default]
+    Last,
+    /// The one that ends latest.
+    Latest,
+    /// The one that starts earliest.
+    Earliest,
+}
+
+impl DatePick {
+    /// Parses the policy from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "first" => Ok(DatePick::First),
+            "last" => Ok(DatePick::Last),
+            "latest" => Ok(DatePick::Latest),
+            "earliest" => Ok(DatePick::Earliest),
+            _ => Err(format!("Unknown date pick {:?}", name)),
+        }
+    }
+
+    /// Picks one of the dates found in a file name, in the order that they appear.
+    fn pick(self, dates: &[FileDate], fiscal: &FiscalYear) -> Option<FileDate> {
+        match self {
+            DatePick::First => dates.first().copied(),
+            DatePick::Last => dates.last().copied(),
+            DatePick::Latest => dates
+                .iter()
+                .copied()
+                .max_by_key(|date| fiscal.last_month(*date)),
+            DatePick::Earliest => dates
+                .iter()
+                .copied()
+                .min_by_key(|date| fiscal.first_month(*date)),
+        }
+    }
+}
+
+/// The date of a file, with how else it could have been read, if the date in its name could be
+/// read in more than one way. Only the run that places the file reports that it could.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dated {
+    /// The date of the file.
+    pub date: FileDate,
+    /// How the date in the name could be read, such as "01-02-2022 could be 2022-02-01 or
+    /// 2022-01-02", if it could be read more than one way.
+    pub ambiguity: Option<String>,
+}
+
+impl From<FileDate> for Dated {
+    fn from(date: FileDate) -> Self {
+        Dated {
+            date,
+            ambiguity: None,
+        }
+    }
+}
+
+/// One of the ways that the date of a file is looked for, and what it found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// Where the date was looked for, such as "rule 2" or "file name".
+    pub source: String,
+    /// The date that was found, or why none was.
+    pub result: Result<FileDate, String>,
+    /// Whether this is the step whose result decides the date of the file.
+    pub used: bool,
+}
+
+/// Settings that control how dates are found in file names, and in the files themselves.
+#[derive(Clone, Debug)]
+pub struct Extractor {
+    /// The languages whose month names are recognised.
+    pub languages: Vec<Lang>,
+    /// Which date to use when a file name has more than one.
+    pub pick: DatePick,
+    /// The order that dates written only in numbers, such as "01-02-2022", are read in.
+    pub order: DateOrder,
+    /// The characters that separate a date from the rest of the file name.
+    pub separators: Vec<char>,
+    /// Whether to recognise Unix timestamps, in seconds or milliseconds, such as "1657843200".
+    pub epoch: bool,
+    /// When the financial year starts and how it is named, used for ranges such as "2021-22".
+    pub fiscal: FiscalYear,
+    /// The time zone that the times of files are read in, or the system's if there is none.
+    pub timezone: Option<chrono_tz::Tz>,
+    /// The most bytes to read from the start of a text file when looking for a date in its
+    /// contents, if text files are looked in at all.
+    pub scan_limit: Option<u64>,
+    /// Rules for finding the date in a column of CSV files.
+    pub csv_rules: Vec<CsvRule>,
+    /// Rules for where to find the date of particular files, of which the first that matches a
+    /// file is used instead of looking in its name.
+    pub rules: Vec<Rule>,
+    /// The script that is asked for the date of each file before anything else.
+    #[cfg(feature = "script")]
+    pub script: Option<sync::Arc<Script>>,
+    /// The plugins that are asked for the date of each file, in order, after any script.
+    #[cfg(feature = "plugin")]
+    pub plugins: Vec<sync::Arc<Plugin>>,
+    /// The extractors registered by a program using classfy as a library, which are asked for the
+    /// date of each file, in order, after any script and plugins.
+    pub extractors: Vec<sync::Arc<dyn DateExtractor>>,
+}
+
+impl Default for Extractor {
+    fn default() -> Self {
+        Extractor {
+            languages: vec![Lang::En],
+            pick: DatePick::default(),
+            order: DateOrder::default(),
+            separators: DEFAULT_SEPARATORS.to_vec(),
+            epoch: false,
+            fiscal: FiscalYear::default(),
+            timezone: None,
+            scan_limit: None,
+            csv_rules: Vec::new(),
+            rules: Vec::new(),
+            #[cfg(feature = "script")]
+            script: None,
+            #[cfg(feature = "plugin")]
+            plugins: Vec::new(),
+            extractors: Vec::new(),
+        }
+    }
+}
+
+impl Extractor {
+    /// Extract the date from the file name. If there is more than one, the one to use is chosen
+    /// by `pick`. If there is none, the date is looked for in the contents of the file. If one of
+    /// the rules matches the file, the date is found as the rule says instead. A script, if there
+    /// is one, then the plugins and then the registered extractors are asked before any of these.
+    /// A date given to the file by hand is used before anything else.
+    pub fn get_date(&self, file_path: &path::Path) -> Result<FileDate, String> {
+        self.dated(file_path).map(|dated| dated.date)
+    }
+
+    /// Gets the date of the file as `get_date` does, along with whether the date in its name could
+    /// be read in more than one way, when that is where the date was found.
+    pub fn dated(&self, file_path: &path::Path) -> Result<Dated, String> {
+        if !file_path.is_file() {
+            return Err(String::from("Not a file"));
+        }
+        if let Some(date) = overrides::lookup(file_path) {
+            report::detail!(
+                "Using the date {} given to {:?} by hand",
+                date,
+                file_path.file_name().unwrap()
+            );
+            return Ok(date.into());
+        }
+        #[cfg(feature = "script")]
+        if let Some(date) = self
+            .script
+            .as_ref()
+            .map(|script| script.classify(file_path))
+            .transpose()?
+            .flatten()
+        {
+            return Ok(date.into());
+        }
+        #[cfg(feature = "plugin")]
+        for plugin in &self.plugins {
+            if let Some(date) = plugin.extract(file_path)? {
+                return Ok(date.into());
+            }
+        }
+        for extractor in &self.extractors {
+            if let Some(date) = extractor.extract(file_path) {
+                return FileDate::from_naive(date).map(Dated::from);
+            }
+        }
+        if let Some(rule) = self.rule(file_path) {
+            return rule.dated(file_path, self);
+        }
+
+        match self.read_name(file_path) {
+            Ok(dated) => Ok(dated),
+            Err(e) => match content::get_date(file_path, self)? {
+                Some(date) => {
+                    report::detail!(
+                        "Found date {} in the contents of {:?}",
+                        date,
+                        file_path.file_name().unwrap()
+                    );
+                    Ok(date.into())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Looks for the date of the file in each of the ways that `get_date` does, in the same order,
+    /// but without stopping at the first that decides it. The step whose result `get_date` gives
+    /// is marked as used.
+    pub fn explain(&self, file_path: &path::Path) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let mut decided = false;
+        let mut step = |source: String, result: Result<FileDate, String>, decides: bool| {
+            let used = decides && !decided;
+            decided |= used;
+            steps.push(Step {
+                source,
+                result,
+                used,
+            });
+        };
+        let found = |found: Result<Option<FileDate>, String>| match found {
+            Ok(Some(date)) => (Ok(date), true),
+            Ok(None) => (Err(String::from("No date found")), false),
+            Err(e) => (Err(e), true),
+        };
+        if !file_path.is_file() {
+            step(String::from("file"), Err(String::from("Not a file")), true);
+            return steps;
+        }
+        if let Some(date) = overrides::lookup(file_path) {
+            step(String::from("date given by hand"), Ok(date), true);
+        }
+
+        #[cfg(feature = "script")]
+        if let Some(script) = &self.script {
+            let (result, decides) = found(script.classify(file_path));
+            step(String::from("script"), result, decides);
+        }
+        #[cfg(feature = "plugin")]
+        for (index, plugin) in self.plugins.iter().enumerate() {
+            let (result, decides) = found(plugin.extract(file_path));
+            step(format!("plugin {}", index + 1), result, decides);
+        }
+        for (index, extractor) in self.extractors.iter().enumerate() {
+            let (result, decides) = found(
+                extractor
+                    .extract(file_path)
+                    .map(FileDate::from_naive)
+                    .transpose(),
+            );
+            step(format!("extractor {}", index + 1), result, decides);
+        }
+        let mut ruled = false;
+        for (index, rule) in self.rules.iter().enumerate() {
+            let source = format!("rule {} ({})", index + 1, rule.pattern());
+            if !rule.matches(file_path) {
+                step(
+                    source,
+                    Err(String::from("Does not apply to the file")),
+                    false,
+                );
+            } else if ruled {
+                step(
+                    source,
+                    Err(String::from(
+                        "Applies to the file, but only the first rule that does is used",
+                    )),
+                    false,
+                );
+            } else {
+                ruled = true;
+                let source = format!("{} with the {} extractor", source, rule.source.name());
+                step(source, rule.get_date(file_path, self), true);
+            }
+        }
+
+        let dates = file_path
+            .file_stem()
+            .map(|name| self.find_dates(&name.to_string_lossy()))
+            .unwrap_or_default();
+        let source = if dates.len() > 1 {
+            let found: Vec<String> = dates.iter().map(FileDate::to_string).collect();
+            format!("file name, which has the dates {}", found.join(", "))
+        } else {
+            String::from("file name")
+        };
+        let name_date = self.name_date(file_path);
+        let named = name_date.is_ok();
+        step(source, name_date, named);
+        let (result, decides) = found(content::get_date(file_path, self));
+        step(String::from("contents"), result, decides);
+        if !decided {
+            if let Some(name_step) = steps.iter_mut().rev().nth(1) {
+                name_step.used = true;
+            }
+        }
+        steps
+    }
+
+    /// Adds an extractor to ask for the date of each file, after those already registered.
+    pub fn register(&mut self, extractor: impl DateExtractor + 'static) {
+        self.extractors.push(sync::Arc::new(extractor));
+    }
+
+    /// Gets the first rule that matches the file, if any.
+    pub fn rule(&self, file_path: &path::Path) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(file_path))
+    }
+
+    /// Extract the date from the file name only. The file does not need to exist, so this also
+    /// works for files inside archives. Where the name is not valid UTF-8, the dates are still
+    /// found in the parts of it that are.
+    pub fn name_date(&self, file_path: &path::Path) -> Result<FileDate, String> {
+        self.read_name(file_path).map(|dated| dated.date)
+    }
+
+    /// Extract the date from the file name as `name_date` does, along with how else it could have
+    /// been read, if any of the dates in the name could be read in more than one way.
+    pub fn read_name(&self, file_path: &path::Path) -> Result<Dated, String> {
+        let file_name = file_path.file_stem();
+        if file_name.is_none() {
+            return Err(String::from("No file name"));
+        }
+
+        let name_string = file_name.unwrap().to_string_lossy();
+        report::detail!("Processing file name: {:?}", file_path.file_name().unwrap());
+
+        let name = unicode::nfc(&name_string);
+        let spans = self.date_spans(&name);
+        let ambiguity = spans.iter().find_map(|(date, range)| {
+            let token = &name[range.clone()];
+            let other = self.other_reading(token)?;
+            Some(format!(
+                "{} could be {} or {}, reading it as {} with --date-order {}",
+                token,
+                date,
+                other,
+                date,
+                self.order.name()
+            ))
+        });
+        let dates: Vec<FileDate> = spans.into_iter().map(|(date, _)| date).collect();
+        let date = self.pick.pick(&dates, &self.fiscal).ok_or_else(|| {
+            match self.impossible_day(&name_string) {
+                Some(why) => format!("No date found in file name, as {}", why),
+                None => String::from("No date found in file name"),
+            }
+        })?;
+        if dates.len() > 1 {
+            let found: Vec<String> = dates.iter().map(FileDate::to_string).collect();
+            report::detail!(
+                "Found dates {} in {:?}, using {}",
+                found.join(", "),
+                file_path.file_name().unwrap(),
+                date
+            );
+        }
+        Ok(Dated { date, ambiguity })
+    }
+
+    /// Gets the name that `--normalize-names` gives the file, which is its stem without the date
+    /// followed by the date, such as "bill_2022-07-10.pdf" for "bill_10JUL2022.pdf".
+    pub fn normalized_name(&self, name: &str, date: FileDate) -> String {
+        let name = unicode::nfc(name);
+        let name_path = path::Path::new(name.as_ref());
+        let stem = name_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&name);
+        let separators = &self.separators[..];
+        let mut parts = Vec::new();
+        let mut start = 0;
+        for (found, range) in self.date_spans(stem) {
+            if found == date {
+                parts.push(&stem[start..range.start]);
+                start = range.end;
+            }
+        }
+        let mut extension = name_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(String::from);
+        // What follows the date after a dot is part of the extension, as in
+        // "backup_10JUL2022.tar.gz".
+        match stem[start..].strip_prefix('.') {
+            Some(rest) if start > 0 => {
+                extension = Some(match extension {
+                    Some(extension) => format!("{}.{}", rest, extension),
+                    None => rest.to_string(),
+                })
+            }
+            _ => parts.push(&stem[start..]),
+        }
+        let mut normalized: Vec<String> = parts
+            .into_iter()
+            .map(|part| part.trim_matches(separators))
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect();
+        normalized.push(date.to_string());
+        let normalized = normalized.join("_");
+        match extension {
+            Some(extension) => format!("{}.{}", normalized, extension),
+            None => normalized,
+        }
+    }
+
+    /// Gets the name that `--stamp-dates` gives the file, which is its name with the date added
+    /// to its stem in the form it is recognised in, such as "notes_14SEP2022.txt". Gets `None` if
+    /// there is already a date in the name.
+    pub fn stamped_name(&self, name: &str, date: FileDate) -> Option<String> {
+        let name_path = path::Path::new(name);
+        let stem = name_path.file_stem()?.to_str()?;
+        if !self.find_dates(stem).is_empty() {
+            return None;
+        }
+        let month = |month: u8| {
+            let lang = self.languages.first().copied().unwrap_or_default();
+            lang.month_names()[usize::from(month) - 1][0]
+        };
+        let stamp = match date {
+            FileDate::Day {
+                year,
+                month: m,
+                day,
+            } => format!("{:02}{}{}", day, month(m), year),
+            FileDate::Month { year, month: m } => format!("{}{}", month(m), year),
+            FileDate::FinancialYear(_) => date.to_string(),
+        };
+        Some(
+            match name_path
+                .extension()
+                .and_then(|extension| extension.to_str())
+            {
+                Some(extension) => format!("{}_{}.{}", stem, stamp, extension),
+                None => format!("{}_{}", stem, stamp),
+            },
+        )
+    }
+
+    /// Finds the dates in the name, in the order that they appear. Each date must be separated
+    /// from the rest of the name by one of the `separators`.
+    fn find_dates(&self, name: &str) -> Vec<FileDate> {
+        let name = unicode::nfc(name);
+        self.date_spans(&name)
+            .into_iter()
+            .map(|(date, _)| date)
+            .collect()
+    }
+
+    /// Finds the dates in the name, as `find_dates` does, along with where each is in the name.
+    fn date_spans(&self, name: &str) -> Vec<(FileDate, ops::Range<usize>)> {
+        let separators = &self.separators[..];
+        let mut dates = Vec::new();
+        let mut rest = name.trim_start_matches(separators);
+        while !rest.is_empty() {
+            let found = DATE_LENGTHS.iter().find_map(|&length| {
+                let end = match rest.char_indices().nth(length) {
+                    Some((end, _)) => end,
+                    None if rest.chars().count() == length => rest.len(),
+                    None => return None,
+                };
+                if !rest[end..].is_empty() && !rest[end..].starts_with(separators) {
+                    return None;
+                }
+                self.parse_token(&rest[..end]).ok().map(|date| (date, end))
+            });
+            let end = match found {
+                Some((date, end)) => {
+                    let start = name.len() - rest.len();
+                    dates.push((date, start..start + end));
+                    end
+                }
+                None => rest.find(separators).unwrap_or(rest.len()),
+            };
+            rest = rest[end..].trim_start_matches(separators);
+        }
+        dates
+    }
+
+    /// Gets why the first part of the name that is written as a day, such as "31FEB2022" or
+    /// "2023-02-29", is not a day of the calendar, if there is one that is not.
+    fn impossible_day(&self, name: &str) -> Option<String> {
+        let separators = &self.separators[..];
+        let name = unicode::nfc(name);
+        let mut starts = iter::once(0).chain(
+            name.match_indices(separators)
+                .map(|(start, separator)| start + separator.len()),
+        );
+        starts.find_map(|start| {
+            let rest = &name[start..];
+            [10, 9].into_iter().find_map(|length| {
+                let end = rest
+                    .char_indices()
+                    .nth(length)
+                    .map_or(rest.len(), |(end, _)| end);
+                let token = &rest[..end];
+                if token.chars().count() != length
+                    || !rest[end..].is_empty() && !rest[end..].starts_with(separators)
+                    || !self.is_day_shaped(token)
+                {
+                    return None;
+                }
+                let e = self.parse_token(token).err()?;
+                Some(format!("{} is not a date: {}", token, e))
+            })
+        })
+    }
+
+    /// Checks if the token is written as a day, as "2022-07-01" or "01JUL2022" are, whether or not
+    /// the calendar has that day.
+    fn is_day_shaped(&self, token: &str) -> bool {
+        let bytes = token.as_bytes();
+        let iso = bytes.len() == 10
+            && bytes.iter().enumerate().all(|(i, byte)| match i {
+                4 | 7 => *byte == b'-',
+                _ => byte.is_ascii_digit(),
+            });
+        let chars: Vec<char> = token.chars().collect();
+        let year = chars.len().saturating_sub(4);
+        let full = matches!(chars.len(), 9 | 10)
+            && chars[..2].iter().all(char::is_ascii_digit)
+            && chars[year..].iter().all(char::is_ascii_digit)
+            && self
+                .get_month(&chars[2..year].iter().collect::<String>())
+                .is_ok();
+        iso || full || self.numeric_separator(token).is_some()
+    }
+
+    /// Gets the character between the numbers of the token, if it is a date written only in
+    /// numbers in the order that they are read in, such as "01-02-2022" or "01.02.2022".
+    fn numeric_separator(&self, token: &str) -> Option<char> {
+        let bytes = token.as_bytes();
+        let positions = match self.order {
+            DateOrder::Dmy | DateOrder::Mdy => [2, 5],
+            DateOrder::Ymd => [4, 7],
+        };
+        let separator = *bytes.get(positions[0])?;
+        let shaped = bytes.len() == 10
+            && b"-._".contains(&separator)
+            && bytes.iter().enumerate().all(|(i, byte)| {
+                if positions.contains(&i) {
+                    *byte == separator
+                } else {
+                    byte.is_ascii_digit()
+                }
+            });
+        shaped.then_some(char::from(separator))
+    }
+
+    /// Gets the other day that a date written only in numbers could be, if it could be read with
+    /// the day and month either way round, such as "01-02-2022".
+    fn other_reading(&self, token: &str) -> Option<FileDate> {
+        let other = match self.order {
+            DateOrder::Dmy => DateOrder::Mdy,
+            DateOrder::Mdy => DateOrder::Dmy,
+            DateOrder::Ymd => return None,
+        };
+        let separator = self.numeric_separator(token)?;
+        let read = parse_day(token, &self.order.format(separator)).ok()?;
+        let other = parse_day(token, &other.format(separator)).ok()?;
+        (other != read).then_some(other)
+    }
+
+    /// Parses a date in one of the forms "2022-07-01", "01JUL2022", "JUL2022", "2022FY", "FY2022",
+    /// "2021-22" or "2021_2022", or a Unix timestamp if `epoch` is set. The month may also be
+    /// named with four letters, as in "01JUIL2022".
+    pub fn parse_token(&self, token: &str) -> Result<FileDate, String> {
+        let token = &unicode::nfc(token);
+        match token.chars().count() {
+            6 => get_fy_year_only(token),
+            7 => self
+                .process_month_and_year(token)
+                .or_else(|e| self.get_fy_range(token).map_err(|_| e)),
+            8 => self.process_month_and_year(token),
+            9 => self
+                .get_full_date(token)
+                .or_else(|e| self.get_fy_range(token).map_err(|_| e)),
+            10 if self.epoch && token.bytes().all(|b| b.is_ascii_digit()) => {
+                get_epoch_date(token, 1)
+            }
+            10 => match self.numeric_separator(token) {
+                Some(separator) => parse_day(token, &self.order.format(separator))
+                    .map_err(|e| format!("Invalid date {:?}: {}", token, e)),
+                None if token.as_bytes().get(4) == Some(&b'-') => get_iso_date(token),
+                None => self.get_full_date(token),
+            },
+            13 if self.epoch => get_epoch_date(token, 1000),
+            _ => Err(format!("Not a date: {}", token)),
+        }
+    }
+
+    /// Get the date from a full date (whose format is DDMMMYYYY, or DDMMMMYYYY).
+    fn get_full_date(&self, date: &str) -> Result<FileDate, String> {
+        let (day, month_and_year) =
+            split_chars(date, 2).ok_or_else(|| format!("Invalid date {:?}", date))?;
+        let FileDate::Month { year, month } = self.process_month_and_year(month_and_year)? else {
+            return Err(format!("Invalid date {:?}", date));
+        };
+        parse_day(&format!("{}{:02}{}", day, month, year), "%d%m%Y")
+            .map_err(|e| format!("Invalid date {:?}: {}", date, e))
+    }
+
+    /// Get the date from a date with just month and year, the year being the last four characters.
+    fn process_month_and_year(&self, date: &str) -> Result<FileDate, String> {
+        let month_length = date.chars().count().saturating_sub(4);
+        let (month, year) = split_chars(date, month_length).unwrap_or((date, ""));
+        let month = self.get_month(month)?;
+        Ok(FileDate::Month {
+            year: parse_year(year)?,
+            month,
+        })
+    }
+
+    /// Get the financial year from the pair of years that it spans, such as "2021-22" or "2021_2022"
+    /// for 2022FY when it is named by the year it ends in. The years must be consecutive.
+    fn get_fy_range(&self, date: &str) -> Result<FileDate, String> {
+        let not_range = || format!("Date is not a range of years: {}", date);
+        let (start, end) = date.split_once(['-', '_']).ok_or_else(not_range)?;
+        let start = parse_year(start).map_err(|_| not_range())?;
+        let short = end.len() == 2;
+        let end = if short {
+            parse_fields(end, "%y")
+                .ok()
+                .and_then(|parsed| parsed.year_mod_100())
+                .and_then(|year| u16::try_from(year).ok())
+        } else {
+            parse_year(end).ok()
+        }
+        .ok_or_else(not_range)?;
+        let next = start + 1;
+        let consecutive = if short {
+            end == next % 100
+        } else {
+            end == next
+        };
+        if consecutive {
+            self.fiscal
+                .name(start)
+                .map(FileDate::FinancialYear)
+                .ok_or_else(not_range)
+        } else {
+            Err(format!("Years in {:?} are not consecutive", date))
+        }
+    }
+
+    /// Gets the number of the month (1 for January to 12 for December). The month is expected to
+    /// be its abbreviated name in one of the languages, capitalised.
+    pub fn get_month(&self, month: &str) -> Result<u8, String> {
+        self.languages
+            .iter()
+            .find_map(|lang| {
+                lang.month_names()
+                    .iter()
+                    .position(|names| names.contains(&month))
+            })
+            .map(|index| index as u8 + 1)
+            .ok_or_else(|| format!("Month {:?} not recognised", month))
+    }
+}
+
+/// Get the date from a date in the ISO 8601 format (YYYY-MM-DD).
+fn get_iso_date(date: &str) -> Result<FileDate, String> {
+    if !date.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("Invalid date {:?}", date));
+    }
+    parse_day(date, "%Y-%m-%d").map_err(|e| format!("Invalid date {:?}: {}", date, e))
+}
+
+/// Parses a day written in the format, such as "%Y-%m-%d", whose numbers must each be written in
+/// full. Days that the calendar does not have, such as 31 February, are rejected.
+pub(crate) fn parse_day(text: &str, format: &str) -> Result<FileDate, String> {
+    let parsed = parse_fields(text, format)?;
+    let field = |value: Option<u32>| {
+        value
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| format!("{:?} is not a whole day", text))
+    };
+    let year = parsed
+        .year()
+        .and_then(|year| u16::try_from(year).ok())
+        .ok_or_else(|| format!("{:?} has no year", text))?;
+    FileDate::day(year, field(parsed.month())?, field(parsed.day())?)
+}
+
+/// Parses the fields of a date written in the format, without checking that they make a date.
+fn parse_fields(text: &str, format: &str) -> Result<chrono::format::Parsed, String> {
+    if !text.is_ascii() {
+        return Err(format!("Invalid date {:?}", text));
+    }
+    let mut parsed = chrono::format::Parsed::new();
+    chrono::format::parse(
+        &mut parsed,
+        text,
+        chrono::format::StrftimeItems::new(format),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(parsed)
+}
+
+/// Parses a year written with four digits.
+fn parse_year(text: &str) -> Result<u16, String> {
+    let invalid = || format!("Could not parse year {:?}", text);
+    if text.len() != 4 || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    parse_fields(text, "%Y")?
+        .year()
+        .and_then(|year| u16::try_from(year).ok())
+        .ok_or_else(invalid)
+}
+
+/// Splits the text after its first `count` characters, or gets `None` if it is not that long.
+fn split_chars(text: &str, count: usize) -> Option<(&str, &str)> {
+    match text.char_indices().nth(count) {
+        Some((end, _)) => Some(text.split_at(end)),
+        None if text.chars().count() == count => Some((text, "")),
+        None => None,
+    }
+}
+
+/// Get the date, in UTC, from a Unix timestamp. The timestamp is divided by `per_second` to get
+/// the number of seconds, such as 1000 for timestamps in milliseconds.
+fn get_epoch_date(timestamp: &str, per_second: i64) -> Result<FileDate, String> {
+    let invalid = || format!("Invalid timestamp {:?}", timestamp);
+    if !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let value = timestamp.parse::<i64>().map_err(|_| invalid())?;
+    let date = chrono::DateTime::from_timestamp(value / per_second, 0).ok_or_else(invalid)?;
+    Ok(FileDate::Day {
+        year: u16::try_from(date.year()).map_err(|_| invalid())?,
+        month: date.month() as u8,
+        day: date.day() as u8,
+    })
+}
+
+/// Get the financial year for dates with just a year and "FY" before or after it. For example
+/// "2022FY" or "FY2022".
+fn get_fy_year_only(date: &str) -> Result<FileDate, String> {
+    let year = date
+        .strip_suffix("FY")
+        .or_else(|| date.strip_prefix("FY"))
+        .ok_or_else(|| format!("Date is not an FY: {}", date))?;
+    parse_year(year)
+        .map(FileDate::FinancialYear)
+        .map_err(|_| format!("Could not parse year {:?}", date))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{DateOrder, DatePick, Extractor, FileDate, Lang};
+    use crate::fiscal::FiscalYear;
+    use crate::rules::Rule;
+
+    #[test]
+    fn test_get_month() {
+        let english = Extractor::default();
+        assert_eq!(english.get_month("OCT"), Ok(10));
+        assert!(english.get_month("OKT").is_err());
+
+        let german = Extractor {
+            languages: vec![Lang::De],
+            ..Extractor::default()
+        };
+        assert_eq!(german.get_month("OKT"), Ok(10));
+        assert_eq!(german.get_month("MÄR"), Ok(3));
+        // As macOS gives the name, with a combining diaeresis.
+        assert_eq!(
+            german.find_dates("rechnung_10MA\u{308}R2023"),
+            [FileDate::Day {
+                year: 2023,
+                month: 3,
+                day: 10
+            }]
+        );
+        assert_eq!(
+            german.process_month_and_year("MÄR2023"),
+            Ok(FileDate::Month {
+                year: 2023,
+                month: 3
+            })
+        );
+        assert_eq!(
+            german.get_full_date("10OKT2022"),
+            Ok(FileDate::Day {
+                year: 2022,
+                month: 10,
+                day: 10
+            })
+        );
+
+        let french = Extractor {
+            languages: vec![Lang::Fr],
+            ..Extractor::default()
+        };
+        assert_eq!(french.get_month("JUIN"), Ok(6));
+        assert_eq!(french.get_month("JUIL"), Ok(7));
+        assert_eq!(french.get_month("DÉC"), Ok(12));
+        assert!(french.get_month("JUL").is_err());
+        // As macOS gives the name, with a combining acute accent.
+        assert_eq!(
+            french.find_dates("facture_10JUIL2023 FE\u{301}VR2024"),
+            [
+                FileDate::Day {
+                    year: 2023,
+                    month: 7,
+                    day: 10
+                },
+                FileDate::Month {
+                    year: 2024,
+                    month: 2
+                }
+            ]
+        );
+        assert_eq!(
+            french.impossible_day("facture_31JUIN2023").as_deref(),
+            Some("31JUIN2023 is not a date: Invalid date \"31JUIN2023\": June 2023 has no day 31")
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("INV-10072022-0001_01JUN2022.pdf");
+        fs::write(&path, "").unwrap();
+        let rule = |glob: &str| {
+            Rule::new(
+                Some(glob),
+                Some(r"^INV-(?P<date>\d{8})-"),
+                "filename",
+                Some("%d%m%Y"),
+                &[],
+                None,
+            )
+            .unwrap()
+        };
+        let extractor = Extractor {
+            rules: vec![rule("REC-*"), rule("INV-*"), rule("*.pdf")],
+            ..Extractor::default()
+        };
+
+        let steps = extractor.explain(&path);
+        let used: Vec<bool> = steps.iter().map(|step| step.used).collect();
+        assert_eq!(used, [false, true, false, false, false]);
+        assert_eq!(
+            steps[1].result,
+            Ok(FileDate::Day {
+                year: 2022,
+                month: 7,
+                day: 10
+            })
+        );
+        assert!(steps[2].result.is_err());
+        assert_eq!(steps[3].source, "file name");
+        assert_eq!(
+            steps.iter().find(|step| step.used).unwrap().result,
+            extractor.get_date(&path)
+        );
+
+        let steps = Extractor::default().explain(&tempdir.path().join("notes.txt"));
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].used && steps[0].result.is_err());
+    }
+
+    #[test]
+    fn test_impossible_dates() {
+        let extractor = Extractor::default();
+        assert_eq!(
+            extractor.parse_token("31FEB2022"),
+            Err(String::from(
+                "Invalid date \"31FEB2022\": February 2022 has no day 31"
+            ))
+        );
+        assert!(extractor.parse_token("99JAN2022").is_err());
+        assert!(extractor.parse_token("2023-02-29").is_err());
+        assert!(extractor.parse_token("2022-13-01").is_err());
+        assert_eq!(
+            extractor.parse_token("29FEB2024"),
+            Ok(FileDate::Day {
+                year: 2024,
+                month: 2,
+                day: 29
+            })
+        );
+        assert!("2023-02-29".parse::<FileDate>().is_err());
+
+        assert_eq!(
+            extractor.name_date(path::Path::new("text_31FEB2022.txt")),
+            Err(String::from(
+                "No date found in file name, as 31FEB2022 is not a date: \
+                 Invalid date \"31FEB2022\": February 2022 has no day 31"
+            ))
+        );
+        assert_eq!(
+            extractor.name_date(path::Path::new("statement 2023-02-29.pdf")),
+            Err(String::from(
+                "No date found in file name, as 2023-02-29 is not a date: \
+                 Invalid date \"2023-02-29\": February 2023 has no day 29"
+            ))
+        );
+        assert_eq!(
+            extractor.name_date(path::Path::new("notes.txt")),
+            Err(String::from("No date found in file name"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_tokens() {
+        let extractor = Extractor::default();
+        for token in [
+            "1ÄJAN2022",
+            "ÄÄJAN2022",
+            "01ÄÄÄ2022",
+            "01JAN20É2",
+            "2022-0É-01",
+            "+022-07-01",
+            "2022-7-010",
+            "ÄÄÄÄ-ÄÄ",
+            "JAN202É",
+            "FY20É",
+            "ÄÄFY",
+        ] {
+            assert!(extractor.parse_token(token).is_err(), "{}", token);
+        }
+        assert!(extractor.get_full_date("1").is_err());
+        assert!(extractor.get_full_date("").is_err());
+        assert!(extractor.process_month_and_year("JU").is_err());
+        assert!(extractor.get_fy_range("2021-").is_err());
+        assert!(extractor.get_fy_range("2021_0022").is_err());
+        assert!(extractor.get_fy_range("1999-0000").is_err());
+        assert_eq!(
+            extractor.get_fy_range("1999-00"),
+            Ok(FileDate::FinancialYear(2000))
+        );
+        assert!(super::get_iso_date("2022").is_err());
+        assert!(extractor.find_dates("ÄÄ_1ÄJAN2022_ÄÄÄÄ-ÄÄ").is_empty());
+    }
+
+    #[test]
+    fn test_find_dates() {
+        let extractor = Extractor::default();
+        let day = |year, month, day| FileDate::Day { year, month, day };
+        assert_eq!(
+            extractor.find_dates("2022-07-01_invoice"),
+            [day(2022, 7, 1)]
+        );
+        assert_eq!(
+            extractor.find_dates("invoice 10JUL2022 final"),
+            [day(2022, 7, 10)]
+        );
+        assert_eq!(
+            extractor.find_dates("statement_01JUL2022_to_JUN2023"),
+            [
+                day(2022, 7, 1),
+                FileDate::Month {
+                    year: 2023,
+                    month: 6
+                }
+            ]
+        );
+        assert!(extractor.find_dates("00JUL2022").is_empty());
+        assert_eq!(
+            extractor.find_dates("_2021FY__2022-13-01 x10JUL2022"),
+            [FileDate::FinancialYear(2021)]
+        );
+        assert!(extractor.find_dates("invoice_10JUL2022final").is_empty());
+        assert_eq!(
+            extractor.find_dates("FY2022_summary"),
+            [FileDate::FinancialYear(2022)]
+        );
+        assert!(extractor.find_dates("FY+202_FYFY22").is_empty());
+        assert_eq!(
+            extractor.find_dates("tax_return_2021-22 2021_2022 1999-2000"),
+            [
+                FileDate::FinancialYear(2022),
+                FileDate::FinancialYear(2022),
+                FileDate::FinancialYear(2000)
+            ]
+        );
+        assert!(extractor.find_dates("2021-23 2019-2021 2022-07").is_empty());
+        assert_eq!(
+            extractor.find_dates("report-10JUL2022.scan"),
+            [day(2022, 7, 10)]
+        );
+
+        let underscores = Extractor {
+            separators: vec!['_'],
+            ..Extractor::default()
+        };
+        assert!(underscores.find_dates("report-10JUL2022").is_empty());
+        assert_eq!(
+            underscores.find_dates("report_2022-07-01"),
+            [day(2022, 7, 1)]
+        );
+    }
+
+    #[test]
+    fn test_normalized_name() {
+        let extractor = Extractor::default();
+        let day = FileDate::Day {
+            year: 2022,
+            month: 7,
+            day: 10,
+        };
+        let normalized = |name| extractor.normalized_name(name, day);
+        assert_eq!(normalized("bill_10JUL2022.pdf"), "bill_2022-07-10.pdf");
+        assert_eq!(normalized("bill_2022-07-10.pdf"), "bill_2022-07-10.pdf");
+        assert_eq!(
+            normalized("10JUL2022 Acme invoice.pdf"),
+            "Acme invoice_2022-07-10.pdf"
+        );
+        assert_eq!(normalized("bill-10JUL2022-acme"), "bill_acme_2022-07-10");
+        assert_eq!(
+            normalized("backup_10JUL2022.tar.gz"),
+            "backup_2022-07-10.tar.gz"
+        );
+        assert_eq!(normalized("notes.txt"), "notes_2022-07-10.txt");
+        // Other dates in the name are kept.
+        assert_eq!(
+            normalized("bill_01JUN2022_10JUL2022.pdf"),
+            "bill_01JUN2022_2022-07-10.pdf"
+        );
+        assert_eq!(
+            extractor.normalized_name("tax_2021-22.pdf", FileDate::FinancialYear(2022)),
+            "tax_2022FY.pdf"
+        );
+    }
+
+    #[test]
+    fn test_stamped_name() {
+        let extractor = Extractor::default();
+        let day = FileDate::Day {
+            year: 2022,
+            month: 9,
+            day: 4,
+        };
+        assert_eq!(
+            extractor.stamped_name("notes.txt", day).as_deref(),
+            Some("notes_04SEP2022.txt")
+        );
+        assert_eq!(
+            extractor.stamped_name("notes", day).as_deref(),
+            Some("notes_04SEP2022")
+        );
+        assert_eq!(extractor.stamped_name("bill_10JUL2022.pdf", day), None);
+        assert_eq!(
+            extractor
+                .stamped_name("notes.txt", FileDate::FinancialYear(2023))
+                .as_deref(),
+            Some("notes_2023FY.txt")
+        );
+        let german = Extractor {
+            languages: vec![Lang::De],
+            ..Extractor::default()
+        };
+        let march = FileDate::Month {
+            year: 2023,
+            month: 3,
+        };
+        let stamped = german.stamped_name("notes.txt", march).unwrap();
+        assert_eq!(stamped, "notes_MÄR2023.txt");
+        assert_eq!(german.name_date(path::Path::new(&stamped)), Ok(march));
+    }
+
+    #[test]
+    fn test_date_order() {
+        let day = |year, month, day| Ok(FileDate::Day { year, month, day });
+        let dmy = Extractor::default();
+        assert_eq!(dmy.order, DateOrder::Dmy);
+        assert_eq!(dmy.parse_token("31-01-2022"), day(2022, 1, 31));
+        assert_eq!(dmy.parse_token("01.02.2022"), day(2022, 2, 1));
+        assert_eq!(dmy.parse_token("2022-02-01"), day(2022, 2, 1));
+        assert!(dmy.parse_token("2022.02.01").is_err());
+        assert_eq!(dmy.other_reading("01-02-2022"), day(2022, 1, 2).ok());
+        assert_eq!(dmy.other_reading("13-02-2022"), None);
+        assert_eq!(dmy.other_reading("02-02-2022"), None);
+        assert_eq!(
+            dmy.name_date(path::Path::new("scan_01_02_2022.pdf")),
+            day(2022, 2, 1)
+        );
+        assert_eq!(
+            dmy.read_name(path::Path::new("scan_01_02_2022.pdf"))
+                .unwrap()
+                .ambiguity
+                .as_deref(),
+            Some("01_02_2022 could be 2022-02-01 or 2022-01-02, reading it as 2022-02-01 with --date-order dmy")
+        );
+        assert_eq!(
+            dmy.read_name(path::Path::new("scan_13_02_2022.pdf"))
+                .unwrap()
+                .ambiguity,
+            None
+        );
+
+        let mdy = Extractor {
+            order: DateOrder::Mdy,
+            ..Extractor::default()
+        };
+        assert_eq!(mdy.parse_token("01.02.2022"), day(2022, 1, 2));
+        assert!(mdy.parse_token("31-01-2022").is_err());
+        assert_eq!(mdy.other_reading("01-02-2022"), day(2022, 2, 1).ok());
+
+        let ymd = Extractor {
+            order: DateOrder::Ymd,
+            ..Extractor::default()
+        };
+        assert_eq!(ymd.parse_token("2022.02.01"), day(2022, 2, 1));
+        assert_eq!(ymd.parse_token("2022_02_01"), day(2022, 2, 1));
+        assert!(ymd.parse_token("01-02-2022").is_err());
+        assert_eq!(ymd.other_reading("2022.02.01"), None);
+
+        assert_eq!(DateOrder::convention("US"), DateOrder::Mdy);
+        assert_eq!(DateOrder::convention("jp"), DateOrder::Ymd);
+        assert_eq!(DateOrder::convention("au"), DateOrder::Dmy);
+    }
+
+    #[test]
+    fn test_epoch() {
+        let name = "backup_1657843200.tar";
+        assert!(Extractor::default().find_dates(name).is_empty());
+
+        let extractor = Extractor {
+            epoch: true,
+            ..Extractor::default()
+        };
+        let day = |day| FileDate::Day {
+            year: 2022,
+            month: 7,
+            day,
+        };
+        assert_eq!(extractor.find_dates(name), [day(15)]);
+        assert_eq!(extractor.find_dates("backup_1657843200123"), [day(15)]);
+        assert_eq!(extractor.find_dates("2022-07-01"), [day(1)]);
+    }
+
+    #[test]
+    fn test_date_pick() {
+        let dates = [
+            FileDate::Month {
+                year: 2022,
+                month: 7,
+            },
+            FileDate::FinancialYear(2022),
+            FileDate::Month {
+                year: 2022,
+                month: 6,
+            },
+        ];
+        let fiscal = FiscalYear::default();
+        assert_eq!(DatePick::First.pick(&dates, &fiscal), Some(dates[0]));
+        assert_eq!(DatePick::Last.pick(&dates, &fiscal), Some(dates[2]));
+        assert_eq!(DatePick::Latest.pick(&dates, &fiscal), Some(dates[0]));
+        assert_eq!(DatePick::Earliest.pick(&dates, &fiscal), Some(dates[1]));
+        assert_eq!(DatePick::Latest.pick(&[], &fiscal), None);
+
+        // A financial year that starts before year 0 is earlier than any other date.
+        let dates = [
+            FileDate::FinancialYear(0),
+            FileDate::Month {
+                year: 2022,
+                month: 1,
+            },
+        ];
+        assert_eq!(DatePick::Latest.pick(&dates, &fiscal), Some(dates[1]));
+        assert_eq!(DatePick::Earliest.pick(&dates, &fiscal), Some(dates[0]));
+    }
+
+    #[test]
+    fn test_languages_agree() {
+        let any = Extractor {
+            languages: Lang::ALL.to_vec(),
+            ..Extractor::default()
+        };
+        for lang in Lang::ALL {
+            for (index, names) in lang.month_names().iter().enumerate() {
+                for name in names.iter() {
+                    assert_eq!(any.get_month(name), Ok(index as u8 + 1), "{}", name);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Finding files that already exist in their financial year directory under another name.
+
+use std::collections::{hash_map, HashMap};
+use std::path;
+
+use crate::manifest;
+use crate::plan::{self, Move};
+
+/// The name of the directory, in the base directory, that duplicates are moved into.
+pub const DUPLICATES_DIR: &str = "duplicates";
+
+/// What to do with a file whose contents are already in its financial year directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Leave the file where it is.
+    Skip,
+    /// Place the file anyway, so that both are kept.
+    KeepBoth,
+    /// Move the file into the duplicates directory instead.
+    MoveToDuplicates,
+}
+
+impl DuplicatePolicy {
+    /// Parses the policy from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "skip-duplicate" => Ok(DuplicatePolicy::Skip),
+            "keep-both" => Ok(DuplicatePolicy::KeepBoth),
+            "move-to-duplicates" => Ok(DuplicatePolicy::MoveToDuplicates),
+            _ => Err(format!("Unknown duplicate policy {:?}", name)),
+        }
+    }
+
+    /// Describes what was done with the duplicate.
+    pub fn action(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Skip => "left in place",
+            DuplicatePolicy::KeepBoth => "kept both",
+            DuplicatePolicy::MoveToDuplicates => "moved to duplicates",
+        }
+    }
+}
+
+/// A file whose contents were already in its financial year directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+    pub path: path::PathBuf,
+    /// The file that already had the same contents.
+    pub original: path::PathBuf,
+    pub policy: DuplicatePolicy,
+}
+
+/// The checksums of the files in each financial year directory, read as they are needed.
+#[derive(Default)]
+pub struct Checksums {
+    dirs: HashMap<path::PathBuf, HashMap<String, path::PathBuf>>,
+}
+
+impl Checksums {
+    /// Finds a file, other than its destination, with the same contents as the file being moved in
+    /// the financial year directory that it is being moved to. If there is none, the file is
+    /// remembered as being in that directory, so that later files are checked against it.
+    pub fn find(&mut self, file_move: &Move) -> Result<Option<path::PathBuf>, String> {
+        let fy_dir = plan::existing_dir(
+            manifest::manifest_path(file_move)
+                .parent()
+                .expect("manifest has no parent"),
+        );
+        let destination = plan::existing_dir(
+            file_move
+                .destination
+                .parent()
+                .expect("destination has no parent"),
+        )
+        .join(
+            file_move
+                .destination
+                .file_name()
+                .expect("destination has no file name"),
+        );
+        let checksum = manifest::checksum(&file_move.source)?;
+        let checksums = match self.dirs.entry(fy_dir) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                let mut checksums = HashMap::new();
+                read_checksums(entry.key(), &mut checksums)?;
+                entry.insert(checksums)
+            }
+        };
+        match checksums.get(&checksum) {
+            Some(original) if *original != destination => Ok(Some(original.clone())),
+            Some(_) => Ok(None),
+            None => {
+                checksums.insert(checksum, destination);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Gets the move that places the file in the duplicates directory, beside its financial year
+/// directory, instead.
+pub fn to_duplicates(file_move: &Move) -> Move {
+    let manifest = manifest::manifest_path(file_move);
+    let base = manifest
+        .parent()
+        .and_then(path::Path::parent)
+        .expect("financial year directory has no parent");
+    Move {
+        destination: base.join(DUPLICATES_DIR).join(
+            file_move
+                .destination
+                .file_name()
+                .expect("destination has no file name"),
+        ),
+        ..file_move.clone()
+    }
+}
+
+/// Reads the checksums of the files in the directory and the directories within it.
+fn read_checksums(
+    dir: &path::Path,
+    checksums: &mut HashMap<String, path::PathBuf>,
+) -> Result<(), String> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) if !dir.exists() => return Ok(()),
+        Err(e) => return Err(format!("Could not read directory {}: {}", dir.display(), e)),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            read_checksums(&path, checksums)?;
+        } else if path.is_file() && !path.ends_with(manifest::MANIFEST_NAME) {
+            checksums.entry(manifest::checksum(&path)?).or_insert(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{to_duplicates, Checksums};
+    use crate::plan::Move;
+
+    #[test]
+    fn test_find() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        fs::create_dir_all(base.join("2023FY").join("Q1")).unwrap();
+        fs::write(base.join("2023FY").join("Q1").join("old.pdf"), "same").unwrap();
+        fs::write(base.join("new_10JUL2022.pdf"), "same").unwrap();
+        fs::write(base.join("other_10JUL2022.pdf"), "other").unwrap();
+
+        let file_move = |name: &str| Move {
+            source: base.join(name),
+            destination: base.join("2023FY").join("Q1").join(name),
+            date: None,
+        };
+        let mut checksums = Checksums::default();
+        assert_eq!(
+            checksums.find(&file_move("new_10JUL2022.pdf")),
+            Ok(Some(base.join("2023FY").join("Q1").join("old.pdf")))
+        );
+        assert_eq!(checksums.find(&file_move("other_10JUL2022.pdf")), Ok(None));
+        // Files placed earlier in the run are found too.
+        fs::write(base.join("copy_10AUG2022.pdf"), "other").unwrap();
+        assert_eq!(
+            checksums.find(&file_move("copy_10AUG2022.pdf")),
+            Ok(Some(
+                base.join("2023FY").join("Q1").join("other_10JUL2022.pdf")
+            ))
+        );
+
+        assert_eq!(
+            to_duplicates(&file_move("new_10JUL2022.pdf")).destination,
+            base.join("duplicates").join("new_10JUL2022.pdf")
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Desktop notifications, so that someone whose scans are classified in the background knows that
+//! they have been filed.
+
+use std::collections::BTreeSet;
+
+use crate::plan::{Outcome, Plan};
+use crate::sink::Bucket;
+
+/// Describes the run in a line, such as "Classified 4 files into 2024FY, 1 unrecognised".
+pub fn message(outcome: &Outcome, plan: &Plan) -> String {
+    let buckets: BTreeSet<String> = plan
+        .moves
+        .iter()
+        .filter_map(|file_move| Bucket::of_move(file_move).top())
+        .collect();
+    let mut message = if outcome.placed == 0 {
+        String::from("No files were classified")
+    } else if buckets.is_empty() {
+        format!("Classified {} files", outcome.placed)
+    } else {
+        let buckets: Vec<String> = buckets.into_iter().collect();
+        format!(
+            "Classified {} files into {}",
+            outcome.placed,
+            buckets.join(", ")
+        )
+    };
+    if outcome.unclassified > 0 {
+        message += &format!(", {} unrecognised", outcome.unclassified);
+    }
+    if !outcome.failures.is_empty() {
+        message += &format!(", {} failed", outcome.failures.len());
+    }
+    message
+}
+
+/// Shows the message as a desktop notification.
+#[cfg(feature = "notify")]
+pub fn notify(message: &str) -> Result<(), String> {
+    notify_rust::Notification::new()
+        .appname("classfy")
+        .summary("classfy")
+        .body(message)
+        .show()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "notify"))]
+pub fn notify(_message: &str) -> Result<(), String> {
+    Err(String::from("classfy was built without the notify feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+
+    use super::message;
+    use crate::plan::{Move, Outcome, Plan};
+
+    fn planned(source: &str, destination: &str) -> Move {
+        Move {
+            source: path::PathBuf::from(source),
+            destination: path::PathBuf::from(destination),
+            date: None,
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let plan = Plan {
+            moves: vec![
+                planned("/scans/a_10JUL2023.pdf", "/scans/2024FY/a_10JUL2023.pdf"),
+                planned("/scans/b_10JUN2023.pdf", "/scans/2023FY/Q4/b_10JUN2023.pdf"),
+                planned("/scans/c_11JUL2023.pdf", "/scans/2024FY/c_11JUL2023.pdf"),
+            ],
+            unclassified: Vec::new(),
+        };
+        let outcome = Outcome {
+            placed: 3,
+            unclassified: 1,
+            ..Outcome::default()
+        };
+        assert_eq!(
+            message(&outcome, &plan),
+            "Classified 3 files into 2023FY, 2024FY, 1 unrecognised"
+        );
+        assert_eq!(
+            message(&Outcome::default(), &Plan::default()),
+            "No files were classified"
+        );
+    }
+}
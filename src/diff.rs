@@ -0,0 +1,209 @@
+//! Showing a plan as a tree of the directories it changes, which is easier to review than a list
+//! of moves when there are many files.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path;
+
+use crate::plan::Plan;
+use crate::unicode;
+
+/// What the plan does to a file in the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Change {
+    /// The file is moved away from here.
+    Leave,
+    /// The file is moved here.
+    Arrive,
+    /// The file is moved here, but the name is already taken.
+    Conflict,
+    /// The file is left where it is.
+    Unclassified,
+}
+
+impl Change {
+    /// Gets the mark shown before the name of the file.
+    fn mark(self) -> char {
+        match self {
+            Change::Leave => '-',
+            Change::Arrive => '+',
+            Change::Conflict => '!',
+            Change::Unclassified => '?',
+        }
+    }
+}
+
+/// A directory in the tree, with the changes to the files in it.
+#[derive(Default)]
+struct Node {
+    dirs: BTreeMap<OsString, Node>,
+    files: BTreeMap<OsString, Vec<(Change, String)>>,
+}
+
+impl Node {
+    /// Adds the change to the file at the path, which is relative to this directory.
+    fn add(&mut self, relative: &path::Path, change: Change, detail: String) {
+        let mut components: Vec<OsString> = relative
+            .components()
+            .map(|component| component.as_os_str().to_os_string())
+            .collect();
+        let Some(name) = components.pop() else {
+            return;
+        };
+        let mut node = self;
+        for component in components {
+            node = node.dirs.entry(component).or_default();
+        }
+        node.files.entry(name).or_default().push((change, detail));
+    }
+
+    /// Adds the lines for the directories and files in this directory, which is at the path.
+    fn lines(&self, dir: &path::Path, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        for (name, node) in &self.dirs {
+            let path = dir.join(name);
+            let mut line = format!("{}{}/", indent, name.to_string_lossy());
+            if !path.exists() {
+                line += " (new)";
+            }
+            lines.push(line);
+            node.lines(&path, depth + 1, lines);
+        }
+        for (name, changes) in &self.files {
+            for (change, detail) in changes {
+                let mut line = format!("{}{} {}", indent, change.mark(), name.to_string_lossy());
+                if !detail.is_empty() {
+                    line += &format!(" ({})", detail);
+                }
+                lines.push(line);
+            }
+        }
+    }
+}
+
+/// Gets the lines of the tree of the directories that the plan changes. Each file is marked with
+/// `-` where it is moved from, `+` where it is moved to, `!` where it is moved to but the name is
+/// taken, and `?` if it is left in place.
+pub fn tree(plan: &Plan) -> Vec<String> {
+    let paths: Vec<&path::Path> = plan
+        .moves
+        .iter()
+        .flat_map(|file_move| [&file_move.source, &file_move.destination])
+        .chain(
+            plan.unclassified
+                .iter()
+                .map(|unclassified| &unclassified.path),
+        )
+        .map(|path| path.parent().unwrap_or(path::Path::new("")))
+        .collect();
+    let Some(root) = common_ancestor(&paths) else {
+        return Vec::new();
+    };
+
+    let mut tree = Node::default();
+    for file_move in &plan.moves {
+        tree.add(
+            relative(&file_move.source, &root),
+            Change::Leave,
+            String::new(),
+        );
+        let taken_by = plan.moves.iter().find(|other| {
+            unicode::same_path(&other.destination, &file_move.destination)
+                && other.source != file_move.source
+        });
+        let (change, detail) = if file_move.destination.exists() {
+            (Change::Conflict, String::from("already exists"))
+        } else if let Some(other) = taken_by {
+            (
+                Change::Conflict,
+                format!("also planned for {}", other.source.display()),
+            )
+        } else {
+            (
+                Change::Arrive,
+                format!("from {}", relative(&file_move.source, &root).display()),
+            )
+        };
+        tree.add(relative(&file_move.destination, &root), change, detail);
+    }
+    for unclassified in &plan.unclassified {
+        tree.add(
+            relative(&unclassified.path, &root),
+            Change::Unclassified,
+            unclassified.reason.clone(),
+        );
+    }
+
+    let mut lines = vec![if root.as_os_str().is_empty() {
+        String::from("./")
+    } else {
+        format!("{}/", root.display())
+    }];
+    tree.lines(&root, 1, &mut lines);
+    lines
+}
+
+/// Gets the directory that all of the paths are in.
+fn common_ancestor(paths: &[&path::Path]) -> Option<path::PathBuf> {
+    let (first, rest) = paths.split_first()?;
+    let mut common = first.to_path_buf();
+    for path in rest {
+        while !path.starts_with(&common) {
+            if !common.pop() {
+                break;
+            }
+        }
+    }
+    Some(common)
+}
+
+/// Gets the path relative to the root of the tree, which it is always in.
+fn relative<'a>(path: &'a path::Path, root: &path::Path) -> &'a path::Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::tree;
+    use crate::plan::{Move, Plan, Unclassified};
+
+    #[test]
+    fn test_tree() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("2023FY")).unwrap();
+        fs::write(root.join("2023FY/b_10MAR2023.pdf"), "").unwrap();
+        let file_move = |source: &str, destination: &str| Move {
+            source: root.join(source),
+            destination: root.join(destination),
+            date: None,
+        };
+        let plan = Plan {
+            moves: vec![
+                file_move("a_10JUL2023.pdf", "2024FY/a_10JUL2023.pdf"),
+                file_move("b_10MAR2023.pdf", "2023FY/b_10MAR2023.pdf"),
+            ],
+            unclassified: vec![Unclassified {
+                path: root.join("notes.txt"),
+                reason: String::from("no date found"),
+            }],
+        };
+
+        let lines = tree(&plan);
+        assert_eq!(lines[0], format!("{}/", root.display()));
+        assert_eq!(
+            &lines[1..],
+            [
+                "  2023FY/",
+                "    ! b_10MAR2023.pdf (already exists)",
+                "  2024FY/ (new)",
+                "    + a_10JUL2023.pdf (from a_10JUL2023.pdf)",
+                "  - a_10JUL2023.pdf",
+                "  - b_10MAR2023.pdf",
+                "  ? notes.txt (no date found)",
+            ]
+        );
+    }
+}
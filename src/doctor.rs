@@ -0,0 +1,272 @@
+//! Checking the configuration and the directories for problems that would get in the way of
+//! classifying the files, and saying how to fix each of them.
+
+use std::fs;
+use std::io;
+use std::path;
+
+use classfy::{config, report, rules};
+
+use crate::{watch, Options, EXIT_IO_ERROR, EXIT_USAGE};
+
+/// The name of the file created to check that a directory can be written to.
+const PROBE_NAME: &str = ".classfy-doctor";
+
+/// The start of the names of the temporary files that are written before they replace an archive.
+const TEMP_PREFIX: &str = ".tmp";
+
+/// How much a finding gets in the way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Severity {
+    /// Something that may not be what was intended.
+    Warning,
+    /// Something that stops files from being classified.
+    Problem,
+}
+
+/// Something found wrong, and how to fix it.
+#[derive(Debug)]
+struct Finding {
+    severity: Severity,
+    message: String,
+    fix: String,
+}
+
+/// The findings so far, and whether the configuration could not be loaded.
+#[derive(Default)]
+struct Findings {
+    found: Vec<Finding>,
+    invalid_config: bool,
+}
+
+impl Findings {
+    fn problem(&mut self, message: String, fix: &str) {
+        self.found.push(Finding {
+            severity: Severity::Problem,
+            message,
+            fix: fix.to_string(),
+        });
+    }
+
+    fn warning(&mut self, message: String, fix: &str) {
+        self.found.push(Finding {
+            severity: Severity::Warning,
+            message,
+            fix: fix.to_string(),
+        });
+    }
+}
+
+/// Runs each of the checks and reports what was found, getting the exit code that describes it.
+pub fn run(sub_matches: &clap::ArgMatches) -> i32 {
+    let mut findings = Findings::default();
+    let config = match Options::new(sub_matches) {
+        Ok(options) => Some(options.config),
+        Err(e) => {
+            findings.invalid_config = true;
+            findings.problem(
+                e,
+                "correct the configuration file, or give another one with --config",
+            );
+            None
+        }
+    };
+    if let Some(config) = &config {
+        check_config(config, &mut findings);
+    }
+    let mut dirs: Vec<path::PathBuf> = sub_matches
+        .get_many::<path::PathBuf>("dirs")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    if dirs.is_empty() {
+        dirs = config
+            .iter()
+            .flat_map(|config| &config.watch)
+            .map(|watch| watch.dir.clone())
+            .collect();
+    }
+    if dirs.is_empty() {
+        dirs.push(path::PathBuf::from("."));
+    }
+    for dir in &dirs {
+        check_dir(dir, &mut findings);
+    }
+    check_lock(&mut findings);
+    check_socket(&mut findings);
+
+    for finding in &findings.found {
+        let severity = match finding.severity {
+            Severity::Warning => "warning",
+            Severity::Problem => "problem",
+        };
+        report::note!("{}: {}\n  fix: {}", severity, finding.message, finding.fix);
+    }
+    let problems = findings
+        .found
+        .iter()
+        .filter(|finding| finding.severity == Severity::Problem)
+        .count();
+    let warnings = findings.found.len() - problems;
+    if findings.found.is_empty() {
+        report::note!("No problems found");
+    } else {
+        report::note!("Found {} problems and {} warnings", problems, warnings);
+    }
+    if findings.invalid_config {
+        EXIT_USAGE
+    } else if problems > 0 {
+        EXIT_IO_ERROR
+    } else {
+        0
+    }
+}
+
+/// Checks the settings in the configuration that can be wrong even though they are valid.
+fn check_config(config: &config::Config, findings: &mut Findings) {
+    for (index, earlier) in rules::shadowed(&config.rules) {
+        findings.warning(
+            format!(
+                "rule {} is never used, as rule {} applies to all of its files",
+                index + 1,
+                earlier + 1
+            ),
+            "remove the rule, or move it before the one that is used instead",
+        );
+    }
+    if let Some(audit_log) = &config.audit_log {
+        let dir = audit_log
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(path::Path::new("."));
+        if dir.is_dir() && !is_writable(dir) {
+            findings.problem(
+                format!(
+                    "the audit log {} cannot be written to, as {} is not writable",
+                    audit_log.display(),
+                    dir.display()
+                ),
+                "give the user running classfy permission to write to the directory",
+            );
+        }
+    }
+}
+
+/// Checks that the files in the directory can be moved, and that no temporary files were left
+/// behind by an interrupted run.
+fn check_dir(dir: &path::Path, findings: &mut Findings) {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            findings.problem(
+                format!("{} does not exist", dir.display()),
+                "create the directory, or give the one the files are in",
+            );
+            return;
+        }
+        Err(e) => {
+            findings.problem(
+                format!("{} cannot be read: {}", dir.display(), e),
+                "give the user running classfy permission to read the directory",
+            );
+            return;
+        }
+    };
+    if !is_writable(dir) {
+        findings.problem(
+            format!(
+                "{} is not writable, so its files cannot be moved",
+                dir.display()
+            ),
+            "give the user running classfy permission to write to the directory, or use --copy \
+             with a writable --dest",
+        );
+    }
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && name.starts_with(TEMP_PREFIX) {
+            findings.warning(
+                format!(
+                    "{} looks like it was left behind by an interrupted run",
+                    path.display()
+                ),
+                "check that it is not needed, and then remove it",
+            );
+        }
+    }
+}
+
+/// Checks if a file can be created in the directory, by creating one and removing it.
+fn is_writable(dir: &path::Path) -> bool {
+    let probe = dir.join(PROBE_NAME);
+    match fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => fs::remove_file(&probe).is_ok(),
+        Err(e) => e.kind() == io::ErrorKind::AlreadyExists,
+    }
+}
+
+/// Checks whether a run of `classfy run` is holding the lock, which makes the next one skip.
+fn check_lock(findings: &mut Findings) {
+    let lock_path = watch::lock_path();
+    let Ok(lock) = fs::File::open(&lock_path) else {
+        return;
+    };
+    if let Err(fs::TryLockError::WouldBlock) = lock.try_lock() {
+        findings.warning(
+            format!(
+                "another run is classifying the watched directories and holds {}",
+                lock_path.display()
+            ),
+            "wait for it to finish, or stop it if it is stuck, as scheduled runs are skipped \
+             until then",
+        );
+    }
+}
+
+/// Checks for a control socket that was left behind by a daemon that is no longer running.
+#[cfg(all(unix, feature = "daemon"))]
+fn check_socket(findings: &mut Findings) {
+    let socket = crate::daemon::default_socket();
+    if socket.exists() && std::os::unix::net::UnixStream::connect(&socket).is_err() {
+        findings.warning(
+            format!(
+                "the control socket {} was left behind by a daemon that is no longer running",
+                socket.display()
+            ),
+            "remove it, although classfy daemon replaces it when it starts",
+        );
+    }
+}
+
+#[cfg(not(all(unix, feature = "daemon")))]
+fn check_socket(_findings: &mut Findings) {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{check_dir, Findings, Severity};
+
+    #[test]
+    fn test_check_dir() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let mut findings = Findings::default();
+        check_dir(tempdir.path(), &mut findings);
+        assert!(findings.found.is_empty());
+
+        fs::write(tempdir.path().join(".tmpA1b2C3"), "").unwrap();
+        check_dir(tempdir.path(), &mut findings);
+        check_dir(&tempdir.path().join("missing"), &mut findings);
+        let severities: Vec<Severity> = findings
+            .found
+            .iter()
+            .map(|finding| finding.severity)
+            .collect();
+        assert_eq!(severities, [Severity::Warning, Severity::Problem]);
+        assert!(!tempdir.path().join(".classfy-doctor").exists());
+    }
+}
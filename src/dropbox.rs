@@ -0,0 +1,285 @@
+//! Uploading the classified files to a folder in Dropbox, given as `dropbox:///Finance`. Dropbox
+//! creates the folders for the buckets as files are uploaded into them, and a file that Dropbox
+//! already has with the same content hash is not uploaded again.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read};
+use std::path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::plan::PlaceError;
+use crate::remote::{Credentials, Destination, Upload};
+
+const UPLOAD_URL: &str = "https://content.dropboxapi.com/2/files/upload";
+const SESSION_START_URL: &str = "https://content.dropboxapi.com/2/files/upload_session/start";
+const SESSION_APPEND_URL: &str = "https://content.dropboxapi.com/2/files/upload_session/append_v2";
+const SESSION_FINISH_URL: &str = "https://content.dropboxapi.com/2/files/upload_session/finish";
+const METADATA_URL: &str = "https://api.dropboxapi.com/2/files/get_metadata";
+
+/// Files larger than this are uploaded in chunks of this size, as Dropbox takes at most 150 MiB in
+/// a request.
+const CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The size of the blocks that Dropbox's content hash is made from.
+const HASH_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Uploads files to a folder in Dropbox.
+#[derive(Debug)]
+pub struct Dropbox {
+    token: String,
+    /// The folder, such as "/Finance", or empty for the top of the Dropbox.
+    root: String,
+    agent: ureq::Agent,
+}
+
+impl Dropbox {
+    /// Uses the access token in the credentials.
+    pub fn new(destination: &Destination, credentials: &Credentials) -> Result<Self, String> {
+        let token = credentials.token.clone().ok_or(
+            "There is no token for Dropbox, set CLASSFY_DROPBOX_TOKEN or token in [dropbox]",
+        )?;
+        let root = [&destination.host, &destination.path]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| format!("/{}", part))
+            .collect();
+        Ok(Dropbox {
+            token,
+            root,
+            agent: ureq::Agent::new(),
+        })
+    }
+
+    /// Gets the path in Dropbox of the file at the path relative to the folder.
+    fn path(&self, key: &str) -> String {
+        format!("{}/{}", self.root, key)
+    }
+
+    /// Starts a request to send the contents of a file, with the arguments in a header.
+    fn content_request(&self, url: &str, arg: &serde_json::Value, len: u64) -> ureq::Request {
+        self.agent
+            .post(url)
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Content-Type", "application/octet-stream")
+            .set("Content-Length", &len.to_string())
+            .set("Dropbox-API-Arg", &api_arg(arg))
+    }
+
+    /// Uploads the file in chunks, for files that are too large to send at once.
+    fn upload_in_chunks(
+        &self,
+        file: &mut fs::File,
+        len: u64,
+        commit: serde_json::Value,
+    ) -> Result<Metadata, String> {
+        let response = self
+            .content_request(SESSION_START_URL, &serde_json::json!({}), 0)
+            .send_bytes(&[]);
+        let session: UploadSession = receive(response, SESSION_START_URL)?;
+        let mut offset = 0;
+        while len - offset > CHUNK_SIZE {
+            let cursor = serde_json::json!({
+                "cursor": { "session_id": session.session_id, "offset": offset },
+            });
+            self.content_request(SESSION_APPEND_URL, &cursor, CHUNK_SIZE)
+                .send((&mut *file).take(CHUNK_SIZE))
+                .map_err(|e| failed(SESSION_APPEND_URL, e))?;
+            offset += CHUNK_SIZE;
+        }
+        let finish = serde_json::json!({
+            "cursor": { "session_id": session.session_id, "offset": offset },
+            "commit": commit,
+        });
+        let response = self
+            .content_request(SESSION_FINISH_URL, &finish, len - offset)
+            .send(file);
+        receive(response, SESSION_FINISH_URL)
+    }
+}
+
+impl Upload for Dropbox {
+    fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+        let mut file = fs::File::open(src).map_err(|e| PlaceError::io("could not open file", e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| PlaceError::io("could not read file", e))?
+            .len();
+        let commit = serde_json::json!({
+            "path": self.path(key),
+            "mode": "overwrite",
+            "autorename": false,
+            "mute": true,
+        });
+        let metadata = if len > CHUNK_SIZE {
+            self.upload_in_chunks(&mut file, len, commit)?
+        } else {
+            let response = self.content_request(UPLOAD_URL, &commit, len).send(file);
+            receive(response, UPLOAD_URL)?
+        };
+        Ok(Some(metadata.id))
+    }
+
+    fn find_same(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+        let response = self
+            .agent
+            .post(METADATA_URL)
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::json!({ "path": self.path(key) }).to_string());
+        let metadata: Metadata = match response {
+            // There is nothing at the path yet.
+            Err(ureq::Error::Status(409, _)) => return Ok(None),
+            response => receive(response, METADATA_URL)?,
+        };
+        match metadata.content_hash {
+            Some(hash) if hash == content_hash(src)? => Ok(Some(metadata.id)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// What Dropbox gives about a file.
+#[derive(Deserialize)]
+struct Metadata {
+    id: String,
+    /// The content hash of a file, which a folder does not have.
+    content_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UploadSession {
+    session_id: String,
+}
+
+/// Gets the hash that Dropbox gives the contents of a file: the SHA-256 of the SHA-256 of each
+/// 4 MiB block, as a hex string.
+fn content_hash(path: &path::Path) -> Result<String, String> {
+    let read_error = |e: io::Error| format!("Could not read {}: {}", path.display(), e);
+    let mut file = fs::File::open(path).map_err(read_error)?;
+    let mut hasher = Sha256::new();
+    let mut block = Vec::new();
+    loop {
+        block.clear();
+        (&mut file)
+            .take(HASH_BLOCK_SIZE)
+            .read_to_end(&mut block)
+            .map_err(read_error)?;
+        if block.is_empty() {
+            break;
+        }
+        hasher.update(Sha256::digest(&block));
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Gets the JSON for the Dropbox-API-Arg header, which must only have ASCII characters.
+fn api_arg(value: &serde_json::Value) -> String {
+    let mut arg = String::new();
+    for c in value.to_string().chars() {
+        if c.is_ascii() && c != '\x7f' {
+            arg.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                write!(arg, "\\u{:04x}", unit).expect("writing to a string cannot fail");
+            }
+        }
+    }
+    arg
+}
+
+/// Reads the JSON response.
+fn receive<T: DeserializeOwned>(
+    response: Result<ureq::Response, ureq::Error>,
+    url: &str,
+) -> Result<T, String> {
+    let response = response.map_err(|e| failed(url, e))?;
+    serde_json::from_reader(response.into_reader())
+        .map_err(|e| format!("Could not read the response from {}: {}", url, e))
+}
+
+/// Describes the failed request, with the summary of the error that Dropbox gave.
+fn failed(url: &str, error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let summary = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|value| value["error_summary"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            format!("POST {} failed with {}: {}", url, status, summary)
+        }
+        e => format!("POST {} failed: {}", url, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sha2::{Digest, Sha256};
+
+    use super::{api_arg, content_hash, Dropbox, HASH_BLOCK_SIZE};
+    use crate::remote::{Credentials, Destination};
+
+    #[test]
+    fn test_new() {
+        let token = Credentials {
+            token: Some(String::from("sl.abc")),
+            ..Credentials::default()
+        };
+        let destination = Destination::parse("dropbox:///Finance/Records").unwrap();
+        let dropbox = Dropbox::new(&destination, &token).unwrap();
+        assert_eq!(
+            dropbox.path("2023FY/a.pdf"),
+            "/Finance/Records/2023FY/a.pdf"
+        );
+        let destination = Destination::parse("dropbox://Finance").unwrap();
+        assert_eq!(Dropbox::new(&destination, &token).unwrap().root, "/Finance");
+        let destination = Destination::parse("dropbox:///").unwrap();
+        let dropbox = Dropbox::new(&destination, &token).unwrap();
+        assert_eq!(dropbox.path("2023FY/a.pdf"), "/2023FY/a.pdf");
+
+        assert!(Dropbox::new(&destination, &Credentials::default()).is_err());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("a.pdf");
+        fs::write(&path, "").unwrap();
+        assert_eq!(
+            content_hash(&path).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let contents = vec![7; HASH_BLOCK_SIZE as usize + 1];
+        fs::write(&path, &contents).unwrap();
+        let (first, second) = contents.split_at(HASH_BLOCK_SIZE as usize);
+        let mut hasher = Sha256::new();
+        hasher.update(Sha256::digest(first));
+        hasher.update(Sha256::digest(second));
+        let expected: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(content_hash(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_api_arg() {
+        let arg = serde_json::json!({ "path": "/Finance/Café 😀.pdf" });
+        assert_eq!(
+            api_arg(&arg),
+            r#"{"path":"/Finance/Caf\u00e9 \ud83d\ude00.pdf"}"#
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Leaving out files that should never be classified, as listed in the ignore file of the directory
+//! that they are in or given with `--exclude`. The patterns have the same syntax and meaning as in
+//! a .gitignore file.
+
+use std::fs;
+use std::io;
+use std::path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// The name of the file, in a directory being classified, that lists the files to leave out.
+pub const IGNORE_FILE: &str = ".classfyignore";
+
+/// The patterns of the files to leave out of a directory.
+#[derive(Clone, Debug)]
+pub struct Ignore {
+    gitignore: Gitignore,
+}
+
+impl Ignore {
+    /// Reads the ignore file in the directory, if there is one, followed by the patterns given
+    /// with `--exclude`. As in a .gitignore file, a later pattern takes precedence over an earlier
+    /// one, so the patterns given with `--exclude` take precedence over the ignore file.
+    pub fn read(dir: &path::Path, excludes: &[String]) -> Result<Self, String> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let path = dir.join(IGNORE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    builder
+                        .add_line(Some(path.clone()), line)
+                        .map_err(|e| format!("In {}: {}", path.display(), e))?;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+        for pattern in excludes {
+            builder
+                .add_line(None, pattern)
+                .map_err(|e| format!("Invalid --exclude {:?}: {}", pattern, e))?;
+        }
+        let gitignore = builder
+            .build()
+            .map_err(|e| format!("Invalid patterns: {}", e))?;
+        Ok(Ignore { gitignore })
+    }
+
+    /// Checks if the file, which is directly in the directory, should be left out.
+    pub fn is_ignored(&self, path: &path::Path) -> bool {
+        self.gitignore.matched(path, false).is_ignore()
+    }
+}
+
+/// Checks that the pattern given with `--exclude` is valid.
+pub fn parse_pattern(pattern: &str) -> Result<String, String> {
+    GitignoreBuilder::new("")
+        .add_line(None, pattern)
+        .map_err(|e| e.to_string())?;
+    Ok(pattern.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{parse_pattern, Ignore, IGNORE_FILE};
+
+    #[test]
+    fn test_syntax() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let dir = tempdir.path();
+        assert!(!Ignore::read(dir, &[])
+            .unwrap()
+            .is_ignored(&dir.join("a.tmp")));
+
+        fs::write(
+            dir.join(IGNORE_FILE),
+            "# Kept by hand\n\n*.tmp  \n/notes_*.txt\nsub/a.pdf\nreceipts/\n\\!important.pdf\n",
+        )
+        .unwrap();
+        let ignore = Ignore::read(dir, &[]).unwrap();
+        let is_ignored = |name: &str| ignore.is_ignored(&dir.join(name));
+        assert!(is_ignored("statement_10JUL2022.pdf.tmp"));
+        // Anchored to the directory with the ignore file.
+        assert!(is_ignored("notes_10JUL2022.txt"));
+        assert!(!is_ignored("a.pdf"));
+        // Directory patterns only match directories.
+        assert!(!is_ignored("receipts"));
+        assert!(is_ignored("!important.pdf"));
+        assert!(!is_ignored("# Kept by hand"));
+        assert!(!is_ignored("statement_10JUL2022.pdf"));
+
+        fs::write(dir.join(IGNORE_FILE), "{unclosed\n").unwrap();
+        assert!(Ignore::read(dir, &[]).is_err());
+        assert!(parse_pattern("{unclosed").is_err());
+        assert_eq!(parse_pattern("!*.pdf"), Ok(String::from("!*.pdf")));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let dir = tempdir.path();
+        let excludes = |patterns: &[&str]| -> Vec<String> {
+            patterns.iter().map(|pattern| pattern.to_string()).collect()
+        };
+
+        // A later pattern takes precedence over an earlier one.
+        fs::write(dir.join(IGNORE_FILE), "*.pdf\n!keep_*.pdf\n").unwrap();
+        let ignore = Ignore::read(dir, &[]).unwrap();
+        assert!(ignore.is_ignored(&dir.join("a.pdf")));
+        assert!(!ignore.is_ignored(&dir.join("keep_a.pdf")));
+        fs::write(dir.join(IGNORE_FILE), "!keep_*.pdf\n*.pdf\n").unwrap();
+        let ignore = Ignore::read(dir, &[]).unwrap();
+        assert!(ignore.is_ignored(&dir.join("keep_a.pdf")));
+
+        // The patterns given with --exclude take precedence over the ignore file.
+        fs::write(dir.join(IGNORE_FILE), "*.tmp\n!a.pdf\n").unwrap();
+        let ignore = Ignore::read(dir, &excludes(&["!draft.tmp", "*.pdf"])).unwrap();
+        assert!(ignore.is_ignored(&dir.join("other.tmp")));
+        assert!(!ignore.is_ignored(&dir.join("draft.tmp")));
+        assert!(ignore.is_ignored(&dir.join("a.pdf")));
+    }
+}
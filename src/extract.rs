@@ -0,0 +1,196 @@
+//! Ways of finding the date of a file, for programs that use classfy as a library. The built-in
+//! ways are provided as extractors, and a program can register its own with an `Extractor`.
+
+use std::fmt;
+use std::fs;
+use std::path;
+use std::time;
+
+use crate::content;
+use crate::date::{Extractor, FileDate};
+
+/// Finds the date of a file in one particular way.
+pub trait DateExtractor: fmt::Debug + Send + Sync {
+    /// Gets the date of the file, or `None` if it cannot be found this way.
+    fn extract(&self, path: &path::Path) -> Option<chrono::NaiveDate>;
+}
+
+/// Finds the date in the file name, with the settings of the extractor. A month is taken as its
+/// first day, and a name with only a financial year has no date.
+#[derive(Clone, Debug, Default)]
+pub struct Filename(pub Extractor);
+
+impl DateExtractor for Filename {
+    fn extract(&self, path: &path::Path) -> Option<chrono::NaiveDate> {
+        self.0.name_date(path).ok()?.naive_date()
+    }
+}
+
+/// Finds when the file was last modified, in the time zone or otherwise the system's.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mtime(pub Option<chrono_tz::Tz>);
+
+impl DateExtractor for Mtime {
+    fn extract(&self, path: &path::Path) -> Option<chrono::NaiveDate> {
+        modified_date(path, self.0).ok()?.naive_date()
+    }
+}
+
+/// Finds when the file was created, from its properties if it is an Office document, or
+/// otherwise from the file system in the time zone or the system's.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Metadata(pub Option<chrono_tz::Tz>);
+
+impl DateExtractor for Metadata {
+    fn extract(&self, path: &path::Path) -> Option<chrono::NaiveDate> {
+        created_date(path, self.0).ok()?.naive_date()
+    }
+}
+
+/// Gets the day that the file was last modified in the time zone, or the system's if there is
+/// none.
+pub fn modified_date(path: &path::Path, zone: Option<chrono_tz::Tz>) -> Result<FileDate, String> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Could not read when {} was modified: {}", path.display(), e))
+        .and_then(|time| system_date(time, zone))
+}
+
+/// Gets the day that the file was created, from its properties if it is an Office document that
+/// has them, or otherwise from the file system in the time zone or the system's.
+pub fn created_date(path: &path::Path, zone: Option<chrono_tz::Tz>) -> Result<FileDate, String> {
+    match content::document_date(path)? {
+        Some(date) => Ok(date),
+        None => fs::metadata(path)
+            .and_then(|metadata| metadata.created())
+            .map_err(|e| format!("Could not read when {} was created: {}", path.display(), e))
+            .and_then(|time| system_date(time, zone)),
+    }
+}
+
+/// Parses the time zone from its IANA name, such as "Australia/Perth".
+pub fn parse_timezone(name: &str) -> Result<chrono_tz::Tz, String> {
+    name.parse()
+        .map_err(|_| format!("Unknown time zone \"{}\"", name))
+}
+
+/// Gets the day of the time in the time zone, or the system's if there is none.
+pub fn local_date(time: time::SystemTime, zone: Option<chrono_tz::Tz>) -> chrono::NaiveDate {
+    let utc = chrono::DateTime::<chrono::Utc>::from(time);
+    match zone {
+        Some(zone) => utc.with_timezone(&zone).date_naive(),
+        None => utc.with_timezone(&chrono::Local).date_naive(),
+    }
+}
+
+fn system_date(time: time::SystemTime, zone: Option<chrono_tz::Tz>) -> Result<FileDate, String> {
+    FileDate::from_naive(local_date(time, zone))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{modified_date, parse_timezone, DateExtractor, Filename, Mtime};
+    use crate::date::{Extractor, FileDate};
+    use crate::fiscal::FiscalYear;
+
+    /// Dates every file as the last day of 2021.
+    #[derive(Debug)]
+    struct NewYearsEve;
+
+    impl DateExtractor for NewYearsEve {
+        fn extract(&self, _path: &path::Path) -> Option<chrono::NaiveDate> {
+            chrono::NaiveDate::from_ymd_opt(2021, 12, 31)
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("statement_10JUL2022.pdf");
+        fs::write(&path, "").unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        assert_eq!(
+            Filename::default().extract(&path),
+            chrono::NaiveDate::from_ymd_opt(2022, 7, 10)
+        );
+        assert_eq!(
+            Filename::default().extract(path::Path::new("statement_JUL2022.pdf")),
+            chrono::NaiveDate::from_ymd_opt(2022, 7, 1)
+        );
+        assert_eq!(
+            Filename::default().extract(path::Path::new("statement_2022FY.pdf")),
+            None
+        );
+        assert_eq!(
+            Mtime::default().extract(&path),
+            Some(chrono::DateTime::<chrono::Local>::from(modified).date_naive())
+        );
+        assert_eq!(
+            Mtime::default().extract(&tempdir.path().join("missing.pdf")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("statement.pdf");
+        let late_on_30_june = chrono::NaiveDate::from_ymd_opt(2022, 6, 30)
+            .unwrap()
+            .and_hms_opt(23, 0, 0)
+            .unwrap()
+            .and_utc();
+        fs::File::create(&path)
+            .unwrap()
+            .set_modified(late_on_30_june.into())
+            .unwrap();
+        let fiscal = FiscalYear::default();
+
+        // It is already 1 July in Sydney, so the file is in the next financial year.
+        let sydney = parse_timezone("Australia/Sydney").unwrap();
+        let date = modified_date(&path, Some(sydney)).unwrap();
+        assert_eq!(date.to_string(), "2022-07-01");
+        assert_eq!(fiscal.fy(date), Ok(2023));
+
+        let utc = parse_timezone("UTC").unwrap();
+        let date = modified_date(&path, Some(utc)).unwrap();
+        assert_eq!(date.to_string(), "2022-06-30");
+        assert_eq!(fiscal.fy(date), Ok(2022));
+
+        assert_eq!(
+            parse_timezone("Australia/Atlantis"),
+            Err("Unknown time zone \"Australia/Atlantis\"".to_string())
+        );
+        assert!(parse_timezone("../../etc/passwd").is_err());
+        assert!(parse_timezone("").is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("statement_10JUL2022.pdf");
+        fs::write(&path, "").unwrap();
+
+        let mut extractor = Extractor::default();
+        extractor.register(NewYearsEve);
+        // Registered extractors are asked before the file name is looked in.
+        assert_eq!(
+            extractor.get_date(&path),
+            Ok(FileDate::Day {
+                year: 2021,
+                month: 12,
+                day: 31
+            })
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use chrono::Datelike;
+
+use crate::date::FileDate;
+
+/// The names of the built-in conventions accepted by `FiscalYear::convention`.
+pub const CONVENTIONS: [&str; 6] = ["au", "nz", "uk", "us", "in", "jp"];
+
+/// Which calendar year a financial year is named after.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum YearLabel {
+    /// The year it starts in, so July 2022 to June 2023 is 2022.
+    Start,
+    /// The year it ends in, so July 2022 to June 2023 is 2023.
+    #[default]
+    End,
+}
+
+impl YearLabel {
+    /// Parses the label from its name on the command line or in the configuration.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "start" => Ok(YearLabel::Start),
+            "end" => Ok(YearLabel::End),
+            _ => Err(format!("Unknown year label {:?}", name)),
+        }
+    }
+}
+
+/// The day on which each financial year starts, in or around its first month.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YearStart {
+    /// The day of the first month, or its last day if the month is shorter, such as 6 for the UK
+    /// tax year that starts on 6 April.
+    Day(u8),
+    /// The day after the last of the weekday in the month before, for a 52/53-week year that
+    /// ends on the last Saturday of June.
+    AfterLast(chrono::Weekday),
+    /// The day after the weekday nearest the end of the month before, for a 52/53-week year that
+    /// ends on the Saturday nearest 30 June.
+    AfterNearest(chrono::Weekday),
+}
+
+impl Default for YearStart {
+    /// The first day of the first month.
+    fn default() -> Self {
+        YearStart::Day(1)
+    }
+}
+
+impl YearStart {
+    /// Parses the start from its name on the command line or in the configuration, which is
+    /// either a day of the month such as "6", or "last-" or "nearest-" followed by a weekday such
+    /// as "last-sat".
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let unknown = || {
+            format!(
+                "Unknown start day {:?}, which should be a day of the month such as 6, or last- \
+                 or nearest- followed by a weekday such as last-sat",
+                name
+            )
+        };
+        if let Ok(day) = name.parse::<u8>() {
+            return match day {
+                1..=31 => Ok(YearStart::Day(day)),
+                _ => Err(unknown()),
+            };
+        }
+        let (rule, weekday) = name.split_once('-').ok_or_else(unknown)?;
+        let weekday = weekday.parse().map_err(|_| unknown())?;
+        match rule {
+            "last" => Ok(YearStart::AfterLast(weekday)),
+            "nearest" => Ok(YearStart::AfterNearest(weekday)),
+            _ => Err(unknown()),
+        }
+    }
+}
+
+/// When the financial year starts and how it is named.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FiscalYear {
+    /// The month (1 for January) in which the financial year starts.
+    pub start_month: u8,
+    /// The day on which the financial year starts, in or around `start_month`.
+    pub start: YearStart,
+    /// Which calendar year the financial year is named after.
+    pub label: YearLabel,
+}
+
+impl Default for FiscalYear {
+    /// The Australian financial year, from July to June, named by the year it ends in.
+    fn default() -> Self {
+        FiscalYear {
+            start_month: 7,
+            start: YearStart::default(),
+            label: YearLabel::End,
+        }
+    }
+}
+
+impl FiscalYear {
+    /// Gets the financial year used in a country, by its code in `CONVENTIONS`.
+    pub fn convention(name: &str) -> Result<Self, String> {
+        let (start_month, label) = match name.to_lowercase().as_str() {
+            "au" => (7, YearLabel::End),
+            "nz" => (4, YearLabel::End),
+            "uk" => (4, YearLabel::Start),
+            "us" => (10, YearLabel::End),
+            "in" => (4, YearLabel::End),
+            "jp" => (4, YearLabel::Start),
+            _ => return Err(format!("Unknown fiscal convention {:?}", name)),
+        };
+        Ok(FiscalYear {
+            start_month,
+            start: YearStart::default(),
+            label,
+        })
+    }
+
+    /// Gets the financial year that the date falls in. A date with only a month is in the year that
+    /// starts in that month, whatever day it starts on. Fails if the year it falls in cannot be
+    /// named, as for January of year 0 in a year that starts in July.
+    pub fn fy(&self, date: FileDate) -> Result<u16, String> {
+        let out_of_range = || format!("{} is not in a financial year that can be named", date);
+        match date {
+            FileDate::Day { year, month, day } => {
+                let date = chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), day.into())
+                    .ok_or_else(out_of_range)?;
+                let has_started = |start_year| {
+                    self.start_date(start_year)
+                        .is_some_and(|start| date >= start)
+                };
+                let start_year = match year.checked_add(1) {
+                    Some(next) if has_started(next) => Some(next),
+                    _ if has_started(year) => Some(year),
+                    _ => year.checked_sub(1),
+                };
+                start_year
+                    .and_then(|start_year| self.name(start_year))
+                    .ok_or_else(out_of_range)
+            }
+            FileDate::Month { year, month } if month >= self.start_month => {
+                self.name(year).ok_or_else(out_of_range)
+            }
+            FileDate::Month { year, .. } => year
+                .checked_sub(1)
+                .and_then(|year| self.name(year))
+                .ok_or_else(out_of_range),
+            FileDate::FinancialYear(fy) => Ok(fy),
+        }
+    }
+
+    /// Gets the position of the month (1 for January) within the financial year, starting from 0.
+    pub fn month_index(&self, month: u8) -> u8 {
+        (month + 12 - self.start_month) % 12
+    }
+
+    /// Gets the position, within its financial year, of the month that the date falls in,
+    /// starting from 0. The days at either end of the year that fall in a month of the year next
+    /// to it are counted in its first or last month. A financial year has no month.
+    pub fn month_of(&self, date: FileDate) -> Option<u8> {
+        let (year, month) = date.year_month()?;
+        let index = self.month_index(month);
+        if self.fy(date) == self.fy(FileDate::Month { year, month }) {
+            Some(index)
+        } else if index == 0 {
+            Some(11)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Gets the day on which the financial year that starts in the calendar year begins, or `None`
+    /// if the start month is not one of the months of the year.
+    fn start_date(&self, start_year: u16) -> Option<chrono::NaiveDate> {
+        let first = chrono::NaiveDate::from_ymd_opt(start_year.into(), self.start_month.into(), 1)?;
+        let end = first.pred_opt()?;
+        let (weekday, nearest) = match self.start {
+            YearStart::Day(day) => {
+                return (1..=u32::from(day))
+                    .rev()
+                    .find_map(|day| first.with_day(day))
+                    .or(Some(first));
+            }
+            YearStart::AfterLast(weekday) => (weekday, false),
+            YearStart::AfterNearest(weekday) => (weekday, true),
+        };
+        // The number of days back from the end of the month before to the weekday.
+        let back = (end.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+        let last = if nearest && back > 3 {
+            end.checked_add_days(chrono::Days::new((7 - back).into()))?
+        } else {
+            end.checked_sub_days(chrono::Days::new(back.into()))?
+        };
+        last.succ_opt()
+    }
+
+    /// Gets the first month, as the year and month, that the date covers, or `None` if it is a
+    /// financial year that starts before year 0.
+    pub fn first_month(&self, date: FileDate) -> Option<(u16, u8)> {
+        match date {
+            FileDate::Day { year, month, .. } | FileDate::Month { year, month } => {
+                Some((year, month))
+            }
+            FileDate::FinancialYear(fy) => Some((self.start_year(fy)?, self.start_month)),
+        }
+    }
+
+    /// Gets the last month, as the year and month, that the date covers, or `None` if it is a
+    /// financial year that starts before year 0 or ends after the last year.
+    pub fn last_month(&self, date: FileDate) -> Option<(u16, u8)> {
+        match date {
+            FileDate::Day { year, month, .. } | FileDate::Month { year, month } => {
+                Some((year, month))
+            }
+            FileDate::FinancialYear(fy) if self.start_month == 1 => {
+                Some((self.start_year(fy)?, 12))
+            }
+            FileDate::FinancialYear(fy) => {
+                Some((self.start_year(fy)?.checked_add(1)?, self.start_month - 1))
+            }
+        }
+    }
+
+    /// Gets the name of the financial year that starts in the given calendar year, or `None` if it
+    /// would be named after a year beyond the last.
+    pub fn name(&self, start_year: u16) -> Option<u16> {
+        match self.label {
+            YearLabel::End if self.start_month > 1 => start_year.checked_add(1),
+            _ => Some(start_year),
+        }
+    }
+
+    /// Gets the calendar year in which the named financial year starts, or `None` if it starts
+    /// before year 0.
+    fn start_year(&self, fy: u16) -> Option<u16> {
+        match self.label {
+            YearLabel::End if self.start_month > 1 => fy.checked_sub(1),
+            _ => Some(fy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FiscalYear, YearLabel, YearStart};
+    use crate::date::FileDate;
+
+    #[test]
+    fn test_conventions() {
+        let date = |year, month| FileDate::Month { year, month };
+        let au = FiscalYear::default();
+        assert_eq!(au, FiscalYear::convention("AU").unwrap());
+        assert_eq!(au.fy(date(2022, 7)), Ok(2023));
+        assert_eq!(au.fy(date(2023, 6)), Ok(2023));
+
+        let uk = FiscalYear::convention("uk").unwrap();
+        assert_eq!(uk.fy(date(2022, 4)), Ok(2022));
+        assert_eq!(uk.fy(date(2023, 3)), Ok(2022));
+        assert_eq!(uk.month_index(3), 11);
+
+        let us = FiscalYear::convention("us").unwrap();
+        assert_eq!(us.fy(date(2022, 10)), Ok(2023));
+        assert_eq!(
+            us.first_month(FileDate::FinancialYear(2023)),
+            Some((2022, 10))
+        );
+        assert_eq!(
+            us.last_month(FileDate::FinancialYear(2023)),
+            Some((2023, 9))
+        );
+
+        let calendar = FiscalYear {
+            start_month: 1,
+            label: YearLabel::End,
+            ..FiscalYear::default()
+        };
+        assert_eq!(calendar.fy(date(2022, 1)), Ok(2022));
+        assert_eq!(
+            calendar.last_month(FileDate::FinancialYear(2022)),
+            Some((2022, 12))
+        );
+
+        assert!(FiscalYear::convention("xx").is_err());
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let au = FiscalYear::default();
+        assert!(au.fy(FileDate::Month { year: 0, month: 1 }).is_err());
+        assert_eq!(au.fy(FileDate::Month { year: 0, month: 7 }), Ok(1));
+        assert!(au
+            .fy(FileDate::Month {
+                year: u16::MAX,
+                month: 7
+            })
+            .is_err());
+        assert_eq!(au.first_month(FileDate::FinancialYear(0)), None);
+        assert_eq!(au.last_month(FileDate::FinancialYear(0)), None);
+
+        let uk = FiscalYear::convention("uk").unwrap();
+        assert_eq!(uk.first_month(FileDate::FinancialYear(0)), Some((0, 4)));
+        assert_eq!(uk.last_month(FileDate::FinancialYear(u16::MAX)), None);
+    }
+
+    #[test]
+    fn test_year_start() {
+        let day = |year, month, day| FileDate::Day { year, month, day };
+        let tax_year = FiscalYear {
+            start: YearStart::parse("6").unwrap(),
+            ..FiscalYear::convention("uk").unwrap()
+        };
+        assert_eq!(tax_year.fy(day(2023, 4, 5)), Ok(2022));
+        assert_eq!(tax_year.fy(day(2023, 4, 6)), Ok(2023));
+        assert_eq!(tax_year.month_of(day(2023, 4, 5)), Some(11));
+        assert_eq!(tax_year.month_of(day(2023, 4, 6)), Some(0));
+        assert_eq!(
+            tax_year.fy(FileDate::Month {
+                year: 2023,
+                month: 4
+            }),
+            Ok(2023)
+        );
+
+        // 24 June 2023 was the last Saturday of June.
+        let last_saturday = FiscalYear {
+            start: YearStart::parse("last-sat").unwrap(),
+            ..FiscalYear::default()
+        };
+        assert_eq!(last_saturday.fy(day(2023, 6, 24)), Ok(2023));
+        assert_eq!(last_saturday.fy(day(2023, 6, 25)), Ok(2024));
+        assert_eq!(last_saturday.month_of(day(2023, 6, 25)), Some(0));
+
+        // 2 July 2022 was the Saturday nearest 30 June.
+        let nearest_saturday = FiscalYear {
+            start: YearStart::parse("nearest-saturday").unwrap(),
+            ..FiscalYear::default()
+        };
+        assert_eq!(nearest_saturday.fy(day(2022, 7, 2)), Ok(2022));
+        assert_eq!(nearest_saturday.fy(day(2022, 7, 3)), Ok(2023));
+        assert_eq!(nearest_saturday.month_of(day(2022, 7, 2)), Some(11));
+        assert_eq!(nearest_saturday.fy(day(2023, 7, 1)), Ok(2023));
+        assert_eq!(nearest_saturday.fy(day(2023, 7, 2)), Ok(2024));
+
+        // A year that starts on 31 February starts on the last day of February instead.
+        let february = FiscalYear {
+            start_month: 2,
+            start: YearStart::Day(31),
+            ..FiscalYear::default()
+        };
+        assert_eq!(february.fy(day(2024, 2, 28)), Ok(2024));
+        assert_eq!(february.fy(day(2024, 2, 29)), Ok(2025));
+
+        // A day is in no year if the year it falls in cannot be named.
+        let au = FiscalYear::default();
+        assert!(au.fy(day(0, 1, 1)).is_err());
+        assert_eq!(au.fy(day(0, 7, 1)), Ok(1));
+        assert!(au.fy(day(u16::MAX, 12, 31)).is_err());
+        assert_eq!(last_saturday.fy(day(u16::MAX, 6, 1)), Ok(u16::MAX));
+        assert!(last_saturday.fy(day(u16::MAX, 6, 30)).is_err());
+        assert!(nearest_saturday.fy(day(0, 6, 30)).is_err());
+
+        assert_eq!(
+            YearStart::parse("last-Fri"),
+            Ok(YearStart::AfterLast(chrono::Weekday::Fri))
+        );
+        assert!(YearStart::parse("32").is_err());
+        assert!(YearStart::parse("first-sat").is_err());
+        assert!(YearStart::parse("last-someday").is_err());
+    }
+}
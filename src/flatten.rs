@@ -0,0 +1,179 @@
+//! Moving the files in the year directories of an archive back into its root, the reverse of
+//! classifying them, so that the archive can be organised again in another way.
+
+use std::fs;
+use std::path;
+
+use crate::bucket::Layout;
+use crate::plan::{self, Failure, Move, Outcome, PlaceError, Plan, Settings};
+use crate::report;
+
+/// Gets the plan that moves each of the files in the year directories of the archive, and the
+/// directories within them, into the root under its own name.
+pub fn plan(root: &path::Path, layout: &Layout) -> Result<(Plan, Vec<Failure>), String> {
+    let mut plan = Plan::default();
+    let mut failures = Vec::new();
+    let year_dirs = year_dirs(root, layout)?;
+    if year_dirs.is_empty() {
+        report::note!("There are no year directories in {}", root.display());
+    }
+    for year_dir in year_dirs {
+        let mut files = Vec::new();
+        collect_files(&year_dir, &mut files, &mut failures);
+        files.sort();
+        plan.moves.extend(files.into_iter().map(|path| Move {
+            destination: root.join(path.file_name().expect("file does not have a name")),
+            source: path,
+            date: None,
+        }));
+    }
+    Ok((plan, failures))
+}
+
+/// Moves the files in the plan into the root, as described by the settings, giving them another
+/// name if theirs is taken. The year directories that are left empty are then removed.
+pub fn apply(root: &path::Path, layout: &Layout, plan: &Plan, settings: &Settings) -> Outcome {
+    let mut outcome = Outcome::default();
+    for file_move in &plan.moves {
+        let bytes = fs::metadata(&file_move.source).map_or(0, |metadata| metadata.len());
+        let placed = plan::place(file_move, settings, None);
+        outcome.record(file_move, None, bytes, placed);
+    }
+    match year_dirs(root, layout) {
+        Ok(year_dirs) => {
+            for year_dir in year_dirs {
+                remove_empty(&year_dir);
+            }
+        }
+        Err(e) => report::note!("{}", e),
+    }
+    outcome
+}
+
+/// Gets the year directories in the root, in order.
+fn year_dirs(root: &path::Path, layout: &Layout) -> Result<Vec<path::PathBuf>, String> {
+    let entries = root
+        .read_dir()
+        .map_err(|e| format!("Could not read directory {}: {}", root.display(), e))?;
+    let mut year_dirs: Vec<path::PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| layout.is_year_dir(name))
+        })
+        .collect();
+    year_dirs.sort();
+    Ok(year_dirs)
+}
+
+/// Adds the files in the directory and the directories within it, other than those that classfy
+/// keeps for itself.
+pub(crate) fn collect_files(
+    dir: &path::Path,
+    files: &mut Vec<path::PathBuf>,
+    failures: &mut Vec<Failure>,
+) {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(e) => {
+            let error = PlaceError::io(&format!("Could not read directory {}", dir.display()), e);
+            report::note!("{}", error);
+            failures.push(Failure {
+                path: dir.to_path_buf(),
+                error,
+            });
+            return;
+        }
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_files(&path, files, failures);
+        } else if path.is_file() && !plan::is_own_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// Removes the directory if nothing but classfy's own files, such as its manifest, are left in it
+/// or in the directories within it. Gets whether it was removed.
+pub(crate) fn remove_empty(dir: &path::Path) -> bool {
+    let Ok(entries) = dir.read_dir() else {
+        return false;
+    };
+    let mut empty = true;
+    let mut own_files = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            empty &= remove_empty(&path);
+        } else if plan::is_own_file(&path) {
+            own_files.push(path);
+        } else {
+            empty = false;
+        }
+    }
+    if !empty {
+        return false;
+    }
+    let removed = own_files
+        .iter()
+        .try_for_each(fs::remove_file)
+        .and_then(|()| fs::remove_dir(dir));
+    match removed {
+        Ok(()) => {
+            report::detail!("Removed directory {}", dir.display());
+            true
+        }
+        Err(e) => {
+            report::note!("Could not remove directory {}: {}", dir.display(), e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply, plan};
+    use crate::bucket::Layout;
+    use crate::manifest::MANIFEST_NAME;
+    use crate::plan::Settings;
+
+    #[test]
+    fn test_flatten() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("2023FY/Q4")).unwrap();
+        fs::create_dir(root.join("2024FY")).unwrap();
+        fs::create_dir(root.join("Other")).unwrap();
+        fs::write(root.join("2023FY/a_10JUN2023.pdf"), "a").unwrap();
+        fs::write(root.join("2023FY/Q4/b_10JUN2023.pdf"), "b").unwrap();
+        fs::write(root.join("2023FY").join(MANIFEST_NAME), "{}\n").unwrap();
+        fs::write(root.join("2024FY/a_10JUN2023.pdf"), "another a").unwrap();
+        fs::write(root.join("Other/c.pdf"), "c").unwrap();
+
+        let (plan, failures) = plan(root, &Layout::default()).unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(plan.moves.len(), 3);
+
+        let outcome = apply(root, &Layout::default(), &plan, &Settings::default());
+        assert_eq!(outcome.placed, 3);
+        assert_eq!(outcome.renamed.len(), 1);
+        assert_eq!(
+            fs::read_to_string(root.join("a_10JUN2023.pdf")).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("a_10JUN2023 (2).pdf")).unwrap(),
+            "another a"
+        );
+        assert!(root.join("b_10JUN2023.pdf").is_file());
+        assert!(!root.join("2023FY").exists());
+        assert!(!root.join("2024FY").exists());
+        assert!(root.join("Other/c.pdf").is_file());
+    }
+}
@@ -0,0 +1,377 @@
+//! Uploading the classified files to a folder in Google Drive, given as `gdrive://folder/path`,
+//! where the folder is `root` for My Drive or the ID of a folder that classfy created. The folders
+//! on the path, and those for the buckets, are created as they are needed.
+//!
+//! classfy is authorised with the OAuth device flow the first time: it shows a code to enter on
+//! another device, and keeps the token that it is given in the user's configuration directory for
+//! the next time. Google only lets classfy see the files and folders that it created itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path;
+use std::thread;
+use std::time;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::plan::PlaceError;
+use crate::remote::{Destination, OAuthClient, Upload};
+use crate::report;
+
+const DEVICE_CODE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const FILES_URL: &str = "https://www.googleapis.com/drive/v3/files";
+const UPLOAD_URL: &str = "https://www.googleapis.com/upload/drive/v3/files";
+
+/// Lets classfy see and change only the files that it created.
+const SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+const FOLDER_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// The name of the file, in the user's configuration directory for classfy, that keeps the token.
+const TOKEN_NAME: &str = "gdrive-token.json";
+
+/// Uploads files to a folder in Google Drive.
+pub struct GoogleDrive {
+    access_token: String,
+    /// The IDs of the folders, by their path relative to the destination's folder, with "" for
+    /// that folder itself.
+    folders: HashMap<String, String>,
+    /// The path of the destination, which the keys are under.
+    path: String,
+    agent: ureq::Agent,
+}
+
+impl GoogleDrive {
+    /// Gets an access token for the client, with the saved token if there is one, or otherwise by
+    /// asking the user to authorise classfy.
+    pub fn authorise(destination: &Destination, client: &OAuthClient) -> Result<Self, String> {
+        let client_id = client.client_id.as_deref().ok_or(
+            "There is no client ID for Google Drive, set CLASSFY_GDRIVE_CLIENT_ID or client-id in \
+             [gdrive]",
+        )?;
+        let agent = ureq::Agent::new();
+        let token_path = dirs::config_dir().map(|dir| dir.join("classfy").join(TOKEN_NAME));
+        let saved = token_path.as_deref().and_then(SavedToken::read);
+        let refreshed = saved.and_then(|saved| {
+            refresh(&agent, client_id, client.client_secret.as_deref(), &saved)
+                .map_err(|e| report::note!("Could not use the saved Google Drive token: {}", e))
+                .ok()
+        });
+        let access_token = match refreshed {
+            Some(access_token) => access_token,
+            None => {
+                let (access_token, saved) =
+                    authorise_device(&agent, client_id, client.client_secret.as_deref())?;
+                if let (Some(path), Some(saved)) = (&token_path, saved) {
+                    if let Err(e) = saved.write(path) {
+                        report::note!("Could not save the Google Drive token: {}", e);
+                    }
+                }
+                access_token
+            }
+        };
+        Ok(GoogleDrive {
+            access_token,
+            folders: HashMap::from([(String::new(), destination.host.clone())]),
+            path: destination.path.clone(),
+            agent,
+        })
+    }
+
+    /// Gets the ID of the folder at the path relative to the destination's folder, creating it and
+    /// those it is in if they do not exist yet.
+    fn folder(&mut self, dir: &str) -> Result<String, String> {
+        if let Some(id) = self.folders.get(dir) {
+            return Ok(id.clone());
+        }
+        let (parent, name) = dir.rsplit_once('/').unwrap_or(("", dir));
+        let parent = self.folder(parent)?;
+        let id = match self.find(&parent, name, true)? {
+            Some(id) => id,
+            None => {
+                let metadata = serde_json::json!({
+                    "name": name,
+                    "mimeType": FOLDER_TYPE,
+                    "parents": [parent],
+                });
+                let request = self.request("POST", FILES_URL).query("fields", "id");
+                let created: DriveFile = send_json(request, &metadata.to_string())?;
+                created.id
+            }
+        };
+        self.folders.insert(dir.to_string(), id.clone());
+        Ok(id)
+    }
+
+    /// Finds the ID of the folder, or the file that is not a folder, with the name in the parent.
+    fn find(&self, parent: &str, name: &str, folder: bool) -> Result<Option<String>, String> {
+        let query = format!(
+            "name = '{}' and '{}' in parents and mimeType {} '{}' and trashed = false",
+            escape(name),
+            escape(parent),
+            if folder { "=" } else { "!=" },
+            FOLDER_TYPE
+        );
+        let request = self
+            .request("GET", FILES_URL)
+            .query("q", &query)
+            .query("fields", "files(id)")
+            .query("spaces", "drive");
+        let found: DriveFiles = receive(request.call(), "GET", FILES_URL)?;
+        Ok(found.files.into_iter().next().map(|file| file.id))
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        self.agent
+            .request(method, url)
+            .set("Authorization", &format!("Bearer {}", self.access_token))
+    }
+}
+
+impl Upload for GoogleDrive {
+    fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+        let key = if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.path, key)
+        };
+        let (dir, name) = key.rsplit_once('/').unwrap_or(("", &key));
+        let parent = self.folder(dir)?;
+        let file = fs::File::open(src).map_err(|e| PlaceError::io("could not open file", e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| PlaceError::io("could not read file", e))?
+            .len();
+
+        // A file that is already there is replaced, so that the folder has one file of the name.
+        let (method, url, metadata) = match self.find(&parent, name, false)? {
+            Some(id) => (
+                "PATCH",
+                format!("{}/{}", UPLOAD_URL, id),
+                serde_json::json!({}),
+            ),
+            None => (
+                "POST",
+                UPLOAD_URL.to_string(),
+                serde_json::json!({ "name": name, "parents": [parent] }),
+            ),
+        };
+        let session = self
+            .request(method, &url)
+            .query("uploadType", "resumable")
+            .set("Content-Type", "application/json; charset=UTF-8")
+            .set("X-Upload-Content-Length", &len.to_string())
+            .send_string(&metadata.to_string())
+            .map_err(|e| failed(method, &url, e))?
+            .header("Location")
+            .map(str::to_string)
+            .ok_or("Google Drive did not give a URL to upload to")?;
+        let response = self
+            .request("PUT", &session)
+            .set("Content-Length", &len.to_string())
+            .send(file);
+        let uploaded: DriveFile = receive(response, "PUT", UPLOAD_URL)?;
+        Ok(Some(uploaded.id))
+    }
+}
+
+/// The token that is kept to get access tokens without asking the user again.
+#[derive(Serialize, Deserialize)]
+struct SavedToken {
+    refresh_token: String,
+}
+
+impl SavedToken {
+    fn read(path: &path::Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    /// Writes the token so that only the user can read it.
+    fn write(&self, path: &path::Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(path)?;
+        serde_json::to_writer(file, self).map_err(io::Error::from)
+    }
+}
+
+/// The response that starts the device flow.
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// The response from the token endpoint, which has an error instead of a token while the user has
+/// not finished authorising classfy.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DriveFile {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct DriveFiles {
+    files: Vec<DriveFile>,
+}
+
+/// Gets an access token with the saved token.
+fn refresh(
+    agent: &ureq::Agent,
+    client_id: &str,
+    client_secret: Option<&str>,
+    saved: &SavedToken,
+) -> Result<String, String> {
+    let mut form = vec![
+        ("client_id", client_id),
+        ("refresh_token", saved.refresh_token.as_str()),
+        ("grant_type", "refresh_token"),
+    ];
+    form.extend(client_secret.map(|secret| ("client_secret", secret)));
+    let response = token(agent, &form)?;
+    match (response.access_token, response.error) {
+        (Some(access_token), _) => Ok(access_token),
+        (None, error) => Err(error.unwrap_or_else(|| String::from("no access token was given"))),
+    }
+}
+
+/// Asks the user to authorise classfy on another device, and waits until they have. Gets the
+/// access token, and the token to keep if one was given.
+fn authorise_device(
+    agent: &ureq::Agent,
+    client_id: &str,
+    client_secret: Option<&str>,
+) -> Result<(String, Option<SavedToken>), String> {
+    let request = agent.post(DEVICE_CODE_URL);
+    let code: DeviceCode = receive(
+        request.send_form(&[("client_id", client_id), ("scope", SCOPE)]),
+        "POST",
+        DEVICE_CODE_URL,
+    )?;
+    report::note!(
+        "To let classfy upload to Google Drive, go to {} and enter {}",
+        code.verification_url,
+        code.user_code
+    );
+
+    let mut form = vec![
+        ("client_id", client_id),
+        ("device_code", code.device_code.as_str()),
+        ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+    ];
+    form.extend(client_secret.map(|secret| ("client_secret", secret)));
+    let deadline = time::Instant::now() + time::Duration::from_secs(code.expires_in);
+    let mut interval = time::Duration::from_secs(code.interval.max(1));
+    while time::Instant::now() < deadline {
+        thread::sleep(interval);
+        let response = token(agent, &form)?;
+        match (response.access_token, response.error.as_deref()) {
+            (Some(access_token), _) => {
+                let saved = response
+                    .refresh_token
+                    .map(|refresh_token| SavedToken { refresh_token });
+                return Ok((access_token, saved));
+            }
+            (None, Some("authorization_pending")) => {}
+            (None, Some("slow_down")) => interval += time::Duration::from_secs(5),
+            (None, Some(error)) => {
+                return Err(format!("Google Drive was not authorised: {}", error))
+            }
+            (None, None) => return Err(String::from("Google Drive did not give a token")),
+        }
+    }
+    Err(String::from(
+        "Google Drive was not authorised before the code expired",
+    ))
+}
+
+/// Posts the form to the token endpoint. The errors are part of the response, rather than a
+/// failure.
+fn token(agent: &ureq::Agent, form: &[(&str, &str)]) -> Result<TokenResponse, String> {
+    let response = match agent.post(TOKEN_URL).send_form(form) {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(format!("POST {} failed: {}", TOKEN_URL, e)),
+    };
+    serde_json::from_reader(response.into_reader())
+        .map_err(|e| format!("Could not read the response from {}: {}", TOKEN_URL, e))
+}
+
+/// Sends the JSON and reads the JSON response.
+fn send_json<T: DeserializeOwned>(request: ureq::Request, body: &str) -> Result<T, String> {
+    let (method, url) = (request.method().to_string(), request.url().to_string());
+    let response = request
+        .set("Content-Type", "application/json; charset=UTF-8")
+        .send_string(body);
+    receive(response, &method, &url)
+}
+
+/// Reads the JSON response.
+fn receive<T: DeserializeOwned>(
+    response: Result<ureq::Response, ureq::Error>,
+    method: &str,
+    url: &str,
+) -> Result<T, String> {
+    let response = response.map_err(|e| failed(method, url, e))?;
+    serde_json::from_reader(response.into_reader())
+        .map_err(|e| format!("Could not read the response from {}: {}", url, e))
+}
+
+/// Describes the failed request, with the message that Google Drive gave.
+fn failed(method: &str, url: &str, error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|value| value["error"]["message"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            format!("{} {} failed with {}: {}", method, url, status, message)
+        }
+        e => format!("{} {} failed: {}", method, url, e),
+    }
+}
+
+/// Escapes the quotes and backslashes in a value for a query.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, SavedToken};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Alex's receipts"), "Alex\\'s receipts");
+        assert_eq!(escape("a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn test_saved_token() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("classfy").join("gdrive-token.json");
+        assert!(SavedToken::read(&path).is_none());
+        SavedToken {
+            refresh_token: String::from("1//refresh"),
+        }
+        .write(&path)
+        .unwrap();
+        assert_eq!(SavedToken::read(&path).unwrap().refresh_token, "1//refresh");
+    }
+}
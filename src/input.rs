@@ -0,0 +1,182 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path;
+
+use crate::bucket::Layout;
+use crate::date::Extractor;
+use crate::plan::{self, Candidate, Failure};
+use crate::report;
+
+/// The files to classify.
+pub struct Input {
+    /// Where to find the files.
+    pub source: Source,
+    /// Whether to classify files in directories that have already been classified.
+    pub reclassify: bool,
+    /// The patterns of the files to leave out, in addition to those in each directory's ignore
+    /// file.
+    pub excludes: Vec<String>,
+    /// Whether to use the decisions recorded in each directory for the files that have not
+    /// changed since the last run, rather than looking at them again. This does not apply to
+    /// lists of files.
+    pub incremental: bool,
+}
+
+/// Where to find the files to classify.
+pub enum Source {
+    /// The files directly within each of the directories.
+    Dirs(Vec<path::PathBuf>),
+    /// The files listed in a file, or in standard input if it is "-". The files are separated by
+    /// the delimiter, which is either a new line or a NUL.
+    FileList(path::PathBuf, u8),
+}
+
+impl Input {
+    /// Finds the files and works out where each of them belongs, without moving anything. If
+    /// `absolute` is set, the files are found by their absolute paths so that the result still
+    /// makes sense from another directory. Directories that cannot be read are given as failures,
+    /// so that the others are still classified.
+    pub fn candidates(
+        &self,
+        layout: &Layout,
+        extractor: &Extractor,
+        absolute: bool,
+    ) -> Result<(Vec<Candidate>, Vec<Failure>), String> {
+        let absolute_path = |path: &path::Path| {
+            if absolute {
+                path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+            } else {
+                path.to_path_buf()
+            }
+        };
+        match &self.source {
+            Source::Dirs(dirs) => {
+                let mut candidates = Vec::new();
+                let mut failures = Vec::new();
+                for dir in dirs {
+                    let dir = absolute_path(dir);
+                    let plan_dir = if self.incremental {
+                        plan::plan_dir_incremental
+                    } else {
+                        plan::plan_dir
+                    };
+                    match plan_dir(&dir, layout, extractor, self.reclassify, &self.excludes) {
+                        Ok((found, entry_failures)) => {
+                            report::event(&report::Event::Scan {
+                                source: &dir,
+                                files: found.len(),
+                            });
+                            candidates.extend(found);
+                            for failure in entry_failures {
+                                report::note!("{}", failure.error);
+                                report::event(&report::Event::Error {
+                                    path: &failure.path,
+                                    error: &failure.error.message,
+                                });
+                                failures.push(failure);
+                            }
+                        }
+                        Err(error) => {
+                            report::note!("{}", error);
+                            report::event(&report::Event::Error {
+                                path: &dir,
+                                error: &error.message,
+                            });
+                            failures.push(Failure { path: dir, error });
+                        }
+                    }
+                }
+                Ok((candidates, failures))
+            }
+            Source::FileList(list, delimiter) => {
+                let candidates: Vec<Candidate> = read_file_list(list, *delimiter)?
+                    .iter()
+                    .filter_map(|file| {
+                        plan::plan_file(
+                            &absolute_path(file),
+                            layout,
+                            extractor,
+                            self.reclassify,
+                            &self.excludes,
+                        )
+                    })
+                    .collect();
+                report::event(&report::Event::Scan {
+                    source: list,
+                    files: candidates.len(),
+                });
+                Ok((candidates, Vec::new()))
+            }
+        }
+    }
+}
+
+/// Reads the paths listed in the file, or in standard input if it is "-".
+fn read_file_list(source: &path::Path, delimiter: u8) -> Result<Vec<path::PathBuf>, String> {
+    let mut contents = Vec::new();
+    let read = if source == path::Path::new("-") {
+        io::stdin().read_to_end(&mut contents)
+    } else {
+        fs::File::open(source).and_then(|mut file| file.read_to_end(&mut contents))
+    };
+    read.map_err(|e| format!("Could not read the list of files: {}", e))?;
+    contents
+        .split(|byte| *byte == delimiter)
+        .map(|line| match delimiter {
+            b'\n' => line.strip_suffix(b"\r").unwrap_or(line),
+            _ => line,
+        })
+        .filter(|line| !line.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+/// Gets the path from its bytes, as they would be given to the operating system where possible.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Result<path::PathBuf, String> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path::PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<path::PathBuf, String> {
+    match std::str::from_utf8(bytes) {
+        Ok(path) => Ok(path::PathBuf::from(path)),
+        Err(_) => Err(format!(
+            "{} is not a valid path",
+            String::from_utf8_lossy(bytes)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::read_file_list;
+
+    #[test]
+    fn test_read_file_list() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let list = tempdir.path().join("list");
+
+        fs::write(&list, "a_10JUL2022.pdf\r\n\nb c.pdf\n").unwrap();
+        assert_eq!(
+            read_file_list(&list, b'\n'),
+            Ok(vec![
+                path::PathBuf::from("a_10JUL2022.pdf"),
+                path::PathBuf::from("b c.pdf")
+            ])
+        );
+
+        fs::write(&list, "new\nline_10JUL2022.pdf\0other.pdf\0").unwrap();
+        assert_eq!(
+            read_file_list(&list, b'\0'),
+            Ok(vec![
+                path::PathBuf::from("new\nline_10JUL2022.pdf"),
+                path::PathBuf::from("other.pdf")
+            ])
+        );
+    }
+}
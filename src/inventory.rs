@@ -0,0 +1,299 @@
+//! Counting the files in each year directory of an existing archive, to spot the years that are
+//! missing documents.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path;
+
+use serde::Serialize;
+
+use crate::bucket::Layout;
+use crate::plan::{self, Failure, PlaceError};
+use crate::report;
+use crate::stats;
+
+/// How the inventory is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A table with aligned columns, for people to read.
+    Table,
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// Parses the format from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown report format {:?}", name)),
+        }
+    }
+}
+
+/// The files of a year, or of an extension within a year.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Row {
+    /// The name of the year directory.
+    pub year: String,
+    /// The extension of the files in lower case, or empty for those without one. There is none
+    /// when the files are not broken down by extension.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// What was found in the archive.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    /// A row for each year, or for each extension within each year, in order.
+    pub rows: Vec<Row>,
+    pub failures: Vec<Failure>,
+}
+
+impl Inventory {
+    /// Writes the inventory in the format.
+    pub fn write(&self, format: Format, out: &mut dyn io::Write) -> Result<(), String> {
+        match format {
+            Format::Table => self.write_table(out).map_err(|e| e.to_string()),
+            Format::Csv => self.write_csv(out).map_err(|e| e.to_string()),
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, &self.rows).map_err(|e| e.to_string())?;
+                writeln!(out).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    fn write_table(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let by_extension = self.rows.iter().any(|row| row.extension.is_some());
+        let mut lines = vec![[
+            String::from("YEAR"),
+            String::from("EXTENSION"),
+            String::from("FILES"),
+            String::from("SIZE"),
+        ]];
+        for row in &self.rows {
+            lines.push([
+                row.year.clone(),
+                match row.extension.as_deref() {
+                    Some("") => String::from("(none)"),
+                    extension => extension.unwrap_or_default().to_string(),
+                },
+                row.files.to_string(),
+                stats::size(row.bytes),
+            ]);
+        }
+        lines.push([
+            String::from("Total"),
+            String::new(),
+            self.rows
+                .iter()
+                .map(|row| row.files)
+                .sum::<usize>()
+                .to_string(),
+            stats::size(self.rows.iter().map(|row| row.bytes).sum()),
+        ]);
+        let mut widths = [0; 4];
+        for line in &lines {
+            for (width, cell) in widths.iter_mut().zip(line) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for line in &lines {
+            let mut text = format!("{:<1$}", line[0], widths[0]);
+            if by_extension {
+                text += &format!("  {:<1$}", line[1], widths[1]);
+            }
+            text += &format!(
+                "  {:>files$}  {:>size$}",
+                line[2],
+                line[3],
+                files = widths[2],
+                size = widths[3]
+            );
+            writeln!(out, "{}", text)?;
+        }
+        Ok(())
+    }
+
+    fn write_csv(&self, out: &mut dyn io::Write) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(out);
+        let by_extension = self.rows.iter().any(|row| row.extension.is_some());
+        if by_extension {
+            writer.write_record(["year", "extension", "files", "bytes"])?;
+        } else {
+            writer.write_record(["year", "files", "bytes"])?;
+        }
+        for row in &self.rows {
+            let mut record = vec![row.year.clone()];
+            if let Some(extension) = &row.extension {
+                record.push(extension.clone());
+            }
+            record.push(row.files.to_string());
+            record.push(row.bytes.to_string());
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Counts the files and their size in each of the year directories of the archive, broken down
+/// by extension if asked. Year directories without any files are included, with no files.
+pub fn inventory(
+    root: &path::Path,
+    layout: &Layout,
+    by_extension: bool,
+) -> Result<Inventory, String> {
+    let entries = root
+        .read_dir()
+        .map_err(|e| format!("Could not read directory {}: {}", root.display(), e))?;
+    let mut year_dirs: Vec<(String, path::PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_string();
+            layout.is_year_dir(&name).then_some((name, path))
+        })
+        .collect();
+    year_dirs.sort();
+    if year_dirs.is_empty() {
+        report::note!("There are no year directories in {}", root.display());
+    }
+
+    let mut inventory = Inventory::default();
+    for (year, dir) in year_dirs {
+        let mut counts: BTreeMap<Option<String>, (usize, u64)> = BTreeMap::new();
+        count_dir(&dir, by_extension, &mut counts, &mut inventory.failures);
+        if counts.is_empty() {
+            counts.insert(by_extension.then(String::new), (0, 0));
+        }
+        inventory
+            .rows
+            .extend(counts.into_iter().map(|(extension, (files, bytes))| Row {
+                year: year.clone(),
+                extension,
+                files,
+                bytes,
+            }));
+    }
+    Ok(inventory)
+}
+
+/// Adds the files in the directory and the directories within it to the counts.
+fn count_dir(
+    dir: &path::Path,
+    by_extension: bool,
+    counts: &mut BTreeMap<Option<String>, (usize, u64)>,
+    failures: &mut Vec<Failure>,
+) {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(e) => {
+            let error = PlaceError::io(&format!("Could not read directory {}", dir.display()), e);
+            report::note!("{}", error);
+            failures.push(Failure {
+                path: dir.to_path_buf(),
+                error,
+            });
+            return;
+        }
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            count_dir(&path, by_extension, counts, failures);
+        } else if path.is_file() && !plan::is_own_file(&path) {
+            let size = path.metadata().map_or(0, |metadata| metadata.len());
+            let extension = by_extension.then(|| {
+                path.extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase())
+                    .unwrap_or_default()
+            });
+            let count = counts.entry(extension).or_default();
+            count.0 += 1;
+            count.1 += size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{inventory, Format, Row};
+    use crate::bucket::Layout;
+
+    #[test]
+    fn test_inventory() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("2023FY/Q4")).unwrap();
+        fs::create_dir(root.join("2024FY")).unwrap();
+        fs::create_dir(root.join("Other")).unwrap();
+        fs::write(root.join("2023FY/a_10JUN2023.pdf"), "abc").unwrap();
+        fs::write(root.join("2023FY/Q4/b_10JUN2023.PDF"), "de").unwrap();
+        fs::write(root.join("2023FY/c_10JUN2023"), "f").unwrap();
+        fs::write(root.join("Other/d.pdf"), "g").unwrap();
+
+        let found = inventory(root, &Layout::default(), false).unwrap();
+        assert_eq!(
+            found.rows,
+            vec![
+                Row {
+                    year: String::from("2023FY"),
+                    extension: None,
+                    files: 3,
+                    bytes: 6,
+                },
+                Row {
+                    year: String::from("2024FY"),
+                    extension: None,
+                    files: 0,
+                    bytes: 0,
+                },
+            ]
+        );
+
+        let found = inventory(root, &Layout::default(), true).unwrap();
+        let extensions: Vec<(&str, &str, usize)> = found
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.year.as_str(),
+                    row.extension.as_deref().unwrap(),
+                    row.files,
+                )
+            })
+            .collect();
+        assert_eq!(
+            extensions,
+            [("2023FY", "", 1), ("2023FY", "pdf", 2), ("2024FY", "", 0)]
+        );
+
+        let mut csv = Vec::new();
+        found.write(Format::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "year,extension,files,bytes\n2023FY,,1,1\n2023FY,pdf,2,5\n2024FY,,0,0\n"
+        );
+    }
+
+    #[test]
+    fn test_write_table() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        fs::create_dir(tempdir.path().join("2023FY")).unwrap();
+        fs::write(tempdir.path().join("2023FY/a.pdf"), vec![0; 2048]).unwrap();
+        let found = inventory(tempdir.path(), &Layout::default(), false).unwrap();
+        let mut table = Vec::new();
+        found.write(Format::Table, &mut table).unwrap();
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "YEAR    FILES     SIZE\n2023FY      1  2.0 KiB\nTotal       1  2.0 KiB\n"
+        );
+    }
+}
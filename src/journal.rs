@@ -0,0 +1,430 @@
+//! Keeping track of the changes made while applying a plan, so that they can be rolled back if the
+//! run fails partway, or undone and redone later with `classfy undo` and `classfy redo`.
+
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::manifest;
+use crate::plan::{self, Transfer};
+use crate::report;
+
+/// The name of the file, in classfy's data directory, that the history of runs is kept in.
+pub const HISTORY_NAME: &str = "history.json";
+
+/// The number of runs kept in the history, beyond which the oldest are forgotten.
+const KEPT_RUNS: usize = 20;
+
+/// A change made while placing the files.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+enum Step {
+    /// A directory that did not exist was created.
+    CreatedDir { dir: path::PathBuf },
+    /// A file was moved or copied to its destination.
+    Placed {
+        source: path::PathBuf,
+        destination: path::PathBuf,
+        transfer: Transfer,
+    },
+    /// A link to the file at the target was left at its original location.
+    Linked {
+        link: path::PathBuf,
+        target: path::PathBuf,
+    },
+    /// A manifest was written to, how long it was before, if it existed, and what was taken out of
+    /// it when it was last undone, so that it can be put back.
+    Manifest {
+        path: path::PathBuf,
+        len: Option<u64>,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        added: String,
+    },
+}
+
+/// The changes made in this run, in the order that they were made.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    steps: Vec<Step>,
+}
+
+impl Journal {
+    /// Records that the directory was created.
+    pub fn created_dir(&mut self, dir: &path::Path) {
+        self.steps.push(Step::CreatedDir { dir: absolute(dir) });
+    }
+
+    /// Records that the file was placed at its destination.
+    pub fn placed(&mut self, source: &path::Path, destination: &path::Path, transfer: Transfer) {
+        self.steps.push(Step::Placed {
+            source: absolute(source),
+            destination: absolute(destination),
+            transfer,
+        });
+    }
+
+    /// Records that a link to the target was left at the original location of the file.
+    pub fn linked(&mut self, link: &path::Path, target: &path::Path) {
+        self.steps.push(Step::Linked {
+            link: absolute(link),
+            target: absolute(target),
+        });
+    }
+
+    /// Records how long the manifest is before it is first written to in this run.
+    pub fn manifest(&mut self, path: &path::Path) {
+        let path = absolute(path);
+        let seen = self
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Manifest { path: seen, .. } if *seen == path));
+        if !seen {
+            let len = fs::metadata(&path).map(|metadata| metadata.len()).ok();
+            self.steps.push(Step::Manifest {
+                path,
+                len,
+                added: String::new(),
+            });
+        }
+    }
+
+    /// Checks if nothing was changed.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Undoes the changes, latest first. Moved files are moved back, copies and links are removed,
+    /// and the directories and manifest entries that were added are removed. Returns how many files were
+    /// put back.
+    pub fn roll_back(mut self) -> usize {
+        self.undo_as("roll-back")
+    }
+
+    /// Undoes the changes as `roll_back` does, keeping what is needed to make them again with
+    /// `redo`. Returns how many files were put back.
+    pub fn undo(&mut self) -> usize {
+        self.undo_as("undo")
+    }
+
+    /// Makes the changes again after they were undone, in the order they were first made, without
+    /// looking for the dates of the files again. Returns how many files were placed.
+    pub fn redo(&mut self) -> usize {
+        let mut placed = 0;
+        for step in &mut self.steps {
+            let redone = match step {
+                Step::CreatedDir { dir } => match fs::create_dir(&*dir) {
+                    Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(format!(
+                        "Could not create directory {}: {}",
+                        dir.display(),
+                        e
+                    )),
+                    _ => Ok(()),
+                },
+                Step::Placed {
+                    source,
+                    destination,
+                    transfer,
+                } => redo_placement(source, destination, *transfer).map(|()| placed += 1),
+                Step::Linked { link, target } => plan::leave_symlink(link, target)
+                    .map_err(|e| format!("Could not leave a link at {}: {}", link.display(), e)),
+                Step::Manifest { path, added, .. } => redo_manifest(path, added),
+            };
+            if let Err(e) = redone {
+                report::note!("{}", e);
+            }
+        }
+        placed
+    }
+
+    fn undo_as(&mut self, action: &str) -> usize {
+        let mut restored = 0;
+        for step in self.steps.iter_mut().rev() {
+            let undone = match step {
+                Step::CreatedDir { dir } => fs::remove_dir(&*dir)
+                    .map_err(|e| format!("Could not remove directory {}: {}", dir.display(), e)),
+                Step::Placed {
+                    source,
+                    destination,
+                    transfer,
+                } => undo_placement(source, destination, *transfer, action).map(|()| restored += 1),
+                Step::Linked { link, .. } => fs::remove_file(&*link)
+                    .map_err(|e| format!("Could not remove link {}: {}", link.display(), e)),
+                Step::Manifest { path, len, added } => {
+                    undo_manifest(path, *len).map(|removed| *added = removed)
+                }
+            };
+            if let Err(e) = undone {
+                report::note!("{}", e);
+            }
+        }
+        restored
+    }
+}
+
+/// The runs that can be undone, latest last, and those that were undone and can be redone, kept
+/// between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    done: Vec<Journal>,
+    undone: Vec<Journal>,
+}
+
+impl History {
+    /// Loads the history kept in the file. If there is none, no runs have been recorded.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| format!("Could not parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Saves the history in the file, replacing what is there.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create directory {}: {}", dir.display(), e))?;
+        }
+        let contents = serde_json::to_string(self).expect("history could not be serialised");
+        fs::write(path, contents + "\n")
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Adds the run to those that can be undone. The runs that were undone can no longer be
+    /// redone, as this one may have changed the files they placed.
+    pub fn record(&mut self, journal: Journal) {
+        if journal.is_empty() {
+            return;
+        }
+        self.undone.clear();
+        self.done.push(journal);
+        let forgotten = self.done.len().saturating_sub(KEPT_RUNS);
+        self.done.drain(..forgotten);
+    }
+
+    /// Undoes the last run, returning how many files were put back, if there is a run to undo.
+    pub fn undo(&mut self) -> Option<usize> {
+        let mut journal = self.done.pop()?;
+        let restored = journal.undo();
+        self.undone.push(journal);
+        Some(restored)
+    }
+
+    /// Redoes the last run that was undone, returning how many files were placed again, if there
+    /// is a run to redo.
+    pub fn redo(&mut self) -> Option<usize> {
+        let mut journal = self.undone.pop()?;
+        let placed = journal.redo();
+        self.done.push(journal);
+        Some(placed)
+    }
+}
+
+/// Adds the run to the history kept in the file.
+pub fn record(path: &path::Path, journal: Journal) -> Result<(), String> {
+    if journal.is_empty() {
+        return Ok(());
+    }
+    let mut history = History::load(path)?;
+    history.record(journal);
+    history.save(path)
+}
+
+/// Gets the file that the history of runs is kept in, if there is somewhere to keep it.
+pub fn history_path() -> Option<path::PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("classfy").join(HISTORY_NAME))
+}
+
+/// Gets the path from the root, so that it is still found when undone from another directory.
+fn absolute(path: &path::Path) -> path::PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Checks if there is already something at the path, even a broken link.
+fn is_taken(path: &path::Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+fn undo_placement(
+    source: &path::Path,
+    destination: &path::Path,
+    transfer: Transfer,
+    action: &str,
+) -> Result<(), String> {
+    let undo_error = |e: String| format!("Could not put back {}: {}", source.display(), e);
+    // The checksum is taken first, as copies are removed rather than put back.
+    let sha256 = audit::is_enabled()
+        .then(|| manifest::checksum(destination).ok())
+        .flatten();
+    match transfer {
+        Transfer::Copy | Transfer::Hardlink => {
+            fs::remove_file(destination).map_err(|e| undo_error(e.to_string()))
+        }
+        Transfer::Move if is_taken(source) => Err(undo_error(String::from(
+            "there is another file in its place",
+        ))),
+        Transfer::Move => move_file(destination, source).map_err(undo_error),
+    }?;
+    let restored = (transfer == Transfer::Move).then_some(source);
+    audit::record(action, destination, restored, sha256.as_deref());
+    Ok(())
+}
+
+fn redo_placement(
+    source: &path::Path,
+    destination: &path::Path,
+    transfer: Transfer,
+) -> Result<(), String> {
+    let redo_error = |e: String| format!("Could not place {} again: {}", source.display(), e);
+    if is_taken(destination) {
+        return Err(redo_error(format!(
+            "{} is taken by another file",
+            destination.display()
+        )));
+    }
+    match transfer {
+        Transfer::Move => move_file(source, destination),
+        Transfer::Copy => plan::copy_verified(source, destination)
+            .map(|_| ())
+            .map_err(|e| e.message),
+        Transfer::Hardlink => fs::hard_link(source, destination).map_err(|e| e.to_string()),
+    }
+    .map_err(redo_error)?;
+    audit::record("redo", source, Some(destination), None);
+    Ok(())
+}
+
+/// Moves the file, copying it and removing the original where it is on another device.
+fn move_file(from: &path::Path, to: &path::Path) -> Result<(), String> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            plan::copy_verified(from, to).map_err(|e| e.message)?;
+            fs::remove_file(from).map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Takes what was added to the manifest back out of it, getting what was taken out.
+fn undo_manifest(path: &path::Path, len: Option<u64>) -> Result<String, String> {
+    let undo_error = |e: io::Error| format!("Could not restore {}: {}", path.display(), e);
+    let mut manifest = fs::File::options()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(undo_error)?;
+    manifest
+        .seek(io::SeekFrom::Start(len.unwrap_or(0)))
+        .map_err(undo_error)?;
+    let mut added = String::new();
+    manifest.read_to_string(&mut added).map_err(undo_error)?;
+    match len {
+        Some(len) => manifest.set_len(len),
+        None => fs::remove_file(path),
+    }
+    .map_err(undo_error)?;
+    Ok(added)
+}
+
+/// Puts what was taken out of the manifest back at its end.
+fn redo_manifest(path: &path::Path, added: &str) -> Result<(), String> {
+    fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut manifest| manifest.write_all(added.as_bytes()))
+        .map_err(|e| format!("Could not write to {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{History, Journal};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+    use crate::exclude::IGNORE_FILE;
+    use crate::manifest::MANIFEST_NAME;
+    use crate::plan::{plan_dir, Plan, Settings, Transfer};
+
+    #[test]
+    fn test_roll_back() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        let manifest = base.join("manifest");
+        fs::write(&manifest, "before\n").unwrap();
+
+        let mut journal = Journal::default();
+        fs::create_dir(base.join("2023FY")).unwrap();
+        journal.created_dir(&base.join("2023FY"));
+        fs::write(base.join("2023FY").join("moved.txt"), "").unwrap();
+        journal.placed(
+            &base.join("moved.txt"),
+            &base.join("2023FY").join("moved.txt"),
+            Transfer::Move,
+        );
+        journal.manifest(&manifest);
+        fs::write(&manifest, "before\nafter\n").unwrap();
+        journal.manifest(&manifest);
+
+        assert_eq!(journal.roll_back(), 1);
+        assert!(base.join("moved.txt").is_file());
+        assert!(!base.join("2023FY").exists());
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), "before\n");
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path().join("docs");
+        fs::create_dir(&base).unwrap();
+        fs::write(base.join("a_10JUL2022.txt"), "a").unwrap();
+        let history = tempdir.path().join("history.json");
+
+        let (candidates, _) =
+            plan_dir(&base, &Layout::default(), &Extractor::default(), false, &[]).unwrap();
+        let outcome = Plan::new(&candidates).apply(&Settings {
+            history: Some(history.clone()),
+            ..Settings::default()
+        });
+        assert_eq!(outcome.placed, 1);
+        let placed = base.join("2023FY").join("a_10JUL2022.txt");
+        let manifest = fs::read_to_string(base.join("2023FY").join(MANIFEST_NAME)).unwrap();
+
+        let mut runs = History::load(&history).unwrap();
+        assert_eq!(runs.redo(), None);
+        assert_eq!(runs.undo(), Some(1));
+        runs.save(&history).unwrap();
+        assert!(base.join("a_10JUL2022.txt").is_file());
+        assert!(!base.join("2023FY").exists());
+
+        // The file would be left out if the directory were looked at again, which redoing the run
+        // does not do.
+        fs::write(base.join(IGNORE_FILE), "*.txt\n").unwrap();
+        let mut runs = History::load(&history).unwrap();
+        assert_eq!(runs.redo(), Some(1));
+        runs.save(&history).unwrap();
+        assert!(!base.join("a_10JUL2022.txt").exists());
+        assert_eq!(fs::read_to_string(&placed).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(base.join("2023FY").join(MANIFEST_NAME)).unwrap(),
+            manifest
+        );
+
+        let mut runs = History::load(&history).unwrap();
+        assert_eq!(runs.undo(), Some(1));
+        assert_eq!(runs.undo(), None);
+        assert!(base.join("a_10JUL2022.txt").is_file());
+
+        // A new run means the undone one can no longer be redone.
+        let mut journal = Journal::default();
+        journal.created_dir(&base.join("other"));
+        runs.record(journal);
+        assert_eq!(runs.redo(), None);
+    }
+}
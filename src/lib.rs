@@ -0,0 +1,59 @@
+//! Classifies files into directories by the financial year of the dates in their names.
+//!
+//! The `classfy` binary is built on this library, which can also be used to find the dates of
+//! files and where they belong from other programs.
+
+pub mod archive;
+pub mod audit;
+pub mod bucket;
+pub mod config;
+pub mod content;
+pub mod date;
+pub mod dedup;
+pub mod desktop;
+pub mod diff;
+#[cfg(feature = "dropbox")]
+pub mod dropbox;
+pub mod exclude;
+pub mod extract;
+pub mod fiscal;
+pub mod flatten;
+#[cfg(feature = "gdrive")]
+pub mod gdrive;
+pub mod input;
+pub mod inventory;
+pub mod journal;
+pub mod manifest;
+pub mod merge;
+pub mod migrate;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+pub mod overrides;
+pub mod pack;
+pub mod plan;
+#[cfg(feature = "plugin")]
+pub mod plugin;
+pub mod reclassify;
+pub mod remote;
+pub mod report;
+pub mod rules;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod schedule;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod sink;
+pub mod state;
+pub mod stats;
+pub mod syslog;
+pub mod tag;
+pub mod template;
+pub mod unicode;
+pub mod verify;
+pub mod walk;
+#[cfg(feature = "webdav")]
+pub mod webdav;
+pub mod webhook;
+pub mod winpath;
@@ -1,160 +1,857 @@
-use std::env;
-use std::fs;
+use std::io;
 use std::path;
+use std::process;
+#[cfg(any(feature = "script", feature = "plugin"))]
+use std::sync;
+use std::time;
+
+#[cfg(feature = "plugin")]
+use classfy::plugin;
+#[cfg(feature = "script")]
+use classfy::script;
+use classfy::{
+    archive, audit, bucket, config, date, desktop, diff, exclude, flatten, inventory, journal,
+    merge, migrate, overrides, pack, plan, reclassify, remote, report, rules, sink, stats, tag,
+    verify, webhook,
+};
+
+mod cli;
+#[cfg(all(unix, feature = "daemon"))]
+mod daemon;
+mod doctor;
+#[cfg(feature = "tui")]
+mod tui;
+mod watch;
+
+/// Settings that control where the classified files are placed.
+#[derive(Default)]
+struct Options {
+    /// Describes the directories that the files are placed in.
+    layout: bucket::Layout,
+    /// Describes how the dates are found in the file names.
+    extractor: date::Extractor,
+    /// Describes how the files are placed.
+    settings: plan::Settings,
+    /// The settings from the configuration file.
+    config: config::Config,
+}
 
-fn main() {
-    let args: env::Args = env::args();
-    if args.len() > 1 {
-        for arg in args.skip(1) {
-            classify_files_in(path::Path::new(&arg));
+impl Options {
+    /// Gets the settings from the command line and the configuration file.
+    fn new(matches: &clap::ArgMatches) -> Result<Self, String> {
+        let profile = matches.get_one::<String>("profile");
+        Options::with_profile(matches, profile.map(String::as_str))
+    }
+
+    /// Gets the settings from the command line and the configuration file, with the settings of
+    /// the profile if one is given.
+    fn with_profile(matches: &clap::ArgMatches, profile: Option<&str>) -> Result<Self, String> {
+        let config = load_config(matches, profile)?;
+        let mut extractor = cli::extractor(matches, &config);
+        if let Some(script) = matches.get_one::<path::PathBuf>("script") {
+            load_script(&mut extractor, script)?;
         }
-    } else {
-        classify_files_in(path::Path::new("."));
+        for plugin in matches
+            .get_many::<path::PathBuf>("plugin")
+            .unwrap_or_default()
+        {
+            load_plugin(&mut extractor, plugin)?;
+        }
+        Ok(Options {
+            layout: cli::layout(matches, &config),
+            extractor,
+            settings: cli::settings(matches),
+            config,
+        })
     }
 }
 
-/// Classify the files by financial year in the given directory.
-fn classify_files_in(path: &path::Path) {
-    assert!(
-        path.try_exists().expect("directory does not exist"),
-        "{:?} does not exist",
-        path
+/// Loads the configuration file given with --config, or the default one, with the settings of the
+/// profile if one is given. The changes made from then on are recorded in its audit log.
+fn load_config(
+    matches: &clap::ArgMatches,
+    profile: Option<&str>,
+) -> Result<config::Config, String> {
+    let config_path = matches.get_one::<path::PathBuf>("config");
+    let config = config::Config::load(config_path.map(path::PathBuf::as_path), profile)?;
+    audit::set_log(config.audit_log.clone());
+    Ok(config)
+}
+
+/// Loads the script given with --script, which is then used to find the dates of the files.
+#[cfg(feature = "script")]
+fn load_script(extractor: &mut date::Extractor, path: &path::Path) -> Result<(), String> {
+    extractor.script = Some(sync::Arc::new(script::Script::load(path)?));
+    Ok(())
+}
+
+#[cfg(not(feature = "script"))]
+fn load_script(_extractor: &mut date::Extractor, _path: &path::Path) -> Result<(), String> {
+    Err(String::from("classfy was built without the script feature"))
+}
+
+/// Loads a plugin given with --plugin, which is then asked for the dates of the files.
+#[cfg(feature = "plugin")]
+fn load_plugin(extractor: &mut date::Extractor, path: &path::Path) -> Result<(), String> {
+    extractor
+        .plugins
+        .push(sync::Arc::new(plugin::Plugin::load(path)?));
+    Ok(())
+}
+
+#[cfg(not(feature = "plugin"))]
+fn load_plugin(_extractor: &mut date::Extractor, _path: &path::Path) -> Result<(), String> {
+    Err(String::from("classfy was built without the plugin feature"))
+}
+
+/// Exit code when some of the files could not be classified and were left in place.
+const EXIT_UNCLASSIFIED: i32 = 1;
+/// Exit code when files or directories could not be read or written.
+const EXIT_IO_ERROR: i32 = 2;
+/// Exit code when the command line or configuration is invalid.
+const EXIT_USAGE: i32 = 3;
+/// Exit code when `verify` finds files that are not in the directory their date belongs in.
+const EXIT_MISPLACED: i32 = 4;
+
+fn main() {
+    // Answers the shell when the script printed by `classfy completions` asks for the completions.
+    clap_complete::CompleteEnv::with_factory(cli::command).complete();
+    let matches = match cli::command().try_get_matches() {
+        Ok(matches) => matches,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            process::exit(EXIT_USAGE);
+        }
+        Err(e) => e.exit(),
+    };
+    if matches.get_flag("print0") {
+        report::set_print0();
+    }
+    report::set_output_format(
+        *matches
+            .get_one::<report::OutputFormat>("output-format")
+            .expect("output format has a default"),
     );
-    assert!(path.is_dir(), "{:?} not a directory", path);
-
-    for entry in path.read_dir().expect("could not read directory") {
-        if let Ok(entry) = entry {
-            let entry_path = entry.path();
-            if entry_path.is_file() {
-                match get_fy(&entry_path) {
-                    Ok(fy) => place(&entry_path, fy),
-                    Err(e) => println!(
-                        "Could not get FY for {}. Leaving in place: {}",
-                        entry.path().display(),
-                        e
-                    ),
+    report::set_color(
+        *matches
+            .get_one::<report::ColorChoice>("color")
+            .expect("color has a default"),
+    );
+    if matches.get_flag("verbose") {
+        report::set_verbose();
+    }
+    if let Some(target) = matches.get_one::<report::LogTarget>("log-target") {
+        if let Err(e) = report::set_log_target(*target) {
+            process::exit(fail(EXIT_USAGE, &e));
+        }
+    }
+    process::exit(run(&matches));
+}
+
+/// Do what was asked on the command line, returning the exit code.
+fn run(matches: &clap::ArgMatches) -> i32 {
+    let started = time::Instant::now();
+    match matches.subcommand() {
+        Some(("completions", sub_matches)) => {
+            let shell = sub_matches
+                .get_one::<clap_complete::Shell>("shell")
+                .expect("shell is required");
+            let shells = clap_complete::env::Shells::builtins();
+            let completer = shells
+                .completer(&shell.to_string())
+                .expect("every shell can be completed");
+            match completer.write_registration(
+                "COMPLETE",
+                "classfy",
+                "classfy",
+                "classfy",
+                &mut io::stdout(),
+            ) {
+                Ok(()) => 0,
+                Err(e) => fail(
+                    EXIT_IO_ERROR,
+                    &format!("Could not write the completion script: {}", e),
+                ),
+            }
+        }
+        Some(("plan", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let output = sub_matches
+                .get_one::<path::PathBuf>("output")
+                .expect("output is required");
+            match cli::input(sub_matches).candidates(&options.layout, &options.extractor, true) {
+                Ok((candidates, failures)) => {
+                    save_plan(&candidates, failures, output, sub_matches.get_flag("diff"))
+                }
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        Some(("apply", sub_matches)) => {
+            let plan = sub_matches
+                .get_one::<path::PathBuf>("plan")
+                .expect("plan is required");
+            let profile = sub_matches.get_one::<String>("profile");
+            let config = match load_config(sub_matches, profile.map(String::as_str)) {
+                Ok(config) => config,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            match plan::Plan::load(plan) {
+                Ok(plan) => {
+                    report::planned(&plan);
+                    let outcome = plan.apply(&cli::settings(sub_matches));
+                    finish(&outcome, &plan, &config, sub_matches, started)
                 }
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        Some(("tui", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            match cli::input(sub_matches).candidates(&options.layout, &options.extractor, false) {
+                Ok((candidates, failures)) => {
+                    review(candidates, failures, &options, sub_matches, started)
+                }
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        Some(("run", sub_matches)) => watch::run(matches, sub_matches),
+        Some(("daemon", sub_matches)) => daemon(matches, sub_matches),
+        Some(("report", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            let format = sub_matches
+                .get_one::<inventory::Format>("format")
+                .expect("format has a default");
+            match inventory::inventory(root, &options.layout, sub_matches.get_flag("by-extension"))
+            {
+                Ok(inventory) => write_inventory(&inventory, *format),
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        Some(("reclassify", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            let audit = match verify::verify(root, &options.layout, &options.extractor) {
+                Ok(audit) => audit,
+                Err(e) => return fail(EXIT_IO_ERROR, &e),
+            };
+            let plan = reclassify::plan(root, &audit);
+            report::planned(&plan);
+            if !sub_matches.get_flag("apply") {
+                let code = audit_exit_code(&audit);
+                if !plan.moves.is_empty() {
+                    report::note!("Nothing was moved, run again with --apply to move them");
+                }
+                return code;
+            }
+            let mut outcome = reclassify::apply(root, &plan, &options.settings);
+            outcome.failures.extend(audit.failures);
+            finish(&outcome, &plan, &options.config, sub_matches, started)
+        }
+        Some(("flatten", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            match flatten::plan(root, &options.layout) {
+                Ok((plan, failures)) => {
+                    report::planned(&plan);
+                    let mut outcome =
+                        flatten::apply(root, &options.layout, &plan, &options.settings);
+                    outcome.failures.extend(failures);
+                    finish(&outcome, &plan, &options.config, sub_matches, started)
+                }
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        Some(("merge-dirs", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            match merge::plan(root, &options.layout) {
+                Ok((plan, failures)) => {
+                    report::planned(&plan);
+                    let mut outcome = merge::apply(root, &options.layout, &plan, &options.settings);
+                    outcome.failures.extend(failures);
+                    finish(&outcome, &plan, &options.config, sub_matches, started)
+                }
+                Err(e) => fail(EXIT_USAGE, &e),
+            }
+        }
+        Some(("migrate-layout", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            let renames = match migrate::plan(root, &options.layout) {
+                Ok(renames) => renames,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            if let Err(e) = migrate::apply(&renames) {
+                return fail(EXIT_IO_ERROR, &e);
+            }
+            report::note!("Renamed {} year directories", renames.len());
+            if !sub_matches.get_flag("nested") {
+                return 0;
+            }
+            let audit = match verify::verify(root, &options.layout, &options.extractor) {
+                Ok(audit) => audit,
+                Err(e) => return fail(EXIT_IO_ERROR, &e),
+            };
+            let plan = reclassify::plan(root, &audit);
+            report::planned(&plan);
+            let mut outcome = reclassify::apply(root, &plan, &options.settings);
+            migrate::remove_empty_levels(root, &options.layout);
+            outcome.failures.extend(audit.failures);
+            finish(&outcome, &plan, &options.config, sub_matches, started)
+        }
+        Some(("doctor", sub_matches)) => doctor::run(sub_matches),
+        Some(("explain", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let files: Vec<&path::PathBuf> = sub_matches
+                .get_many::<path::PathBuf>("files")
+                .expect("files are required")
+                .collect();
+            explain(&files, &options)
+        }
+        Some(("override", sub_matches)) => {
+            let files: Vec<&path::PathBuf> = sub_matches
+                .get_many::<path::PathBuf>("files")
+                .expect("files are required")
+                .collect();
+            let date = match sub_matches.get_one::<String>("date") {
+                Some(date) => match date.parse::<date::FileDate>() {
+                    Ok(date) => Some(date),
+                    Err(e) => return fail(EXIT_USAGE, &e),
+                },
+                None => None,
+            };
+            override_dates(&files, date)
+        }
+        Some(("test-rules", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let mut names: Vec<String> = sub_matches
+                .get_many::<String>("names")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            if names.is_empty() {
+                match io::stdin().lines().collect::<Result<Vec<_>, _>>() {
+                    Ok(lines) => names = lines,
+                    Err(e) => {
+                        return fail(
+                            EXIT_IO_ERROR,
+                            &format!("Could not read the names from standard input: {}", e),
+                        )
+                    }
+                }
+            }
+            test_rules(&names, &options)
+        }
+        Some(("query", sub_matches)) => {
+            let mut dirs: Vec<path::PathBuf> = sub_matches
+                .get_many::<path::PathBuf>("dirs")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            if dirs.is_empty() {
+                dirs.push(path::PathBuf::from("."));
+            }
+            query(&dirs, sub_matches.get_one::<String>("fy"))
+        }
+        Some(("undo", _)) => revisit(
+            journal::History::undo,
+            "undo",
+            "Undid the last run, putting back",
+        ),
+        Some(("redo", _)) => revisit(
+            journal::History::redo,
+            "redo",
+            "Redid the last run that was undone, placing",
+        ),
+        Some(("verify", sub_matches)) => {
+            let options = match Options::new(sub_matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            let root = sub_matches
+                .get_one::<path::PathBuf>("root")
+                .expect("root is required");
+            match verify::verify(root, &options.layout, &options.extractor) {
+                Ok(audit) => audit_exit_code(&audit),
+                Err(e) => fail(EXIT_IO_ERROR, &e),
+            }
+        }
+        _ => {
+            let options = match Options::new(matches) {
+                Ok(options) => options,
+                Err(e) => return fail(EXIT_USAGE, &e),
+            };
+            match cli::input(matches).candidates(&options.layout, &options.extractor, false) {
+                Ok((candidates, failures)) => {
+                    classify(candidates, failures, &options, matches, started)
+                }
+                Err(e) => fail(EXIT_IO_ERROR, &e),
             }
         }
     }
 }
 
-fn place(path: &path::Path, fy: u16) {
-    println!("Placing {} in {}", path.display(), fy);
-
-    let base_dir = path.parent().expect("file has no parent");
-    let file_name = path.file_name().expect("file does not have a name");
-    let dest_dir = base_dir.join(format!("{}FY", fy));
+/// Report the error and return the exit code.
+fn fail(code: i32, message: &str) -> i32 {
+    eprintln!("{}", message);
+    code
+}
 
-    if !dest_dir.exists() {
-        println!("directory {:?} doesn't exit, creating it", &dest_dir);
-        fs::create_dir(&dest_dir).expect("could not create directory");
+/// Summarise the outcome of applying a plan, and get the exit code that describes it.
+fn exit_code(outcome: &plan::Outcome) -> i32 {
+    report::summarise(outcome);
+    if !outcome.failures.is_empty() {
+        EXIT_IO_ERROR
+    } else if outcome.unclassified > 0 {
+        EXIT_UNCLASSIFIED
+    } else {
+        0
     }
+}
 
-    if !dest_dir.is_dir() {
-        println!("{:?} is not a directory?", &dest_dir);
+/// Summarise the outcome of a run that started at the given time with its statistics, save them
+/// with --stats-file, post them to the webhook in the configuration if there is one and show them
+/// as a notification with --notify, and get the exit code that describes the run.
+fn finish(
+    outcome: &plan::Outcome,
+    plan: &plan::Plan,
+    config: &config::Config,
+    matches: &clap::ArgMatches,
+    started: time::Instant,
+) -> i32 {
+    let code = exit_code(outcome);
+    let stats = stats::Stats::new(outcome, plan, started.elapsed());
+    report::note!("{}", stats);
+    report::event(&report::Event::Done { stats: &stats });
+    if let Some(path) = matches.get_one::<path::PathBuf>("stats-file") {
+        if let Err(e) = stats.save(path) {
+            report::note!("{}", e);
+        }
+    }
+    if let Some(url) = &config.webhook {
+        let summary = webhook::Summary::new(outcome, &plan.unclassified, &stats);
+        if let Err(e) = webhook::send(url, &summary) {
+            report::note!("Could not send the summary to the webhook: {}", e);
+        }
+    }
+    if matches.get_flag("notify") {
+        if let Err(e) = desktop::notify(&desktop::message(outcome, plan)) {
+            report::note!("Could not show a notification: {}", e);
+        }
     }
-    assert!(dest_dir.is_dir(), "{:?} is not a directory", &dest_dir);
+    code
+}
 
-    let dest = dest_dir.join(file_name);
-    assert!(!dest.exists(), "{:?} already exists", dest);
+/// Summarise what was found by `verify`, and get the exit code that describes it.
+fn audit_exit_code(audit: &verify::Audit) -> i32 {
+    report::note!(
+        "{} files are in the right directory, {} are not",
+        audit.correct,
+        audit.misplaced.len()
+    );
+    if audit.unchecked > 0 {
+        report::note!(
+            "{} files could not be checked as their date could not be found",
+            audit.unchecked
+        );
+    }
+    report::summarise_failures(&audit.failures);
+    if !audit.failures.is_empty() {
+        EXIT_IO_ERROR
+    } else if !audit.misplaced.is_empty() {
+        EXIT_MISPLACED
+    } else {
+        0
+    }
+}
 
-    fs::rename(&path, &dest).expect("could not move file");
+/// Write the inventory of an archive to the standard output, and get the exit code that describes
+/// it.
+fn write_inventory(inventory: &inventory::Inventory, format: inventory::Format) -> i32 {
+    if let Err(e) = inventory.write(format, &mut io::stdout().lock()) {
+        return fail(EXIT_IO_ERROR, &format!("Could not write the report: {}", e));
+    }
+    report::summarise_failures(&inventory.failures);
+    if inventory.failures.is_empty() {
+        0
+    } else {
+        EXIT_IO_ERROR
+    }
 }
 
-/// Extract the financial year from the file name.
-fn get_fy(file_path: &path::Path) -> Result<u16, String> {
-    if !file_path.is_file() {
-        return Err(String::from("Not a file"));
+/// Write each of the ways that the date of each file is looked for and what it found, followed by
+/// where the file is placed, and get the exit code that describes them.
+fn explain(files: &[&path::PathBuf], options: &Options) -> i32 {
+    let mut unclassified = 0;
+    for file in files {
+        report::note!("{}", file.display());
+        for step in options.extractor.explain(file) {
+            let used = if step.used { " (used)" } else { "" };
+            match step.result {
+                Ok(date) => report::note!("  {}: {}{}", step.source, date, used),
+                Err(e) => report::note!("  {}: {}{}", step.source, e, used),
+            }
+        }
+        let candidate = plan::plan_file(file, &options.layout, &options.extractor, true, &[]);
+        match candidate
+            .as_ref()
+            .map(|candidate| (candidate.to_move(), &candidate.bucket))
+        {
+            Some((Some(file_move), _)) => {
+                report::note!("  Placed in {}", file_move.destination.display())
+            }
+            Some((None, Err(reason))) => {
+                unclassified += 1;
+                report::note!("  Left in place: {}", reason)
+            }
+            Some((None, Ok(_))) | None => {
+                unclassified += 1;
+                report::note!(
+                    "  Skipped, as it is one of classfy's own files or is ignored by {}",
+                    exclude::IGNORE_FILE
+                )
+            }
+        }
+    }
+    if unclassified > 0 {
+        EXIT_UNCLASSIFIED
+    } else {
+        0
     }
+}
 
-    let file_name = file_path.file_stem();
-    if file_name.is_none() {
-        return Err(String::from("No file name"));
+/// Write which rule applies to each of the file names, the date found and the directory it is
+/// placed in, and get the exit code that describes them.
+fn test_rules(names: &[String], options: &Options) -> i32 {
+    let rules = &options.extractor.rules;
+    let mut unclassified = 0;
+    for name in names.iter().map(|name| name.trim()) {
+        if name.is_empty() {
+            continue;
+        }
+        let trial = rules::trial(name, &options.layout, &options.extractor);
+        report::note!("{}", name);
+        match trial.rule {
+            Some(index) => report::note!("  rule:   {} ({})", index + 1, rules[index].pattern()),
+            None => report::note!("  rule:   none, the date is looked for in the name"),
+        }
+        match &trial.date {
+            Ok(date) => report::note!("  date:   {}", date),
+            Err(e) => report::note!("  date:   none ({})", e),
+        }
+        match &trial.bucket {
+            Ok(bucket) => report::note!("  bucket: {}", bucket.display()),
+            Err(e) if trial.date.is_ok() => report::note!("  bucket: none ({})", e),
+            Err(_) => {}
+        }
+        if trial.bucket.is_err() {
+            unclassified += 1;
+        }
     }
+    if unclassified > 0 {
+        EXIT_UNCLASSIFIED
+    } else {
+        0
+    }
+}
 
-    let name_string = file_name
-        .unwrap()
-        .to_os_string()
-        .into_string()
-        .expect("could convert to string");
-    println!("Processing file name: {:?}", file_path.file_name().unwrap());
+/// Lists the files in the directories that were tagged with their year, or only the paths of those
+/// tagged with the year if one is given.
+fn query(dirs: &[path::PathBuf], year: Option<&String>) -> i32 {
+    let mut code = 0;
+    for dir in dirs {
+        let tagged = match tag::query(dir) {
+            Ok(tagged) => tagged,
+            Err(e) => {
+                code = fail(EXIT_IO_ERROR, &e.to_string());
+                continue;
+            }
+        };
+        for tagged in tagged {
+            match year {
+                Some(year) if *year == tagged.year => {
+                    report::note!("{}", tagged.path.display())
+                }
+                Some(_) => {}
+                None => report::note!("{}\t{}", tagged.year, tagged.path.display()),
+            }
+        }
+    }
+    code
+}
 
-    let candidate = name_string.split_terminator('_').last();
-    if candidate.is_none() {
-        return Err(String::from("Incorrect file name format"));
+/// Give the files the date, which is kept in the overrides of the directory they are in, or forget
+/// the dates given to them if there is none.
+fn override_dates(files: &[&path::PathBuf], date: Option<date::FileDate>) -> i32 {
+    let mut code = 0;
+    for file in files {
+        let base = file
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(path::Path::new("."));
+        let result = match date {
+            Some(date) => overrides::remember(file, base, date)
+                .map(|()| format!("{} will be dated {}", file.display(), date)),
+            None => overrides::forget(file).map(|forgotten| {
+                if forgotten {
+                    format!("Forgot the date given to {}", file.display())
+                } else {
+                    format!("{} had not been given a date", file.display())
+                }
+            }),
+        };
+        match result {
+            Ok(message) => report::note!("{}", message),
+            Err(e) => code = fail(EXIT_IO_ERROR, &e),
+        }
     }
+    code
+}
 
-    let candidate_name = candidate.unwrap();
+/// Undo the last run or redo the last one undone with `step`, as named by `action`, keeping the
+/// history of runs, and say what was `done` with how many files. Gets the exit code that describes
+/// it.
+fn revisit(step: fn(&mut journal::History) -> Option<usize>, action: &str, done: &str) -> i32 {
+    let Some(path) = journal::history_path() else {
+        return fail(
+            EXIT_USAGE,
+            "There is no data directory to keep the history of runs in",
+        );
+    };
+    let mut history = match journal::History::load(&path) {
+        Ok(history) => history,
+        Err(e) => return fail(EXIT_IO_ERROR, &e),
+    };
+    let Some(files) = step(&mut history) else {
+        report::note!("There is no run to {}", action);
+        return 0;
+    };
+    if let Err(e) = history.save(&path) {
+        return fail(EXIT_IO_ERROR, &e);
+    }
+    report::note!("{} {} files", done, files);
+    0
+}
 
-    match candidate_name.len() {
-        6 => get_fy_fy_year_only(&candidate_name),
-        7 => process_month_and_year(&candidate_name),
-        9 => get_fy_full_date(&candidate_name),
-        _ => Err(String::from("File name does not end with date")),
+/// Place the files, and summarise what was done since the run started.
+fn classify(
+    candidates: Vec<plan::Candidate>,
+    failures: Vec<plan::Failure>,
+    options: &Options,
+    matches: &clap::ArgMatches,
+    started: time::Instant,
+) -> i32 {
+    match place(candidates, failures, options, matches) {
+        Ok((plan, outcome)) => finish(&outcome, &plan, &options.config, matches, started),
+        Err(e) => fail(EXIT_USAGE, &e),
     }
 }
 
-/// Get the financial year for dates with just a year and the "FY" suffix. For example "2022FY".
-fn get_fy_fy_year_only(date: &str) -> Result<u16, String> {
-    if !date[4..6].eq("FY") {
-        return Err(String::from(format!("Date is not an FY: {}", date)));
+/// Move the files into their buckets, add them to an archive for each bucket with --archive, or
+/// upload them to the buckets at the destination with --dest. With --expand-archives, the files
+/// in zip archives are extracted into their own buckets instead of the archive being moved. With
+/// --dry-run, where they would go is only listed. Gets the plan that was followed and what
+/// happened.
+fn place(
+    candidates: Vec<plan::Candidate>,
+    failures: Vec<plan::Failure>,
+    options: &Options,
+    matches: &clap::ArgMatches,
+) -> Result<(plan::Plan, plan::Outcome), String> {
+    let archive = matches.get_one::<pack::Format>("archive");
+    let dest = matches.get_one::<remote::Destination>("dest");
+    if options.settings.mode == plan::Mode::Tag && (archive.is_some() || dest.is_some()) {
+        return Err(String::from(
+            "--mode tag leaves the files where they are, so it cannot be used with --archive or \
+             --dest",
+        ));
+    }
+    let (archives, candidates) = if matches.get_flag("expand-archives") {
+        candidates
+            .into_iter()
+            .partition(|candidate| archive::is_archive(&candidate.path))
+    } else {
+        (Vec::new(), candidates)
+    };
+    let plan = plan::Plan::new(&candidates);
+    report::planned(&plan);
+    let mut outcome = plan::Outcome {
+        failures,
+        ..plan::Outcome::default()
+    };
+    if matches.get_flag("dry-run") {
+        list_plan(&plan);
+        report::note!("Nothing was moved, as this is a dry run");
+        outcome.unclassified = plan.unclassified.len();
+        return Ok((plan, outcome));
     }
-    match date[0..4].parse::<u16>() {
-        Ok(year) => return Ok(year),
-        Err(e) => Err(format!(
-            "Could not parse year {:?}: {}",
-            date,
-            e.to_string()
-        )),
+    outcome += match (archive, dest) {
+        (Some(format), _) => pack::pack(&candidates, *format),
+        (None, Some(destination)) => {
+            let mut sink = remote::sink(destination, &options.settings, &options.config)?;
+            plan.apply_to(&options.settings, sink.as_mut())
+        }
+        (None, None) => plan.apply(&options.settings),
+    };
+    for candidate in &archives {
+        outcome += archive::expand(
+            &candidate.path,
+            &candidate.base,
+            &options.layout,
+            &options.extractor,
+            matches.get_flag("delete-archives"),
+        );
     }
+    Ok((plan, outcome))
 }
 
-/// Get the financial year from a full date (whose format is DDMMMYYYY).
-fn get_fy_full_date(date: &str) -> Result<u16, String> {
-    let day_str = &date[0..2];
-    match date[0..2].parse::<u8>() {
-        Ok(_) => process_month_and_year(&date[2..9]),
-        Err(e) => Err(format!(
-            "Could not parse day of month {:?}: {}",
-            day_str,
-            e.to_string()
-        )),
+/// Let the user review and adjust where the files will be placed before moving them.
+#[cfg(feature = "tui")]
+fn review(
+    candidates: Vec<plan::Candidate>,
+    failures: Vec<plan::Failure>,
+    options: &Options,
+    matches: &clap::ArgMatches,
+    started: time::Instant,
+) -> i32 {
+    match tui::run(
+        candidates,
+        &options.layout,
+        &options.extractor,
+        &options.settings,
+    ) {
+        Ok((plan, outcome)) => finish(
+            &plan::Outcome {
+                failures: [failures, outcome.failures].concat(),
+                ..outcome
+            },
+            &plan,
+            &options.config,
+            matches,
+            started,
+        ),
+        Err(e) => fail(
+            EXIT_IO_ERROR,
+            &format!("Could not run the interface: {}", e),
+        ),
     }
 }
 
-/// Get the financial year from a date with just month and year.
-fn process_month_and_year(date: &str) -> Result<u16, String> {
-    let offset = get_month_offset(&date[0..3])?;
-    let date_str = &date[3..7];
-    match date_str.parse::<u16>() {
-        Ok(year) => return Ok(year + offset as u16),
-        Err(e) => Err(format!(
-            "Could not parse year {:?}: {}",
-            date_str,
-            e.to_string()
-        )),
+#[cfg(not(feature = "tui"))]
+fn review(
+    _candidates: Vec<plan::Candidate>,
+    _failures: Vec<plan::Failure>,
+    _options: &Options,
+    _matches: &clap::ArgMatches,
+    _started: time::Instant,
+) -> i32 {
+    fail(EXIT_USAGE, "classfy was built without the tui feature")
+}
+
+/// Keep classifying the files that arrive in the directories listed in the configuration.
+#[cfg(all(unix, feature = "daemon"))]
+fn daemon(matches: &clap::ArgMatches, sub_matches: &clap::ArgMatches) -> i32 {
+    daemon::run(matches, sub_matches)
+}
+
+#[cfg(not(all(unix, feature = "daemon")))]
+fn daemon(_matches: &clap::ArgMatches, _sub_matches: &clap::ArgMatches) -> i32 {
+    fail(
+        EXIT_USAGE,
+        "classfy was built without the daemon feature, which needs a Unix system",
+    )
+}
+
+/// Report where each of the files in the plan is to be placed, or why it is left in place.
+fn list_plan(plan: &plan::Plan) {
+    for file_move in &plan.moves {
+        let bucket = sink::Bucket::of_move(file_move);
+        report::action(&file_move.source, bucket.top().as_deref(), "planned", None);
+    }
+    for unclassified in &plan.unclassified {
+        report::action(
+            &unclassified.path,
+            None,
+            "unclassified",
+            Some(&unclassified.reason),
+        );
     }
 }
 
-/// Gets the offset for each month. The offset (0 for January to June and 1 for July to December)
-/// should be added to the current year to get the corresponding financial year. The month is
-/// expected to be the first three characters of their name, capitalised.
-fn get_month_offset(month: &str) -> Result<i8, String> {
-    match month {
-        "JAN" => Ok(0),
-        "FEB" => Ok(0),
-        "MAR" => Ok(0),
-        "APR" => Ok(0),
-        "MAY" => Ok(0),
-        "JUN" => Ok(0),
-        "JUL" => Ok(1),
-        "AUG" => Ok(1),
-        "SEP" => Ok(1),
-        "OCT" => Ok(1),
-        "NOV" => Ok(1),
-        "DEC" => Ok(1),
-        _ => Err(format!("Month {:?} not recognised", month)),
+/// Save where the files belong as a plan, without moving anything. With `diff`, the plan is shown
+/// as a tree of the directories it changes rather than a row for each file.
+fn save_plan(
+    candidates: &[plan::Candidate],
+    failures: Vec<plan::Failure>,
+    output: &path::Path,
+    diff: bool,
+) -> i32 {
+    let plan = plan::Plan::new(candidates);
+    if let Err(e) = plan.save(output) {
+        return fail(EXIT_IO_ERROR, &e);
+    }
+    report::planned(&plan);
+    if diff {
+        for line in diff::tree(&plan) {
+            report::note!("{}", line);
+        }
+    } else {
+        list_plan(&plan);
+    }
+    report::note!(
+        "Planned {} moves ({} files left in place) in {}",
+        plan.moves.len(),
+        plan.unclassified.len(),
+        output.display()
+    );
+    report::summarise_ambiguous();
+    if !failures.is_empty() {
+        return exit_code(&plan::Outcome {
+            failures,
+            ..plan::Outcome::default()
+        });
+    }
+    if plan.unclassified.is_empty() {
+        0
+    } else {
+        EXIT_UNCLASSIFIED
     }
 }
 
@@ -165,7 +862,10 @@ mod tests {
     use std::fs;
     use std::path;
 
-    use crate::classify_files_in;
+    use classfy::bucket::Layout;
+    use classfy::date::Extractor;
+    use classfy::manifest;
+    use classfy::plan::{plan_dir, Plan, Settings};
 
     struct TestData {
         base_path: path::PathBuf,
@@ -186,7 +886,7 @@ mod tests {
                 .write(true)
                 .create_new(true)
                 .open(&sample_path)
-                .expect(format!("could not create file {:?}", &sample_path).as_str());
+                .unwrap_or_else(|_| panic!("could not create file {:?}", &sample_path));
             self.expected.insert((*self.base_path).join(file_name));
         }
 
@@ -196,7 +896,7 @@ mod tests {
                 .write(true)
                 .create_new(true)
                 .open(&sample_path)
-                .expect(format!("could not create file {:?}", &sample_path).as_str());
+                .unwrap_or_else(|_| panic!("could not create file {:?}", &sample_path));
             self.expected
                 .insert((*self.base_path).join(subdir).join(file_name));
         }
@@ -207,7 +907,7 @@ mod tests {
         let tempdir = tempfile::tempdir().expect("could not create temp directory");
         let base_path = tempdir.path();
         println!("Temp directory: {:?}", base_path);
-        assert!(env::set_current_dir(&base_path).is_ok());
+        assert!(env::set_current_dir(base_path).is_ok());
 
         let mut context: TestData = TestData::new(base_path);
         context.add_subdir_file("2021FY", "text_21JAN2021.txt");
@@ -223,16 +923,29 @@ mod tests {
         context.add_subdir_file("2021FY", "text_08NOV2020");
         context.add_subdir_file("2022FY", "text_01DEC2021.txt");
         context.add_subdir_file("2020FY", "text_2020FY.txt");
+        context.add_subdir_file("2023FY", "2022-07-01_text.txt");
+        context.add_subdir_file("2022FY", "text 10JUN2022 final.txt");
         context.add_file("text.txt");
         context.add_file("text_other_2015fy.txt");
         context.add_file("text_abcdFY.txt");
         context.add_file("text_A1JAN2020.txt");
         context.add_file("text_10NAN2020.txt");
 
-        classify_files_in(base_path);
+        Plan::new(
+            &plan_dir(
+                base_path,
+                &Layout::default(),
+                &Extractor::default(),
+                false,
+                &[],
+            )
+            .unwrap()
+            .0,
+        )
+        .apply(&Settings::default());
 
         let mut acc: collections::HashSet<path::PathBuf> = collections::HashSet::new();
-        collect_files(&base_path, &mut acc);
+        collect_files(base_path, &mut acc);
 
         for p in &acc {
             println!("Found file {:?}", p);
@@ -247,7 +960,9 @@ mod tests {
     fn collect_files(path: &path::Path, acc: &mut collections::HashSet<path::PathBuf>) {
         for entry in path.read_dir().expect("could not read directory") {
             let entry_path = entry.expect("could not read entry").path();
-            if entry_path.is_file() {
+            if entry_path.ends_with(manifest::MANIFEST_NAME) {
+                continue;
+            } else if entry_path.is_file() {
                 acc.insert(entry_path);
             } else if entry_path.is_dir() {
                 collect_files(&entry_path, acc);
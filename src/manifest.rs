@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::date::FileDate;
+use crate::plan::Move;
+
+/// The name of the file, in each financial year directory, that records where its files came from.
+pub const MANIFEST_NAME: &str = ".classfy-manifest.jsonl";
+
+/// A line in the manifest, describing a file that was placed in the directory.
+#[derive(Serialize)]
+struct Entry<'a> {
+    original_path: &'a path::Path,
+    path: &'a path::Path,
+    date: Option<FileDate>,
+    sha256: &'a str,
+    moved_at: String,
+}
+
+/// Gets the SHA-256 checksum of the file's contents, as a hex string.
+pub fn checksum(path: &path::Path) -> Result<String, String> {
+    let mut file =
+        fs::File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    checksum_of(&mut file).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+}
+
+/// Gets the SHA-256 checksum of everything read from the reader, as a hex string.
+pub fn checksum_of(reader: &mut impl io::Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Append the move to the manifest of the directory that the file was placed in. For nested
+/// layouts, the manifest is kept in the top level directory (such as "2023FY" for "2023FY/Q1").
+pub fn record(file_move: &Move, checksum: &str) -> Result<(), String> {
+    let manifest_path = manifest_path(file_move);
+    let entry = Entry {
+        original_path: &file_move.source,
+        path: &file_move.destination,
+        date: file_move.date,
+        sha256: checksum,
+        moved_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    };
+    let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+
+    let mut manifest = fs::File::options()
+        .create(true)
+        .append(true)
+        .open(&manifest_path)
+        .map_err(|e| format!("Could not open {}: {}", manifest_path.display(), e))?;
+    writeln!(manifest, "{}", line)
+        .map_err(|e| format!("Could not write to {}: {}", manifest_path.display(), e))
+}
+
+/// Gets the manifest that the move is recorded in.
+pub fn manifest_path(file_move: &Move) -> path::PathBuf {
+    manifest_dir(file_move).join(MANIFEST_NAME)
+}
+
+/// Gets the top level directory, below the source's directory, that the file was moved into.
+pub(crate) fn manifest_dir(file_move: &Move) -> path::PathBuf {
+    let dest_dir = file_move
+        .destination
+        .parent()
+        .expect("destination has no parent");
+    let top_level = file_move.source.parent().and_then(|base_dir| {
+        let first = dest_dir.strip_prefix(base_dir).ok()?.components().next()?;
+        Some(base_dir.join(first))
+    });
+    top_level.unwrap_or_else(|| dest_dir.to_path_buf())
+}
@@ -0,0 +1,189 @@
+//! Merging the directories of an archive that are for the same financial year but were named
+//! differently, such as "2022FY" and "FY2022" made by different tools, into the one with the name
+//! that the layout gives it.
+
+use std::fs;
+use std::io::Write;
+use std::path;
+
+use crate::audit;
+use crate::bucket::Layout;
+use crate::flatten::{collect_files, remove_empty};
+use crate::manifest::{self, MANIFEST_NAME};
+use crate::plan::{self, Failure, Move, Outcome, PlaceError, Placement, Plan, Settings, Transfer};
+use crate::report;
+
+/// A financial year directory that does not have the name the layout gives it.
+struct Variant {
+    dir: path::PathBuf,
+    /// The name that the layout gives to the directory.
+    canonical: String,
+}
+
+/// Gets the plan that moves each of the files in the financial year directories that are named
+/// differently to how the layout names them into the directory with that name. The files keep
+/// their paths within the year directory.
+pub fn plan(root: &path::Path, layout: &Layout) -> Result<(Plan, Vec<Failure>), String> {
+    if layout.calendar {
+        return Err(String::from(
+            "Only financial year directories can be merged, not calendar years",
+        ));
+    }
+    let mut plan = Plan::default();
+    let mut failures = Vec::new();
+    let variants = variants(root, layout)?;
+    if variants.is_empty() {
+        report::note!(
+            "There are no year directories to merge in {}",
+            root.display()
+        );
+    }
+    for variant in variants {
+        report::note!(
+            "Merging {} into {}",
+            variant.dir.display(),
+            variant.canonical
+        );
+        let mut files = Vec::new();
+        collect_files(&variant.dir, &mut files, &mut failures);
+        files.sort();
+        plan.moves.extend(files.into_iter().map(|path| {
+            Move {
+                destination: root.join(&variant.canonical).join(
+                    path.strip_prefix(&variant.dir)
+                        .expect("file is in the directory"),
+                ),
+                source: path,
+                date: None,
+            }
+        }));
+    }
+    Ok((plan, failures))
+}
+
+/// Moves the files in the plan, as described by the settings. Files whose contents are already
+/// in the year directory are removed rather than moved. The manifests of the merged directories
+/// are added to those of the directories they were merged into, and the directories are removed
+/// if they are left empty.
+pub fn apply(root: &path::Path, layout: &Layout, plan: &Plan, settings: &Settings) -> Outcome {
+    let mut outcome = Outcome::default();
+    for file_move in &plan.moves {
+        let year = file_move
+            .destination
+            .strip_prefix(root)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|year| year.as_os_str().to_string_lossy().into_owned());
+        let bytes = fs::metadata(&file_move.source).map_or(0, |metadata| metadata.len());
+        let mut placed = plan::place(file_move, settings, None);
+        if placed == Ok(Placement::UpToDate) && settings.transfer == Transfer::Move {
+            placed = remove_duplicate(&file_move.source);
+        }
+        outcome.record(file_move, year, bytes, placed);
+    }
+    match variants(root, layout) {
+        Ok(variants) => {
+            for variant in variants {
+                if let Err(e) = merge_manifest(&variant.dir, &root.join(&variant.canonical)) {
+                    report::note!("{}", e);
+                }
+                remove_empty(&variant.dir);
+            }
+        }
+        Err(e) => report::note!("{}", e),
+    }
+    outcome
+}
+
+/// Gets the year directories in the root that are named differently to how the layout names them.
+fn variants(root: &path::Path, layout: &Layout) -> Result<Vec<Variant>, String> {
+    let entries = root
+        .read_dir()
+        .map_err(|e| format!("Could not read directory {}: {}", root.display(), e))?;
+    let mut variants: Vec<Variant> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|dir| {
+            let name = dir.file_name()?.to_str()?;
+            let canonical = layout.canonical_year_dir(name)?;
+            (name != canonical).then_some(Variant { dir, canonical })
+        })
+        .collect();
+    variants.sort_by(|a, b| a.dir.cmp(&b.dir));
+    Ok(variants)
+}
+
+/// Removes the file, as the year directory already has a file with the same contents.
+fn remove_duplicate(path: &path::Path) -> Result<Placement, PlaceError> {
+    let sha256 = manifest::checksum(path)?;
+    fs::remove_file(path).map_err(|e| PlaceError::io("could not remove file", e))?;
+    audit::record("remove", path, None, Some(&sha256));
+    Ok(Placement::AlreadyArchived)
+}
+
+/// Appends the manifest of the merged directory, if it has one, to the manifest of the directory
+/// it was merged into, so that where its files came from is not lost.
+fn merge_manifest(dir: &path::Path, into: &path::Path) -> Result<(), String> {
+    let merged = dir.join(MANIFEST_NAME);
+    let Ok(contents) = fs::read(&merged) else {
+        return Ok(());
+    };
+    let manifest_path = into.join(MANIFEST_NAME);
+    fs::create_dir_all(into)
+        .and_then(|()| {
+            fs::File::options()
+                .create(true)
+                .append(true)
+                .open(&manifest_path)
+        })
+        .and_then(|mut manifest| manifest.write_all(&contents))
+        .map_err(|e| format!("Could not write to {}: {}", manifest_path.display(), e))?;
+    fs::remove_file(&merged).map_err(|e| format!("Could not remove {}: {}", merged.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply, plan};
+    use crate::bucket::Layout;
+    use crate::manifest::MANIFEST_NAME;
+    use crate::plan::Settings;
+
+    #[test]
+    fn test_merge() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("2022FY")).unwrap();
+        fs::create_dir_all(root.join("FY2022/receipts")).unwrap();
+        fs::create_dir(root.join("2022-23")).unwrap();
+        fs::write(root.join("2022FY/a.pdf"), "a").unwrap();
+        fs::write(root.join("2022FY").join(MANIFEST_NAME), "first\n").unwrap();
+        fs::write(root.join("FY2022/a.pdf"), "another a").unwrap();
+        fs::write(root.join("FY2022/receipts/b.pdf"), "b").unwrap();
+        fs::write(root.join("FY2022").join(MANIFEST_NAME), "second\n").unwrap();
+        fs::write(root.join("2022FY/d.pdf"), "d").unwrap();
+        fs::write(root.join("FY2022/d.pdf"), "d").unwrap();
+        fs::write(root.join("2022-23/c.pdf"), "c").unwrap();
+
+        let (plan, failures) = plan(root, &Layout::default()).unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(plan.moves.len(), 4);
+        assert_eq!(plan.moves[0].destination, root.join("2023FY").join("c.pdf"));
+
+        let outcome = apply(root, &Layout::default(), &plan, &Settings::default());
+        assert!(outcome.failures.is_empty());
+        assert_eq!((outcome.placed, outcome.already_archived), (3, 1));
+        assert_eq!(
+            fs::read_to_string(root.join("2022FY/a (2).pdf")).unwrap(),
+            "another a"
+        );
+        assert!(root.join("2022FY/receipts/b.pdf").is_file());
+        assert!(root.join("2023FY/c.pdf").is_file());
+        assert!(!root.join("FY2022").exists());
+        assert!(!root.join("2022-23").exists());
+        let manifest = fs::read_to_string(root.join("2022FY").join(MANIFEST_NAME)).unwrap();
+        assert!(manifest.starts_with("first\n") && manifest.ends_with("second\n"));
+    }
+}
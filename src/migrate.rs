@@ -0,0 +1,173 @@
+//! Renaming the year directories of an existing archive after the way they are named has changed,
+//! such as with a new `--dir-template`.
+
+use std::fs;
+use std::path;
+
+use crate::audit;
+use crate::bucket::Layout;
+use crate::flatten::remove_empty;
+use crate::journal::Journal;
+use crate::plan::Transfer;
+use crate::report;
+
+/// A year directory, and the name that the layout now gives it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rename {
+    pub from: path::PathBuf,
+    pub to: path::PathBuf,
+}
+
+/// Gets the year directories in the archive that the layout names differently, and what they are
+/// renamed to. Nothing is renamed if any of them would take the name of another directory, as
+/// those have to be merged instead.
+pub fn plan(root: &path::Path, layout: &Layout) -> Result<Vec<Rename>, String> {
+    if layout.calendar {
+        return Err(String::from(
+            "Only financial year directories can be renamed, not calendar years",
+        ));
+    }
+    let entries = root
+        .read_dir()
+        .map_err(|e| format!("Could not read directory {}: {}", root.display(), e))?;
+    let mut renames: Vec<Rename> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|from| {
+            let name = from.file_name()?.to_str()?;
+            let canonical = layout.canonical_year_dir(name)?;
+            (name != canonical).then(|| Rename {
+                to: root.join(canonical),
+                from,
+            })
+        })
+        .collect();
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+
+    let taken: Vec<String> = renames
+        .iter()
+        .filter(|rename| {
+            renames
+                .iter()
+                .any(|other| other != *rename && other.to == rename.to)
+                || (rename.to.exists() && !is_same_dir(&rename.from, &rename.to))
+        })
+        .map(|rename| format!("{} to {}", rename.from.display(), rename.to.display()))
+        .collect();
+    if !taken.is_empty() {
+        return Err(format!(
+            "Could not rename {} as the directories are already taken, merge them with \
+             classfy merge-dirs first",
+            taken.join(", ")
+        ));
+    }
+    Ok(renames)
+}
+
+/// Renames each of the directories. If any of them cannot be renamed, those that were renamed are
+/// given back their names.
+pub fn apply(renames: &[Rename]) -> Result<(), String> {
+    let mut journal = Journal::default();
+    for rename in renames {
+        if let Err(e) = fs::rename(&rename.from, &rename.to) {
+            journal.roll_back();
+            return Err(format!(
+                "Could not rename {} to {}: {}",
+                rename.from.display(),
+                rename.to.display(),
+                e
+            ));
+        }
+        journal.placed(&rename.from, &rename.to, Transfer::Move);
+        audit::record("rename", &rename.from, Some(&rename.to), None);
+        report::note!(
+            "Renamed {} to {}",
+            rename.from.display(),
+            rename.to.file_name().unwrap_or_default().to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Removes the directories within the year directories that were left empty once their files were
+/// moved into the levels of the new layout.
+pub fn remove_empty_levels(root: &path::Path, layout: &Layout) {
+    let Ok(entries) = root.read_dir() else {
+        return;
+    };
+    let year_dirs = entries.flatten().map(|entry| entry.path()).filter(|path| {
+        path.is_dir()
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| layout.is_year_dir(name))
+    });
+    for year_dir in year_dirs {
+        let Ok(entries) = year_dir.read_dir() else {
+            continue;
+        };
+        for level in entries.flatten().map(|entry| entry.path()) {
+            if level.is_dir() {
+                remove_empty(&level);
+            }
+        }
+    }
+}
+
+/// Checks if the paths are for the same directory, as they are when only the case of a name
+/// changes on file systems that ignore case.
+fn is_same_dir(a: &path::Path, b: &path::Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply, plan, Rename};
+    use crate::bucket::Layout;
+    use crate::template::DirTemplate;
+
+    #[test]
+    fn test_migrate() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("2022FY/Q1")).unwrap();
+        fs::create_dir(root.join("2023FY")).unwrap();
+        fs::create_dir(root.join("Other")).unwrap();
+        fs::write(root.join("2022FY/Q1/a.pdf"), "a").unwrap();
+        let layout = Layout {
+            dir_template: DirTemplate::parse("FY{year}").unwrap(),
+            ..Layout::default()
+        };
+
+        let renames = plan(root, &layout).unwrap();
+        assert_eq!(
+            renames,
+            vec![
+                Rename {
+                    from: root.join("2022FY"),
+                    to: root.join("FY2022"),
+                },
+                Rename {
+                    from: root.join("2023FY"),
+                    to: root.join("FY2023"),
+                },
+            ]
+        );
+        apply(&renames).unwrap();
+        assert!(root.join("FY2022/Q1/a.pdf").is_file());
+        assert!(root.join("FY2023").is_dir());
+        assert!(!root.join("2022FY").exists());
+        assert!(plan(root, &layout).unwrap().is_empty());
+
+        // Both would be named FY2024.
+        fs::create_dir(root.join("2024FY")).unwrap();
+        fs::create_dir(root.join("2023-24")).unwrap();
+        assert!(plan(root, &layout).is_err());
+    }
+}
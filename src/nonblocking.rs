@@ -0,0 +1,132 @@
+//! Planning and applying from async code, for programs that run classfy on a tokio runtime. The
+//! directories are read with tokio, and the work that reads or moves the files is done on tokio's
+//! blocking threads, so that the runtime's own threads are never blocked.
+
+use std::panic;
+use std::path;
+
+use tokio::task;
+
+use crate::bucket::Layout;
+use crate::date::Extractor;
+use crate::plan::{self, Candidate, Failure, Outcome, PlaceError, Plan, Settings};
+use crate::sink::Sink;
+use crate::walk;
+
+/// Finds the files in the directory and works out where each of them belongs, as
+/// `plan::plan_dir` does.
+pub async fn plan_dir(
+    path: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    reclassify: bool,
+    excludes: &[String],
+) -> Result<(Vec<Candidate>, Vec<Failure>), PlaceError> {
+    let (dir, dir_layout, excludes) = (path.to_path_buf(), layout.clone(), excludes.to_vec());
+    let Some((base, ignore)) =
+        blocking(move || plan::dir_base(&dir, &dir_layout, reclassify, &excludes)).await?
+    else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+
+    let mut entries = tokio::fs::read_dir(path)
+        .await
+        .map_err(|e| PlaceError::io(&format!("Could not read directory {}", path.display()), e))?;
+    let mut files = Vec::new();
+    let mut failures = Vec::new();
+    let mut read = 0;
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) => {
+                let doing = format!(
+                    "Could not read directory {} after {} entries",
+                    path.display(),
+                    read
+                );
+                failures.push(Failure {
+                    path: path.to_path_buf(),
+                    error: PlaceError::io(&doing, e),
+                });
+                break;
+            }
+        };
+        read += 1;
+        walk::progress(path, read);
+        let entry_path = entry.path();
+        if tokio::fs::metadata(&entry_path)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+        {
+            files.push(entry_path);
+        }
+    }
+
+    let (layout, extractor) = (layout.clone(), extractor.clone());
+    let candidates =
+        blocking(move || plan::plan_files(files, &base, &ignore, &layout, &extractor)).await;
+    Ok((candidates, failures))
+}
+
+/// Makes the moves in the plan, as `Plan::apply` does.
+pub async fn apply(plan: Plan, settings: &Settings) -> Outcome {
+    let settings = settings.clone();
+    blocking(move || plan.apply(&settings)).await
+}
+
+/// Places the files in the plan with the sink, as `Plan::apply_to` does.
+pub async fn apply_to<S>(plan: Plan, settings: &Settings, mut sink: S) -> Outcome
+where
+    S: Sink + Send + 'static,
+{
+    let settings = settings.clone();
+    blocking(move || plan.apply_to(&settings, &mut sink)).await
+}
+
+/// Runs the work on one of tokio's blocking threads, passing on any panic.
+async fn blocking<T, F>(work: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match task::spawn_blocking(work).await {
+        Ok(value) => value,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply, plan_dir};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+    use crate::plan::{Plan, Settings};
+
+    #[test]
+    fn test_plan_and_apply() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        fs::write(base.join("a_10JUL2022.txt"), "a").unwrap();
+        fs::write(base.join("notes.txt"), "").unwrap();
+        fs::create_dir(base.join("receipts_10JUL2022")).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let outcome = runtime.block_on(async {
+            let (candidates, failures) =
+                plan_dir(base, &Layout::default(), &Extractor::default(), false, &[])
+                    .await
+                    .unwrap();
+            assert_eq!(candidates.len(), 2);
+            assert!(failures.is_empty());
+            apply(Plan::new(&candidates), &Settings::default()).await
+        });
+        assert_eq!((outcome.placed, outcome.unclassified), (1, 1));
+        assert!(base.join("2023FY").join("a_10JUL2022.txt").is_file());
+        assert!(base.join("receipts_10JUL2022").is_dir());
+    }
+}
@@ -0,0 +1,204 @@
+//! Dates given by hand to files whose dates could not be found, or were found wrongly. They are
+//! kept in `.classfy/overrides.json` in the classified directory, so that later runs use them
+//! instead of looking for the dates again.
+//!
+//! Each date is kept by the path of the file and by the checksum of its contents, so that it is
+//! still found once the file has been moved into its year directory, or back out of it by
+//! `undo`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::date::FileDate;
+use crate::manifest;
+use crate::report;
+
+/// The directory, within a classified directory, that classfy keeps its own records in.
+pub const CLASSFY_DIR: &str = ".classfy";
+
+/// The name of the file, in `CLASSFY_DIR`, that holds the dates given by hand.
+pub const OVERRIDES_NAME: &str = "overrides.json";
+
+/// The dates given by hand to files, by their paths relative to the directory the overrides are
+/// kept in and by the checksums of their contents.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Overrides {
+    files: BTreeMap<String, FileDate>,
+    #[serde(default)]
+    checksums: BTreeMap<String, FileDate>,
+}
+
+impl Overrides {
+    /// Loads the overrides kept in the directory. If there are none, there are no overrides.
+    pub fn load(root: &path::Path) -> Result<Self, String> {
+        let path = overrides_path(root);
+        match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| format!("Could not parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Overrides::default()),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Saves the overrides in the directory, replacing those there.
+    pub fn save(&self, root: &path::Path) -> Result<(), String> {
+        let path = overrides_path(root);
+        let dir = root.join(CLASSFY_DIR);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Could not create directory {}: {}", dir.display(), e))?;
+        let contents =
+            serde_json::to_string_pretty(self).expect("overrides could not be serialised");
+        fs::write(&path, contents + "\n")
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Gets the date given to the file at the path, relative to the directory.
+    pub fn get(&self, relative: &path::Path) -> Option<FileDate> {
+        self.files.get(&key(relative)).copied()
+    }
+
+    /// Gets the date given to the file with the checksum, wherever it was.
+    pub fn get_checksum(&self, checksum: &str) -> Option<FileDate> {
+        self.checksums.get(checksum).copied()
+    }
+
+    /// Checks if any of the dates are kept by checksum, which is only worth working out for a file
+    /// if they are.
+    pub fn has_checksums(&self) -> bool {
+        !self.checksums.is_empty()
+    }
+
+    /// Gives the date to the file at the path, relative to the directory, and to any file with the
+    /// checksum.
+    pub fn set(&mut self, relative: &path::Path, checksum: Option<&str>, date: FileDate) {
+        self.files.insert(key(relative), date);
+        if let Some(checksum) = checksum {
+            self.checksums.insert(checksum.to_string(), date);
+        }
+    }
+
+    /// Removes the date given to the file at the path, relative to the directory, and to any file
+    /// with the checksum. Gets whether there was one.
+    pub fn remove(&mut self, relative: &path::Path, checksum: Option<&str>) -> bool {
+        let by_path = self.files.remove(&key(relative)).is_some();
+        let by_checksum =
+            checksum.is_some_and(|checksum| self.checksums.remove(checksum).is_some());
+        by_path || by_checksum
+    }
+}
+
+/// Gets the date given by hand to the file, from the overrides of the nearest directory above it
+/// that has them.
+pub fn lookup(path: &path::Path) -> Option<FileDate> {
+    let path = path::absolute(path).ok()?;
+    let root = overrides_root(&path)?;
+    match Overrides::load(root) {
+        Ok(overrides) => overrides.get(path.strip_prefix(root).ok()?).or_else(|| {
+            if !overrides.has_checksums() {
+                return None;
+            }
+            let checksum = manifest::checksum(&path).ok()?;
+            overrides.get_checksum(&checksum)
+        }),
+        Err(e) => {
+            report::note!("Ignoring the dates given by hand: {}", e);
+            None
+        }
+    }
+}
+
+/// Remembers the date given to the file, in the overrides of the nearest directory above it that
+/// has them, or else in those of `base`.
+pub fn remember(path: &path::Path, base: &path::Path, date: FileDate) -> Result<(), String> {
+    let path = path::absolute(path).map_err(|e| e.to_string())?;
+    let base = path::absolute(base).map_err(|e| e.to_string())?;
+    let root = overrides_root(&path).unwrap_or(&base);
+    let relative = path
+        .strip_prefix(root)
+        .map_err(|_| format!("{} is not in {}", path.display(), root.display()))?;
+    let checksum = manifest::checksum(&path)?;
+    let mut overrides = Overrides::load(root)?;
+    overrides.set(relative, Some(&checksum), date);
+    overrides.save(root)
+}
+
+/// Forgets the date given to the file, so that it is looked for again. Gets whether it had one.
+pub fn forget(path: &path::Path) -> Result<bool, String> {
+    let path = path::absolute(path).map_err(|e| e.to_string())?;
+    let Some(root) = overrides_root(&path) else {
+        return Ok(false);
+    };
+    let relative = path.strip_prefix(root).map_err(|e| e.to_string())?;
+    let checksum = manifest::checksum(&path).ok();
+    let mut overrides = Overrides::load(root)?;
+    if !overrides.remove(relative, checksum.as_deref()) {
+        return Ok(false);
+    }
+    overrides.save(root).map(|()| true)
+}
+
+/// Gets the nearest directory above the file that has overrides.
+fn overrides_root(path: &path::Path) -> Option<&path::Path> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| overrides_path(dir).is_file())
+}
+
+fn overrides_path(root: &path::Path) -> path::PathBuf {
+    root.join(CLASSFY_DIR).join(OVERRIDES_NAME)
+}
+
+/// Gets the key of the relative path, which is the same on every system.
+fn key(relative: &path::Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{forget, lookup, remember};
+    use crate::date::FileDate;
+
+    #[test]
+    fn test_overrides() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir(root.join("scans")).unwrap();
+        let path = root.join("scans/notes.txt");
+        fs::write(&path, "").unwrap();
+        assert_eq!(lookup(&path), None);
+
+        let date = FileDate::Day {
+            year: 2022,
+            month: 9,
+            day: 14,
+        };
+        remember(&path, root, date).unwrap();
+        assert_eq!(lookup(&path), Some(date));
+        assert_eq!(lookup(&root.join("notes.txt")), None);
+        assert!(fs::read_to_string(root.join(".classfy/overrides.json"))
+            .unwrap()
+            .contains("\"scans/notes.txt\": \"2022-09-14\""));
+
+        // The date follows the contents of the file when it is moved.
+        fs::create_dir(root.join("2023FY")).unwrap();
+        let moved = root.join("2023FY/notes.txt");
+        fs::rename(&path, &moved).unwrap();
+        assert_eq!(lookup(&moved), Some(date));
+        fs::rename(&moved, &path).unwrap();
+        assert_eq!(lookup(&path), Some(date));
+
+        assert_eq!(forget(&path), Ok(true));
+        assert_eq!(lookup(&path), None);
+        assert_eq!(forget(&path), Ok(false));
+    }
+}
@@ -0,0 +1,518 @@
+//! Packing the classified files into an archive for each financial year, such as `2022FY.zip`,
+//! instead of moving them into directories.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::date::FileDate;
+use crate::manifest;
+use crate::plan::{self, Candidate, Outcome, PlaceError, Placement, Plan};
+use crate::report;
+use crate::sink::{Bucket, Sink};
+
+/// The kind of archive that the files are packed into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    /// A tar file compressed with zstd.
+    TarZst,
+}
+
+impl Format {
+    /// Parses the format from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "zip" => Ok(Format::Zip),
+            "tar.zst" => Ok(Format::TarZst),
+            _ => Err(format!("Unknown archive format {:?}", name)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// A line in the index kept beside each archive, describing a file that was added to it.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    /// The name of the file in the archive.
+    name: String,
+    original_path: path::PathBuf,
+    date: Option<FileDate>,
+    sha256: String,
+    added_at: String,
+}
+
+/// A file to be added to an archive.
+struct Addition<'a> {
+    candidate: &'a Candidate,
+    name: String,
+    sha256: String,
+}
+
+/// Adds each of the classified files to the archive for its financial year, in its base directory.
+/// The files in a nested layout are kept in directories within the archive. Files are removed once
+/// the archive they were added to has been written.
+pub fn pack(candidates: &[Candidate], format: Format) -> Outcome {
+    let mut outcome = Plan::new(candidates).leave_unclassified();
+    let mut archives: BTreeMap<path::PathBuf, Vec<(&Candidate, String)>> = BTreeMap::new();
+    for candidate in candidates {
+        let Ok(bucket) = &candidate.bucket else {
+            continue;
+        };
+        let Some((archive, name)) = member(candidate.file_name(), &candidate.base, bucket, format)
+        else {
+            continue;
+        };
+        if is_own_file(&candidate.path, &archive) {
+            continue;
+        }
+        archives.entry(archive).or_default().push((candidate, name));
+    }
+    for (archive, files) in &archives {
+        outcome += pack_into(archive, format, files);
+    }
+    outcome
+}
+
+/// Adds each file to the archive for its financial year as it is given. As a compressed tar file
+/// is written again for each file, `pack` is quicker when all of the files are known up front.
+#[derive(Clone, Copy, Debug)]
+pub struct Archives {
+    pub format: Format,
+}
+
+impl Sink for Archives {
+    fn put(&mut self, src: &path::Path, bucket: &Bucket) -> Result<Placement, PlaceError> {
+        let (archive, name) = member(
+            bucket.file_name(src),
+            &bucket.base,
+            &bucket.dir,
+            self.format,
+        )
+        .ok_or("the file has no financial year directory")?;
+        if is_own_file(src, &archive) {
+            return Ok(Placement::Skipped(format!(
+                "{} is part of the archive",
+                src.display()
+            )));
+        }
+        let candidate = Candidate {
+            path: src.to_path_buf(),
+            base: bucket.base.clone(),
+            date: bucket.date.ok_or_else(|| String::from("No date found")),
+            bucket: Ok(bucket.dir.clone()),
+            name: bucket.name.clone(),
+        };
+        let outcome = pack_into(&archive, self.format, &[(&candidate, name)]);
+        match outcome.failures.into_iter().next() {
+            Some(failure) => Err(failure.error),
+            None if outcome.up_to_date > 0 => Ok(Placement::UpToDate),
+            None => Ok(Placement::Moved),
+        }
+    }
+}
+
+/// Gets the archive, in the base directory, for the file's bucket and the name that the file is
+/// given in it. The directories of a nested layout are kept in the name. Gets `None` if the
+/// bucket is empty.
+fn member(
+    file_name: &ffi::OsStr,
+    base: &path::Path,
+    bucket: &path::Path,
+    format: Format,
+) -> Option<(path::PathBuf, String)> {
+    let mut components = bucket.components();
+    let top_level = components.next()?;
+    let archive = base.join(format!(
+        "{}.{}",
+        top_level.as_os_str().to_string_lossy(),
+        format.extension()
+    ));
+    let name = components
+        .map(|component| component.as_os_str())
+        .chain([file_name])
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((archive, name))
+}
+
+/// Checks if the file is the archive or its index, which are never added to the archive.
+fn is_own_file(path: &path::Path, archive: &path::Path) -> bool {
+    path == archive || path == index_path(archive)
+}
+
+/// Adds the files to the archive, under the given names. A file is left alone if the archive
+/// already has the same contents under its name. If the name is taken by a different file, a
+/// number is added to it.
+fn pack_into(archive: &path::Path, format: Format, files: &[(&Candidate, String)]) -> Outcome {
+    let mut outcome = Outcome::default();
+    let index = match read_index(&index_path(archive)) {
+        Ok(index) => index,
+        Err(e) => {
+            report::note!("Could not read the index of {}: {}", archive.display(), e);
+            for (candidate, _) in files {
+                outcome.fail(&candidate.path, format!("could not read the index: {}", e));
+            }
+            return outcome;
+        }
+    };
+    let mut taken = match existing_names(archive, format) {
+        Ok(names) => names,
+        Err(e) => {
+            report::note!("Could not read archive {}: {}", archive.display(), e);
+            for (candidate, _) in files {
+                outcome.fail(
+                    &candidate.path,
+                    format!("could not read the archive: {}", e),
+                );
+            }
+            return outcome;
+        }
+    };
+    let checksums: HashMap<&str, &str> = index
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.sha256.as_str()))
+        .collect();
+
+    let mut additions = Vec::new();
+    for (candidate, name) in files {
+        let sha256 = match manifest::checksum(&candidate.path) {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                report::note!("Could not add {}: {}", candidate.path.display(), e);
+                outcome.fail(&candidate.path, e);
+                continue;
+            }
+        };
+        if checksums.get(name.as_str()) == Some(&sha256.as_str()) {
+            report::note!(
+                "{} is up to date in {}",
+                candidate.path.display(),
+                archive.display()
+            );
+            outcome.up_to_date += 1;
+            continue;
+        }
+        let name = if taken.contains(name) {
+            (2..)
+                .map(|number| plan::numbered(name, number))
+                .find(|name| !taken.contains(name))
+                .expect("there is always a free name")
+        } else {
+            name.clone()
+        };
+        taken.insert(name.clone());
+        report::note!(
+            "Adding {} to {} as {}",
+            candidate.path.display(),
+            archive.display(),
+            name
+        );
+        additions.push(Addition {
+            candidate,
+            name,
+            sha256,
+        });
+    }
+    if additions.is_empty() {
+        return outcome;
+    }
+
+    let written = match format {
+        Format::Zip => write_zip(archive, &additions),
+        Format::TarZst => write_tar_zst(archive, &additions),
+    };
+    if let Err(e) = written {
+        report::note!("Could not write archive {}: {}", archive.display(), e);
+        for addition in &additions {
+            outcome.fail(
+                &addition.candidate.path,
+                format!("could not write the archive: {}", e),
+            );
+        }
+        return outcome;
+    }
+    report::placed(archive);
+    if let Err(e) = append_index(archive, &additions) {
+        report::note!(
+            "Could not record the files in the index of {}: {}",
+            archive.display(),
+            e
+        );
+    }
+    for addition in &additions {
+        let year = Bucket::of(addition.candidate).and_then(|bucket| bucket.top());
+        let size = fs::metadata(&addition.candidate.path).map_or(0, |metadata| metadata.len());
+        audit::record(
+            "pack",
+            &addition.candidate.path,
+            Some(&archive.join(&addition.name)),
+            Some(&addition.sha256),
+        );
+        match fs::remove_file(&addition.candidate.path) {
+            Ok(()) => outcome.place(year, size),
+            Err(e) => {
+                report::note!(
+                    "Added {} to {} but could not remove it: {}",
+                    addition.candidate.path.display(),
+                    archive.display(),
+                    e
+                );
+                outcome.fail(
+                    &addition.candidate.path,
+                    PlaceError::io("could not remove the file", e),
+                );
+            }
+        }
+    }
+    outcome
+}
+
+/// Gets the index kept beside the archive, such as `2022FY.zip.index.jsonl`.
+fn index_path(archive: &path::Path) -> path::PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(".index.jsonl");
+    path::PathBuf::from(name)
+}
+
+/// Reads the entries in the index, if there is one.
+fn read_index(path: &path::Path) -> Result<Vec<IndexEntry>, String> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    io::BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|e| e.to_string())?;
+            serde_json::from_str(&line).map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Records the files that were added to the archive in its index.
+fn append_index(archive: &path::Path, additions: &[Addition]) -> Result<(), String> {
+    let mut index = fs::File::options()
+        .create(true)
+        .append(true)
+        .open(index_path(archive))
+        .map_err(|e| e.to_string())?;
+    let added_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    for addition in additions {
+        let entry = IndexEntry {
+            name: addition.name.clone(),
+            original_path: addition.candidate.path.clone(),
+            date: addition.candidate.date.clone().ok(),
+            sha256: addition.sha256.clone(),
+            added_at: added_at.clone(),
+        };
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        writeln!(index, "{}", line).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Gets the names of the files already in the archive, if it exists.
+fn existing_names(archive: &path::Path, format: Format) -> Result<HashSet<String>, String> {
+    if !archive.exists() {
+        return Ok(HashSet::new());
+    }
+    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+    match format {
+        Format::Zip => {
+            let zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+            Ok(zip.file_names().map(String::from).collect())
+        }
+        Format::TarZst => {
+            let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+            let mut tar = tar::Archive::new(decoder);
+            let entries = tar.entries().map_err(|e| e.to_string())?;
+            entries
+                .map(|entry| {
+                    let entry = entry.map_err(|e| e.to_string())?;
+                    let name = entry.path().map_err(|e| e.to_string())?;
+                    Ok(name.to_string_lossy().into_owned())
+                })
+                .collect()
+        }
+    }
+}
+
+/// Appends the files to the zip archive, creating it if needed.
+fn write_zip(archive: &path::Path, additions: &[Addition]) -> Result<(), String> {
+    let mut writer = if archive.exists() {
+        let file = fs::File::options()
+            .read(true)
+            .write(true)
+            .open(archive)
+            .map_err(|e| e.to_string())?;
+        zip::ZipWriter::new_append(file).map_err(|e| e.to_string())?
+    } else {
+        zip::ZipWriter::new(fs::File::create_new(archive).map_err(|e| e.to_string())?)
+    };
+    for addition in additions {
+        let mut file = fs::File::open(&addition.candidate.path).map_err(|e| e.to_string())?;
+        writer
+            .start_file(
+                addition.name.as_str(),
+                zip::write::SimpleFileOptions::default(),
+            )
+            .map_err(|e| e.to_string())?;
+        io::copy(&mut file, &mut writer).map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Writes the files to the end of the tar archive. As a compressed tar file cannot be appended
+/// to, the archive is written again, with the new files after the existing ones, and then replaces
+/// the old one.
+fn write_tar_zst(archive: &path::Path, additions: &[Addition]) -> Result<(), String> {
+    let dir = archive.parent().ok_or("archive has no parent")?;
+    let temp = tempfile::NamedTempFile::new_in(dir).map_err(|e| e.to_string())?;
+    let encoder = zstd::Encoder::new(temp, 0).map_err(|e| e.to_string())?;
+    let mut builder = tar::Builder::new(encoder);
+    if archive.exists() {
+        let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+        let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+        let mut existing = tar::Archive::new(decoder);
+        for entry in existing.entries().map_err(|e| e.to_string())? {
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            let mut header = entry.header().clone();
+            let name = entry.path().map_err(|e| e.to_string())?.into_owned();
+            builder
+                .append_data(&mut header, name, &mut entry)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    for addition in additions {
+        builder
+            .append_path_with_name(&addition.candidate.path, &addition.name)
+            .map_err(|e| e.to_string())?;
+    }
+    let temp = builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| e.to_string())?;
+    temp.persist(archive).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+
+    use super::{pack, Archives, Format};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+    use crate::plan::{plan_dir, Plan, Settings};
+
+    #[test]
+    fn test_pack() {
+        for format in [Format::Zip, Format::TarZst] {
+            let tempdir = tempfile::tempdir().expect("could not create temp directory");
+            let base = tempdir.path();
+            let pack_dir = |contents: &[(&str, &str)]| {
+                for (name, content) in contents {
+                    fs::write(base.join(name), content).unwrap();
+                }
+                let candidates =
+                    plan_dir(base, &Layout::default(), &Extractor::default(), false, &[])
+                        .unwrap()
+                        .0;
+                pack(&candidates, format)
+            };
+
+            let outcome = pack_dir(&[("a_10JUL2022.txt", "a"), ("b_10JUN2022.txt", "b")]);
+            assert_eq!((outcome.placed, outcome.failures.len()), (2, 0));
+            assert!(!base.join("a_10JUL2022.txt").exists());
+
+            let outcome = pack_dir(&[("a_10JUL2022.txt", "a"), ("c_10AUG2022.txt", "c")]);
+            assert_eq!((outcome.placed, outcome.up_to_date), (1, 1));
+            // The unchanged copy is left in place, as it is when moving into directories.
+            fs::remove_file(base.join("a_10JUL2022.txt")).unwrap();
+
+            let outcome = pack_dir(&[("a_10JUL2022.txt", "changed")]);
+            assert_eq!(outcome.placed, 1);
+
+            let archive = base.join(format!("2023FY.{}", format.extension()));
+            let mut names = match format {
+                Format::Zip => {
+                    let zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+                    zip.file_names().map(String::from).collect::<Vec<_>>()
+                }
+                Format::TarZst => {
+                    let decoder = zstd::Decoder::new(fs::File::open(&archive).unwrap()).unwrap();
+                    let mut tar = tar::Archive::new(decoder);
+                    let mut names = Vec::new();
+                    for entry in tar.entries().unwrap() {
+                        let mut entry = entry.unwrap();
+                        let name = entry.path().unwrap().to_string_lossy().into_owned();
+                        if name == "a_10JUL2022 (2).txt" {
+                            let mut contents = String::new();
+                            entry.read_to_string(&mut contents).unwrap();
+                            assert_eq!(contents, "changed");
+                        }
+                        names.push(name);
+                    }
+                    names
+                }
+            };
+            names.sort();
+            assert_eq!(
+                names,
+                ["a_10JUL2022 (2).txt", "a_10JUL2022.txt", "c_10AUG2022.txt"]
+            );
+            let index =
+                fs::read_to_string(base.join(format!("2023FY.{}.index.jsonl", format.extension())))
+                    .unwrap();
+            assert_eq!(index.lines().count(), 3);
+            assert!(base
+                .join(format!("2022FY.{}", format.extension()))
+                .is_file());
+        }
+    }
+
+    #[test]
+    fn test_archives() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        fs::write(base.join("a_10JUL2022.txt"), "a").unwrap();
+        fs::write(base.join("b_10AUG2022.txt"), "b").unwrap();
+        let plan = || {
+            Plan::new(
+                &plan_dir(base, &Layout::default(), &Extractor::default(), false, &[])
+                    .unwrap()
+                    .0,
+            )
+        };
+
+        let mut archives = Archives {
+            format: Format::TarZst,
+        };
+        let outcome = plan().apply_to(&Settings::default(), &mut archives);
+        assert_eq!((outcome.placed, outcome.failures.len()), (2, 0));
+        assert!(!base.join("a_10JUL2022.txt").exists());
+
+        // The archive and its index are left in place.
+        let outcome = plan().apply_to(&Settings::default(), &mut archives);
+        assert_eq!((outcome.placed, outcome.skipped), (0, 2));
+        let decoder =
+            zstd::Decoder::new(fs::File::open(base.join("2023FY.tar.zst")).unwrap()).unwrap();
+        assert_eq!(tar::Archive::new(decoder).entries().unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,1878 @@
+use std::collections::BTreeMap;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::ops;
+use std::path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::bucket::Layout;
+use crate::date::{Extractor, FileDate};
+use crate::dedup::{self, Duplicate, DuplicatePolicy};
+use crate::exclude::{Ignore, IGNORE_FILE};
+use crate::extract;
+use crate::journal::Journal;
+use crate::manifest;
+use crate::overrides;
+use crate::remote;
+use crate::report;
+use crate::sink::{Bucket, Local, Sink};
+use crate::state::{self, State};
+use crate::tag::{self, Tagger};
+use crate::unicode;
+use crate::walk::Walk;
+use crate::winpath;
+
+/// A file found in a directory being classified, along with where it belongs.
+pub struct Candidate {
+    /// The file to be classified.
+    pub path: path::PathBuf,
+    /// The directory that the buckets are in. This is usually the directory that the file is in.
+    pub base: path::PathBuf,
+    /// The date found in the file name, or why there is none.
+    pub date: Result<FileDate, String>,
+    /// The directory, relative to the base directory, to move the file into. If there is none,
+    /// this describes why the file is to be left in place.
+    pub bucket: Result<path::PathBuf, String>,
+    /// The name to give the file in its bucket, if not its own.
+    pub name: Option<String>,
+}
+
+impl Candidate {
+    /// Gets the name that the file is given in its bucket.
+    pub fn file_name(&self) -> &ffi::OsStr {
+        match &self.name {
+            Some(name) => ffi::OsStr::new(name),
+            None => self.path.file_name().expect("file does not have a name"),
+        }
+    }
+
+    /// Gets the move that places the file in its bucket, if it has one.
+    pub fn to_move(&self) -> Option<Move> {
+        let bucket = self.bucket.as_ref().ok()?;
+        let file_name = self.file_name();
+        Some(Move {
+            source: self.path.clone(),
+            destination: self
+                .base
+                .join(winpath::destination(&bucket.join(file_name))),
+            date: self.date.clone().ok(),
+        })
+    }
+}
+
+/// A file to be moved, and where it is to be moved to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub source: path::PathBuf,
+    pub destination: path::PathBuf,
+    /// The date found in the name of the file, if any.
+    pub date: Option<FileDate>,
+}
+
+/// A file that is to be left in place, and why.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unclassified {
+    pub path: path::PathBuf,
+    pub reason: String,
+}
+
+/// The moves that classify a set of files. A plan can be saved, reviewed and then applied later.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub moves: Vec<Move>,
+    pub unclassified: Vec<Unclassified>,
+}
+
+impl Plan {
+    /// Creates the plan that places each of the candidates in their bucket.
+    pub fn new(candidates: &[Candidate]) -> Self {
+        let mut plan = Plan::default();
+        for candidate in candidates {
+            match &candidate.bucket {
+                Ok(_) => plan.moves.extend(candidate.to_move()),
+                Err(reason) => plan.unclassified.push(Unclassified {
+                    path: candidate.path.clone(),
+                    reason: reason.clone(),
+                }),
+            }
+        }
+        plan
+    }
+
+    /// Reads a plan that was previously saved.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read plan {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Could not parse plan {}: {}", path.display(), e))
+    }
+
+    /// Saves the plan so that it can be applied later. Files whose paths are not valid UTF-8
+    /// cannot be saved, so they are left out and reported.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        let valid = |path: &path::Path| {
+            let valid = path.to_str().is_some();
+            if !valid {
+                report::note!(
+                    "Leaving {} out of the plan, as its path is not valid UTF-8",
+                    path.display()
+                );
+            }
+            valid
+        };
+        let plan = Plan {
+            moves: self
+                .moves
+                .iter()
+                .filter(|file_move| valid(&file_move.source) && valid(&file_move.destination))
+                .cloned()
+                .collect(),
+            unclassified: self
+                .unclassified
+                .iter()
+                .filter(|unclassified| valid(&unclassified.path))
+                .cloned()
+                .collect(),
+        };
+        let contents = serde_json::to_string_pretty(&plan).expect("plan could not be serialised");
+        fs::write(path, contents + "\n")
+            .map_err(|e| format!("Could not write plan {}: {}", path.display(), e))
+    }
+
+    /// Makes the moves in the plan, or tags the files with `--mode tag`, as described by the
+    /// settings. Files that are no longer where the plan expects them are left alone. If the run
+    /// is transactional, the first failure stops it and everything done so far is rolled back.
+    pub fn apply(&self, settings: &Settings) -> Outcome {
+        match settings.mode {
+            Mode::Place => {
+                let mut local = Local::new(settings);
+                let outcome = self.apply_to(settings, &mut local);
+                local.finish();
+                outcome
+            }
+            Mode::Tag => self.apply_to(settings, &mut Tagger::new(settings)),
+        }
+    }
+
+    /// Places the files in the plan with the sink, as described by the settings.
+    pub fn apply_to(&self, settings: &Settings, sink: &mut dyn Sink) -> Outcome {
+        let mut outcome = self.leave_unclassified();
+        let mut checksums = dedup::Checksums::default();
+        for file_move in &self.moves {
+            let mut file_move = file_move.clone();
+            if !file_move.source.is_file() {
+                report::action(&file_move.source, None, "failed", Some("no longer there"));
+                outcome.fail(
+                    &file_move.source,
+                    PlaceError::new(io::ErrorKind::NotFound, "no longer there"),
+                );
+            } else {
+                if let Some(policy) = settings.duplicates {
+                    match checksums.find(&file_move) {
+                        Ok(Some(original)) => {
+                            report::note!(
+                                "{} is the same as {}",
+                                file_move.source.display(),
+                                original.display()
+                            );
+                            outcome.duplicates.push(Duplicate {
+                                path: file_move.source.clone(),
+                                original,
+                                policy,
+                            });
+                            match policy {
+                                DuplicatePolicy::Skip => continue,
+                                DuplicatePolicy::KeepBoth => {}
+                                DuplicatePolicy::MoveToDuplicates => {
+                                    file_move = dedup::to_duplicates(&file_move)
+                                }
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => report::note!(
+                            "Could not check if {} is a duplicate: {}",
+                            file_move.source.display(),
+                            e
+                        ),
+                    }
+                }
+                let bucket = Bucket::of_move(&file_move);
+                let bytes = fs::metadata(&file_move.source).map_or(0, |metadata| metadata.len());
+                let placed = sink.put(&file_move.source, &bucket);
+                outcome.record(&file_move, bucket.top(), bytes, placed);
+            }
+            if settings.transactional && !outcome.failures.is_empty() {
+                report::note!("Rolling back the {} files placed so far", outcome.placed);
+                sink.roll_back();
+                outcome = Outcome {
+                    unclassified: outcome.unclassified,
+                    failures: outcome.failures,
+                    ..Outcome::default()
+                };
+                break;
+            }
+        }
+        outcome
+    }
+
+    /// Reports why each of the unclassified files is being left in place.
+    pub fn leave_unclassified(&self) -> Outcome {
+        for unclassified in &self.unclassified {
+            report::action(
+                &unclassified.path,
+                None,
+                "unclassified",
+                Some(&unclassified.reason),
+            );
+        }
+        Outcome {
+            unclassified: self.unclassified.len(),
+            ..Outcome::default()
+        }
+    }
+}
+
+/// Counts what happened to the files when a plan was applied.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// The number of files that were moved.
+    pub placed: usize,
+    /// The number of files that were already in their destination.
+    pub up_to_date: usize,
+    /// The number of files left in place because they could not be classified.
+    pub unclassified: usize,
+    /// The files that could not be moved, and why.
+    pub failures: Vec<Failure>,
+    /// The number of files left in place as the file at their destination was kept.
+    pub skipped: usize,
+    /// The number of files whose destination already existed when skipping existing files.
+    pub already_archived: usize,
+    /// The files whose contents were already in their financial year directory.
+    pub duplicates: Vec<Duplicate>,
+    /// The files that were given another name as their destination was taken.
+    pub renamed: Vec<Move>,
+    /// The number of files placed in each financial year.
+    pub placed_by_year: BTreeMap<String, usize>,
+    /// The total size of the files that were placed.
+    pub bytes_placed: u64,
+}
+
+impl Outcome {
+    /// Records that a file of the given size was placed in the financial year.
+    pub fn place(&mut self, year: Option<String>, bytes: u64) {
+        self.placed += 1;
+        if let Some(year) = year {
+            *self.placed_by_year.entry(year).or_default() += 1;
+        }
+        self.bytes_placed += bytes;
+    }
+
+    /// Records what was done to place the file of the given size in the financial year, and
+    /// reports it.
+    pub fn record(
+        &mut self,
+        file_move: &Move,
+        year: Option<String>,
+        bytes: u64,
+        placed: Result<Placement, PlaceError>,
+    ) {
+        let (action, detail) = match placed {
+            Ok(Placement::Moved) => {
+                self.place(year.clone(), bytes);
+                ("placed", None)
+            }
+            Ok(Placement::Renamed(destination)) => {
+                self.place(year.clone(), bytes);
+                let detail = format!(
+                    "as {}",
+                    destination
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                );
+                self.renamed.push(Move {
+                    destination,
+                    ..file_move.clone()
+                });
+                ("renamed", Some(detail))
+            }
+            Ok(Placement::Tagged) => {
+                self.place(year.clone(), 0);
+                ("tagged", None)
+            }
+            Ok(Placement::UpToDate) => {
+                self.up_to_date += 1;
+                ("up-to-date", None)
+            }
+            Ok(Placement::AlreadyArchived) => {
+                self.already_archived += 1;
+                ("already-archived", None)
+            }
+            Ok(Placement::Skipped(reason)) => {
+                self.skipped += 1;
+                ("skipped", Some(reason))
+            }
+            Err(e) => {
+                let detail = e.to_string();
+                self.fail(&file_move.source, e);
+                ("failed", Some(detail))
+            }
+        };
+        report::action(
+            &file_move.source,
+            year.as_deref(),
+            action,
+            detail.as_deref(),
+        );
+    }
+
+    /// Records that the file could not be placed.
+    pub fn fail(&mut self, path: &path::Path, error: impl Into<PlaceError>) {
+        let error = error.into();
+        report::event(&report::Event::Error {
+            path,
+            error: &error.message,
+        });
+        self.failures.push(Failure {
+            path: path.to_path_buf(),
+            error,
+        });
+    }
+}
+
+/// A file that could not be placed, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub path: path::PathBuf,
+    pub error: PlaceError,
+}
+
+/// Why a file could not be placed. The kind of error is kept so that failures can be summarised
+/// by their cause, such as permission being denied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaceError {
+    pub kind: io::ErrorKind,
+    pub message: String,
+}
+
+impl PlaceError {
+    pub fn new(kind: io::ErrorKind, message: &str) -> Self {
+        PlaceError {
+            kind,
+            message: String::from(message),
+        }
+    }
+
+    /// Describes an I/O error, with what was being done when it happened.
+    pub fn io(doing: &str, error: io::Error) -> Self {
+        PlaceError {
+            kind: error.kind(),
+            message: format!("{}: {}", doing, error),
+        }
+    }
+}
+
+impl From<String> for PlaceError {
+    fn from(message: String) -> Self {
+        PlaceError {
+            kind: io::ErrorKind::Other,
+            message,
+        }
+    }
+}
+
+impl From<&str> for PlaceError {
+    fn from(message: &str) -> Self {
+        PlaceError::from(String::from(message))
+    }
+}
+
+impl fmt::Display for PlaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ops::AddAssign for Outcome {
+    fn add_assign(&mut self, other: Outcome) {
+        self.placed += other.placed;
+        self.up_to_date += other.up_to_date;
+        self.unclassified += other.unclassified;
+        self.failures.extend(other.failures);
+        self.skipped += other.skipped;
+        self.already_archived += other.already_archived;
+        self.duplicates.extend(other.duplicates);
+        self.renamed.extend(other.renamed);
+        for (year, placed) in other.placed_by_year {
+            *self.placed_by_year.entry(year).or_default() += placed;
+        }
+        self.bytes_placed += other.bytes_placed;
+    }
+}
+
+/// Find the files in the given directory and work out where each of them belongs, without moving
+/// anything. Files matching the directory's ignore file or `excludes` are left out. Unless
+/// `reclassify` is set, directories that have already been classified are skipped. The entries
+/// are read one at a time, and if one cannot be read, the files found before it are still given
+/// along with the failure.
+pub fn plan_dir(
+    path: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    reclassify: bool,
+    excludes: &[String],
+) -> Result<(Vec<Candidate>, Vec<Failure>), PlaceError> {
+    let Some((base, ignore)) = dir_base(path, layout, reclassify, excludes)? else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let mut failures = Vec::new();
+    let files = Walk::new(path)?.filter_map(|found| found.map_err(|e| failures.push(e)).ok());
+    let candidates = plan_files(files, &base, &ignore, layout, extractor);
+    Ok((candidates, failures))
+}
+
+/// Find the files in the directory and work out where each of them belongs, as `plan_dir` does,
+/// but using the decisions recorded in the directory's state for the files that have not changed
+/// since they were made. Those files that have already been placed in their bucket, as they are
+/// when copying, are left out. The decisions are then recorded for the next run.
+pub fn plan_dir_incremental(
+    path: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    reclassify: bool,
+    excludes: &[String],
+) -> Result<(Vec<Candidate>, Vec<Failure>), PlaceError> {
+    let Some((base, ignore)) = dir_base(path, layout, reclassify, excludes)? else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let previous = State::load(path, layout, extractor);
+    let mut state = State::new(layout, extractor);
+    let mut candidates = Vec::new();
+    let mut failures = Vec::new();
+    for found in Walk::new(path)? {
+        let file = match found {
+            Ok(file) => file,
+            Err(failure) => {
+                failures.push(failure);
+                continue;
+            }
+        };
+        if is_own_file(&file) || is_ignored(&file, &ignore) || is_left_link(&file, layout) {
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(&file) else {
+            candidates.push(candidate(&file, &base, layout, extractor));
+            continue;
+        };
+        // A date given by hand since the decision was made takes its place.
+        let cached = previous
+            .get(&file, &metadata)
+            .filter(|_| overrides::lookup(&file).is_none());
+        let (found, previously) = match cached {
+            Some(entry) => (
+                Candidate {
+                    path: file.clone(),
+                    base: base.clone(),
+                    date: entry.date.clone(),
+                    bucket: entry.bucket.clone(),
+                    name: placed_name(&file, &entry.date, layout, extractor),
+                },
+                true,
+            ),
+            None => (candidate(&file, &base, layout, extractor), false),
+        };
+        state.insert(
+            &file,
+            state::Entry::new(&metadata, found.date.clone(), found.bucket.clone()),
+        );
+        if previously && is_placed(&found, &metadata) {
+            report::detail!(
+                "Skipping {} as it has not changed since it was placed",
+                file.display()
+            );
+        } else {
+            candidates.push(found);
+        }
+    }
+    if let Err(e) = state.save(path) {
+        report::note!("{}", e);
+    }
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((candidates, failures))
+}
+
+/// Checks if the candidate is already in its bucket, by there being a file of the same size there.
+fn is_placed(candidate: &Candidate, metadata: &fs::Metadata) -> bool {
+    candidate.to_move().is_some_and(|file_move| {
+        fs::metadata(&file_move.destination)
+            .is_ok_and(|placed| placed.is_file() && placed.len() == metadata.len())
+    })
+}
+
+/// Gets the directory that the buckets for the files in the directory are in, and the files to
+/// leave out of it. Gets `None` if the directory has already been classified and is not to be
+/// classified again.
+pub(crate) fn dir_base(
+    path: &path::Path,
+    layout: &Layout,
+    reclassify: bool,
+    excludes: &[String],
+) -> Result<Option<(path::PathBuf, Ignore)>, PlaceError> {
+    let base = match layout.classified_root(path) {
+        Some(root) if reclassify => root,
+        Some(_) => {
+            skip_classified(path);
+            return Ok(None);
+        }
+        None => path.to_path_buf(),
+    };
+    Ok(Some((base, Ignore::read(path, excludes)?)))
+}
+
+/// Works out where each of the files, which are all in one directory, belongs. The files that are
+/// ignored are left out, and the rest are sorted by their paths.
+pub(crate) fn plan_files(
+    files: impl IntoIterator<Item = path::PathBuf>,
+    base: &path::Path,
+    ignore: &Ignore,
+    layout: &Layout,
+    extractor: &Extractor,
+) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = files
+        .into_iter()
+        .filter(|path| {
+            !is_own_file(path) && !is_ignored(path, ignore) && !is_left_link(path, layout)
+        })
+        .map(|path| candidate(&path, base, layout, extractor))
+        .collect();
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    candidates
+}
+
+/// Work out where the file belongs, without moving it. Files matching the ignore file in their
+/// directory or `excludes` are skipped, as are files in directories that have already been
+/// classified unless `reclassify` is set.
+pub fn plan_file(
+    path: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    reclassify: bool,
+    excludes: &[String],
+) -> Option<Candidate> {
+    if is_own_file(path) || is_left_link(path, layout) {
+        return None;
+    }
+    let parent = path.parent().unwrap_or(path::Path::new(""));
+    match Ignore::read(parent, excludes) {
+        Ok(ignore) if is_ignored(path, &ignore) => return None,
+        Ok(_) => {}
+        Err(e) => {
+            report::note!("Skipping {}: {}", path.display(), e);
+            return None;
+        }
+    }
+    let base = match layout.classified_root(parent) {
+        Some(root) if reclassify => root,
+        Some(_) => {
+            skip_classified(path);
+            return None;
+        }
+        None => parent.to_path_buf(),
+    };
+    Some(candidate(path, &base, layout, extractor))
+}
+
+fn candidate(
+    path: &path::Path,
+    base: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+) -> Candidate {
+    let mut date = extractor.dated(path).map(|dated| {
+        if let Some(ambiguity) = &dated.ambiguity {
+            report::ambiguous(path, ambiguity);
+        }
+        dated.date
+    });
+    if layout.stamp_dates && date.is_err() {
+        if let Ok(modified) = extract::modified_date(path, extractor.timezone) {
+            report::detail!(
+                "Using the date {} that {} was last modified",
+                modified,
+                path.display()
+            );
+            date = Ok(modified);
+        }
+    }
+    let bucket = date.clone().and_then(|date| {
+        let bucket = layout.existing_bucket(base, date)?;
+        let fy = layout.fiscal.fy(date)?;
+        match extractor
+            .rule(path)
+            .and_then(|rule| rule.destination(&bucket, fy))
+        {
+            Some(destination) => destination,
+            None => Ok(bucket),
+        }
+    });
+    Candidate {
+        path: path.to_path_buf(),
+        base: base.to_path_buf(),
+        name: placed_name(path, &date, layout, extractor),
+        date,
+        bucket,
+    }
+}
+
+/// Gets the name that the file is given in its bucket, if the layout renames the files and its
+/// name is not already the one it would be given.
+pub fn placed_name(
+    path: &path::Path,
+    date: &Result<FileDate, String>,
+    layout: &Layout,
+    extractor: &Extractor,
+) -> Option<String> {
+    let date = *date.as_ref().ok()?;
+    let name = path.file_name()?.to_str()?;
+    if layout.normalize_names {
+        Some(extractor.normalized_name(name, date)).filter(|normalized| normalized != name)
+    } else if layout.stamp_dates {
+        extractor.stamped_name(name, date)
+    } else {
+        None
+    }
+}
+
+/// Checks if the file is one that classfy keeps for itself, such as a manifest.
+pub fn is_own_file(path: &path::Path) -> bool {
+    path.file_name() == Some(ffi::OsStr::new(manifest::MANIFEST_NAME))
+        || path.file_name() == Some(ffi::OsStr::new(IGNORE_FILE))
+        || path.file_name() == Some(ffi::OsStr::new(remote::UPLOADED_NAME))
+        || path.file_name() == Some(ffi::OsStr::new(state::STATE_NAME))
+        || path.file_name() == Some(ffi::OsStr::new(tag::INDEX_NAME))
+}
+
+fn is_ignored(path: &path::Path, ignore: &Ignore) -> bool {
+    let ignored = ignore.is_ignored(path);
+    if ignored {
+        report::detail!(
+            "Skipping {} as it matches {} or --exclude",
+            path.display(),
+            IGNORE_FILE
+        );
+    }
+    ignored
+}
+
+fn skip_classified(path: &path::Path) {
+    report::note!(
+        "Skipping {} as it has already been classified, use --reclassify to classify it again",
+        path.display()
+    );
+}
+
+/// Settings that control how a plan is applied.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Whether the files are moved or copied.
+    pub transfer: Transfer,
+    /// Whether to roll back all of the changes if any file cannot be placed.
+    pub transactional: bool,
+    /// What to do with files whose contents are already in their financial year directory, if
+    /// they are to be looked for.
+    pub duplicates: Option<DuplicatePolicy>,
+    /// What to do when the destination is taken by a different file.
+    pub collision: Collision,
+    /// When to replace a different file at the destination, if ever. This takes the place of the
+    /// collision strategy.
+    pub overwrite: Option<Overwrite>,
+    /// Whether to leave files whose destination already exists in place, as they have already
+    /// been archived. This takes the place of the collision strategy.
+    pub skip_existing: bool,
+    /// Whether to remove the files that have already been archived, where the archived file has
+    /// the same contents.
+    pub remove_archived: bool,
+    /// The directory, relative to the base directory, that files replaced by `overwrite` are moved
+    /// into. Without one, they are not kept.
+    pub backup: Option<path::PathBuf>,
+    /// Whether to leave a relative symbolic link to each moved file at its original location, so
+    /// that what refers to it there still finds it.
+    pub leave_symlink: bool,
+    /// Whether to tag each placed file with the name of its year directory, such as "2023FY".
+    pub tag: bool,
+    /// Whether the files are placed in their buckets, or only tagged with them.
+    pub mode: Mode,
+    /// The file that the changes made to the local file system are recorded in, so that the run
+    /// can be undone with `classfy undo`.
+    pub history: Option<path::PathBuf>,
+}
+
+/// What is done with the classified files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The files are placed in the directories of their buckets.
+    #[default]
+    Place,
+    /// The files are left where they are, and the name of their year directory is recorded for
+    /// each of them to be found by `classfy query`.
+    Tag,
+}
+
+impl Mode {
+    /// Parses the mode from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "place" => Ok(Mode::Place),
+            "tag" => Ok(Mode::Tag),
+            _ => Err(format!("Unknown mode {:?}", name)),
+        }
+    }
+}
+
+/// When to replace a different file that is already at the destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Only when the file being placed was modified more recently.
+    Newer,
+    /// Whenever the contents differ.
+    Always,
+}
+
+impl Overwrite {
+    /// Parses when to overwrite from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "newer" => Ok(Overwrite::Newer),
+            "always" => Ok(Overwrite::Always),
+            _ => Err(format!("Unknown overwrite condition {:?}", name)),
+        }
+    }
+}
+
+/// What to do when a file's destination is taken by a different file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collision {
+    /// Add the first free number to the name, such as "statement (2).pdf".
+    #[default]
+    Number,
+    /// Add the start of the file's checksum to the name, such as "statement (3fa9c2d1).pdf".
+    Hash,
+    /// Leave the file in place.
+    Fail,
+}
+
+impl Collision {
+    /// Parses the strategy from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "number" => Ok(Collision::Number),
+            "hash" => Ok(Collision::Hash),
+            "fail" => Ok(Collision::Fail),
+            _ => Err(format!("Unknown collision strategy {:?}", name)),
+        }
+    }
+}
+
+/// How the files are placed in their buckets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transfer {
+    /// The files are moved. Where they cannot be renamed, as their bucket is on another device,
+    /// they are copied and then removed.
+    #[default]
+    Move,
+    /// The files are copied, leaving the originals in place.
+    Copy,
+    /// The files are hard linked into their buckets, leaving the originals in place without
+    /// taking up more space. The buckets must be on the same file system as the files.
+    Hardlink,
+}
+
+/// What was done to place a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// The file was moved to its destination.
+    Moved,
+    /// The file was moved to the given path, as its destination was taken by a different file.
+    Renamed(path::PathBuf),
+    /// The file was left in place, for the given reason, as the file at its destination was kept.
+    Skipped(String),
+    /// The destination already exists, so the file is treated as already archived. It may have
+    /// been removed.
+    AlreadyArchived,
+    /// The file is already at its destination, or the destination has the same contents, so it
+    /// was left alone.
+    UpToDate,
+    /// The file was left where it is and tagged with its year directory.
+    Tagged,
+}
+
+/// Move or copy the file to its destination, creating the directory it goes in if needed. Nothing
+/// is done if the destination already has the same contents, so classifying again does not change
+/// anything. The changes made are recorded in the journal, if there is one.
+pub fn place(
+    file_move: &Move,
+    settings: &Settings,
+    mut journal: Option<&mut Journal>,
+) -> Result<Placement, PlaceError> {
+    let transfer = settings.transfer;
+    let source = winpath::long(&file_move.source);
+    let path: &path::Path = &source;
+    let dest_dir = file_move
+        .destination
+        .parent()
+        .ok_or("destination has no parent")?;
+    let file_name = file_move
+        .destination
+        .file_name()
+        .ok_or("destination has no file name")?;
+    let dest_dir = winpath::long(&existing_dir(dest_dir)).into_owned();
+    let mut dest = existing_file(&dest_dir, file_name);
+    let mut renamed = false;
+    let mut replace = false;
+    if settings.skip_existing && dest.exists() {
+        if settings.remove_archived && !is_same_file(path, &dest)? && same_contents(path, &dest)? {
+            fs::remove_file(path).map_err(|e| PlaceError::io("could not remove file", e))?;
+            audit::record("remove", path, Some(&dest), None);
+            report::detail!(
+                "Removed {} as it is already archived in {}",
+                path.display(),
+                dest_dir.display()
+            );
+        } else {
+            report::detail!(
+                "{} is already archived in {}",
+                path.display(),
+                dest_dir.display()
+            );
+        }
+        return Ok(Placement::AlreadyArchived);
+    } else if dest.exists() && same_contents(path, &dest)? {
+        report::detail!("{} is up to date in {}", path.display(), dest_dir.display());
+        return Ok(Placement::UpToDate);
+    } else if let (true, Some(overwrite)) = (dest.exists(), settings.overwrite) {
+        if overwrite == Overwrite::Newer && !is_newer(path, &dest)? {
+            return Ok(Placement::Skipped(format!(
+                "{} is not older",
+                dest.display()
+            )));
+        }
+        report::detail!("Replacing {}", dest.display());
+        replace = true;
+    } else if dest.exists() {
+        match free_name(path, &dest, settings.collision)? {
+            Some(free) => {
+                report::detail!(
+                    "{} is taken, using {}",
+                    dest.display(),
+                    free.file_name().unwrap_or_default().to_string_lossy()
+                );
+                dest = free;
+                renamed = true;
+            }
+            None => return Ok(Placement::UpToDate),
+        }
+    }
+    report::detail!("Placing {} in {}", path.display(), dest_dir.display());
+
+    if !dest_dir.exists() {
+        report::detail!("directory {:?} doesn't exit, creating it", &dest_dir);
+        create_dir(&dest_dir, journal.as_deref_mut())?;
+    }
+
+    if !dest_dir.is_dir() {
+        return Err(PlaceError::new(
+            io::ErrorKind::NotADirectory,
+            &format!("{:?} is not a directory", dest_dir),
+        ));
+    }
+    if dest.exists() && !replace {
+        return Err(PlaceError::new(
+            io::ErrorKind::AlreadyExists,
+            &format!("{:?} already exists", dest),
+        ));
+    }
+    if let (true, Some(backup)) = (replace, &settings.backup) {
+        back_up(file_move, &dest, backup, journal.as_deref_mut())?;
+    }
+
+    // Only a copy reads the file while placing it. Otherwise, the checksum for the manifest is read
+    // from the placed file, so that a rename doesn't have to read the file at all to place it.
+    let copied = match transfer {
+        Transfer::Move => match fs::rename(path, &dest) {
+            Ok(()) => None,
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let checksum = copy_verified(path, &dest)?;
+                fs::remove_file(path).map_err(|e| {
+                    PlaceError::io("copied file but could not remove the original", e)
+                })?;
+                Some(checksum)
+            }
+            Err(e) => return Err(PlaceError::io("could not move file", e)),
+        },
+        Transfer::Copy => Some(copy_verified(path, &dest)?),
+        Transfer::Hardlink => {
+            link_file(path, &dest)?;
+            None
+        }
+    };
+    let checksum = copied.map_or_else(|| manifest::checksum(&dest), Ok);
+    report::placed(&dest);
+    let action = match (replace, transfer) {
+        (true, _) => "replace",
+        (false, Transfer::Move) => "move",
+        (false, Transfer::Copy) => "copy",
+        (false, Transfer::Hardlink) => "link",
+    };
+    audit::record(action, path, Some(&dest), checksum.as_deref().ok());
+
+    let placed = Move {
+        destination: dest.clone(),
+        ..file_move.clone()
+    };
+    if let Some(journal) = journal.as_deref_mut() {
+        journal.placed(path, &dest, transfer);
+        journal.manifest(&manifest::manifest_path(&placed));
+    }
+    if settings.leave_symlink && transfer == Transfer::Move {
+        match leave_symlink(path, &dest) {
+            Ok(()) => {
+                if let Some(journal) = journal {
+                    journal.linked(path, &dest);
+                }
+            }
+            Err(e) => report::note!(
+                "Could not leave a link to {} at {}: {}",
+                dest.display(),
+                path.display(),
+                e
+            ),
+        }
+    }
+    if let Err(e) = checksum.and_then(|checksum| manifest::record(&placed, &checksum)) {
+        report::note!("Could not record {} in the manifest: {}", dest.display(), e);
+    }
+    if settings.tag {
+        let year_dir = manifest::manifest_dir(&placed);
+        let label = year_dir.file_name().unwrap_or_default().to_string_lossy();
+        if let Err(e) = tag::tag(&dest, &label) {
+            report::note!("Could not tag {} with {}: {}", dest.display(), label, e);
+        }
+    }
+    if renamed {
+        Ok(Placement::Renamed(dest))
+    } else {
+        Ok(Placement::Moved)
+    }
+}
+
+/// Creates a symbolic link at the original location of the moved file to where it is now, relative
+/// to the directory that the link is in.
+pub(crate) fn leave_symlink(original: &path::Path, dest: &path::Path) -> io::Result<()> {
+    let dir = original
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(path::Path::new("."))
+        .canonicalize()?;
+    let dest = dest.canonicalize()?;
+    let common = dir
+        .components()
+        .zip(dest.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let target: path::PathBuf = dir
+        .components()
+        .skip(common)
+        .map(|_| path::Component::ParentDir)
+        .chain(dest.components().skip(common))
+        .collect();
+    symlink(&target, original)?;
+    audit::record("link", original, Some(&dest), None);
+    report::detail!(
+        "Left a link to {} at {}",
+        target.display(),
+        original.display()
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &path::Path, link: &path::Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &path::Path, link: &path::Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &path::Path, _link: &path::Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Checks if the file is a link to a file that has already been classified, as those left by
+/// `leave_symlink` are.
+fn is_left_link(path: &path::Path, layout: &Layout) -> bool {
+    let left = fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink())
+        && path
+            .canonicalize()
+            .ok()
+            .and_then(|target| target.parent().and_then(|dir| layout.classified_root(dir)))
+            .is_some();
+    if left {
+        report::detail!(
+            "Skipping {} as it is a link to a file that has already been classified",
+            path.display()
+        );
+    }
+    left
+}
+
+/// Creates the directory and any of its parents that are missing, recording them in the journal.
+fn create_dir(dir: &path::Path, journal: Option<&mut Journal>) -> Result<(), PlaceError> {
+    let missing: Vec<&path::Path> = dir.ancestors().take_while(|dir| !dir.exists()).collect();
+    fs::create_dir_all(dir).map_err(|e| PlaceError::io("could not create directory", e))?;
+    if let Some(journal) = journal {
+        missing
+            .iter()
+            .rev()
+            .for_each(|dir| journal.created_dir(dir));
+    }
+    Ok(())
+}
+
+/// Moves the file at the destination, which is about to be replaced, into the backup directory in
+/// the base directory. It keeps its path relative to the base directory, so "2023FY/a.pdf" is
+/// backed up as "<backup>/2023FY/a.pdf".
+fn back_up(
+    file_move: &Move,
+    dest: &path::Path,
+    backup: &path::Path,
+    mut journal: Option<&mut Journal>,
+) -> Result<(), PlaceError> {
+    let manifest = manifest::manifest_path(file_move);
+    let base = manifest
+        .parent()
+        .and_then(path::Path::parent)
+        .ok_or("destination has no base directory")?;
+    let relative = dest
+        .strip_prefix(base)
+        .unwrap_or(dest.file_name().map(path::Path::new).unwrap_or(dest));
+    let backup_path = base.join(backup).join(relative);
+    if let Some(backup_dir) = backup_path.parent() {
+        create_dir(backup_dir, journal.as_deref_mut())?;
+    }
+    fs::rename(dest, &backup_path)
+        .map_err(|e| PlaceError::io(&format!("could not back up {}", dest.display()), e))?;
+    report::note!("Backed up {} to {}", dest.display(), backup_path.display());
+    audit::record("back-up", dest, Some(&backup_path), None);
+    if let Some(journal) = journal {
+        journal.placed(dest, &backup_path, Transfer::Move);
+    }
+    Ok(())
+}
+
+/// Checks if the paths are for the same file.
+fn is_same_file(a: &path::Path, b: &path::Path) -> Result<bool, String> {
+    let canonical = |path: &path::Path| {
+        path.canonicalize()
+            .map_err(|e| format!("Could not find {}: {}", path.display(), e))
+    };
+    Ok(canonical(a)? == canonical(b)?)
+}
+
+/// Checks if the file was modified more recently than the other one.
+fn is_newer(path: &path::Path, other: &path::Path) -> Result<bool, String> {
+    let modified = |path: &path::Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+    };
+    Ok(modified(path)? > modified(other)?)
+}
+
+/// Finds another name for the file, as described by the collision strategy, when its destination
+/// is taken by a different file. Gets `None` if the file is already there under that name.
+fn free_name(
+    source: &path::Path,
+    dest: &path::Path,
+    collision: Collision,
+) -> Result<Option<path::PathBuf>, PlaceError> {
+    let dest_dir = dest.parent().ok_or("destination has no parent")?;
+    let name = dest
+        .file_name()
+        .ok_or("destination has no file name")?
+        .to_string_lossy();
+    let names: Box<dyn Iterator<Item = String>> = match collision {
+        Collision::Number => Box::new((2..).map(|number| numbered(&name, number))),
+        Collision::Hash => Box::new(iter::once(tagged(&name, &manifest::checksum(source)?[..8]))),
+        Collision::Fail => Box::new(iter::empty()),
+    };
+    for name in names {
+        let free = dest_dir.join(name);
+        if !free.exists() {
+            return Ok(Some(free));
+        } else if same_contents(source, &free)? {
+            report::detail!("{} is up to date as {}", source.display(), free.display());
+            return Ok(None);
+        }
+    }
+    Err(PlaceError::new(
+        io::ErrorKind::AlreadyExists,
+        &format!("{:?} already exists", dest),
+    ))
+}
+
+/// Gets the name with a number added before its extension, such as "statement (2).pdf".
+pub fn numbered(name: &str, number: u32) -> String {
+    tagged(name, &number.to_string())
+}
+
+/// Gets the name with the tag added in brackets before its extension. If the name is a path
+/// separated by "/", the tag is added to the last part.
+fn tagged(name: &str, tag: &str) -> String {
+    let file_start = name.rfind('/').map_or(0, |slash| slash + 1);
+    match name[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = name.split_at(file_start + dot);
+            format!("{} ({}){}", stem, tag, extension)
+        }
+        _ => format!("{} ({})", name, tag),
+    }
+}
+
+/// Gets a path for a temporary file in the directory of the destination, so that it can be renamed
+/// over the destination. The file is removed when the path is dropped, unless it is persisted.
+fn temp_beside(dest: &path::Path) -> io::Result<tempfile::TempPath> {
+    let dir = match dest.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => path::Path::new("."),
+    };
+    Ok(tempfile::Builder::new()
+        .prefix(".tmp")
+        .tempfile_in(dir)?
+        .into_temp_path())
+}
+
+/// Hard links the file to the destination. As with [`copy_verified`], the link is made beside the
+/// destination and renamed over it, so that a file being replaced is only removed once the link
+/// has been made.
+fn link_file(source: &path::Path, dest: &path::Path) -> Result<(), PlaceError> {
+    let link = |temp: &tempfile::TempPath| {
+        fs::remove_file(temp)?;
+        fs::hard_link(source, temp)
+    };
+    let temp = temp_beside(dest).map_err(|e| PlaceError::io("could not link file", e))?;
+    link(&temp).map_err(|e| PlaceError::io("could not link file", e))?;
+    temp.persist(dest)
+        .map_err(|e| PlaceError::io("could not link file", e.error))
+}
+
+/// Copies the file and checks that the copy has the same checksum, returning it. The copy is
+/// made beside the destination and only replaces it once it has been checked, so that a file
+/// being replaced is kept if the copy fails. The copy is given the times and, where possible, the
+/// owner of the original, along with its permissions.
+pub fn copy_verified(source: &path::Path, dest: &path::Path) -> Result<String, PlaceError> {
+    let checksum = manifest::checksum(source)?;
+    let temp = temp_beside(dest).map_err(|e| PlaceError::io("could not copy file", e))?;
+    if copy_file(source, &temp).map_err(|e| PlaceError::io("could not copy file", e))? {
+        report::detail!("Cloned {} to {}", source.display(), dest.display());
+    }
+    if manifest::checksum(&temp)? != checksum {
+        return Err(PlaceError::new(
+            io::ErrorKind::InvalidData,
+            "the copy does not match the original",
+        ));
+    }
+    if let Err(e) = preserve_metadata(source, &temp) {
+        report::note!(
+            "Could not give {} the times of {}: {}",
+            dest.display(),
+            source.display(),
+            e
+        );
+    }
+    temp.persist(dest)
+        .map_err(|e| PlaceError::io("could not copy file", e.error))?;
+    Ok(checksum)
+}
+
+/// Copies the file to a path where there is none, as a reflink where the file system supports it
+/// (such as Btrfs, XFS and APFS), which shares the contents with the original until either is
+/// changed. Elsewhere, or across file systems, the contents are copied. Gets whether it was
+/// cloned.
+fn copy_file(source: &path::Path, dest: &path::Path) -> io::Result<bool> {
+    // The name is kept free, as clonefile will not replace a file.
+    fs::remove_file(dest)?;
+    match reflink(source, dest) {
+        Ok(()) => Ok(true),
+        Err(_) => fs::copy(source, dest).map(|_| false),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &path::Path, dest: &path::Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let source = fs::File::open(source)?;
+    let dest = fs::File::create(dest)?;
+    // SAFETY: both descriptors belong to files that stay open for the duration of the call.
+    if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    dest.set_permissions(source.metadata()?.permissions())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink(source: &path::Path, dest: &path::Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let source = ffi::CString::new(source.as_os_str().as_bytes())?;
+    let dest = ffi::CString::new(dest.as_os_str().as_bytes())?;
+    // SAFETY: both are valid NUL terminated strings that outlive the call.
+    if unsafe { libc::clonefile(source.as_ptr(), dest.as_ptr(), 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_source: &path::Path, _dest: &path::Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Gives the copy the extended attributes and owner of the original, where the user is allowed
+/// to, and then when it was last accessed and modified. The permissions are already copied with
+/// the contents.
+fn preserve_metadata(source: &path::Path, dest: &path::Path) -> io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    preserve_xattrs(source, dest);
+    preserve_owner(&metadata, dest);
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    fs::File::options()
+        .write(true)
+        .open(dest)
+        .or_else(|_| fs::File::open(dest))?
+        .set_times(times)
+}
+
+/// Gives the copy the extended attributes of the original, such as the Finder tags and quarantine
+/// flag on macOS, so that tags applied to the file are kept. Those that cannot be set, such as
+/// security labels that only the superuser can change, are left out. A read-only copy is made
+/// writable while they are set, as the attributes of one cannot be changed.
+#[cfg(unix)]
+fn preserve_xattrs(source: &path::Path, dest: &path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(names) = xattr::list(source) else {
+        return;
+    };
+    let names: Vec<ffi::OsString> = names.collect();
+    if names.is_empty() {
+        return;
+    }
+    let permissions = fs::metadata(dest).map(|metadata| metadata.permissions());
+    if let Ok(permissions) = &permissions {
+        let writable = fs::Permissions::from_mode(permissions.mode() | 0o200);
+        let _ = fs::set_permissions(dest, writable);
+    }
+    for name in names {
+        let set = xattr::get(source, &name)
+            .and_then(|value| xattr::set(dest, &name, &value.unwrap_or_default()));
+        if let Err(e) = set {
+            report::detail!(
+                "Could not give {} the attribute {}: {}",
+                dest.display(),
+                name.to_string_lossy(),
+                e
+            );
+        }
+    }
+    if let Ok(permissions) = permissions {
+        let _ = fs::set_permissions(dest, permissions);
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_xattrs(_source: &path::Path, _dest: &path::Path) {}
+
+/// Gives the copy the owner and group of the original. Only the superuser can give away a file,
+/// so otherwise only the group is changed, if the user is in it.
+#[cfg(unix)]
+fn preserve_owner(metadata: &fs::Metadata, dest: &path::Path) {
+    use std::os::unix::fs::{chown, MetadataExt};
+    if chown(dest, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = chown(dest, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_metadata: &fs::Metadata, _dest: &path::Path) {}
+
+/// Gets the directory to place files in. Where a directory in the path does not exist, but there
+/// is one whose name only differs in case (such as "2022fy" for "2022FY"), that one is used
+/// instead.
+pub fn existing_dir(dir: &path::Path) -> path::PathBuf {
+    let (parent, name) = match (dir.parent(), dir.file_name()) {
+        (Some(parent), Some(name)) if !dir.exists() => (parent, name),
+        _ => return dir.to_path_buf(),
+    };
+    let parent = existing_dir(parent);
+    let lowercase_name = unicode::nfc(&name.to_string_lossy()).to_lowercase();
+    let same_name = parent.read_dir().ok().and_then(|entries| {
+        entries.flatten().find(|entry| {
+            unicode::nfc(&entry.file_name().to_string_lossy()).to_lowercase() == lowercase_name
+                && entry.path().is_dir()
+        })
+    });
+    match same_name {
+        Some(entry) => entry.path(),
+        None => parent.join(name),
+    }
+}
+
+/// Gets the path of the file in the directory. If there is none with the name, but there is one
+/// whose name is only encoded differently (such as "Café" with a combining accent), that one is
+/// used instead.
+fn existing_file(dir: &path::Path, name: &ffi::OsStr) -> path::PathBuf {
+    let path = dir.join(name);
+    if path.exists() {
+        return path;
+    }
+    dir.read_dir()
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .find(|entry| unicode::same_name(&entry.file_name(), name))
+        })
+        .map_or(path, |entry| entry.path())
+}
+
+/// Checks if the files are the same file, or have the same contents.
+fn same_contents(a: &path::Path, b: &path::Path) -> Result<bool, String> {
+    if is_same_file(a, b)? {
+        return Ok(true);
+    }
+    let len = |path: &path::Path| {
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+    };
+    if len(a)? != len(b)? {
+        return Ok(false);
+    }
+    Ok(manifest::checksum(a)? == manifest::checksum(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{
+        copy_verified, numbered, plan_dir, Collision, Overwrite, Plan, Settings, Transfer,
+    };
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+
+    /// Plans where the files in the directory belong with the layout.
+    fn plan_in(base_path: &std::path::Path, layout: &Layout) -> Plan {
+        Plan::new(
+            &plan_dir(base_path, layout, &Extractor::default(), false, &[])
+                .unwrap()
+                .0,
+        )
+    }
+
+    #[test]
+    fn test_reuses_dir_in_other_case() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir_all(base_path.join("2023fy").join("q1")).unwrap();
+        fs::write(base_path.join("text_10JUL2022.txt"), "").unwrap();
+
+        let layout = Layout {
+            granularity: crate::bucket::Granularity::Quarter,
+            ..Layout::default()
+        };
+        let outcome = plan_in(base_path, &layout).apply(&Settings::default());
+        assert_eq!(outcome.placed, 1);
+        assert!(base_path
+            .join("2023fy")
+            .join("q1")
+            .join("text_10JUL2022.txt")
+            .is_file());
+        assert!(base_path
+            .join("2023fy")
+            .join(crate::manifest::MANIFEST_NAME)
+            .is_file());
+    }
+
+    #[test]
+    fn test_matches_name_encoded_differently() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir(base_path.join("2023FY")).unwrap();
+        fs::write(base_path.join("2023FY/Caf\u{e9}_10JUL2022.txt"), "a").unwrap();
+        fs::write(base_path.join("Cafe\u{301}_10JUL2022.txt"), "a").unwrap();
+
+        let outcome = plan_in(base_path, &Layout::default()).apply(&Settings::default());
+        assert_eq!(outcome.up_to_date, 1);
+        assert_eq!(fs::read_dir(base_path.join("2023FY")).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_name_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        let name = std::ffi::OsStr::from_bytes(b"inv\xff_10JUL2022.pdf");
+        fs::write(base_path.join(name), "").unwrap();
+
+        let plan = plan_in(base_path, &Layout::default());
+        assert_eq!(
+            plan.moves[0].destination,
+            base_path.join("2023FY").join(name)
+        );
+        let plan_path = base_path.join("plan.json");
+        plan.save(&plan_path).unwrap();
+        assert!(Plan::load(&plan_path).unwrap().moves.is_empty());
+
+        let outcome = plan.apply(&Settings::default());
+        assert_eq!(outcome.placed, 1);
+        assert!(base_path.join("2023FY").join(name).is_file());
+    }
+
+    #[test]
+    fn test_save_and_apply() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::write(base_path.join("text_10JUL2022.txt"), "").unwrap();
+        fs::write(base_path.join("text.txt"), "").unwrap();
+
+        let plan = plan_in(base_path, &Layout::default());
+        assert_eq!(plan.moves.len(), 1);
+        assert_eq!(
+            plan.moves[0].destination,
+            base_path.join("2023FY").join("text_10JUL2022.txt")
+        );
+        assert_eq!(plan.unclassified.len(), 1);
+        assert!(base_path.join("text_10JUL2022.txt").is_file());
+
+        let plan_path = base_path.join("plan.json");
+        plan.save(&plan_path).unwrap();
+        let loaded = Plan::load(&plan_path).unwrap();
+        assert_eq!(loaded, plan);
+
+        let outcome = loaded.apply(&Settings::default());
+        assert_eq!(outcome.placed, 1);
+        assert_eq!(outcome.unclassified, 1);
+        assert!(outcome.failures.is_empty());
+        assert!(base_path
+            .join("2023FY")
+            .join("text_10JUL2022.txt")
+            .is_file());
+        assert!(base_path.join("text.txt").is_file());
+
+        fs::write(base_path.join("text_10JUL2022.txt"), "").unwrap();
+        let again = plan_in(base_path, &Layout::default()).apply(&Settings::default());
+        assert_eq!(again.placed, 0);
+        assert_eq!(again.up_to_date, 1);
+        assert!(again.failures.is_empty());
+    }
+
+    #[test]
+    fn test_stamp_dates() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        let path = base_path.join("notes.txt");
+        fs::write(&path, "").unwrap();
+        let noon = chrono::NaiveDate::from_ymd_opt(2022, 9, 14)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(chrono::Local)
+            .unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(noon.into())
+            .unwrap();
+        fs::write(base_path.join("bill_10JUL2022.pdf"), "").unwrap();
+
+        let layout = Layout {
+            stamp_dates: true,
+            ..Layout::default()
+        };
+        let outcome = plan_in(base_path, &layout).apply(&Settings::default());
+        assert_eq!(outcome.placed, 2);
+        assert!(base_path.join("2023FY/notes_14SEP2022.txt").is_file());
+        assert!(base_path.join("2023FY/bill_10JUL2022.pdf").is_file());
+    }
+
+    #[test]
+    fn test_copy() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::write(base_path.join("text_10JUL2022.txt"), "contents").unwrap();
+
+        let plan = plan_in(base_path, &Layout::default());
+        let copy = Settings {
+            transfer: Transfer::Copy,
+            ..Settings::default()
+        };
+        let outcome = plan.apply(&copy);
+        assert_eq!((outcome.placed, outcome.failures.len()), (1, 0));
+        assert!(base_path.join("text_10JUL2022.txt").is_file());
+        assert_eq!(
+            fs::read_to_string(base_path.join("2023FY").join("text_10JUL2022.txt")).unwrap(),
+            "contents"
+        );
+        let manifest = fs::read_to_string(
+            base_path
+                .join("2023FY")
+                .join(crate::manifest::MANIFEST_NAME),
+        )
+        .unwrap();
+        assert!(manifest
+            .contains(&crate::manifest::checksum(&base_path.join("text_10JUL2022.txt")).unwrap()));
+
+        assert_eq!(plan.apply(&copy).up_to_date, 1);
+    }
+
+    #[test]
+    fn test_copy_verified() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let source = tempdir.path().join("a.txt");
+        let dest = tempdir.path().join("b.txt");
+        fs::write(&source, "contents").unwrap();
+        let last_year = std::time::SystemTime::now() - std::time::Duration::from_secs(365 * 86400);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(last_year)
+            .unwrap();
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+
+        copy_verified(&source, &dest).unwrap();
+        let copied = fs::metadata(&dest).unwrap();
+        assert_eq!(copied.modified().unwrap(), last_year);
+        assert!(copied.permissions().readonly());
+    }
+
+    #[test]
+    fn test_copy_keeps_replaced_file() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        let dest = base_path.join("text_10JUL2022.txt");
+        fs::write(&dest, "old").unwrap();
+        fs::create_dir(base_path.join("source")).unwrap();
+        fs::write(base_path.join("source").join("a.txt"), "a").unwrap();
+
+        // A directory cannot be copied as a file.
+        assert!(copy_verified(&base_path.join("source"), &dest).is_err());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old");
+        assert_eq!(fs::read_dir(base_path).unwrap().count(), 2);
+
+        fs::write(base_path.join("new.txt"), "new").unwrap();
+        copy_verified(&base_path.join("new.txt"), &dest).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+        assert_eq!(fs::read_dir(base_path).unwrap().count(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_xattrs() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let source = tempdir.path().join("a.txt");
+        let dest = tempdir.path().join("b.txt");
+        fs::write(&source, "contents").unwrap();
+        if xattr::set(&source, "user.xdg.tags", b"receipt").is_err() {
+            // The file system does not support extended attributes.
+            return;
+        }
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+
+        copy_verified(&source, &dest).unwrap();
+        assert_eq!(
+            xattr::get(&dest, "user.xdg.tags").unwrap(),
+            Some(b"receipt".to_vec())
+        );
+        assert!(fs::metadata(&dest).unwrap().permissions().readonly());
+    }
+
+    #[test]
+    fn test_hardlink() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::write(base_path.join("text_10JUL2022.txt"), "contents").unwrap();
+
+        let plan = plan_in(base_path, &Layout::default());
+        let hardlink = Settings {
+            transfer: Transfer::Hardlink,
+            ..Settings::default()
+        };
+        let outcome = plan.apply(&hardlink);
+        assert_eq!((outcome.placed, outcome.failures.len()), (1, 0));
+        // Both names are for the same file, so a change through one is seen through the other.
+        fs::write(base_path.join("text_10JUL2022.txt"), "changed").unwrap();
+        assert_eq!(
+            fs::read_to_string(base_path.join("2023FY").join("text_10JUL2022.txt")).unwrap(),
+            "changed"
+        );
+        assert_eq!(plan.apply(&hardlink).up_to_date, 1);
+
+        // An existing file is replaced by the link, even when it is not backed up first.
+        let existing = base_path.join("2023FY").join("text_01AUG2022.txt");
+        fs::write(&existing, "old").unwrap();
+        fs::write(base_path.join("text_01AUG2022.txt"), "new").unwrap();
+        let overwrite = Settings {
+            transfer: Transfer::Hardlink,
+            overwrite: Some(Overwrite::Always),
+            backup: None,
+            ..Settings::default()
+        };
+        let outcome = plan_in(base_path, &Layout::default()).apply(&overwrite);
+        assert_eq!((outcome.placed, outcome.failures.len()), (1, 0));
+        fs::write(base_path.join("text_01AUG2022.txt"), "changed").unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "changed");
+        let mut names: Vec<_> = fs::read_dir(base_path.join("2023FY"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                crate::manifest::MANIFEST_NAME,
+                "text_01AUG2022.txt",
+                "text_10JUL2022.txt"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_leave_symlink() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::write(base_path.join("text_10JUL2022.txt"), "contents").unwrap();
+        let settings = Settings {
+            leave_symlink: true,
+            ..Settings::default()
+        };
+        let outcome = plan_in(base_path, &Layout::default()).apply(&settings);
+        assert_eq!((outcome.placed, outcome.failures.len()), (1, 0));
+        let link = base_path.join("text_10JUL2022.txt");
+        assert_eq!(
+            fs::read_link(&link).unwrap(),
+            std::path::Path::new("2023FY").join("text_10JUL2022.txt")
+        );
+        assert_eq!(fs::read_to_string(&link).unwrap(), "contents");
+        // The link is not classified again.
+        assert_eq!(plan_in(base_path, &Layout::default()), Plan::default());
+    }
+
+    #[test]
+    fn test_numbered() {
+        assert_eq!(numbered("statement.pdf", 2), "statement (2).pdf");
+        assert_eq!(
+            numbered("Q1/statement.tar.gz", 2),
+            "Q1/statement.tar (2).gz"
+        );
+        assert_eq!(numbered("a.b/statement", 3), "a.b/statement (3)");
+        assert_eq!(numbered(".hidden", 2), ".hidden (2)");
+    }
+
+    #[test]
+    fn test_collision() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir(base_path.join("2023FY")).unwrap();
+        fs::write(base_path.join("2023FY").join("text_10JUL2022.txt"), "old").unwrap();
+
+        let apply = |contents: &str, collision| {
+            fs::write(base_path.join("text_10JUL2022.txt"), contents).unwrap();
+            let settings = Settings {
+                collision,
+                ..Settings::default()
+            };
+            plan_in(base_path, &Layout::default()).apply(&settings)
+        };
+        let outcome = apply("new", Collision::Fail);
+        assert_eq!(outcome.placed, 0);
+        assert_eq!(
+            outcome.failures[0].error.kind,
+            std::io::ErrorKind::AlreadyExists
+        );
+
+        let outcome = apply("new", Collision::Number);
+        let renamed = base_path.join("2023FY").join("text_10JUL2022 (2).txt");
+        assert_eq!(outcome.renamed[0].destination, renamed);
+        assert_eq!(fs::read_to_string(renamed).unwrap(), "new");
+        assert_eq!(apply("new", Collision::Number).up_to_date, 1);
+        fs::remove_file(base_path.join("text_10JUL2022.txt")).unwrap();
+        let outcome = apply("newer", Collision::Number);
+        assert!(outcome.renamed[0]
+            .destination
+            .ends_with("text_10JUL2022 (3).txt"));
+
+        let outcome = apply("newest", Collision::Hash);
+        let checksum = crate::manifest::checksum(&outcome.renamed[0].destination).unwrap();
+        assert!(outcome.renamed[0]
+            .destination
+            .ends_with(format!("text_10JUL2022 ({}).txt", &checksum[..8])));
+    }
+
+    #[test]
+    fn test_transactional() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir(base_path.join("2023FY")).unwrap();
+        for (name, placed, contents) in [
+            ("a_10JUL2022.txt", "same", "same"),
+            ("b_10JUL2022.txt", "old", "new"),
+        ] {
+            fs::write(base_path.join("2023FY").join(name), placed).unwrap();
+            fs::write(base_path.join(name), contents).unwrap();
+        }
+        fs::write(base_path.join("z_10JUL2022.txt"), "").unwrap();
+
+        let plan = plan_in(base_path, &Layout::default());
+        fs::remove_file(base_path.join("z_10JUL2022.txt")).unwrap();
+        let outcome = plan.apply(&Settings {
+            collision: Collision::Number,
+            transactional: true,
+            ..Settings::default()
+        });
+        // Only the failure is left once everything done before it is rolled back.
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(
+            outcome,
+            super::Outcome {
+                failures: outcome.failures.clone(),
+                ..super::Outcome::default()
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(base_path.join("b_10JUL2022.txt")).unwrap(),
+            "new"
+        );
+        assert!(!base_path.join("2023FY/b_10JUL2022 (2).txt").exists());
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        let existing = base_path.join("2023FY").join("text_10JUL2022.txt");
+        fs::create_dir(base_path.join("2023FY")).unwrap();
+        fs::write(&existing, "old").unwrap();
+
+        let apply = |contents: &str, overwrite| {
+            fs::write(base_path.join("text_10JUL2022.txt"), contents).unwrap();
+            let settings = Settings {
+                overwrite: Some(overwrite),
+                ..Settings::default()
+            };
+            plan_in(base_path, &Layout::default()).apply(&settings)
+        };
+        let set_modified = |path: &std::path::Path, seconds| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(
+                    std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds),
+                )
+                .unwrap();
+        };
+
+        set_modified(&existing, 2_000_000_000);
+        let outcome = apply("new", Overwrite::Newer);
+        assert_eq!((outcome.placed, outcome.skipped), (0, 1));
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old");
+
+        set_modified(&existing, 1_000_000_000);
+        let outcome = apply("new", Overwrite::Newer);
+        assert_eq!(outcome.placed, 1);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+
+        set_modified(&existing, 2_000_000_000);
+        let outcome = apply("newer", Overwrite::Always);
+        assert_eq!(outcome.placed, 1);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "newer");
+    }
+
+    #[test]
+    fn test_backup() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir_all(base_path.join("2023FY").join("Q1")).unwrap();
+        fs::write(
+            base_path.join("2023FY").join("Q1").join("a_10JUL2022.txt"),
+            "old",
+        )
+        .unwrap();
+        fs::write(base_path.join("a_10JUL2022.txt"), "new").unwrap();
+
+        let layout = Layout {
+            granularity: crate::bucket::Granularity::Quarter,
+            ..Layout::default()
+        };
+        let settings = Settings {
+            overwrite: Some(Overwrite::Always),
+            backup: Some(std::path::PathBuf::from(".classfy-backup/1")),
+            ..Settings::default()
+        };
+        let plan = plan_in(base_path, &layout);
+        assert_eq!(plan.apply(&settings).placed, 1);
+        let backup = base_path
+            .join(".classfy-backup")
+            .join("1")
+            .join("2023FY")
+            .join("Q1")
+            .join("a_10JUL2022.txt");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_skip_existing() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base_path = tempdir.path();
+        fs::create_dir(base_path.join("2023FY")).unwrap();
+        fs::write(base_path.join("2023FY").join("a_10JUL2022.txt"), "a").unwrap();
+        fs::write(base_path.join("2023FY").join("b_10JUL2022.txt"), "old").unwrap();
+        fs::write(base_path.join("a_10JUL2022.txt"), "a").unwrap();
+        fs::write(base_path.join("b_10JUL2022.txt"), "new").unwrap();
+        fs::write(base_path.join("c_10JUL2022.txt"), "c").unwrap();
+
+        let settings = Settings {
+            skip_existing: true,
+            remove_archived: true,
+            ..Settings::default()
+        };
+        let outcome = plan_in(base_path, &Layout::default()).apply(&settings);
+        assert_eq!((outcome.placed, outcome.already_archived), (1, 2));
+        assert!(!base_path.join("a_10JUL2022.txt").exists());
+        assert!(base_path.join("b_10JUL2022.txt").exists());
+        assert_eq!(
+            fs::read_to_string(base_path.join("2023FY").join("b_10JUL2022.txt")).unwrap(),
+            "old"
+        );
+    }
+}
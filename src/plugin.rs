@@ -0,0 +1,214 @@
+//! Finding the dates of files with WebAssembly plugins, for formats that classfy does not know.
+//!
+//! A plugin is a WebAssembly module that exports:
+//!
+//! - `memory`, its linear memory.
+//! - `classfy_alloc(len: i32) -> i32`, which reserves `len` bytes of the memory and gives where
+//!   they start.
+//! - `classfy_extract(name: i32, name_len: i32, data: i32, data_len: i32) -> i32`, which is given
+//!   the file name, as UTF-8, and the start of the file's contents in memory reserved with
+//!   `classfy_alloc`. It returns the date as YYYYMMDD, a month as YYYYMM00, a financial year as
+//!   YYYY0000, 0 if it does not know the date, or a negative number if it failed.
+//!
+//! Plugins are given no imports, so they cannot reach the file system or anything else, and each
+//! call is limited in how much work it can do.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path;
+
+use crate::date::FileDate;
+
+/// The most bytes from the start of a file that are given to a plugin.
+const READ_LIMIT: u64 = 64 * 1024;
+
+/// How much work a plugin may do for each file, in wasmi's units of fuel.
+const FUEL: u64 = 100_000_000;
+
+/// A plugin that has been compiled, ready to be asked for the dates of files.
+pub struct Plugin {
+    path: path::PathBuf,
+    engine: wasmi::Engine,
+    module: wasmi::Module,
+}
+
+impl Plugin {
+    /// Reads and compiles the plugin, checking that it can be instantiated.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        let wasm =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = wasmi::Engine::new(&config);
+        let module = wasmi::Module::new(&engine, &wasm[..])
+            .map_err(|e| format!("Could not compile {}: {}", path.display(), e))?;
+        let plugin = Plugin {
+            path: path.to_path_buf(),
+            engine,
+            module,
+        };
+        plugin.instantiate()?;
+        Ok(plugin)
+    }
+
+    /// Gets the date that the plugin gives the file, if it gives one. Each file is given to a new
+    /// instance of the plugin, so nothing is kept from one file to the next.
+    pub fn extract(&self, file_path: &path::Path) -> Result<Option<FileDate>, String> {
+        let name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let mut data = Vec::new();
+        fs::File::open(file_path)
+            .and_then(|file| file.take(READ_LIMIT).read_to_end(&mut data))
+            .map_err(|e| format!("Could not read {}: {}", file_path.display(), e))?;
+
+        let (mut store, instance) = self.instantiate()?;
+        let failed = |e: &dyn fmt::Display| format!("{} failed: {}", self.path.display(), e);
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| failed(&"it does not export its memory"))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "classfy_alloc")
+            .map_err(|e| failed(&e))?;
+        let extract = instance
+            .get_typed_func::<(i32, i32, i32, i32), i32>(&store, "classfy_extract")
+            .map_err(|e| failed(&e))?;
+        let mut pass = |bytes: &[u8]| -> Result<(i32, i32), String> {
+            let len = i32::try_from(bytes.len()).map_err(|e| failed(&e))?;
+            let ptr = alloc.call(&mut store, len).map_err(|e| failed(&e))?;
+            let offset = usize::try_from(ptr).map_err(|e| failed(&e))?;
+            memory
+                .write(&mut store, offset, bytes)
+                .map_err(|e| failed(&e))?;
+            Ok((ptr, len))
+        };
+        let (name_ptr, name_len) = pass(name.as_bytes())?;
+        let (data_ptr, data_len) = pass(&data)?;
+        let date = extract
+            .call(&mut store, (name_ptr, name_len, data_ptr, data_len))
+            .map_err(|e| failed(&e))?;
+        decode(date).map_err(|e| failed(&e))
+    }
+
+    fn instantiate(&self) -> Result<(wasmi::Store<()>, wasmi::Instance), String> {
+        let mut store = wasmi::Store::new(&self.engine, ());
+        store
+            .set_fuel(FUEL)
+            .expect("fuel is enabled for the engine");
+        let instance = wasmi::Linker::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| format!("Could not start {}: {}", self.path.display(), e))?;
+        Ok((store, instance))
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plugin").field("path", &self.path).finish()
+    }
+}
+
+/// Gets the date from the number that `classfy_extract` returns.
+fn decode(date: i32) -> Result<Option<FileDate>, String> {
+    if date < 0 {
+        return Err(format!("it returned the error {}", date));
+    }
+    let invalid = || format!("it returned {}, which is not a date", date);
+    let year = u16::try_from(date / 10000).map_err(|_| invalid())?;
+    let month = (date / 100 % 100) as u8;
+    let day = (date % 100) as u8;
+    Ok(match (date, month, day) {
+        (0, _, _) => None,
+        (_, 0, 0) => Some(FileDate::FinancialYear(year)),
+        (_, 1..=12, 0) => Some(FileDate::Month { year, month }),
+        _ if chrono::NaiveDate::from_ymd_opt(year.into(), month.into(), day.into()).is_some() => {
+            Some(FileDate::Day { year, month, day })
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{decode, Plugin};
+    use crate::date::FileDate;
+
+    /// A plugin that dates files starting with "I" as 10 July 2022.
+    const INVOICES: &str = r#"
+        (module
+          (memory (export "memory") 2)
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "classfy_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "classfy_extract")
+            (param $name i32) (param $name_len i32) (param $data i32) (param $data_len i32)
+            (result i32)
+            (if (result i32)
+              (i32.and
+                (i32.gt_s (local.get $data_len) (i32.const 0))
+                (i32.eq (i32.load8_u (local.get $data)) (i32.const 73)))
+              (then (i32.const 20220710))
+              (else (i32.const 0)))))
+    "#;
+
+    #[test]
+    fn test_extract() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let write = |name: &str, contents: &[u8]| {
+            let path = tempdir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let plugin =
+            Plugin::load(&write("invoices.wasm", &wat::parse_str(INVOICES).unwrap())).unwrap();
+        assert_eq!(
+            plugin.extract(&write("a.dat", b"INVOICE")),
+            Ok(Some(FileDate::Day {
+                year: 2022,
+                month: 7,
+                day: 10
+            }))
+        );
+        assert_eq!(plugin.extract(&write("b.dat", b"RECEIPT")), Ok(None));
+
+        // Plugins cannot import anything, and cannot run forever.
+        let importing = wat::parse_str(
+            r#"(module (import "wasi_snapshot_preview1" "fd_write"
+                 (func (param i32 i32 i32 i32) (result i32))))"#,
+        )
+        .unwrap();
+        assert!(Plugin::load(&write("importing.wasm", &importing)).is_err());
+        let looping = INVOICES.replace(
+            "(if (result i32)",
+            "(loop $forever (br $forever)) (if (result i32)",
+        );
+        let plugin =
+            Plugin::load(&write("looping.wasm", &wat::parse_str(looping).unwrap())).unwrap();
+        assert!(plugin.extract(&write("c.dat", b"INVOICE")).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0), Ok(None));
+        assert_eq!(
+            decode(20220700),
+            Ok(Some(FileDate::Month {
+                year: 2022,
+                month: 7
+            }))
+        );
+        assert_eq!(decode(20230000), Ok(Some(FileDate::FinancialYear(2023))));
+        assert!(decode(20220230).is_err());
+        assert!(decode(20221300).is_err());
+        assert!(decode(-1).is_err());
+    }
+}
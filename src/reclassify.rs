@@ -0,0 +1,86 @@
+//! Moving the files in an existing archive that no longer belong in the directory they are in, such
+//! as after changing the fiscal year convention or the rules in the configuration.
+
+use std::fs;
+use std::path;
+
+use crate::plan::{self, Move, Outcome, Plan, Settings};
+use crate::verify::Audit;
+
+/// Gets the plan that moves each of the misplaced files found in the archive into the directory
+/// that it belongs in.
+pub fn plan(root: &path::Path, audit: &Audit) -> Plan {
+    Plan {
+        moves: audit
+            .misplaced
+            .iter()
+            .map(|misplaced| Move {
+                source: misplaced.path.clone(),
+                destination: root.join(&misplaced.bucket).join(
+                    misplaced
+                        .path
+                        .file_name()
+                        .expect("file does not have a name"),
+                ),
+                date: None,
+            })
+            .collect(),
+        unclassified: Vec::new(),
+    }
+}
+
+/// Moves the files in the plan, as described by the settings.
+pub fn apply(root: &path::Path, plan: &Plan, settings: &Settings) -> Outcome {
+    let mut outcome = Outcome::default();
+    for file_move in &plan.moves {
+        let year = file_move
+            .destination
+            .strip_prefix(root)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|year| year.as_os_str().to_string_lossy().into_owned());
+        let bytes = fs::metadata(&file_move.source).map_or(0, |metadata| metadata.len());
+        let placed = plan::place(file_move, settings, None);
+        outcome.record(file_move, year, bytes, placed);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply, plan};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+    use crate::fiscal::FiscalYear;
+    use crate::plan::Settings;
+    use crate::verify::verify;
+
+    #[test]
+    fn test_reclassify() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let root = tempdir.path();
+        fs::create_dir(root.join("2023FY")).unwrap();
+        fs::write(root.join("2023FY/march_10MAR2023.pdf"), "").unwrap();
+        fs::write(root.join("2023FY/may_10MAY2023.pdf"), "").unwrap();
+
+        // Under the New Zealand convention, the year starts in April rather than July.
+        let layout = Layout {
+            fiscal: FiscalYear::convention("nz").unwrap(),
+            ..Layout::default()
+        };
+        let audit = verify(root, &layout, &Extractor::default()).unwrap();
+        let plan = plan(root, &audit);
+        assert_eq!(plan.moves.len(), 1);
+        assert_eq!(plan.moves[0].source, root.join("2023FY/may_10MAY2023.pdf"));
+        // Nothing is moved until the plan is applied.
+        assert!(root.join("2023FY/may_10MAY2023.pdf").is_file());
+
+        let outcome = apply(root, &plan, &Settings::default());
+        assert_eq!(outcome.placed, 1);
+        assert_eq!(outcome.placed_by_year.get("2024FY"), Some(&1));
+        assert!(root.join("2024FY/may_10MAY2023.pdf").is_file());
+        assert!(root.join("2023FY/march_10MAR2023.pdf").is_file());
+    }
+}
@@ -0,0 +1,596 @@
+//! Placing the classified files somewhere other than the local file system, given with `--dest`.
+//! The files are uploaded into a directory for each bucket under the destination, and each upload
+//! is recorded in the directory that the file came from, so that it is not uploaded again.
+
+use std::env;
+use std::ffi;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::config::Config;
+use crate::manifest;
+use crate::plan::{PlaceError, Placement, Settings, Transfer};
+use crate::report;
+use crate::sink::{Bucket, Sink};
+
+/// The name of the file, in each directory that files are uploaded from, that records the uploads.
+pub const UPLOADED_NAME: &str = ".classfy-uploaded.jsonl";
+
+/// The credentials for a destination, from the configuration file or the environment.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// A token to give instead of a user and password.
+    pub token: Option<String>,
+}
+
+impl Credentials {
+    /// Gets the credentials with any that are set in the environment variables starting with the
+    /// prefix, such as `CLASSFY_WEBDAV_USER`, replacing these.
+    pub fn from_env(&self, prefix: &str) -> Self {
+        let var = |name: &str| {
+            env::var(format!("{}_{}", prefix, name))
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        Credentials {
+            user: var("USER").or_else(|| self.user.clone()),
+            password: var("PASSWORD").or_else(|| self.password.clone()),
+            token: var("TOKEN").or_else(|| self.token.clone()),
+        }
+    }
+}
+
+/// An application registered with a service that uses OAuth, that classfy is authorised as.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OAuthClient {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+impl OAuthClient {
+    /// Gets the client with any that are set in the environment variables starting with the prefix,
+    /// such as `CLASSFY_GDRIVE_CLIENT_ID`, replacing these.
+    pub fn from_env(&self, prefix: &str) -> Self {
+        let var = |name: &str| {
+            env::var(format!("{}_{}", prefix, name))
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        OAuthClient {
+            client_id: var("CLIENT_ID").or_else(|| self.client_id.clone()),
+            client_secret: var("CLIENT_SECRET").or_else(|| self.client_secret.clone()),
+        }
+    }
+}
+
+/// The kinds of destination that files can be uploaded to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    /// An S3 bucket, or one in a service compatible with it.
+    S3,
+    /// A directory on a WebDAV server, such as Nextcloud, reached with HTTPS.
+    WebDav,
+    /// A directory on a server reached with SSH.
+    Sftp,
+    /// A folder in Google Drive.
+    GoogleDrive,
+    /// A folder in Dropbox.
+    Dropbox,
+}
+
+impl Scheme {
+    /// Parses the scheme from the start of a destination's URL.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "s3" => Ok(Scheme::S3),
+            "webdav" => Ok(Scheme::WebDav),
+            "sftp" => Ok(Scheme::Sftp),
+            "gdrive" => Ok(Scheme::GoogleDrive),
+            "dropbox" => Ok(Scheme::Dropbox),
+            _ => Err(format!("Unknown destination scheme {:?}", name)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scheme::S3 => "s3",
+            Scheme::WebDav => "webdav",
+            Scheme::Sftp => "sftp",
+            Scheme::GoogleDrive => "gdrive",
+            Scheme::Dropbox => "dropbox",
+        }
+    }
+}
+
+/// Where the files are uploaded to, such as `s3://bucket/prefix`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Destination {
+    pub scheme: Scheme,
+    /// The user to connect as, if given before the host.
+    pub user: Option<String>,
+    /// The host, or the name of the bucket for S3.
+    pub host: String,
+    /// The directory that the buckets are in, without a leading or trailing "/".
+    pub path: String,
+}
+
+impl Destination {
+    /// Parses the destination from its URL.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| format!("{:?} is not a URL", url))?;
+        let scheme = Scheme::parse(scheme)?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        // Dropbox has no host, as in dropbox:///Finance.
+        if host.is_empty() && scheme != Scheme::Dropbox {
+            return Err(format!("{:?} does not have a host", url));
+        }
+        Ok(Destination {
+            scheme,
+            user,
+            host: host.to_string(),
+            path: path.trim_matches('/').to_string(),
+        })
+    }
+
+    /// Gets the path of the file, relative to the destination, with "/" between the parts.
+    pub fn join(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.path, key)
+        }
+    }
+
+    /// Gets the URL of the file at the path, relative to the destination.
+    pub fn url(&self, key: &str) -> String {
+        Destination {
+            path: self.join(key),
+            ..self.clone()
+        }
+        .to_string()
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}://", self.scheme.name())?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        write!(f, "{}/{}", self.host, self.path)
+    }
+}
+
+/// Gets the sink that uploads the files to the destination.
+pub fn sink(
+    destination: &Destination,
+    settings: &Settings,
+    config: &Config,
+) -> Result<Box<dyn Sink>, String> {
+    match destination.scheme {
+        Scheme::S3 => s3_sink(destination, settings),
+        Scheme::WebDav => webdav_sink(destination, settings, &config.webdav),
+        Scheme::Sftp => sftp_sink(destination, settings, &config.sftp),
+        Scheme::GoogleDrive => gdrive_sink(destination, settings, &config.gdrive),
+        Scheme::Dropbox => dropbox_sink(destination, settings, &config.dropbox),
+    }
+}
+
+#[cfg(feature = "s3")]
+fn s3_sink(destination: &Destination, settings: &Settings) -> Result<Box<dyn Sink>, String> {
+    let upload = crate::s3::S3::new(destination)?;
+    Ok(Box::new(Remote::new(destination, settings, upload)))
+}
+
+#[cfg(not(feature = "s3"))]
+fn s3_sink(_destination: &Destination, _settings: &Settings) -> Result<Box<dyn Sink>, String> {
+    Err(String::from("classfy was built without the s3 feature"))
+}
+
+#[cfg(feature = "webdav")]
+fn webdav_sink(
+    destination: &Destination,
+    settings: &Settings,
+    credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    let credentials = credentials.from_env("CLASSFY_WEBDAV");
+    let upload = crate::webdav::WebDav::new(destination, &credentials)?;
+    Ok(Box::new(Remote::new(destination, settings, upload)))
+}
+
+#[cfg(not(feature = "webdav"))]
+fn webdav_sink(
+    _destination: &Destination,
+    _settings: &Settings,
+    _credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    Err(String::from("classfy was built without the webdav feature"))
+}
+
+#[cfg(feature = "sftp")]
+fn sftp_sink(
+    destination: &Destination,
+    settings: &Settings,
+    credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    let credentials = credentials.from_env("CLASSFY_SFTP");
+    let upload = crate::sftp::Sftp::connect(destination, &credentials)?;
+    Ok(Box::new(Remote::new(destination, settings, upload)))
+}
+
+#[cfg(not(feature = "sftp"))]
+fn sftp_sink(
+    _destination: &Destination,
+    _settings: &Settings,
+    _credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    Err(String::from("classfy was built without the sftp feature"))
+}
+
+#[cfg(feature = "gdrive")]
+fn gdrive_sink(
+    destination: &Destination,
+    settings: &Settings,
+    client: &OAuthClient,
+) -> Result<Box<dyn Sink>, String> {
+    let client = client.from_env("CLASSFY_GDRIVE");
+    let upload = crate::gdrive::GoogleDrive::authorise(destination, &client)?;
+    Ok(Box::new(Remote::new(destination, settings, upload)))
+}
+
+#[cfg(not(feature = "gdrive"))]
+fn gdrive_sink(
+    _destination: &Destination,
+    _settings: &Settings,
+    _client: &OAuthClient,
+) -> Result<Box<dyn Sink>, String> {
+    Err(String::from("classfy was built without the gdrive feature"))
+}
+
+#[cfg(feature = "dropbox")]
+fn dropbox_sink(
+    destination: &Destination,
+    settings: &Settings,
+    credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    let credentials = credentials.from_env("CLASSFY_DROPBOX");
+    let upload = crate::dropbox::Dropbox::new(destination, &credentials)?;
+    Ok(Box::new(Remote::new(destination, settings, upload)))
+}
+
+#[cfg(not(feature = "dropbox"))]
+fn dropbox_sink(
+    _destination: &Destination,
+    _settings: &Settings,
+    _credentials: &Credentials,
+) -> Result<Box<dyn Sink>, String> {
+    Err(String::from(
+        "classfy was built without the dropbox feature",
+    ))
+}
+
+/// Uploads files to a destination.
+pub trait Upload {
+    /// Uploads the file to the path, relative to the destination, creating any directories that
+    /// it goes in. Gets the ID that the destination gave the file, if it gives them.
+    fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError>;
+
+    /// Finds the ID of the file at the path if it already has the same contents as the file, so
+    /// that it does not need to be uploaded. This is only done by destinations that can tell
+    /// cheaply.
+    fn find_same(&mut self, _src: &path::Path, _key: &str) -> Result<Option<String>, PlaceError> {
+        Ok(None)
+    }
+}
+
+/// Places the files by uploading them. The originals are removed once they have been uploaded,
+/// unless they are being copied.
+pub struct Remote<U> {
+    destination: Destination,
+    transfer: Transfer,
+    upload: U,
+}
+
+impl<U: Upload> Remote<U> {
+    pub fn new(destination: &Destination, settings: &Settings, upload: U) -> Self {
+        Remote {
+            destination: destination.clone(),
+            transfer: settings.transfer,
+            upload,
+        }
+    }
+}
+
+impl<U: Upload> Sink for Remote<U> {
+    fn put(&mut self, src: &path::Path, bucket: &Bucket) -> Result<Placement, PlaceError> {
+        let key = key(bucket.file_name(src), &bucket.dir)?;
+        let url = self.destination.url(&key);
+        let sha256 = manifest::checksum(src)?;
+        let uploads = Uploads::read(&bucket.base)?;
+        if uploads.contains(&url, &sha256) {
+            report::detail!("{} is up to date in {}", src.display(), url);
+            return Ok(Placement::UpToDate);
+        }
+        if let Some(remote_id) = self.upload.find_same(src, &key)? {
+            report::detail!("{} is already in {}", src.display(), url);
+            let uploaded = Uploaded::new(src, &url, &sha256, Some(remote_id));
+            if let Err(e) = Uploads::record(&bucket.base, &uploaded) {
+                report::note!("Could not record the upload of {}: {}", src.display(), e);
+            }
+            return Ok(Placement::UpToDate);
+        }
+        report::detail!("Uploading {} to {}", src.display(), url);
+        let remote_id = self.upload.upload(src, &key)?;
+        let action = match self.transfer {
+            Transfer::Move => "move",
+            Transfer::Copy | Transfer::Hardlink => "copy",
+        };
+        audit::record(action, src, Some(path::Path::new(&url)), Some(&sha256));
+        let uploaded = Uploaded::new(src, &url, &sha256, remote_id);
+        if let Err(e) = Uploads::record(&bucket.base, &uploaded) {
+            report::note!("Could not record the upload of {}: {}", src.display(), e);
+        }
+        if self.transfer == Transfer::Move {
+            fs::remove_file(src).map_err(|e| {
+                PlaceError::io("uploaded file but could not remove the original", e)
+            })?;
+        }
+        Ok(Placement::Moved)
+    }
+}
+
+/// Gets the path that the file is uploaded to, relative to the destination, such as
+/// "2023FY/Q1/statement.pdf".
+fn key(file_name: &ffi::OsStr, dir: &path::Path) -> Result<String, PlaceError> {
+    let mut parts = Vec::new();
+    for component in dir.components() {
+        match component {
+            path::Component::Normal(part) => parts.push(part.to_string_lossy()),
+            path::Component::CurDir => {}
+            _ => {
+                return Err(PlaceError::from(format!(
+                    "{} is not a bucket",
+                    dir.display()
+                )))
+            }
+        }
+    }
+    parts.push(file_name.to_string_lossy());
+    Ok(parts.join("/"))
+}
+
+/// Percent-encodes everything but the unreserved characters, and "/" unless `slash` is set.
+pub fn uri_encode(text: &str, slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !slash => encoded.push('/'),
+            _ => write!(encoded, "%{:02X}", byte).expect("writing to a string cannot fail"),
+        }
+    }
+    encoded
+}
+
+/// A line in the record of the uploads from a directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct Uploaded {
+    original_path: path::PathBuf,
+    url: String,
+    sha256: String,
+    /// The ID that the destination gave the file, if it gives them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remote_id: Option<String>,
+    uploaded_at: String,
+}
+
+impl Uploaded {
+    fn new(src: &path::Path, url: &str, sha256: &str, remote_id: Option<String>) -> Self {
+        Uploaded {
+            original_path: src.to_path_buf(),
+            url: url.to_string(),
+            sha256: sha256.to_string(),
+            remote_id,
+            uploaded_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        }
+    }
+}
+
+/// The files that have been uploaded from a directory.
+struct Uploads {
+    entries: Vec<Uploaded>,
+}
+
+impl Uploads {
+    /// Reads the record in the directory, if there is one.
+    fn read(dir: &path::Path) -> Result<Self, String> {
+        let path = dir.join(UPLOADED_NAME);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Uploads {
+                    entries: Vec::new(),
+                })
+            }
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        let entries = io::BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line.map_err(|e| e.to_string())?;
+                serde_json::from_str(&line).map_err(|e| e.to_string())
+            })
+            .collect::<Result<_, String>>()
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Ok(Uploads { entries })
+    }
+
+    /// Checks if the same contents have already been uploaded to the URL.
+    fn contains(&self, url: &str, sha256: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.url == url && entry.sha256 == sha256)
+    }
+
+    /// Records the upload in the directory that the file was uploaded from.
+    fn record(dir: &path::Path, uploaded: &Uploaded) -> Result<(), String> {
+        let path = dir.join(UPLOADED_NAME);
+        let line = serde_json::to_string(uploaded)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| format!("Could not write to {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{uri_encode, Destination, Remote, Scheme, Upload, UPLOADED_NAME};
+    use crate::plan::{PlaceError, Placement, Settings, Transfer};
+    use crate::sink::{Bucket, Sink};
+
+    /// Uploads the files by copying them into a directory.
+    struct Copying(path::PathBuf);
+
+    impl Upload for Copying {
+        fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+            let dest = self.0.join(key);
+            fs::create_dir_all(dest.parent().unwrap()).unwrap();
+            fs::copy(src, dest).map_err(|e| PlaceError::io("could not copy", e))?;
+            Ok(Some(format!("id:{}", key)))
+        }
+
+        fn find_same(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+            let same =
+                fs::read(self.0.join(key)).is_ok_and(|contents| contents == fs::read(src).unwrap());
+            Ok(same.then(|| format!("id:{}", key)))
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let destination = Destination::parse("s3://records/archive/").unwrap();
+        assert_eq!(
+            destination,
+            Destination {
+                scheme: Scheme::S3,
+                user: None,
+                host: String::from("records"),
+                path: String::from("archive"),
+            }
+        );
+        assert_eq!(
+            destination.url("2023FY/a.pdf"),
+            "s3://records/archive/2023FY/a.pdf"
+        );
+        assert_eq!(Destination::parse("s3://records").unwrap().path, "");
+        assert!(Destination::parse("records/archive").is_err());
+        assert!(Destination::parse("ftp://host/archive").is_err());
+        assert!(Destination::parse("s3:///archive").is_err());
+
+        let destination = Destination::parse("sftp://alex@files.example:2222/~/archive").unwrap();
+        assert_eq!(destination.scheme, Scheme::Sftp);
+        assert_eq!(destination.user.as_deref(), Some("alex"));
+        assert_eq!(destination.host, "files.example:2222");
+        assert_eq!(
+            destination.url("2023FY/a.pdf"),
+            "sftp://alex@files.example:2222/~/archive/2023FY/a.pdf"
+        );
+
+        let destination = Destination::parse("dropbox:///Finance").unwrap();
+        assert_eq!(
+            (destination.host.as_str(), destination.path.as_str()),
+            ("", "Finance")
+        );
+        assert_eq!(
+            destination.url("2023FY/a.pdf"),
+            "dropbox:///Finance/2023FY/a.pdf"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("/docs/2023FY/bank statement (2).pdf", false),
+            "/docs/2023FY/bank%20statement%20%282%29.pdf"
+        );
+        assert_eq!(uri_encode("a/b", true), "a%2Fb");
+    }
+
+    #[test]
+    fn test_remote() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path().join("inbox");
+        let remote = tempdir.path().join("remote");
+        fs::create_dir(&base).unwrap();
+        let destination = Destination::parse("s3://records/archive").unwrap();
+        let bucket = Bucket {
+            base: base.clone(),
+            dir: path::PathBuf::from("2023FY/Q1"),
+            date: None,
+            name: None,
+        };
+        let path = base.join("a_10JUL2022.pdf");
+
+        let copy = Settings {
+            transfer: Transfer::Copy,
+            ..Settings::default()
+        };
+        let mut sink = Remote::new(&destination, &copy, Copying(remote.clone()));
+        fs::write(&path, "a").unwrap();
+        assert_eq!(sink.put(&path, &bucket), Ok(Placement::Moved));
+        assert!(path.is_file());
+        assert!(remote.join("2023FY/Q1/a_10JUL2022.pdf").is_file());
+        // The upload is recorded, so the same contents are not uploaded again.
+        fs::remove_dir_all(&remote).unwrap();
+        assert_eq!(sink.put(&path, &bucket), Ok(Placement::UpToDate));
+        assert!(!remote.exists());
+
+        let mut sink = Remote::new(&destination, &Settings::default(), Copying(remote.clone()));
+        fs::write(&path, "changed").unwrap();
+        assert_eq!(sink.put(&path, &bucket), Ok(Placement::Moved));
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(remote.join("2023FY/Q1/a_10JUL2022.pdf")).unwrap(),
+            "changed"
+        );
+        let uploads = fs::read_to_string(base.join(UPLOADED_NAME)).unwrap();
+        assert!(uploads.contains(r#""remote_id":"id:2023FY/Q1/a_10JUL2022.pdf""#));
+
+        // Contents that the destination already has are not uploaded again.
+        let other = tempdir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        let path = other.join("a_10JUL2022.pdf");
+        fs::write(&path, "changed").unwrap();
+        let bucket = Bucket {
+            base: other.clone(),
+            ..bucket
+        };
+        assert_eq!(sink.put(&path, &bucket), Ok(Placement::UpToDate));
+        assert!(path.is_file());
+        assert!(other.join(UPLOADED_NAME).is_file());
+    }
+}
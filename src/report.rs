@@ -0,0 +1,490 @@
+//! Output for the people and programs running classfy.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+
+use crate::plan::{Failure, Outcome, Plan};
+use crate::stats::Stats;
+use crate::syslog::{self, Syslog};
+
+/// Whether standard output is reserved for the NUL terminated paths of the placed files.
+static PRINT0: AtomicBool = AtomicBool::new(false);
+
+/// Whether to write the details of how each file was classified.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// The system log that messages are written to instead, if one was asked for.
+static SYSLOG: OnceLock<Syslog> = OnceLock::new();
+
+/// Whether standard output is reserved for a line of JSON for each event.
+static NDJSON: AtomicBool = AtomicBool::new(false);
+
+/// Whether to colour the status of each file.
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// The files whose dates could be read in more than one way, with how they were read, by their
+/// paths.
+static AMBIGUOUS: Mutex<BTreeMap<path::PathBuf, String>> = Mutex::new(BTreeMap::new());
+
+/// The width of the status column of the files, which fits the longest status.
+const STATUS_WIDTH: usize = 16;
+
+/// The width of the year column of the files, which fits the usual names of the years.
+const YEAR_WIDTH: usize = 8;
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+
+/// How the progress is written to standard output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Messages for people to read.
+    Text,
+    /// A line of JSON for each event, with the messages written to standard error instead.
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// Parses the format from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("Unknown output format {:?}", name)),
+        }
+    }
+}
+
+/// Something that happened during a run, written as a line of JSON with `--output ndjson`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// Files to classify were found in a directory, or in a list of files.
+    Scan {
+        source: &'a path::Path,
+        files: usize,
+    },
+    /// Another batch of the entries of a directory has been read, given with how many have been
+    /// read so far.
+    Progress {
+        source: &'a path::Path,
+        entries: u64,
+    },
+    /// The files were planned to be placed, or left in place.
+    Plan { moves: usize, unclassified: usize },
+    /// Something was done with a file, such as placing it.
+    Move {
+        path: &'a path::Path,
+        fy: Option<&'a str>,
+        action: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        detail: Option<&'a str>,
+    },
+    /// The date in the name of a file could be read in more than one way.
+    Ambiguous {
+        path: &'a path::Path,
+        detail: &'a str,
+    },
+    /// A file or directory could not be read or placed.
+    Error {
+        path: &'a path::Path,
+        error: &'a str,
+    },
+    /// The run has finished.
+    Done { stats: &'a Stats },
+}
+
+/// When to colour the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when writing to a terminal, and NO_COLOR is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parses the choice from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice {:?}", name)),
+        }
+    }
+}
+
+/// Where the messages are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    /// Standard output, or standard error with `set_print0`.
+    Stdout,
+    /// The system journal, or syslog where there is no journal.
+    Syslog,
+}
+
+impl LogTarget {
+    /// Parses the target from its name on the command line.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "stdout" => Ok(LogTarget::Stdout),
+            "syslog" => Ok(LogTarget::Syslog),
+            _ => Err(format!("Unknown log target {:?}", name)),
+        }
+    }
+}
+
+/// Reserve standard output for the paths of the placed files, each terminated by a NUL. Messages
+/// about the progress are written to standard error instead.
+pub fn set_print0() {
+    PRINT0.store(true, Ordering::Relaxed);
+}
+
+/// Also write the details of how each file was classified.
+pub fn set_verbose() {
+    VERBOSE.store(true, Ordering::Relaxed);
+}
+
+/// Colour the status of each file as chosen. This is to be called after `set_print0`, as it
+/// depends on whether the messages are written to standard output or standard error.
+pub fn set_color(choice: ColorChoice) {
+    let color = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let terminal = if PRINT0.load(Ordering::Relaxed) {
+                io::stderr().is_terminal()
+            } else {
+                io::stdout().is_terminal()
+            };
+            terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    };
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Write the progress to standard output in the format.
+pub fn set_output_format(format: OutputFormat) {
+    NDJSON.store(format == OutputFormat::Ndjson, Ordering::Relaxed);
+}
+
+/// Write the messages to the target.
+pub fn set_log_target(target: LogTarget) -> Result<(), String> {
+    if target == LogTarget::Syslog {
+        let _ = SYSLOG.set(Syslog::connect()?);
+    }
+    Ok(())
+}
+
+/// Writes the message only when the details were asked for.
+pub fn write_detail(message: fmt::Arguments) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        write_note(message);
+    }
+}
+
+/// Writes a message about the progress of the classification.
+pub fn write_note(message: fmt::Arguments) {
+    if let Some(syslog) = SYSLOG.get() {
+        log(syslog, syslog::INFO, &message.to_string(), &[]);
+    } else {
+        write_line(message);
+    }
+}
+
+/// Writes the line to standard output, or to standard error when standard output is reserved for
+/// paths or events.
+fn write_line(line: impl fmt::Display) {
+    if PRINT0.load(Ordering::Relaxed) || NDJSON.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Writes the event as a line of JSON, if events were asked for.
+pub fn event(event: &Event) {
+    if NDJSON.load(Ordering::Relaxed) {
+        match serde_json::to_string(event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => eprintln!("Could not write the event {:?}: {}", event, e),
+        }
+    }
+}
+
+/// Writes the event for the plan that is about to be followed.
+pub fn planned(plan: &Plan) {
+    event(&Event::Plan {
+        moves: plan.moves.len(),
+        unclassified: plan.unclassified.len(),
+    });
+}
+
+/// Records what was done with a file, such as "placed", the financial year it was placed in and
+/// the details, such as why it was left in place. This is written as a row of a table, with the
+/// status coloured if asked for, or to the system log with the details as fields. A file that
+/// failed has its event written when the failure is recorded, rather than here.
+pub fn action(path: &path::Path, fy: Option<&str>, action: &str, detail: Option<&str>) {
+    if action != "failed" {
+        event(&Event::Move {
+            path,
+            fy,
+            action,
+            detail,
+        });
+    }
+    if let Some(syslog) = SYSLOG.get() {
+        let file = path.to_string_lossy();
+        let mut fields = vec![("file", file.as_ref()), ("action", action)];
+        let mut message = match fy {
+            Some(fy) => {
+                fields.push(("fy", fy));
+                format!("{} {} in {}", action, file, fy)
+            }
+            None => format!("{} {}", action, file),
+        };
+        if let Some(detail) = detail {
+            fields.push(("detail", detail));
+            message += &format!(": {}", detail);
+        }
+        log(syslog, syslog::NOTICE, &message, &fields);
+    } else if !NDJSON.load(Ordering::Relaxed) {
+        write_line(action_row(
+            path,
+            fy,
+            action,
+            detail,
+            COLOR.load(Ordering::Relaxed),
+        ));
+    }
+}
+
+/// Gets the row of the table for what was done with a file, with the columns aligned.
+fn action_row(
+    path: &path::Path,
+    fy: Option<&str>,
+    action: &str,
+    detail: Option<&str>,
+    color: bool,
+) -> String {
+    let mut status = format!("{:<1$}", action, STATUS_WIDTH);
+    if color {
+        let code = match action {
+            "placed" | "renamed" => Some(GREEN),
+            "failed" => Some(RED),
+            "skipped" | "unclassified" | "up-to-date" | "already-archived" => Some(YELLOW),
+            _ => None,
+        };
+        if let Some(code) = code {
+            status = format!("\x1b[{}m{}\x1b[0m", code, status);
+        }
+    }
+    let mut row = format!(
+        "{} {:<3$} {}",
+        status,
+        fy.unwrap_or("-"),
+        path.display(),
+        YEAR_WIDTH
+    );
+    if let Some(detail) = detail {
+        row += &format!(" ({})", detail);
+    }
+    row
+}
+
+/// Writes the message to the system log, or to standard error if it cannot be.
+fn log(syslog: &Syslog, priority: u8, message: &str, fields: &[(&str, &str)]) {
+    if let Err(e) = syslog.send(priority, message, fields) {
+        eprintln!("Could not write to the system log: {}: {}", e, message);
+    }
+}
+
+/// Writes a message about the progress of the classification, formatted like `println!`.
+#[macro_export]
+macro_rules! note {
+    ($($arg:tt)*) => {
+        $crate::report::write_note(format_args!($($arg)*))
+    };
+}
+pub use crate::note;
+
+/// Writes the details of how a file was classified, formatted like `println!`, if they were asked
+/// for.
+#[macro_export]
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        $crate::report::write_detail(format_args!($($arg)*))
+    };
+}
+pub use crate::detail;
+
+/// Writes a summary of what needs attention after applying a plan, with the failures grouped by
+/// their cause.
+pub fn summarise(outcome: &Outcome) {
+    if outcome.already_archived > 0 {
+        note!("{} files were already archived", outcome.already_archived);
+    }
+    if !outcome.renamed.is_empty() {
+        note!("Renamed as their destination was taken:");
+        for renamed in &outcome.renamed {
+            note!(
+                "  {} as {}",
+                renamed.source.display(),
+                renamed.destination.display()
+            );
+        }
+    }
+    if !outcome.duplicates.is_empty() {
+        note!("Duplicates:");
+        for duplicate in &outcome.duplicates {
+            note!(
+                "  {} is the same as {}, {}",
+                duplicate.path.display(),
+                duplicate.original.display(),
+                duplicate.policy.action()
+            );
+        }
+    }
+    summarise_ambiguous();
+    summarise_failures(&outcome.failures);
+}
+
+/// Writes the files that could not be read or written, grouped by the cause.
+pub fn summarise_failures(failures: &[Failure]) {
+    if !failures.is_empty() {
+        note!("Failures:");
+        let mut kinds: Vec<io::ErrorKind> = Vec::new();
+        for failure in failures {
+            if !kinds.contains(&failure.error.kind) {
+                kinds.push(failure.error.kind);
+            }
+        }
+        for kind in kinds {
+            let failures: Vec<_> = failures
+                .iter()
+                .filter(|failure| failure.error.kind == kind)
+                .collect();
+            note!("  {} ({}):", kind, failures.len());
+            for failure in failures {
+                note!("    {}: {}", failure.path.display(), failure.error);
+            }
+        }
+    }
+}
+
+/// Records that the date in the name of the file could be read in more than one way, and how it
+/// was read, for the summary at the end of the run. Each file is recorded once, however often its
+/// name is read.
+pub fn ambiguous(path: &path::Path, detail: &str) {
+    let mut ambiguous = AMBIGUOUS.lock().unwrap_or_else(|e| e.into_inner());
+    if ambiguous.contains_key(path) {
+        return;
+    }
+    ambiguous.insert(path.to_path_buf(), detail.to_string());
+    drop(ambiguous);
+    event(&Event::Ambiguous { path, detail });
+}
+
+/// Writes the files whose dates could be read in more than one way since this was last called,
+/// one for each file.
+pub fn summarise_ambiguous() {
+    let ambiguous = mem::take(&mut *AMBIGUOUS.lock().unwrap_or_else(|e| e.into_inner()));
+    if !ambiguous.is_empty() {
+        note!("Ambiguous dates:");
+        for (path, detail) in ambiguous {
+            note!("  {}: {}", path.display(), detail);
+        }
+    }
+}
+
+/// Reports that a file has been placed at the given path.
+pub fn placed(path: &path::Path) {
+    if PRINT0.load(Ordering::Relaxed) {
+        let mut stdout = io::stdout().lock();
+        let written = stdout
+            .write_all(&path_bytes(path))
+            .and_then(|_| stdout.write_all(b"\0"));
+        if let Err(e) = written {
+            eprintln!("Could not write {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Gets the bytes of the path, as they would be given to the operating system where possible.
+#[cfg(unix)]
+fn path_bytes(path: &path::Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &path::Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+
+    use super::{action_row, ambiguous, Event, AMBIGUOUS};
+
+    #[test]
+    fn test_action_row() {
+        let path = path::Path::new("scans/a_10JUL2023.pdf");
+        assert_eq!(
+            action_row(path, Some("2024FY"), "placed", None, false),
+            "placed           2024FY   scans/a_10JUL2023.pdf"
+        );
+        assert_eq!(
+            action_row(path, None, "failed", Some("permission denied"), false),
+            "failed           -        scans/a_10JUL2023.pdf (permission denied)"
+        );
+        assert_eq!(
+            action_row(path, None, "skipped", None, true),
+            "\x1b[33mskipped         \x1b[0m -        scans/a_10JUL2023.pdf"
+        );
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let path = path::Path::new("scans/a_01-02-2022.pdf");
+        ambiguous(path, "read as 2022-02-01");
+        ambiguous(path, "read as 2022-01-02");
+        assert_eq!(
+            AMBIGUOUS.lock().unwrap().get(path).map(String::as_str),
+            Some("read as 2022-02-01")
+        );
+    }
+
+    #[test]
+    fn test_event() {
+        let event = Event::Move {
+            path: path::Path::new("scans/a_10JUL2023.pdf"),
+            fy: Some("2024FY"),
+            action: "placed",
+            detail: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"move","path":"scans/a_10JUL2023.pdf","fy":"2024FY","action":"placed"}"#
+        );
+        let event = Event::Plan {
+            moves: 2,
+            unclassified: 1,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"plan","moves":2,"unclassified":1}"#
+        );
+    }
+}
@@ -0,0 +1,523 @@
+//! Rules, from the configuration file, for how to find the date of particular files and where to
+//! place them. The rules are tried in order, and the first that matches the file is used.
+
+use std::path;
+use std::process;
+
+use crate::bucket::Layout;
+use crate::content;
+use crate::date::{Dated, Extractor, FileDate};
+use crate::extract;
+use crate::template::DirTemplate;
+use crate::unicode;
+
+/// The placeholder in a rule's destination for the directory that the layout places the file in.
+const BUCKET_PLACEHOLDER: &str = "{bucket}";
+
+/// The name of the capture group in a rule's regex that has the date, for rules with a format.
+const DATE_GROUP: &str = "date";
+
+/// Where a rule finds the date of the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateSource {
+    /// The file name, as it is found without a rule, or in the rule's format.
+    Filename,
+    /// When the file was last modified.
+    Mtime,
+    /// The contents of the file, as with `--scan-content`.
+    Content,
+    /// The properties of an Office document, or otherwise when the file was created.
+    Metadata,
+    /// The output of the rule's command.
+    Command,
+}
+
+impl DateSource {
+    /// Parses the source from its name in the configuration file.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "filename" => Ok(DateSource::Filename),
+            "mtime" => Ok(DateSource::Mtime),
+            "content" => Ok(DateSource::Content),
+            "metadata" => Ok(DateSource::Metadata),
+            "command" => Ok(DateSource::Command),
+            _ => Err(format!("Unknown extractor {:?}", name)),
+        }
+    }
+
+    /// Gets the name of the source in the configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            DateSource::Filename => "filename",
+            DateSource::Mtime => "mtime",
+            DateSource::Content => "content",
+            DateSource::Metadata => "metadata",
+            DateSource::Command => "command",
+        }
+    }
+}
+
+/// A rule for the files whose names match its glob and regex.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// Matches the names of the files that the rule applies to, if given.
+    pub glob: Option<globset::GlobMatcher>,
+    /// Matches the names of the files that the rule applies to, if given.
+    pub regex: Option<regex::Regex>,
+    /// Where the date of the file is found.
+    pub source: DateSource,
+    /// The format, as for `strftime`, of the date in the file name. The date is the text of the
+    /// regex's "date" group if it has one, or otherwise the whole name without its extension. For
+    /// the command extractor, this is the format of the date that the command prints.
+    pub format: Option<String>,
+    /// The program, and the arguments before the path of the file, that prints the date of the
+    /// file for the command extractor.
+    pub command: Vec<String>,
+    /// Where to place the file, relative to the directory being classified, instead of the
+    /// directory given by the layout.
+    pub destination: Option<String>,
+}
+
+impl Rule {
+    /// Creates the rule, checking that it matches something and that the format, command and
+    /// destination are valid.
+    pub fn new(
+        glob: Option<&str>,
+        regex: Option<&str>,
+        source: &str,
+        format: Option<&str>,
+        command: &[String],
+        destination: Option<&str>,
+    ) -> Result<Self, String> {
+        if glob.is_none() && regex.is_none() {
+            return Err(String::from("A rule needs a glob or a regex"));
+        }
+        let glob = glob
+            .map(|glob| {
+                globset::Glob::new(glob)
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|e| format!("Invalid glob {:?}: {}", glob, e))
+            })
+            .transpose()?;
+        let regex = regex
+            .map(|regex| {
+                regex::Regex::new(regex).map_err(|e| format!("Invalid regex {:?}: {}", regex, e))
+            })
+            .transpose()?;
+        let source = DateSource::parse(source)?;
+        if format.is_some() && !matches!(source, DateSource::Filename | DateSource::Command) {
+            return Err(String::from(
+                "Only the filename and command extractors have a format",
+            ));
+        }
+        if command.is_empty() == (source == DateSource::Command) {
+            return Err(String::from(
+                "The command extractor, and only it, needs a command",
+            ));
+        }
+        if let Some(destination) = destination {
+            let relative = path::Path::new(destination)
+                .components()
+                .all(|component| matches!(component, path::Component::Normal(_)));
+            if !relative {
+                return Err(format!(
+                    "Destination {:?} must be within the directory being classified",
+                    destination
+                ));
+            }
+            DirTemplate::parse(&destination.replace(BUCKET_PLACEHOLDER, "bucket"))?;
+        }
+        Ok(Rule {
+            glob,
+            regex,
+            source,
+            format: format.map(String::from),
+            command: command.to_vec(),
+            destination: destination.map(String::from),
+        })
+    }
+
+    /// Checks if the rule applies to every file that the other one does, as it does when it has the
+    /// same glob and regex, or matches all files.
+    pub fn covers(&self, other: &Rule) -> bool {
+        let glob = |rule: &Rule| {
+            rule.glob
+                .as_ref()
+                .map(|glob| glob.glob().glob().to_string())
+        };
+        let regex = |rule: &Rule| rule.regex.as_ref().map(|regex| regex.as_str().to_string());
+        let matches_all = self.regex.is_none()
+            && glob(self).is_some_and(|glob| matches!(glob.as_str(), "*" | "**" | "**/*"));
+        matches_all || (glob(self) == glob(other) && regex(self) == regex(other))
+    }
+
+    /// Describes the files that the rule applies to, by its glob and regex.
+    pub fn pattern(&self) -> String {
+        let glob = self
+            .glob
+            .as_ref()
+            .map(|glob| format!("glob {:?}", glob.glob().glob()));
+        let regex = self
+            .regex
+            .as_ref()
+            .map(|regex| format!("regex \"{}\"", regex.as_str()));
+        glob.into_iter()
+            .chain(regex)
+            .collect::<Vec<_>>()
+            .join(" and ")
+    }
+
+    /// Checks if the rule applies to the file.
+    pub fn matches(&self, path: &path::Path) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        self.glob.as_ref().is_none_or(|glob| glob.is_match(name))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&unicode::nfc(&name.to_string_lossy())))
+    }
+
+    /// Gets the date of the file from where the rule says it is.
+    pub fn get_date(&self, path: &path::Path, extractor: &Extractor) -> Result<FileDate, String> {
+        self.dated(path, extractor).map(|dated| dated.date)
+    }
+
+    /// Gets the date of the file as `get_date` does, along with whether the date in its name could
+    /// be read in more than one way, when the rule reads the name as `--date-order` says.
+    pub fn dated(&self, path: &path::Path, extractor: &Extractor) -> Result<Dated, String> {
+        let date = match self.source {
+            DateSource::Filename => match &self.format {
+                Some(format) => self.formatted_date(path, format),
+                None => return extractor.read_name(path),
+            },
+            DateSource::Mtime => extract::modified_date(path, extractor.timezone),
+            DateSource::Content => {
+                let scanning = Extractor {
+                    scan_limit: extractor.scan_limit.or(Some(content::DEFAULT_SCAN_LIMIT)),
+                    ..extractor.clone()
+                };
+                content::get_date(path, &scanning)?
+                    .ok_or_else(|| String::from("No date found in the contents"))
+            }
+            DateSource::Metadata => extract::created_date(path, extractor.timezone),
+            DateSource::Command => self.command_date(path),
+        };
+        date.map(Dated::from)
+    }
+
+    /// Runs the rule's command with the path of the file, and gets the date from the first line
+    /// that it prints. The date is in the rule's format, or otherwise like "2022-07-10", "2022-07"
+    /// or "2022FY".
+    fn command_date(&self, path: &path::Path) -> Result<FileDate, String> {
+        let (program, args) = self.command.split_first().expect("command is not empty");
+        let output = process::Command::new(program)
+            .args(args)
+            .arg(path)
+            .stdin(process::Stdio::null())
+            .output()
+            .map_err(|e| format!("Could not run {}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let text = stdout.lines().next().unwrap_or("").trim();
+        if text.is_empty() {
+            return Err(format!("{} did not print a date", program));
+        }
+        match &self.format {
+            Some(format) => chrono::NaiveDate::parse_from_str(text, format)
+                .map_err(|e| format!("{:?} is not a date in the format {:?}: {}", text, format, e))
+                .and_then(FileDate::from_naive),
+            None => text.parse(),
+        }
+    }
+
+    /// Gets the date in the file name in the rule's format.
+    fn formatted_date(&self, path: &path::Path, format: &str) -> Result<FileDate, String> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = unicode::nfc(&name);
+        let text = match self
+            .regex
+            .as_ref()
+            .and_then(|regex| regex.captures(&name))
+            .and_then(|captures| captures.name(DATE_GROUP))
+        {
+            Some(date) => date.as_str().to_string(),
+            None => {
+                unicode::nfc(&path.file_stem().unwrap_or_default().to_string_lossy()).into_owned()
+            }
+        };
+        let date = chrono::NaiveDate::parse_from_str(&text, format)
+            .map_err(|e| format!("{:?} is not a date in the format {:?}: {}", text, format, e))?;
+        FileDate::from_naive(date)
+    }
+
+    /// Gets where to place the file, relative to the directory being classified, given the
+    /// directory that the layout places it in and its financial year. Gives `None` if the rule
+    /// does not have a destination.
+    pub fn destination(
+        &self,
+        bucket: &path::Path,
+        fy: u16,
+    ) -> Option<Result<path::PathBuf, String>> {
+        let destination = self.destination.as_ref()?;
+        let template = destination.replace(BUCKET_PLACEHOLDER, &bucket.to_string_lossy());
+        Some(
+            DirTemplate::parse(&template)
+                .and_then(|template| template.render(fy))
+                .map(path::PathBuf::from),
+        )
+    }
+}
+
+/// What the rules make of a file name, found without the file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Trial {
+    /// The index of the rule that applies to the file, if any.
+    pub rule: Option<usize>,
+    pub date: Result<FileDate, String>,
+    /// The directory that the file is placed in, relative to the directory being classified.
+    pub bucket: Result<path::PathBuf, String>,
+}
+
+/// Works out which rule applies to a file with the name, its date and where it is placed, without
+/// the file. Only the dates in the names can be found this way, so the rules that find them
+/// elsewhere are reported as needing the file.
+pub fn trial(name: &str, layout: &Layout, extractor: &Extractor) -> Trial {
+    let path = path::Path::new(name);
+    let rule = extractor.rules.iter().position(|rule| rule.matches(path));
+    let date = match rule.map(|index| &extractor.rules[index]) {
+        Some(rule) if rule.source != DateSource::Filename => Err(format!(
+            "The date is found by the {} extractor, which needs the file",
+            rule.source.name()
+        )),
+        Some(rule) => rule.get_date(path, extractor),
+        None => extractor.name_date(path),
+    };
+    let bucket = date.clone().and_then(|date| {
+        let bucket = layout.bucket(date)?;
+        let fy = layout.fiscal.fy(date)?;
+        match rule.and_then(|index| extractor.rules[index].destination(&bucket, fy)) {
+            Some(destination) => destination,
+            None => Ok(bucket),
+        }
+    });
+    Trial { rule, date, bucket }
+}
+
+/// Gets the rules that are never used, as a rule before them applies to all of their files. Each
+/// is given by its index, with the index of the rule that is used instead.
+pub fn shadowed(rules: &[Rule]) -> Vec<(usize, usize)> {
+    rules
+        .iter()
+        .enumerate()
+        .filter_map(|(index, rule)| {
+            let earlier = rules[..index]
+                .iter()
+                .position(|earlier| earlier.covers(rule))?;
+            Some((index, earlier))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{shadowed, trial, DateSource, Rule};
+    use crate::bucket::Layout;
+    use crate::date::{Extractor, FileDate};
+
+    #[test]
+    fn test_matches() {
+        let rule = Rule::new(
+            Some("INV-*.pdf"),
+            Some(r"-\d{8}\."),
+            "filename",
+            None,
+            &[],
+            None,
+        )
+        .unwrap();
+        assert!(rule.matches(path::Path::new("dir/INV-20220710.pdf")));
+        assert!(!rule.matches(path::Path::new("dir/INV-2022.pdf")));
+        assert!(!rule.matches(path::Path::new("dir/REC-20220710.pdf")));
+
+        assert!(Rule::new(None, None, "filename", None, &[], None).is_err());
+        assert!(Rule::new(Some("*"), Some("("), "filename", None, &[], None).is_err());
+        assert!(Rule::new(Some("*"), None, "ctime", None, &[], None).is_err());
+        assert!(Rule::new(Some("*"), None, "mtime", Some("%Y"), &[], None).is_err());
+        assert!(Rule::new(Some("*"), None, "mtime", None, &[], Some("../{bucket}")).is_err());
+        assert!(Rule::new(Some("*"), None, "mtime", None, &[], Some("{month}")).is_err());
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let rule = |glob: &str, regex: Option<&str>| {
+            Rule::new(Some(glob), regex, "mtime", None, &[], None).unwrap()
+        };
+        let rules = [
+            rule("INV-*.pdf", None),
+            rule("INV-*.pdf", Some("^INV-")),
+            rule("INV-*.pdf", None),
+            rule("*", None),
+            rule("REC-*.pdf", None),
+        ];
+        assert_eq!(shadowed(&rules), vec![(2, 0), (4, 3)]);
+        assert!(shadowed(&rules[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_trial() {
+        let extractor = Extractor {
+            rules: vec![
+                Rule::new(
+                    Some("INV-*"),
+                    Some(r"^INV-(?P<date>\d{8})-"),
+                    "filename",
+                    Some("%d%m%Y"),
+                    &[],
+                    Some("Invoices/{bucket}"),
+                )
+                .unwrap(),
+                Rule::new(Some("*.jpg"), None, "mtime", None, &[], None).unwrap(),
+            ],
+            ..Extractor::default()
+        };
+        let layout = Layout::default();
+
+        let found = trial("INV-10072022-0001.pdf", &layout, &extractor);
+        assert_eq!(found.rule, Some(0));
+        assert_eq!(
+            found.bucket,
+            Ok(path::PathBuf::from("Invoices").join("2023FY"))
+        );
+        let found = trial("photo_10JUL2022.jpg", &layout, &extractor);
+        assert_eq!(found.rule, Some(1));
+        assert!(found.date.is_err() && found.bucket.is_err());
+        let found = trial("receipt_10JUN2022.pdf", &layout, &extractor);
+        assert_eq!(found.rule, None);
+        assert_eq!(found.bucket, Ok(path::PathBuf::from("2022FY")));
+        assert!(trial("notes.txt", &layout, &extractor).date.is_err());
+    }
+
+    #[test]
+    fn test_get_date() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("INV-10072022-0001.pdf");
+        fs::write(&path, "").unwrap();
+        let extractor = Extractor::default();
+
+        let rule = Rule::new(
+            Some("INV-*"),
+            Some(r"^INV-(?P<date>\d{8})-"),
+            "filename",
+            Some("%d%m%Y"),
+            &[],
+            None,
+        )
+        .unwrap();
+        let july = FileDate::Day {
+            year: 2022,
+            month: 7,
+            day: 10,
+        };
+        assert_eq!(rule.get_date(&path, &extractor), Ok(july));
+
+        let rule = Rule::new(Some("*"), None, "mtime", None, &[], None).unwrap();
+        assert_eq!(rule.source, DateSource::Mtime);
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(
+            rule.get_date(&path, &extractor),
+            FileDate::from_naive(chrono::DateTime::<chrono::Local>::from(modified).date_naive())
+        );
+
+        let rule = Rule::new(Some("*"), None, "content", None, &[], None).unwrap();
+        assert!(rule.get_date(&path, &extractor).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_date() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("scan.pdf");
+        fs::write(&path, "2022-07-10\nignored\n").unwrap();
+        let extractor = Extractor::default();
+        let command =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+
+        let rule = Rule::new(Some("*"), None, "command", None, &command(&["cat"]), None).unwrap();
+        assert_eq!(
+            rule.get_date(&path, &extractor),
+            Ok(FileDate::Day {
+                year: 2022,
+                month: 7,
+                day: 10
+            })
+        );
+        let rule = Rule::new(
+            Some("*"),
+            None,
+            "command",
+            Some("%d/%m/%Y"),
+            &command(&["sh", "-c", "echo 14/09/2022", "sh"]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            rule.get_date(&path, &extractor),
+            Ok(FileDate::Day {
+                year: 2022,
+                month: 9,
+                day: 14
+            })
+        );
+
+        let rule = Rule::new(Some("*"), None, "command", None, &command(&["false"]), None).unwrap();
+        assert!(rule
+            .get_date(&path, &extractor)
+            .unwrap_err()
+            .contains("failed"));
+        let rule = Rule::new(Some("*"), None, "command", None, &command(&["true"]), None).unwrap();
+        assert!(rule.get_date(&path, &extractor).is_err());
+
+        assert!(Rule::new(Some("*"), None, "command", None, &[], None).is_err());
+        assert!(Rule::new(Some("*"), None, "mtime", None, &command(&["cat"]), None).is_err());
+    }
+
+    #[test]
+    fn test_destination() {
+        let rule = Rule::new(
+            Some("*"),
+            None,
+            "mtime",
+            None,
+            &[],
+            Some("Work/{bucket}/Invoices"),
+        )
+        .unwrap();
+        assert_eq!(
+            rule.destination(path::Path::new("2023FY"), 2023),
+            Some(Ok(path::PathBuf::from("Work/2023FY/Invoices")))
+        );
+        let rule = Rule::new(Some("*"), None, "mtime", None, &[], Some("FY{year_short}")).unwrap();
+        assert_eq!(
+            rule.destination(path::Path::new("2023FY"), 2023),
+            Some(Ok(path::PathBuf::from("FY23")))
+        );
+        let rule = Rule::new(Some("*"), None, "mtime", None, &[], None).unwrap();
+        assert_eq!(rule.destination(path::Path::new("2023FY"), 2023), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! When to classify the configured directories on systems without cron, given in the configuration
+//! as `schedule = "daily 02:00"`.
+
+use std::hash::{BuildHasher, RandomState};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Weekday};
+
+/// The most that a scheduled run is delayed by when the configuration does not say.
+pub const DEFAULT_JITTER: Duration = Duration::from_secs(5 * 60);
+
+/// How often to run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every hour, at the minute past the hour.
+    Hourly(u32),
+    /// Every day, at the time.
+    Daily(NaiveTime),
+    /// Every week, on the day at the time.
+    Weekly(Weekday, NaiveTime),
+}
+
+impl Schedule {
+    /// Parses the schedule as it is written in the configuration, such as "hourly :15",
+    /// "daily 02:00" or "weekly sun 02:00". Without a time, it runs at the start of the hour or
+    /// at midnight.
+    pub fn parse(schedule: &str) -> Result<Self, String> {
+        let words: Vec<&str> = schedule.split_whitespace().collect();
+        let time = |word: Option<&&str>| match word {
+            Some(word) => NaiveTime::parse_from_str(word, "%H:%M")
+                .map_err(|_| format!("Invalid time {:?}, expected HH:MM", word)),
+            None => Ok(NaiveTime::MIN),
+        };
+        match words.as_slice() {
+            ["hourly"] => Ok(Schedule::Hourly(0)),
+            ["hourly", minute] => match minute.strip_prefix(':').map(str::parse) {
+                Some(Ok(minute)) if minute < 60 => Ok(Schedule::Hourly(minute)),
+                _ => Err(format!("Invalid minute {:?}, expected :MM", minute)),
+            },
+            ["daily", rest @ ..] if rest.len() <= 1 => Ok(Schedule::Daily(time(rest.first())?)),
+            ["weekly", day, rest @ ..] if rest.len() <= 1 => {
+                let day = day
+                    .parse::<Weekday>()
+                    .map_err(|_| format!("Unknown day {:?}", day))?;
+                Ok(Schedule::Weekly(day, time(rest.first())?))
+            }
+            _ => Err(format!(
+                "Invalid schedule {:?}, expected hourly [:MM], daily [HH:MM] or weekly DAY [HH:MM]",
+                schedule
+            )),
+        }
+    }
+
+    /// Gets the first time after the given one that the schedule runs at.
+    pub fn next_after(&self, after: NaiveDateTime) -> NaiveDateTime {
+        let date = after.date();
+        match self {
+            Schedule::Hourly(minute) => {
+                let hour = date
+                    .and_hms_opt(after.hour(), *minute, 0)
+                    .expect("the minute is less than 60");
+                if hour > after {
+                    hour
+                } else {
+                    hour + TimeDelta::hours(1)
+                }
+            }
+            Schedule::Daily(time) => {
+                let today = date.and_time(*time);
+                if today > after {
+                    today
+                } else {
+                    today + TimeDelta::days(1)
+                }
+            }
+            Schedule::Weekly(day, time) => {
+                let days =
+                    (7 + day.num_days_from_monday() - date.weekday().num_days_from_monday()) % 7;
+                let next = date.and_time(*time) + TimeDelta::days(days.into());
+                if next > after {
+                    next
+                } else {
+                    next + TimeDelta::weeks(1)
+                }
+            }
+        }
+    }
+}
+
+/// Gets a random delay of up to the given length, so that machines on the same schedule do not
+/// all run at once.
+pub fn jitter(most: Duration) -> Duration {
+    if most.is_zero() {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().hash_one(SystemTime::now());
+    Duration::from_millis(random % most.as_millis().max(1) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+    use super::{jitter, Schedule};
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 1 July 2024 is a Monday.
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let two = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+        assert_eq!(Schedule::parse("daily 02:00"), Ok(Schedule::Daily(two)));
+        assert_eq!(
+            Schedule::parse("daily"),
+            Ok(Schedule::Daily(NaiveTime::MIN))
+        );
+        assert_eq!(Schedule::parse("hourly :15"), Ok(Schedule::Hourly(15)));
+        assert_eq!(
+            Schedule::parse("weekly sun 02:00"),
+            Ok(Schedule::Weekly(Weekday::Sun, two))
+        );
+        assert!(Schedule::parse("daily 2am").is_err());
+        assert!(Schedule::parse("hourly :60").is_err());
+        assert!(Schedule::parse("weekly someday").is_err());
+        assert!(Schedule::parse("monthly").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let daily = Schedule::parse("daily 02:00").unwrap();
+        assert_eq!(daily.next_after(at(1, 1, 30)), at(1, 2, 0));
+        assert_eq!(daily.next_after(at(1, 2, 0)), at(2, 2, 0));
+
+        let hourly = Schedule::parse("hourly :15").unwrap();
+        assert_eq!(hourly.next_after(at(1, 1, 10)), at(1, 1, 15));
+        assert_eq!(hourly.next_after(at(1, 23, 30)), at(2, 0, 15));
+
+        let weekly = Schedule::parse("weekly wed 02:00").unwrap();
+        assert_eq!(weekly.next_after(at(1, 9, 0)), at(3, 2, 0));
+        assert_eq!(weekly.next_after(at(3, 2, 0)), at(10, 2, 0));
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        assert!(jitter(Duration::from_secs(60)) < Duration::from_secs(60));
+    }
+}
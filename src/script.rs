@@ -0,0 +1,144 @@
+//! Classifying files with a Rhai script, for naming schemes that the rules cannot describe.
+
+use std::fmt;
+use std::fs;
+use std::path;
+use std::time;
+
+use crate::date::FileDate;
+
+/// The function that the script must define.
+const CLASSIFY_FN: &str = "classify";
+
+/// A script that defines `fn classify(name, mtime, size)`, given the file name, when it was last
+/// modified in seconds since the Unix epoch, and its size in bytes. It returns the financial year
+/// as a number, a date such as "2022-07-10", or `()` to classify the file as usual.
+pub struct Script {
+    path: path::PathBuf,
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+impl Script {
+    /// Compiles the script, checking that it defines the classify function.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("Could not compile {}: {}", path.display(), e))?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == CLASSIFY_FN && function.params.len() == 3)
+        {
+            return Err(format!(
+                "{} does not define fn {}(name, mtime, size)",
+                path.display(),
+                CLASSIFY_FN
+            ));
+        }
+        Ok(Script {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+        })
+    }
+
+    /// Gets the date that the script gives the file, if it gives one.
+    pub fn classify(&self, file_path: &path::Path) -> Result<Option<FileDate>, String> {
+        let name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let metadata = fs::metadata(file_path)
+            .map_err(|e| format!("Could not read {}: {}", file_path.display(), e))?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(time::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs() as i64);
+        let size = metadata.len() as i64;
+        let result: rhai::Dynamic = self
+            .engine
+            .call_fn(
+                &mut rhai::Scope::new(),
+                &self.ast,
+                CLASSIFY_FN,
+                (name, mtime, size),
+            )
+            .map_err(|e| format!("{} failed: {}", self.path.display(), e))?;
+        if result.is_unit() {
+            Ok(None)
+        } else if let Ok(fy) = result.as_int() {
+            u16::try_from(fy)
+                .map(|fy| Some(FileDate::FinancialYear(fy)))
+                .map_err(|_| format!("{} is not a financial year", fy))
+        } else if let Ok(date) = result.into_string() {
+            date.parse().map(Some)
+        } else {
+            Err(format!(
+                "{} must return a financial year, a date or ()",
+                CLASSIFY_FN
+            ))
+        }
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Script").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Script;
+    use crate::date::FileDate;
+
+    #[test]
+    fn test_classify() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let script = tempdir.path().join("classify.rhai");
+        fs::write(
+            &script,
+            r#"
+            fn classify(name, mtime, size) {
+                if name.starts_with("big") && size > 3 {
+                    2021
+                } else if name.starts_with("statement") {
+                    "2022-07-10"
+                } else if name.starts_with("bad") {
+                    "July"
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        let script = Script::load(&script).unwrap();
+        let file = |name: &str, contents: &str| {
+            let path = tempdir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            script.classify(&path)
+        };
+        assert_eq!(
+            file("big.bin", "1234"),
+            Ok(Some(FileDate::FinancialYear(2021)))
+        );
+        assert_eq!(file("big.txt", ""), Ok(None));
+        assert_eq!(
+            file("statement.pdf", ""),
+            Ok(Some(FileDate::Day {
+                year: 2022,
+                month: 7,
+                day: 10
+            }))
+        );
+        assert!(file("bad.pdf", "").is_err());
+
+        let other = tempdir.path().join("other.rhai");
+        fs::write(&other, "fn classify(name) { 2022 }").unwrap();
+        assert!(Script::load(&other).is_err());
+    }
+}
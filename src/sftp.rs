@@ -0,0 +1,244 @@
+//! Uploading the classified files to a directory on a server with SFTP, given as
+//! `sftp://user@host/path`. The path is from the root of the server, or from the home directory if
+//! it starts with `~`, as in `sftp://user@host/~/archive`. Files are written to a `.part` file that
+//! is renamed once it is complete, so that an upload that was interrupted carries on from where it
+//! stopped the next time.
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Seek};
+use std::net;
+use std::path;
+
+use ssh2::{CheckResult, KnownHostFileKind, OpenFlags, OpenType, RenameFlags, Session};
+
+use crate::plan::PlaceError;
+use crate::remote::{Credentials, Destination, Upload};
+
+/// The port that SSH servers listen on, unless another is given after the host.
+const DEFAULT_PORT: u16 = 22;
+
+/// The suffix of a file that is still being uploaded.
+const PART_SUFFIX: &str = ".part";
+
+/// Uploads files to a directory on a server over SSH.
+pub struct Sftp {
+    sftp: ssh2::Sftp,
+    /// The directory on the server, or empty for the home directory.
+    root: String,
+    /// The directories that are known to exist, relative to the root.
+    created: HashSet<String>,
+}
+
+impl Sftp {
+    /// Connects to the server, checking its key against `~/.ssh/known_hosts`. The password is used
+    /// if there is one, or otherwise the keys in the SSH agent and then the usual key files in
+    /// `~/.ssh`.
+    pub fn connect(destination: &Destination, credentials: &Credentials) -> Result<Self, String> {
+        let (host, port) = address(&destination.host)?;
+        let user = destination
+            .user
+            .clone()
+            .or_else(|| credentials.user.clone())
+            .or_else(|| env::var("USER").ok())
+            .ok_or_else(|| format!("There is no user to connect to {} as", destination))?;
+
+        let stream = net::TcpStream::connect((host, port))
+            .map_err(|e| format!("Could not connect to {}: {}", destination.host, e))?;
+        let mut session = Session::new().map_err(|e| e.to_string())?;
+        session.set_tcp_stream(stream);
+        session
+            .handshake()
+            .map_err(|e| format!("Could not connect to {}: {}", destination.host, e))?;
+        check_host_key(&session, host, port)?;
+
+        match &credentials.password {
+            Some(password) => session
+                .userauth_password(&user, password)
+                .map_err(|e| format!("Could not log in to {} as {}: {}", host, user, e))?,
+            None => authenticate_with_keys(&session, &user),
+        }
+        if !session.authenticated() {
+            return Err(format!(
+                "Could not log in to {} as {}, set CLASSFY_SFTP_PASSWORD or add a key to the SSH \
+                 agent",
+                host, user
+            ));
+        }
+        let sftp = session
+            .sftp()
+            .map_err(|e| format!("Could not start SFTP on {}: {}", host, e))?;
+        Ok(Sftp {
+            sftp,
+            root: root(&destination.path),
+            created: HashSet::new(),
+        })
+    }
+
+    /// Gets the path on the server of the file at the path relative to the root.
+    fn path(&self, key: &str) -> path::PathBuf {
+        if self.root.is_empty() {
+            path::PathBuf::from(key)
+        } else {
+            path::PathBuf::from(format!("{}/{}", self.root.trim_end_matches('/'), key))
+        }
+    }
+
+    /// Creates each of the directories that the file at the path goes in, that do not exist yet.
+    fn create_dirs(&mut self, key: &str) -> Result<(), String> {
+        let Some((dirs, _)) = key.rsplit_once('/') else {
+            return Ok(());
+        };
+        let mut dir = String::new();
+        for part in dirs.split('/') {
+            if !dir.is_empty() {
+                dir.push('/');
+            }
+            dir.push_str(part);
+            if self.created.contains(&dir) {
+                continue;
+            }
+            let path = self.path(&dir);
+            if !self.sftp.stat(&path).is_ok_and(|stat| stat.is_dir()) {
+                self.sftp
+                    .mkdir(&path, 0o755)
+                    .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+            }
+            self.created.insert(dir.clone());
+        }
+        Ok(())
+    }
+}
+
+impl Upload for Sftp {
+    fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+        self.create_dirs(key)?;
+        let mut file = fs::File::open(src).map_err(|e| PlaceError::io("could not open file", e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| PlaceError::io("could not read file", e))?
+            .len();
+        let dest = self.path(key);
+        let part = self.path(&format!("{}{}", key, PART_SUFFIX));
+
+        // Carry on from the end of what was uploaded before, unless that is more than the file.
+        let uploaded = self
+            .sftp
+            .stat(&part)
+            .ok()
+            .and_then(|stat| stat.size)
+            .filter(|size| *size <= len);
+        let flags = match uploaded {
+            Some(_) => OpenFlags::WRITE,
+            None => OpenFlags::WRITE | OpenFlags::TRUNCATE,
+        };
+        let offset = uploaded.unwrap_or(0);
+        let failed =
+            |e: &dyn fmt::Display| format!("Could not upload to {}: {}", part.display(), e);
+        let mut remote = self
+            .sftp
+            .open_mode(&part, flags, 0o644, OpenType::File)
+            .map_err(|e| failed(&e))?;
+        remote
+            .seek(io::SeekFrom::Start(offset))
+            .and_then(|_| file.seek(io::SeekFrom::Start(offset)))
+            .and_then(|_| io::copy(&mut file, &mut remote))
+            .map_err(|e| failed(&e))?;
+        drop(remote);
+
+        let flags = Some(RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE);
+        if self.sftp.rename(&part, &dest, flags).is_err() {
+            // Servers with older versions of SFTP cannot rename over a file, so remove it first.
+            let _ = self.sftp.unlink(&dest);
+            self.sftp
+                .rename(&part, &dest, flags)
+                .map_err(|e| format!("Could not rename {}: {}", part.display(), e))?;
+        }
+        Ok(None)
+    }
+}
+
+/// Splits the port, if there is one, from the host.
+fn address(host: &str) -> Result<(&str, u16), String> {
+    match host.rsplit_once(':') {
+        Some((name, port)) => port
+            .parse()
+            .map(|port| (name, port))
+            .map_err(|_| format!("{:?} is not a port", port)),
+        None => Ok((host, DEFAULT_PORT)),
+    }
+}
+
+/// Gets the directory on the server from the path in the URL. A path starting with "~" is left
+/// relative, so that the server takes it from the home directory.
+fn root(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(home) => home.trim_start_matches('/').to_string(),
+        None => format!("/{}", path),
+    }
+}
+
+/// Checks that the server's key is the one for it in `~/.ssh/known_hosts`.
+fn check_host_key(session: &Session, host: &str, port: u16) -> Result<(), String> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| format!("{} did not give a host key", host))?;
+    let mut known_hosts = session.known_hosts().map_err(|e| e.to_string())?;
+    if let Some(path) = dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts")) {
+        if path.is_file() {
+            known_hosts
+                .read_file(&path, KnownHostFileKind::OpenSSH)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        }
+    }
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(format!(
+            "{} is not a known host, connect to it with ssh first to add it to known_hosts",
+            host
+        )),
+        CheckResult::Mismatch => Err(format!(
+            "The key of {} does not match the one in known_hosts",
+            host
+        )),
+        CheckResult::Failure => Err(format!("Could not check the key of {}", host)),
+    }
+}
+
+/// Tries the keys in the SSH agent, and then the usual key files, until one is accepted.
+fn authenticate_with_keys(session: &Session, user: &str) {
+    if session.userauth_agent(user).is_ok() {
+        return;
+    }
+    let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) else {
+        return;
+    };
+    for name in ["id_ed25519", "id_ecdsa", "id_rsa"] {
+        let key = ssh_dir.join(name);
+        if key.is_file() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{address, root};
+
+    #[test]
+    fn test_address() {
+        assert_eq!(address("files.example"), Ok(("files.example", 22)));
+        assert_eq!(address("files.example:2222"), Ok(("files.example", 2222)));
+        assert!(address("files.example:ssh").is_err());
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(root("srv/archive"), "/srv/archive");
+        assert_eq!(root("~/archive"), "archive");
+        assert_eq!(root("~"), "");
+        assert_eq!(root(""), "/");
+    }
+}
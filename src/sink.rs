@@ -0,0 +1,226 @@
+//! Where the classified files are placed. Applying a plan hands each file to a sink, so that other
+//! ways of placing the files can be added without changing how the plan is made or applied.
+
+use std::ffi;
+use std::path;
+
+use crate::date::FileDate;
+use crate::journal::{self, Journal};
+use crate::plan::{self, Candidate, Move, PlaceError, Placement, Settings};
+use crate::report;
+
+/// The bucket that a file is placed in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bucket {
+    /// The directory that the buckets are in.
+    pub base: path::PathBuf,
+    /// The bucket, relative to the base directory, such as "2023FY".
+    pub dir: path::PathBuf,
+    /// The date found for the file, if any.
+    pub date: Option<FileDate>,
+    /// The name to give the file in the bucket, if not its own.
+    pub name: Option<String>,
+}
+
+impl Bucket {
+    /// Gets the bucket of the candidate, if it has one.
+    pub fn of(candidate: &Candidate) -> Option<Self> {
+        Some(Bucket {
+            base: candidate.base.clone(),
+            dir: candidate.bucket.clone().ok()?,
+            date: candidate.date.clone().ok(),
+            name: candidate.name.clone(),
+        })
+    }
+
+    /// Gets the bucket that the move places the file in. The base directory is taken to be the
+    /// one that the file is in, as it is when a plan is made.
+    pub fn of_move(file_move: &Move) -> Self {
+        let base = file_move
+            .source
+            .parent()
+            .unwrap_or(path::Path::new(""))
+            .to_path_buf();
+        let dest_dir = file_move
+            .destination
+            .parent()
+            .unwrap_or(path::Path::new(""));
+        Bucket {
+            dir: dest_dir
+                .strip_prefix(&base)
+                .unwrap_or(dest_dir)
+                .to_path_buf(),
+            base,
+            date: file_move.date,
+            name: file_move
+                .destination
+                .file_name()
+                .filter(|&name| Some(name) != file_move.source.file_name())
+                .map(|name| name.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// Gets the top level directory of the bucket, such as "2023FY" for "2023FY/Q1".
+    pub fn top(&self) -> Option<String> {
+        let first = self.dir.components().next()?;
+        Some(first.as_os_str().to_string_lossy().into_owned())
+    }
+
+    /// Gets the name that the file is given in the bucket.
+    pub fn file_name<'a>(&'a self, src: &'a path::Path) -> &'a ffi::OsStr {
+        match &self.name {
+            Some(name) => ffi::OsStr::new(name),
+            None => src.file_name().expect("file does not have a name"),
+        }
+    }
+
+    /// Gets the move that places the file in the bucket.
+    pub fn to_move(&self, src: &path::Path) -> Move {
+        let file_name = self.file_name(src);
+        Move {
+            source: src.to_path_buf(),
+            destination: self.base.join(&self.dir).join(file_name),
+            date: self.date,
+        }
+    }
+}
+
+/// Somewhere that the classified files can be placed.
+pub trait Sink {
+    /// Places the file in the bucket.
+    fn put(&mut self, src: &path::Path, bucket: &Bucket) -> Result<Placement, PlaceError>;
+
+    /// Undoes everything placed so far, when a transactional run fails. Sinks that cannot undo
+    /// what they did leave it as it is.
+    fn roll_back(&mut self) {}
+}
+
+/// Places the files in directories in the local file system, moving or copying them as the
+/// settings say.
+pub struct Local {
+    settings: Settings,
+    /// The changes made so far, if they are to be rolled back when a file cannot be placed or
+    /// recorded in the history.
+    journal: Option<Journal>,
+}
+
+impl Local {
+    /// Moves or copies the files as the settings' transfer says.
+    pub fn new(settings: &Settings) -> Self {
+        Local {
+            settings: settings.clone(),
+            journal: (settings.transactional || settings.history.is_some()).then(Journal::default),
+        }
+    }
+
+    /// Moves the files into their buckets.
+    pub fn moving(settings: &Settings) -> Self {
+        Local::new(&Settings {
+            transfer: plan::Transfer::Move,
+            ..settings.clone()
+        })
+    }
+
+    /// Copies the files into their buckets, leaving the originals in place.
+    pub fn copying(settings: &Settings) -> Self {
+        Local::new(&Settings {
+            transfer: plan::Transfer::Copy,
+            ..settings.clone()
+        })
+    }
+
+    /// Records the changes made in the history given by the settings, if there is one, so that
+    /// they can be undone. Nothing is recorded if they were rolled back.
+    pub fn finish(self) {
+        if let (Some(path), Some(journal)) = (&self.settings.history, self.journal) {
+            if let Err(e) = journal::record(path, journal) {
+                report::note!("Could not record the run so that it can be undone: {}", e);
+            }
+        }
+    }
+}
+
+impl Sink for Local {
+    fn put(&mut self, src: &path::Path, bucket: &Bucket) -> Result<Placement, PlaceError> {
+        plan::place(&bucket.to_move(src), &self.settings, self.journal.as_mut())
+    }
+
+    fn roll_back(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            journal.roll_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{Bucket, Local, Sink};
+    use crate::plan::{Move, Placement, Settings};
+
+    #[test]
+    fn test_local() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        let bucket = Bucket {
+            base: base.to_path_buf(),
+            dir: path::PathBuf::from("2023FY"),
+            date: None,
+            name: None,
+        };
+        let write = |name: &str| {
+            let path = base.join(name);
+            fs::write(&path, name).unwrap();
+            path
+        };
+
+        let mut copying = Local::copying(&Settings::default());
+        let path = write("a_10JUL2022.txt");
+        assert_eq!(copying.put(&path, &bucket), Ok(Placement::Moved));
+        assert!(path.is_file());
+        assert!(base.join("2023FY").join("a_10JUL2022.txt").is_file());
+        assert_eq!(copying.put(&path, &bucket), Ok(Placement::UpToDate));
+
+        let mut moving = Local::moving(&Settings::default());
+        let path = write("b_10JUL2022.txt");
+        assert_eq!(moving.put(&path, &bucket), Ok(Placement::Moved));
+        assert!(!path.exists());
+        assert!(base.join("2023FY").join("b_10JUL2022.txt").is_file());
+
+        let mut transactional = Local::moving(&Settings {
+            transactional: true,
+            ..Settings::default()
+        });
+        let path = write("c_10JUL2022.txt");
+        assert_eq!(transactional.put(&path, &bucket), Ok(Placement::Moved));
+        transactional.roll_back();
+        assert!(path.is_file());
+        assert!(!base.join("2023FY").join("c_10JUL2022.txt").exists());
+    }
+
+    #[test]
+    fn test_of_move() {
+        let file_move = Move {
+            source: path::PathBuf::from("/docs/a.pdf"),
+            destination: path::PathBuf::from("/docs/2023FY/Q1/a.pdf"),
+            date: None,
+        };
+        let bucket = Bucket::of_move(&file_move);
+        assert_eq!(bucket.base, path::Path::new("/docs"));
+        assert_eq!(bucket.dir, path::Path::new("2023FY/Q1"));
+        assert_eq!(bucket.top().as_deref(), Some("2023FY"));
+        assert_eq!(bucket.to_move(&file_move.source), file_move);
+
+        // A destination outside the directory that the file is in is kept as it is.
+        let file_move = Move {
+            destination: path::PathBuf::from("/elsewhere/2023FY/a.pdf"),
+            ..file_move
+        };
+        assert_eq!(
+            Bucket::of_move(&file_move).to_move(&file_move.source),
+            file_move
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! The decisions made for the files in a directory, kept between runs with `--incremental` so that
+//! the files that have not changed since are not looked at again.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bucket::Layout;
+use crate::date::{Extractor, FileDate};
+use crate::report;
+
+/// The name of the file, in each directory classified with `--incremental`, that records the
+/// decisions made for its files.
+pub const STATE_NAME: &str = ".classfy-state.json";
+
+/// The size and modification time of a file when its decision was made, and the decision.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub size: u64,
+    /// The modification time, in nanoseconds since the Unix epoch.
+    pub modified: u64,
+    pub date: Result<FileDate, String>,
+    /// The directory, relative to the base directory, that the file belongs in, or why it is
+    /// left in place.
+    pub bucket: Result<path::PathBuf, String>,
+}
+
+impl Entry {
+    /// Creates the entry for the decision made for a file with the metadata.
+    pub fn new(
+        metadata: &fs::Metadata,
+        date: Result<FileDate, String>,
+        bucket: Result<path::PathBuf, String>,
+    ) -> Self {
+        Entry {
+            size: metadata.len(),
+            modified: modified(metadata),
+            date,
+            bucket,
+        }
+    }
+
+    /// Checks if the file still has the size and modification time it had when the decision was
+    /// made.
+    pub fn is_current(&self, metadata: &fs::Metadata) -> bool {
+        self.size == metadata.len() && self.modified == modified(metadata)
+    }
+}
+
+/// The decisions made for the files in a directory, by their names, along with the settings that
+/// they were made with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    fingerprint: String,
+    files: BTreeMap<String, Entry>,
+}
+
+impl State {
+    /// Creates an empty state for decisions made with the settings.
+    pub fn new(layout: &Layout, extractor: &Extractor) -> Self {
+        State {
+            fingerprint: fingerprint(layout, extractor),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the state of the directory. If there is none, or it was made with other settings, an
+    /// empty state is given so that every file is looked at again.
+    pub fn load(dir: &path::Path, layout: &Layout, extractor: &Extractor) -> Self {
+        let empty = State::new(layout, extractor);
+        let path = dir.join(STATE_NAME);
+        let state: State = match fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice(&contents) {
+                Ok(state) => state,
+                Err(e) => {
+                    report::note!("Ignoring {}: {}", path.display(), e);
+                    return empty;
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return empty,
+            Err(e) => {
+                report::note!("Could not read {}: {}", path.display(), e);
+                return empty;
+            }
+        };
+        if state.fingerprint == empty.fingerprint {
+            state
+        } else {
+            report::detail!(
+                "Looking at every file in {} again as the settings have changed",
+                dir.display()
+            );
+            empty
+        }
+    }
+
+    /// Gets the decision made for the file, if it has not changed since.
+    pub fn get(&self, path: &path::Path, metadata: &fs::Metadata) -> Option<&Entry> {
+        let name = path.file_name()?.to_str()?;
+        self.files
+            .get(name)
+            .filter(|entry| entry.is_current(metadata))
+    }
+
+    /// Records the decision made for the file.
+    pub fn insert(&mut self, path: &path::Path, entry: Entry) {
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            self.files.insert(name.to_string(), entry);
+        }
+    }
+
+    /// Saves the state in the directory, replacing the one there.
+    pub fn save(&self, dir: &path::Path) -> Result<(), String> {
+        let path = dir.join(STATE_NAME);
+        let contents = serde_json::to_vec(self).expect("state could not be serialised");
+        fs::write(&path, contents).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// Gets a checksum of the settings that decide where the files are placed, so that decisions made
+/// with other settings are not used.
+fn fingerprint(layout: &Layout, extractor: &Extractor) -> String {
+    let settings = format!("{:?}\n{:?}", layout, extractor);
+    Sha256::digest(settings.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Gets when the file was last modified, in nanoseconds since the Unix epoch.
+fn modified(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{Entry, State};
+    use crate::bucket::Layout;
+    use crate::date::{Extractor, FileDate};
+
+    #[test]
+    fn test_state() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("notes.txt");
+        fs::write(&path, "a").unwrap();
+        let (layout, extractor) = (Layout::default(), Extractor::default());
+
+        let mut state = State::load(tempdir.path(), &layout, &extractor);
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(state.get(&path, &metadata).is_none());
+        let date = FileDate::FinancialYear(2023);
+        state.insert(
+            &path,
+            Entry::new(&metadata, Ok(date), Ok(path::PathBuf::from("2023FY"))),
+        );
+        state.save(tempdir.path()).unwrap();
+
+        let state = State::load(tempdir.path(), &layout, &extractor);
+        assert_eq!(state.get(&path, &metadata).unwrap().date, Ok(date));
+        fs::write(&path, "changed").unwrap();
+        assert!(state.get(&path, &fs::metadata(&path).unwrap()).is_none());
+
+        let calendar = Layout {
+            calendar: true,
+            ..Layout::default()
+        };
+        let state = State::load(tempdir.path(), &calendar, &extractor);
+        assert!(state.get(&path, &metadata).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! The statistics of a run, which are written at the end for people to read, and as JSON with
+//! --stats-file for monitoring to collect.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::plan::{Outcome, Plan};
+
+/// What was done in a run, and how long it took.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Stats {
+    /// The number of files that were found to classify.
+    pub scanned: usize,
+    pub placed: usize,
+    pub placed_by_year: BTreeMap<String, usize>,
+    pub up_to_date: usize,
+    pub skipped: usize,
+    pub unclassified: usize,
+    pub errors: usize,
+    pub bytes_placed: u64,
+    pub elapsed_secs: f64,
+}
+
+impl Stats {
+    /// Gets the statistics of a run that followed the plan.
+    pub fn new(outcome: &Outcome, plan: &Plan, elapsed: Duration) -> Self {
+        Stats {
+            scanned: plan.moves.len() + plan.unclassified.len(),
+            placed: outcome.placed,
+            placed_by_year: outcome.placed_by_year.clone(),
+            up_to_date: outcome.up_to_date,
+            skipped: outcome.skipped + outcome.already_archived,
+            unclassified: outcome.unclassified,
+            errors: outcome.failures.len(),
+            bytes_placed: outcome.bytes_placed,
+            elapsed_secs: elapsed.as_secs_f64(),
+        }
+    }
+
+    /// Writes the statistics to the file as JSON, replacing what was there.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).expect("stats could not be serialised");
+        fs::write(path, contents + "\n")
+            .map_err(|e| format!("Could not write stats {}: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for Stats {
+    /// Describes the run in a few lines, such as "Placed 3 files (2.1 MiB): 2023FY 1, 2024FY 2".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Scanned {} files in {:.1}s",
+            self.scanned, self.elapsed_secs
+        )?;
+        write!(
+            f,
+            "Placed {} files ({})",
+            self.placed,
+            size(self.bytes_placed)
+        )?;
+        if !self.placed_by_year.is_empty() {
+            let years: Vec<String> = self
+                .placed_by_year
+                .iter()
+                .map(|(year, placed)| format!("{} {}", year, placed))
+                .collect();
+            write!(f, ": {}", years.join(", "))?;
+        }
+        write!(
+            f,
+            "\n{} up to date, {} skipped, {} unclassified, {} errors",
+            self.up_to_date, self.skipped, self.unclassified, self.errors
+        )
+    }
+}
+
+/// Describes the number of bytes in the largest unit that it is at least one of.
+pub fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path;
+    use std::time::Duration;
+
+    use super::{size, Stats};
+    use crate::plan::{Move, Outcome, Plan, Unclassified};
+
+    #[test]
+    fn test_stats() {
+        let plan = Plan {
+            moves: vec![Move {
+                source: path::PathBuf::from("/scans/a_10JUL2023.pdf"),
+                destination: path::PathBuf::from("/scans/2024FY/a_10JUL2023.pdf"),
+                date: None,
+            }],
+            unclassified: vec![Unclassified {
+                path: path::PathBuf::from("/scans/notes.txt"),
+                reason: String::from("no date found"),
+            }],
+        };
+        let mut outcome = Outcome {
+            unclassified: 1,
+            ..Outcome::default()
+        };
+        outcome.place(Some(String::from("2024FY")), 3 * 1024 * 1024);
+        let stats = Stats::new(&outcome, &plan, Duration::from_millis(1500));
+        assert_eq!(
+            stats,
+            Stats {
+                scanned: 2,
+                placed: 1,
+                placed_by_year: BTreeMap::from([(String::from("2024FY"), 1)]),
+                up_to_date: 0,
+                skipped: 0,
+                unclassified: 1,
+                errors: 0,
+                bytes_placed: 3 * 1024 * 1024,
+                elapsed_secs: 1.5,
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "Scanned 2 files in 1.5s\nPlaced 1 files (3.0 MiB): 2024FY 1\n\
+             0 up to date, 0 skipped, 1 unclassified, 0 errors"
+        );
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(size(512), "512 B");
+        assert_eq!(size(1536), "1.5 KiB");
+        assert_eq!(size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
@@ -0,0 +1,146 @@
+//! Logging to the system journal, or to syslog where there is no journal, for when classfy runs in
+//! the background and nobody reads its output. Entries about a file have the fields CLASSFY_FILE,
+//! CLASSFY_FY and CLASSFY_ACTION in the journal, and have them added to the message for syslog.
+
+use std::io;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path;
+use std::process;
+
+/// The socket that journald reads entries from.
+#[cfg(unix)]
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The socket that syslog reads messages from.
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// The name that the entries are logged under.
+const IDENTIFIER: &str = "classfy";
+
+/// The priority of entries about what was done with a file.
+pub const NOTICE: u8 = 5;
+/// The priority of other messages.
+pub const INFO: u8 = 6;
+
+/// The syslog facility for system daemons.
+const DAEMON: u8 = 3;
+
+/// A connection to the system journal or syslog.
+#[derive(Debug)]
+pub struct Syslog {
+    #[cfg(unix)]
+    socket: UnixDatagram,
+    /// Whether the socket is the journal's, which takes the fields as they are.
+    journal: bool,
+}
+
+impl Syslog {
+    /// Connects to the journal if there is one, or otherwise to syslog.
+    #[cfg(unix)]
+    pub fn connect() -> Result<Self, String> {
+        let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+        let journal = path::Path::new(JOURNAL_SOCKET).exists();
+        let path = if journal {
+            JOURNAL_SOCKET
+        } else {
+            SYSLOG_SOCKET
+        };
+        socket
+            .connect(path)
+            .map_err(|e| format!("Could not connect to {}: {}", path, e))?;
+        Ok(Syslog { socket, journal })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect() -> Result<Self, String> {
+        Err(String::from("Logging to syslog needs a Unix system"))
+    }
+
+    /// Logs the message with the fields, which are named without the CLASSFY_ prefix.
+    #[cfg(unix)]
+    pub fn send(&self, priority: u8, message: &str, fields: &[(&str, &str)]) -> io::Result<()> {
+        let entry = if self.journal {
+            journal_entry(priority, message, fields)
+        } else {
+            syslog_line(priority, message, fields).into_bytes()
+        };
+        self.socket.send(&entry).map(|_| ())
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(&self, _priority: u8, _message: &str, _fields: &[(&str, &str)]) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+/// Gets the entry in journald's native protocol, with a line for each field.
+fn journal_entry(priority: u8, message: &str, fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let priority = priority.to_string();
+    let mut add = |name: &str, value: &str| {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            // A value with a new line is given with its length instead.
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+    add("MESSAGE", message);
+    add("PRIORITY", &priority);
+    add("SYSLOG_IDENTIFIER", IDENTIFIER);
+    for (name, value) in fields {
+        add(&format!("CLASSFY_{}", name.to_uppercase()), value);
+    }
+    entry
+}
+
+/// Gets the message as a line for syslog, with the fields after it.
+fn syslog_line(priority: u8, message: &str, fields: &[(&str, &str)]) -> String {
+    let mut line = format!(
+        "<{}>{}[{}]: {}",
+        DAEMON * 8 + priority,
+        IDENTIFIER,
+        process::id(),
+        message
+    );
+    for (name, value) in fields {
+        line += &format!(" {}={:?}", name, value);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::{journal_entry, syslog_line, NOTICE};
+
+    #[test]
+    fn test_journal_entry() {
+        let entry = journal_entry(NOTICE, "placed a.pdf", &[("fy", "2023FY")]);
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "MESSAGE=placed a.pdf\nPRIORITY=5\nSYSLOG_IDENTIFIER=classfy\nCLASSFY_FY=2023FY\n"
+        );
+        let entry = journal_entry(NOTICE, "a\nb", &[]);
+        assert!(entry.starts_with(b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\nPRIORITY=5\n"));
+    }
+
+    #[test]
+    fn test_syslog_line() {
+        assert_eq!(
+            syslog_line(NOTICE, "placed a.pdf", &[("file", "/scans/a.pdf")]),
+            format!(
+                "<29>classfy[{}]: placed a.pdf file=\"/scans/a.pdf\"",
+                process::id()
+            )
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Tagging the classified files with their financial year, such as "2023FY", for those who find
+//! their files by tag rather than by directory. On macOS this is a Finder tag, and on Linux and
+//! the BSDs the `user.xdg.tags` attribute that file managers such as Dolphin show.
+//!
+//! With `--mode tag`, the files are not moved at all. Their year is recorded in the
+//! `user.classfy.fy` attribute instead, or in an index in their directory where the file system
+//! has no attributes, so that `classfy query` can find them later.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+use crate::audit;
+use crate::plan::{PlaceError, Placement, Settings};
+use crate::report;
+use crate::sink::{Bucket, Sink};
+use crate::walk::Walk;
+
+/// The extended attribute that `--mode tag` records the year of each file in.
+pub const YEAR_ATTRIBUTE: &str = "user.classfy.fy";
+
+/// The name of the file, in each directory tagged with `--mode tag`, that records the years of the
+/// files that cannot be given the attribute.
+pub const INDEX_NAME: &str = ".classfy-tags.json";
+
+/// The extended attribute that holds the Finder tags of a file, as a property list.
+#[cfg(target_os = "macos")]
+const FINDER_TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// The extended attribute that holds the tags of a file, separated by commas.
+#[cfg(all(unix, not(target_os = "macos")))]
+const XDG_TAGS: &str = "user.xdg.tags";
+
+/// The alternate data stream, on Windows, that the tags of a file are written to, one per line.
+#[cfg(windows)]
+const TAGS_STREAM: &str = "classfy.tags";
+
+/// Adds the tag to those of the file, unless it already has it.
+#[cfg(target_os = "macos")]
+pub fn tag(path: &path::Path, label: &str) -> io::Result<()> {
+    let mut tags: Vec<String> = match xattr::get(path, FINDER_TAGS)? {
+        Some(tags) => plist::from_bytes(&tags).map_err(io::Error::other)?,
+        None => Vec::new(),
+    };
+    // Finder follows the name of a tag with its colour, such as "2023FY\n6".
+    if tags.iter().any(|tag| tag.split('\n').next() == Some(label)) {
+        return Ok(());
+    }
+    tags.push(label.to_string());
+    let mut contents = Vec::new();
+    plist::to_writer_binary(&mut contents, &tags).map_err(io::Error::other)?;
+    xattr::set(path, FINDER_TAGS, &contents)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn tag(path: &path::Path, label: &str) -> io::Result<()> {
+    let tags = xattr::get(path, XDG_TAGS)?.unwrap_or_default();
+    let tags = String::from_utf8_lossy(&tags);
+    match with_tag(&tags, label) {
+        Some(tags) => xattr::set(path, XDG_TAGS, tags.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+pub fn tag(path: &path::Path, label: &str) -> io::Result<()> {
+    let mut stream = path.as_os_str().to_os_string();
+    stream.push(format!(":{}", TAGS_STREAM));
+    let tags = match std::fs::read_to_string(&stream) {
+        Ok(tags) => tags,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if tags.lines().any(|tag| tag == label) {
+        return Ok(());
+    }
+    std::fs::write(&stream, format!("{}{}\n", tags, label))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn tag(_path: &path::Path, _label: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Gets the tags, separated by commas, with the label added to them, or `None` if it is already
+/// one of them.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn with_tag(tags: &str, label: &str) -> Option<String> {
+    let mut tags: Vec<&str> = tags.split(',').filter(|tag| !tag.is_empty()).collect();
+    if tags.contains(&label) {
+        return None;
+    }
+    tags.push(label);
+    Some(tags.join(","))
+}
+
+/// Gets the year recorded for the file by `--mode tag`, if it has been tagged.
+pub fn year(path: &path::Path) -> Option<String> {
+    read_attribute(path).or_else(|| {
+        let name = path.file_name()?.to_str()?;
+        load_index(path.parent()?).remove(name)
+    })
+}
+
+/// Records the year of the file, or removes it if there is none. The year is kept in the file's
+/// attribute where the file system supports them, and otherwise in the index of its directory.
+pub fn set_year(path: &path::Path, year: Option<&str>) -> io::Result<()> {
+    if write_attribute(path, year).is_ok() {
+        return Ok(());
+    }
+    let dir = path.parent().unwrap_or(path::Path::new(""));
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "name is not valid UTF-8"))?;
+    let mut index = load_index(dir);
+    match year {
+        Some(year) => index.insert(name.to_string(), year.to_string()),
+        None => index.remove(name),
+    };
+    let contents = serde_json::to_vec_pretty(&index).map_err(io::Error::other)?;
+    fs::write(dir.join(INDEX_NAME), contents)
+}
+
+#[cfg(unix)]
+fn read_attribute(path: &path::Path) -> Option<String> {
+    let year = xattr::get(path, YEAR_ATTRIBUTE).ok()??;
+    String::from_utf8(year).ok()
+}
+
+#[cfg(not(unix))]
+fn read_attribute(_path: &path::Path) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn write_attribute(path: &path::Path, year: Option<&str>) -> io::Result<()> {
+    match year {
+        Some(year) => xattr::set(path, YEAR_ATTRIBUTE, year.as_bytes()),
+        None => xattr::remove(path, YEAR_ATTRIBUTE),
+    }
+}
+
+#[cfg(not(unix))]
+fn write_attribute(_path: &path::Path, _year: Option<&str>) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Loads the years of the files in the directory that could not be given the attribute, by their
+/// names.
+fn load_index(dir: &path::Path) -> BTreeMap<String, String> {
+    let path = dir.join(INDEX_NAME);
+    match fs::read(&path) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+            report::note!("Ignoring {}: {}", path.display(), e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+/// A file found by `query`, and the year it was tagged with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tagged {
+    pub path: path::PathBuf,
+    pub year: String,
+}
+
+/// Gets the files directly within the directory that have been tagged with their year, sorted by
+/// year and then by path.
+pub fn query(dir: &path::Path) -> Result<Vec<Tagged>, PlaceError> {
+    let index = load_index(dir);
+    let mut tagged = Vec::new();
+    for path in Walk::new(dir)? {
+        let path = path.map_err(|failure| failure.error)?;
+        let indexed = || {
+            let name = path.file_name()?.to_str()?;
+            index.get(name).cloned()
+        };
+        if let Some(year) = read_attribute(&path).or_else(indexed) {
+            tagged.push(Tagged { path, year });
+        }
+    }
+    tagged.sort_by(|a, b| (&a.year, &a.path).cmp(&(&b.year, &b.path)));
+    Ok(tagged)
+}
+
+/// Tags the files with the year directory of their bucket, leaving them where they are.
+pub struct Tagger {
+    /// The files tagged so far and the years they had before, if they are to be restored when a
+    /// file cannot be tagged.
+    tagged: Option<Vec<(path::PathBuf, Option<String>)>>,
+}
+
+impl Tagger {
+    pub fn new(settings: &Settings) -> Self {
+        Tagger {
+            tagged: settings.transactional.then(Vec::new),
+        }
+    }
+}
+
+impl Sink for Tagger {
+    fn put(&mut self, src: &path::Path, bucket: &Bucket) -> Result<Placement, PlaceError> {
+        let year = bucket.top().ok_or("the bucket has no year directory")?;
+        let previous = self::year(src);
+        if previous.as_deref() == Some(year.as_str()) {
+            return Ok(Placement::UpToDate);
+        }
+        set_year(src, Some(&year)).map_err(|e| PlaceError::io("could not tag file", e))?;
+        audit::record("tag", src, None, None);
+        report::detail!("Tagged {} with {}", src.display(), year);
+        if let Some(tagged) = &mut self.tagged {
+            tagged.push((src.to_path_buf(), previous));
+        }
+        Ok(Placement::Tagged)
+    }
+
+    fn roll_back(&mut self) {
+        for (path, previous) in self.tagged.take().unwrap_or_default().into_iter().rev() {
+            if let Err(e) = set_year(&path, previous.as_deref()) {
+                report::note!("Could not restore the tag of {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{query, set_year, year, Tagger, INDEX_NAME};
+    use crate::plan::{Placement, Settings};
+    use crate::sink::{Bucket, Sink};
+
+    #[test]
+    fn test_tag_mode() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let a = tempdir.path().join("a_10JUL2022.pdf");
+        let b = tempdir.path().join("b_10JUL2021.pdf");
+        fs::write(&a, "").unwrap();
+        fs::write(&b, "").unwrap();
+        let mut tagger = Tagger::new(&Settings::default());
+        let bucket = |dir: &str| Bucket {
+            base: tempdir.path().to_path_buf(),
+            dir: path::PathBuf::from(dir),
+            date: None,
+            name: None,
+        };
+
+        assert_eq!(tagger.put(&a, &bucket("2023FY/Q1")), Ok(Placement::Tagged));
+        assert_eq!(
+            tagger.put(&a, &bucket("2023FY/Q1")),
+            Ok(Placement::UpToDate)
+        );
+        tagger.put(&b, &bucket("2022FY")).unwrap();
+        assert_eq!(year(&a).as_deref(), Some("2023FY"));
+        let years: Vec<String> = query(tempdir.path())
+            .unwrap()
+            .into_iter()
+            .map(|tagged| tagged.year)
+            .collect();
+        assert_eq!(years, ["2022FY", "2023FY"]);
+
+        set_year(&a, None).unwrap();
+        assert_eq!(year(&a), None);
+        assert!(a.is_file());
+
+        // Where the file system has no attributes, the years are kept in the index instead.
+        let c = tempdir.path().join("c.pdf");
+        fs::write(&c, "").unwrap();
+        fs::write(tempdir.path().join(INDEX_NAME), r#"{"c.pdf": "2021FY"}"#).unwrap();
+        assert_eq!(year(&c).as_deref(), Some("2021FY"));
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_with_tag() {
+        use super::with_tag;
+
+        assert_eq!(with_tag("", "2023FY").as_deref(), Some("2023FY"));
+        assert_eq!(
+            with_tag("receipt", "2023FY").as_deref(),
+            Some("receipt,2023FY")
+        );
+        assert_eq!(with_tag("2023FY,receipt", "2023FY"), None);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_tag() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let path = tempdir.path().join("a_10JUL2022.pdf");
+        fs::write(&path, "").unwrap();
+        if xattr::set(&path, super::XDG_TAGS, b"receipt").is_err() {
+            // The file system does not support extended attributes.
+            return;
+        }
+        super::tag(&path, "2023FY").unwrap();
+        super::tag(&path, "2023FY").unwrap();
+        assert_eq!(
+            xattr::get(&path, super::XDG_TAGS).unwrap(),
+            Some(b"receipt,2023FY".to_vec())
+        );
+    }
+}
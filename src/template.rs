@@ -0,0 +1,269 @@
+use std::fmt;
+use std::ops;
+
+/// The template used when none is given, producing names such as "2022FY".
+pub const DEFAULT_DIR_TEMPLATE: &str = "{year}FY";
+
+/// The names of the built-in directory naming styles, each an example of the name it gives to the
+/// 2022 financial year, with the template that it stands for.
+pub const FY_STYLES: [(&str, &str); 4] = [
+    ("2022FY", "{year}FY"),
+    ("FY2022", "FY{year}"),
+    ("FY21-22", "FY{year_short_prev}-{year_short}"),
+    ("2021-22", "{year_prev}-{year_short}"),
+];
+
+/// The placeholders that may appear in a directory template.
+const PLACEHOLDERS: [&str; 6] = [
+    "year",
+    "year_short",
+    "year_prev",
+    "year_short_prev",
+    "year_next",
+    "year_short_next",
+];
+
+/// A template for naming the financial year directories. Placeholders are written in braces, for
+/// example `FY{year}` or `{year_prev}-{year_short} Financial Year`.
+#[derive(Clone)]
+pub struct DirTemplate {
+    template: String,
+    /// Matches the names given by the template, ignoring case, capturing the first of each
+    /// placeholder by its name.
+    pattern: regex::Regex,
+}
+
+impl DirTemplate {
+    /// Parses the template, checking that every placeholder in it is recognised.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut pattern = String::from("(?i)^");
+        let mut captured = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("Unclosed placeholder in template {:?}", template)),
+            };
+            let name = &rest[start + 1..end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in template {:?}",
+                    name, template
+                ));
+            }
+            pattern.push_str(&regex::escape(&rest[..start]));
+            let digits = if name.contains("short") { 2 } else { 4 };
+            if captured.contains(&name) {
+                pattern.push_str(&format!(r"\d{{{}}}", digits));
+            } else {
+                captured.push(name);
+                pattern.push_str(&format!(r"(?P<{}>\d{{{}}})", name, digits));
+            }
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("Unopened placeholder in template {:?}", template));
+        }
+        if template.trim().is_empty() {
+            return Err(String::from("Template must not be empty"));
+        }
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+        Ok(DirTemplate {
+            template: String::from(template),
+            pattern: regex::Regex::new(&pattern).map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// Gets the template for one of the built-in styles in `FY_STYLES`, such as "FY2022".
+    pub fn style(name: &str) -> Result<Self, String> {
+        FY_STYLES
+            .iter()
+            .find(|(style, _)| style.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown FY style {:?}", name))
+            .and_then(|(_, template)| DirTemplate::parse(template))
+    }
+
+    /// Gets the name of the directory for the given financial year. Fails if the template names
+    /// the year before or after it and there is no such year.
+    pub fn render(&self, fy: u16) -> Result<String, String> {
+        let out_of_range = || {
+            format!(
+                "Cannot name the directory of {}FY with {:?}, as there is no year before or after it",
+                fy, self.template
+            )
+        };
+        let mut name = self.template.clone();
+        if name.contains("_prev}") {
+            let prev = fy.checked_sub(1).ok_or_else(out_of_range)?;
+            name = name
+                .replace("{year_short_prev}", &format!("{:02}", prev % 100))
+                .replace("{year_prev}", &prev.to_string());
+        }
+        if name.contains("_next}") {
+            let next = fy.checked_add(1).ok_or_else(out_of_range)?;
+            name = name
+                .replace("{year_short_next}", &format!("{:02}", next % 100))
+                .replace("{year_next}", &next.to_string());
+        }
+        Ok(name
+            .replace("{year_short}", &format!("{:02}", fy % 100))
+            .replace("{year}", &fy.to_string()))
+    }
+
+    /// Gets the financial year, among the years, whose directory the template gives the name to,
+    /// ignoring case. When the template only has the last two digits of the year, the first of
+    /// the years ending in them is used.
+    pub fn year_of(&self, name: &str, years: ops::RangeInclusive<u16>) -> Option<u16> {
+        let captures = self.pattern.captures(name)?;
+        let number = |placeholder| {
+            captures
+                .name(placeholder)
+                .and_then(|digits| digits.as_str().parse::<u16>().ok())
+        };
+        let first = *years.start();
+        let ending_in = |short: u16| first + (short + 100 - first % 100) % 100;
+        let fy = if let Some(year) = number("year") {
+            year
+        } else if let Some(prev) = number("year_prev") {
+            prev.checked_add(1)?
+        } else if let Some(next) = number("year_next") {
+            next.checked_sub(1)?
+        } else if let Some(short) = number("year_short") {
+            ending_in(short)
+        } else if let Some(prev) = number("year_short_prev") {
+            ending_in((prev + 1) % 100)
+        } else if let Some(next) = number("year_short_next") {
+            ending_in((next + 99) % 100)
+        } else {
+            first
+        };
+        // The other placeholders have to agree with the year, which rendering it checks.
+        (years.contains(&fy) && self.render(fy).ok()?.to_uppercase() == name.to_uppercase())
+            .then_some(fy)
+    }
+}
+
+impl Default for DirTemplate {
+    fn default() -> Self {
+        DirTemplate::parse(DEFAULT_DIR_TEMPLATE).expect("default template should be valid")
+    }
+}
+
+impl PartialEq for DirTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.template == other.template
+    }
+}
+
+impl Eq for DirTemplate {}
+
+impl fmt::Debug for DirTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirTemplate")
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirTemplate, FY_STYLES};
+
+    #[test]
+    fn test_render() {
+        assert_eq!(DirTemplate::default().render(2022).unwrap(), "2022FY");
+        assert_eq!(
+            DirTemplate::parse("FY{year}")
+                .unwrap()
+                .render(2022)
+                .unwrap(),
+            "FY2022"
+        );
+        assert_eq!(
+            DirTemplate::parse("{year_prev}-{year_short} Financial Year")
+                .unwrap()
+                .render(2022)
+                .unwrap(),
+            "2021-22 Financial Year"
+        );
+        assert_eq!(
+            DirTemplate::parse("{year}-{year_short_next}")
+                .unwrap()
+                .render(2009)
+                .unwrap(),
+            "2009-10"
+        );
+    }
+
+    #[test]
+    fn test_render_out_of_range() {
+        assert_eq!(DirTemplate::default().render(0).unwrap(), "0FY");
+        assert!(DirTemplate::style("FY21-22").unwrap().render(0).is_err());
+        assert!(DirTemplate::parse("{year}-{year_next}")
+            .unwrap()
+            .render(u16::MAX)
+            .is_err());
+    }
+
+    #[test]
+    fn test_styles() {
+        for (style, _) in FY_STYLES {
+            assert_eq!(
+                DirTemplate::style(style).unwrap().render(2022).unwrap(),
+                style
+            );
+        }
+        assert_eq!(
+            DirTemplate::style("fy2022").unwrap().render(2023).unwrap(),
+            "FY2023"
+        );
+        assert!(DirTemplate::style("2022").is_err());
+    }
+
+    #[test]
+    fn test_year_of() {
+        let years = 1900..=2199;
+        assert_eq!(
+            DirTemplate::default().year_of("2022fy", years.clone()),
+            Some(2022)
+        );
+        assert_eq!(DirTemplate::default().year_of("2022", years.clone()), None);
+        assert_eq!(
+            DirTemplate::default().year_of("1899FY", years.clone()),
+            None
+        );
+        let template = DirTemplate::parse("{year_prev}-{year_short} (FY{year})").unwrap();
+        assert_eq!(
+            template.year_of("2021-22 (fy2022)", years.clone()),
+            Some(2022)
+        );
+        assert_eq!(template.year_of("2021-23 (FY2022)", years.clone()), None);
+        assert_eq!(
+            DirTemplate::style("FY21-22")
+                .unwrap()
+                .year_of("FY99-00", years.clone()),
+            Some(1900)
+        );
+        assert_eq!(
+            DirTemplate::parse("FY{year_short}")
+                .unwrap()
+                .year_of("FY22", years.clone()),
+            Some(1922)
+        );
+        assert_eq!(
+            DirTemplate::parse("FY.{year}")
+                .unwrap()
+                .year_of("FYX2022", years),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(DirTemplate::parse("FY{yr}").is_err());
+        assert!(DirTemplate::parse("FY{year").is_err());
+        assert!(DirTemplate::parse("FY}year").is_err());
+        assert!(DirTemplate::parse("").is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! A full-screen interface for reviewing where the files will be placed before moving them.
+
+use std::fs;
+use std::io;
+use std::path;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout as ScreenLayout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use classfy::bucket::Layout;
+use classfy::date::{Extractor, FileDate};
+use classfy::extract;
+use classfy::overrides;
+use classfy::plan::{self, Candidate};
+
+/// The reason given for files that the user has chosen to leave in place.
+const EXCLUDED: &str = "Excluded";
+
+/// Help shown at the bottom of the screen while browsing the files.
+const BROWSE_HELP: &str =
+    "↑/↓ move  space include/exclude  d set date  e edit destination  a apply  q quit without moving";
+
+/// What the keyboard is currently being used for.
+enum Mode {
+    /// Moving between the files.
+    Browse,
+    /// Typing a new destination for the selected file.
+    Edit(String),
+    /// Typing the date of the selected file.
+    Date(String),
+    /// Asking whether to remember the date given to the selected file in later runs.
+    Remember(FileDate),
+    /// Waiting for the user to confirm that the files should be moved.
+    Confirm,
+}
+
+/// The state of the review screen.
+struct App<'a> {
+    layout: &'a Layout,
+    extractor: &'a Extractor,
+    candidates: Vec<Candidate>,
+    state: TableState,
+    mode: Mode,
+    message: Option<String>,
+}
+
+/// Show the files with where they will be placed, and move them once the user confirms. Gets the
+/// plan as the user left it, along with what applying it did, which is nothing if they abandoned
+/// it.
+pub fn run(
+    candidates: Vec<Candidate>,
+    layout: &Layout,
+    extractor: &Extractor,
+    settings: &plan::Settings,
+) -> io::Result<(plan::Plan, plan::Outcome)> {
+    if candidates.is_empty() {
+        println!("There are no files to classify");
+        return Ok((plan::Plan::default(), plan::Outcome::default()));
+    }
+
+    // Start at the first file without a date, so that the user can give it one.
+    let dateless: Vec<usize> = (0..candidates.len())
+        .filter(|&index| candidates[index].date.is_err())
+        .collect();
+    let mut app = App {
+        layout,
+        extractor,
+        candidates,
+        state: TableState::default().with_selected(dateless.first().copied().unwrap_or(0)),
+        mode: Mode::Browse,
+        message: (!dateless.is_empty()).then(|| {
+            format!(
+                "{} files have no date, press d to give the selected one a date",
+                dateless.len()
+            )
+        }),
+    };
+    let apply = ratatui::run(|terminal| app.run(terminal))?;
+    let plan = plan::Plan::new(&app.candidates);
+    if apply {
+        let outcome = plan.apply(settings);
+        Ok((plan, outcome))
+    } else {
+        println!("Nothing was moved");
+        Ok((plan, plan::Outcome::default()))
+    }
+}
+
+impl App<'_> {
+    /// Handle the user's input until they either apply or abandon the plan. Returns whether the
+    /// plan should be applied.
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            self.message = None;
+            match &mut self.mode {
+                Mode::Browse => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                    KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+                    KeyCode::Down | KeyCode::Char('j') => self.state.select_next(),
+                    KeyCode::Char(' ') => self.toggle(),
+                    KeyCode::Char('e') | KeyCode::Enter => {
+                        let current = match &self.selected().bucket {
+                            Ok(bucket) => bucket.display().to_string(),
+                            Err(_) => String::new(),
+                        };
+                        self.mode = Mode::Edit(current);
+                    }
+                    KeyCode::Char('d') => {
+                        let current = self.current_date();
+                        self.mode = Mode::Date(current);
+                    }
+                    KeyCode::Char('a') => self.mode = Mode::Confirm,
+                    _ => {}
+                },
+                Mode::Date(text) => match key.code {
+                    KeyCode::Char(c) => text.push(c),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Enter => {
+                        let text = text.clone();
+                        self.mode = match self.set_date(&text) {
+                            Some(date) => Mode::Remember(date),
+                            None => Mode::Browse,
+                        };
+                    }
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    _ => {}
+                },
+                Mode::Remember(date) => {
+                    if key.code == KeyCode::Char('y') {
+                        let date = *date;
+                        let candidate = self.selected();
+                        let (path, base) = (candidate.path.clone(), candidate.base.clone());
+                        self.message = Some(match overrides::remember(&path, &base, date) {
+                            Ok(()) => {
+                                format!("{} will be dated {} from now on", path.display(), date)
+                            }
+                            Err(e) => e,
+                        });
+                    }
+                    self.mode = Mode::Browse;
+                }
+                Mode::Edit(text) => match key.code {
+                    KeyCode::Char(c) => text.push(c),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Enter => {
+                        let text = text.clone();
+                        self.set_destination(&text);
+                        self.mode = Mode::Browse;
+                    }
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    _ => {}
+                },
+                Mode::Confirm => match key.code {
+                    KeyCode::Char('y') => return Ok(true),
+                    _ => self.mode = Mode::Browse,
+                },
+            }
+        }
+    }
+
+    fn selected(&mut self) -> &mut Candidate {
+        let index = self
+            .state
+            .selected()
+            .unwrap_or(0)
+            .min(self.candidates.len() - 1);
+        &mut self.candidates[index]
+    }
+
+    /// Include or exclude the selected file. Files without a destination can only be included by
+    /// giving them one.
+    fn toggle(&mut self) {
+        let layout = self.layout;
+        let candidate = self.selected();
+        candidate.bucket = match &candidate.bucket {
+            Ok(_) => Err(String::from(EXCLUDED)),
+            Err(e) if e == EXCLUDED => candidate
+                .date
+                .clone()
+                .and_then(|date| layout.existing_bucket(&candidate.base, date)),
+            Err(e) => {
+                let message = format!(
+                    "No destination ({}), press d to give a date or e to set one",
+                    e
+                );
+                self.message = Some(message);
+                return;
+            }
+        };
+    }
+
+    /// Gets the date of the selected file to start from when giving it one. Files without a date
+    /// start from the day they were last modified.
+    fn current_date(&mut self) -> String {
+        let candidate = self.selected();
+        if let Ok(date) = &candidate.date {
+            return date.to_string();
+        }
+        fs::metadata(&candidate.path)
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| {
+                extract::local_date(modified, self.extractor.timezone)
+                    .format("%Y-%m-%d")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Set the date of the selected file from what the user typed, placing it in the bucket for
+    /// the date. Gets the date, unless it could not be read.
+    fn set_date(&mut self, text: &str) -> Option<FileDate> {
+        let date = match text.trim().parse::<FileDate>() {
+            Ok(date) => date,
+            Err(e) => {
+                self.message = Some(e);
+                return None;
+            }
+        };
+        let (layout, extractor) = (self.layout, self.extractor);
+        let candidate = self.selected();
+        candidate.date = Ok(date);
+        candidate.bucket = layout.existing_bucket(&candidate.base, date);
+        candidate.name = plan::placed_name(&candidate.path, &candidate.date, layout, extractor);
+        Some(date)
+    }
+
+    /// Set the destination of the selected file from what the user typed. A year on its own is
+    /// taken to be the financial year. Leaving it empty excludes the file.
+    fn set_destination(&mut self, text: &str) {
+        let text = text.trim();
+        let bucket = if text.is_empty() {
+            Err(String::from(EXCLUDED))
+        } else if let Ok(fy) = text.parse::<u16>() {
+            self.layout.dir_template.render(fy).map(path::PathBuf::from)
+        } else {
+            let bucket = path::PathBuf::from(text);
+            let escapes = bucket
+                .components()
+                .any(|c| !matches!(c, path::Component::Normal(_)));
+            if escapes {
+                self.message = Some(format!(
+                    "{} must be a directory within the classified directory",
+                    text
+                ));
+                return;
+            }
+            Ok(bucket)
+        };
+        self.selected().bucket = bucket;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, footer_area] =
+            ScreenLayout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+
+        let rows = self.candidates.iter().map(|candidate| {
+            let (mark, destination, style) = match &candidate.bucket {
+                Ok(bucket) => ("[x]", bucket.display().to_string(), Style::default()),
+                Err(e) => ("[ ]", e.clone(), Style::default().fg(Color::DarkGray)),
+            };
+            let date = match &candidate.date {
+                Ok(date) => date.to_string(),
+                Err(_) => String::from("-"),
+            };
+            Row::new(vec![
+                Cell::from(mark),
+                Cell::from(candidate.path.display().to_string()),
+                Cell::from(date),
+                Cell::from(destination),
+            ])
+            .style(style)
+        });
+        let moving = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.bucket.is_ok())
+            .count();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Percentage(50),
+                Constraint::Length(8),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec!["", "File", "Date", "Destination"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " classfy: {} of {} files to move ",
+            moving,
+            self.candidates.len()
+        )))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.state);
+
+        let footer = match &self.mode {
+            Mode::Browse => match &self.message {
+                Some(message) => message.clone(),
+                None => String::from(BROWSE_HELP),
+            },
+            Mode::Edit(text) => format!(
+                "Destination (a year, a directory, or empty to exclude): {}_",
+                text
+            ),
+            Mode::Date(text) => format!("Date (such as 2022-09-14, 2022-09 or 2023FY): {}_", text),
+            Mode::Remember(date) => format!(
+                "Dated {}. Remember this date in later runs? y to remember, any other key not to",
+                date
+            ),
+            Mode::Confirm => format!(
+                "Move {} files? y to confirm, any other key to go back",
+                moving
+            ),
+        };
+        frame.render_widget(
+            Paragraph::new(footer).block(Block::default().borders(Borders::ALL)),
+            footer_area,
+        );
+    }
+}
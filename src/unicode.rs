@@ -0,0 +1,65 @@
+//! Normalising file names, which macOS gives in decomposed form (NFD, such as "A" followed by a
+//! combining diaeresis) where other systems usually give them composed (NFC, "Ä"), so that the same
+//! name is read and compared the same way wherever the file came from.
+
+use std::borrow::Cow;
+use std::ffi;
+use std::path;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Gets the text in composed form (NFC).
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.nfc().collect())
+    }
+}
+
+/// Checks if the names are the same once they are both in composed form.
+pub fn same_name(a: &ffi::OsStr, b: &ffi::OsStr) -> bool {
+    a == b || nfc(&a.to_string_lossy()) == nfc(&b.to_string_lossy())
+}
+
+/// Checks if the paths are the same once their names are all in composed form.
+pub fn same_path(a: &path::Path, b: &path::Path) -> bool {
+    a == b
+        || a.components().count() == b.components().count()
+            && a.components()
+                .zip(b.components())
+                .all(|(a, b)| same_name(a.as_os_str(), b.as_os_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi;
+    use std::path;
+
+    use super::{nfc, same_name, same_path};
+
+    #[test]
+    fn test_nfc() {
+        let decomposed = "FE\u{301}V2023";
+        assert_eq!(decomposed.chars().count(), 8);
+        assert_eq!(nfc(decomposed), "FÉV2023");
+        assert_eq!(nfc("FÉV2023"), "FÉV2023");
+
+        assert!(same_name(
+            ffi::OsStr::new("Cafe\u{301}.pdf"),
+            ffi::OsStr::new("Café.pdf")
+        ));
+        assert!(!same_name(
+            ffi::OsStr::new("Cafe.pdf"),
+            ffi::OsStr::new("Café.pdf")
+        ));
+        assert!(same_path(
+            path::Path::new("2023FY/Cafe\u{301}.pdf"),
+            path::Path::new("2023FY/Café.pdf")
+        ));
+        assert!(!same_path(
+            path::Path::new("2024FY/Café.pdf"),
+            path::Path::new("2023FY/Café.pdf")
+        ));
+    }
+}
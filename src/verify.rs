@@ -0,0 +1,176 @@
+//! Auditing an existing archive for files that are not in the directory that their date belongs in.
+
+use std::path;
+
+use crate::bucket::Layout;
+use crate::date::Extractor;
+use crate::plan::{self, Failure, PlaceError};
+use crate::report;
+
+/// A file that is not in the directory that its date belongs in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Misplaced {
+    pub path: path::PathBuf,
+    /// The directory, relative to the archive, that the file belongs in.
+    pub bucket: path::PathBuf,
+}
+
+/// What was found when auditing an archive.
+#[derive(Debug, Default)]
+pub struct Audit {
+    /// The number of files that are in the right directory.
+    pub correct: usize,
+    /// The number of files that could not be checked, as their date could not be found.
+    pub unchecked: usize,
+    pub misplaced: Vec<Misplaced>,
+    pub failures: Vec<Failure>,
+}
+
+/// Checks each of the files in the year directories of the archive, without moving anything.
+/// Files that are directly in the archive, rather than in a year directory, are not checked.
+pub fn verify(root: &path::Path, layout: &Layout, extractor: &Extractor) -> Result<Audit, String> {
+    let entries = root
+        .read_dir()
+        .map_err(|e| format!("Could not read directory {}: {}", root.display(), e))?;
+    let mut year_dirs: Vec<path::PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| layout.is_year_dir(name))
+        })
+        .collect();
+    year_dirs.sort();
+    if year_dirs.is_empty() {
+        report::note!("There are no year directories in {}", root.display());
+    }
+
+    let mut audit = Audit::default();
+    for year_dir in year_dirs {
+        audit_dir(root, &year_dir, layout, extractor, &mut audit);
+    }
+    Ok(audit)
+}
+
+/// Checks the files in the directory and the directories within it.
+fn audit_dir(
+    root: &path::Path,
+    dir: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    audit: &mut Audit,
+) {
+    let mut paths: Vec<path::PathBuf> = match dir.read_dir() {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(e) => {
+            let error = PlaceError::io(&format!("Could not read directory {}", dir.display()), e);
+            report::note!("{}", error);
+            audit.failures.push(Failure {
+                path: dir.to_path_buf(),
+                error,
+            });
+            return;
+        }
+    };
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            audit_dir(root, &path, layout, extractor, audit);
+        } else if path.is_file() && !plan::is_own_file(&path) {
+            audit_file(root, &path, layout, extractor, audit);
+        }
+    }
+}
+
+fn audit_file(
+    root: &path::Path,
+    path: &path::Path,
+    layout: &Layout,
+    extractor: &Extractor,
+    audit: &mut Audit,
+) {
+    let dir = path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .expect("file should be within the archive");
+    let checked = extractor.get_date(path).and_then(|date| {
+        if layout.belongs_in(dir, date)? {
+            Ok(None)
+        } else {
+            Ok(Some(layout.existing_bucket(root, date)?))
+        }
+    });
+    match checked {
+        Ok(None) => audit.correct += 1,
+        Ok(Some(bucket)) => {
+            report::note!(
+                "{} is in {} but belongs in {}",
+                path.display(),
+                dir.display(),
+                bucket.display()
+            );
+            audit.misplaced.push(Misplaced {
+                path: path.to_path_buf(),
+                bucket,
+            });
+        }
+        Err(reason) => {
+            report::detail!("Could not check {}: {}", path.display(), reason);
+            audit.unchecked += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path;
+
+    use super::{verify, Misplaced};
+    use crate::bucket::Layout;
+    use crate::date::Extractor;
+
+    #[test]
+    fn test_verify() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        let base = tempdir.path();
+        fs::create_dir_all(base.join("2023FY").join("receipts")).unwrap();
+        fs::create_dir(base.join("FY2022")).unwrap();
+        fs::write(base.join("2023FY").join("right_10JUL2022.pdf"), "").unwrap();
+        fs::write(
+            base.join("2023FY")
+                .join("receipts")
+                .join("wrong_10JUN2022.pdf"),
+            "",
+        )
+        .unwrap();
+        fs::write(base.join("2023FY").join("notes.txt"), "").unwrap();
+        fs::write(base.join("FY2022").join("wrong_10AUG2022.pdf"), "").unwrap();
+        fs::write(base.join("unclassified_10AUG2022.pdf"), "").unwrap();
+
+        let audit = verify(base, &Layout::default(), &Extractor::default()).unwrap();
+        assert_eq!((audit.correct, audit.unchecked), (1, 1));
+        assert!(audit.failures.is_empty());
+        assert_eq!(
+            audit.misplaced,
+            vec![
+                Misplaced {
+                    path: base
+                        .join("2023FY")
+                        .join("receipts")
+                        .join("wrong_10JUN2022.pdf"),
+                    bucket: path::PathBuf::from("FY2022"),
+                },
+                Misplaced {
+                    path: base.join("FY2022").join("wrong_10AUG2022.pdf"),
+                    bucket: path::PathBuf::from("2023FY"),
+                },
+            ]
+        );
+        // Nothing is moved.
+        assert!(base.join("FY2022").join("wrong_10AUG2022.pdf").is_file());
+    }
+}
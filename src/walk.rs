@@ -0,0 +1,106 @@
+//! Reading the entries of a directory one at a time, so that the files in directories with
+//! millions of entries are classified as they are found rather than after collecting all of
+//! their paths.
+
+use std::fs;
+use std::path;
+
+use crate::plan::{Failure, PlaceError};
+use crate::report;
+
+/// How many entries are read between each report of the progress.
+pub const PROGRESS_INTERVAL: u64 = 10_000;
+
+/// The files directly within a directory, found as its entries are read. An entry that cannot be
+/// read is given as a failure, which ends the walk without losing the files already found.
+pub struct Walk {
+    dir: path::PathBuf,
+    entries: fs::ReadDir,
+    read: u64,
+}
+
+impl Walk {
+    /// Starts reading the entries of the directory.
+    pub fn new(dir: &path::Path) -> Result<Self, PlaceError> {
+        let entries = dir.read_dir().map_err(|e| {
+            PlaceError::io(&format!("Could not read directory {}", dir.display()), e)
+        })?;
+        Ok(Walk {
+            dir: dir.to_path_buf(),
+            entries,
+            read: 0,
+        })
+    }
+
+    /// Gets how many entries have been read so far, including those that are not files.
+    pub fn read(&self) -> u64 {
+        self.read
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<path::PathBuf, Failure>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.entries.next()? {
+                Ok(entry) => entry,
+                Err(e) => {
+                    return Some(Err(Failure {
+                        path: self.dir.clone(),
+                        error: PlaceError::io(
+                            &format!(
+                                "Could not read directory {} after {} entries",
+                                self.dir.display(),
+                                self.read
+                            ),
+                            e,
+                        ),
+                    }))
+                }
+            };
+            self.read += 1;
+            progress(&self.dir, self.read);
+            let path = entry.path();
+            if path.is_file() {
+                return Some(Ok(path));
+            }
+        }
+    }
+}
+
+/// Reports how many entries of the directory have been read, every `PROGRESS_INTERVAL` entries.
+pub(crate) fn progress(dir: &path::Path, read: u64) {
+    if read.is_multiple_of(PROGRESS_INTERVAL) {
+        report::note!("Read {} entries of {}", read, dir.display());
+        report::event(&report::Event::Progress {
+            source: dir,
+            entries: read,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Walk;
+
+    #[test]
+    fn test_walk() {
+        let tempdir = tempfile::tempdir().expect("could not create temp directory");
+        fs::write(tempdir.path().join("a.pdf"), "").unwrap();
+        fs::write(tempdir.path().join("b.pdf"), "").unwrap();
+        fs::create_dir(tempdir.path().join("2023FY")).unwrap();
+
+        let mut walk = Walk::new(tempdir.path()).unwrap();
+        let mut files: Vec<_> = walk.by_ref().map(Result::unwrap).collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [tempdir.path().join("a.pdf"), tempdir.path().join("b.pdf")]
+        );
+        assert_eq!(walk.read(), 3);
+        assert!(Walk::new(&tempdir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Classifying the directories listed with `[[watch]]` in the configuration, each with the
+//! settings of its own profile. `classfy run` classifies them once, or on the schedule in the
+//! configuration with --scheduled, and the daemon classifies them as files arrive.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{Local, TimeZone};
+
+use classfy::config::{self, Watch};
+use classfy::input::{Input, Source};
+use classfy::schedule::{self, Schedule};
+use classfy::{plan, report};
+
+use crate::{cli, fail, finish, load_config, place, Options, EXIT_IO_ERROR, EXIT_USAGE};
+
+/// The name of the file that is locked while the directories are being classified.
+const LOCK_NAME: &str = "classfy-run.lock";
+
+/// How long a file must be left alone before it is classified, so that files that are still being
+/// written are not moved.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+/// A directory being watched, with the settings to classify its files with.
+pub struct Watched {
+    pub watch: Watch,
+    options: Options,
+    /// The files that were left in place, with when they were modified, so that they are only tried
+    /// again once they change.
+    left: HashSet<(path::PathBuf, SystemTime)>,
+}
+
+/// Classifies the configured directories once, or on the schedule in the configuration with
+/// --scheduled until it is stopped.
+pub fn run(matches: &clap::ArgMatches, sub_matches: &clap::ArgMatches) -> i32 {
+    let config = match load_config(matches, None) {
+        Ok(config) => config,
+        Err(e) => return fail(EXIT_USAGE, &e),
+    };
+    let mut watched = match load_watches(&config, matches) {
+        Ok(watched) => watched,
+        Err(e) => return fail(EXIT_USAGE, &e),
+    };
+    if !sub_matches.get_flag("scheduled") {
+        return match run_locked(&mut watched, matches) {
+            Ok(code) => code,
+            Err(e) => fail(EXIT_IO_ERROR, &e),
+        };
+    }
+    let Some(schedule) = config.schedule else {
+        return fail(
+            EXIT_USAGE,
+            "There is no schedule in the configuration, add one such as schedule = \"daily 02:00\"",
+        );
+    };
+    loop {
+        let next = next_run(&schedule, config.schedule_jitter);
+        report::note!(
+            "Next run at {}",
+            (Local::now() + next).format("%Y-%m-%d %H:%M:%S")
+        );
+        thread::sleep(next);
+        // A run that takes longer than the schedule leads into the next one after it rather than
+        // catching up on those that were missed.
+        if let Err(e) = run_locked(&mut watched, matches) {
+            report::note!("{}", e);
+        }
+    }
+}
+
+/// Gets how long to wait until the next run, with a random delay added.
+fn next_run(schedule: &Schedule, jitter: Duration) -> Duration {
+    let now = Local::now();
+    let next = schedule.next_after(now.naive_local());
+    // A time that is skipped when the clocks go forward is taken as the hour after it.
+    let next = Local
+        .from_local_datetime(&next)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(next + chrono::TimeDelta::hours(1)))
+                .earliest()
+        })
+        .unwrap_or(now);
+    (next - now).to_std().unwrap_or_default() + schedule::jitter(jitter)
+}
+
+/// Classifies each of the directories while holding the lock, so that runs from other processes do
+/// not overlap with this one, getting the exit code that describes the runs.
+fn run_locked(watched: &mut [Watched], matches: &clap::ArgMatches) -> Result<i32, String> {
+    let lock_path = lock_path();
+    let lock = fs::File::create(&lock_path)
+        .map_err(|e| format!("Could not create {}: {}", lock_path.display(), e))?;
+    match lock.try_lock() {
+        Ok(()) => (),
+        Err(fs::TryLockError::WouldBlock) => {
+            return Err(String::from(
+                "Skipping this run, as another is still classifying the directories",
+            ))
+        }
+        Err(fs::TryLockError::Error(e)) => {
+            return Err(format!("Could not lock {}: {}", lock_path.display(), e))
+        }
+    }
+    let mut code = 0;
+    for watched in watched.iter_mut() {
+        match scan(watched, matches) {
+            Ok(Some((_, 0))) | Ok(None) => (),
+            Ok(Some((_, exit_code))) => code = code.max(exit_code),
+            Err(e) => {
+                report::note!("Could not classify {}: {}", watched.watch.dir.display(), e);
+                code = EXIT_IO_ERROR;
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// Gets the file that is locked while the directories are being classified, in the user's runtime
+/// directory.
+pub fn lock_path() -> path::PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(LOCK_NAME)
+}
+
+/// Gets the directories to watch in the configuration, with the settings for each.
+pub fn load_watches(
+    config: &config::Config,
+    matches: &clap::ArgMatches,
+) -> Result<Vec<Watched>, String> {
+    if config.watch.is_empty() {
+        return Err(String::from(
+            "There are no directories to watch, add them to the configuration with [[watch]]",
+        ));
+    }
+    let default_profile = matches.get_one::<String>("profile").map(String::as_str);
+    config
+        .watch
+        .iter()
+        .map(|watch| {
+            let profile = watch.profile.as_deref().or(default_profile);
+            let options = Options::with_profile(matches, profile)
+                .map_err(|e| format!("{}: {}", watch.dir.display(), e))?;
+            Ok(Watched {
+                watch: watch.clone(),
+                options,
+                left: HashSet::new(),
+            })
+        })
+        .collect()
+}
+
+/// Classifies the files in the directory that have settled and have not already been left in
+/// place, getting the outcome and the exit code that describes it if there were any.
+pub fn scan(
+    watched: &mut Watched,
+    matches: &clap::ArgMatches,
+) -> Result<Option<(plan::Outcome, i32)>, String> {
+    let started = Instant::now();
+    let input = Input {
+        source: Source::Dirs(vec![watched.watch.dir.clone()]),
+        ..cli::input(matches)
+    };
+    let options = &watched.options;
+    let (candidates, failures) = input.candidates(&options.layout, &options.extractor, false)?;
+    let now = SystemTime::now();
+    let candidates: Vec<plan::Candidate> = candidates
+        .into_iter()
+        .filter(|candidate| match modified(&candidate.path) {
+            Some(mtime) => {
+                !watched.left.contains(&(candidate.path.clone(), mtime))
+                    && now.duration_since(mtime).unwrap_or_default() >= SETTLE_TIME
+            }
+            None => false,
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let paths: Vec<path::PathBuf> = candidates
+        .iter()
+        .map(|candidate| candidate.path.clone())
+        .collect();
+    let (plan, outcome) = place(candidates, failures, options, matches)?;
+    let code = finish(&outcome, &plan, &options.config, matches, started);
+    for path in paths {
+        if let Some(mtime) = modified(&path) {
+            watched.left.insert((path, mtime));
+        }
+    }
+    Ok(Some((outcome, code)))
+}
+
+/// Gets when the file was last modified, if it still exists.
+fn modified(path: &path::Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
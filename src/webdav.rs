@@ -0,0 +1,164 @@
+//! Uploading the classified files to a directory on a WebDAV server, such as a Nextcloud or
+//! ownCloud folder, given as `webdav://host/path`. The directories for the buckets are created as
+//! they are needed.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path;
+
+use base64::Engine;
+
+use crate::plan::PlaceError;
+use crate::remote::{uri_encode, Credentials, Destination, Upload};
+
+/// Uploads files to a directory on a WebDAV server over HTTPS.
+#[derive(Debug)]
+pub struct WebDav {
+    /// The URL of the directory, such as "https://nextcloud.example/archive", without a trailing
+    /// "/".
+    root: String,
+    /// The Authorization header to send, if there are credentials.
+    authorization: Option<String>,
+    /// The directories that are known to exist, relative to the root.
+    created: HashSet<String>,
+    agent: ureq::Agent,
+}
+
+impl WebDav {
+    /// Uses the token if there is one, or otherwise the user and password. The user may also be
+    /// given in the URL, as in `webdav://alex@nextcloud.example/archive`.
+    pub fn new(destination: &Destination, credentials: &Credentials) -> Result<Self, String> {
+        let user = destination.user.as_ref().or(credentials.user.as_ref());
+        let authorization = match (&credentials.token, user, &credentials.password) {
+            (Some(token), _, _) => Some(format!("Bearer {}", token)),
+            (None, Some(user), Some(password)) => Some(basic(user, password)),
+            (None, Some(_), None) => {
+                return Err(format!(
+                    "There is no password for {}, set CLASSFY_WEBDAV_PASSWORD or password in \
+                     [webdav]",
+                    destination
+                ))
+            }
+            (None, None, _) => None,
+        };
+        let mut root = format!("https://{}", destination.host);
+        if !destination.path.is_empty() {
+            root = format!("{}/{}", root, uri_encode(&destination.path, false));
+        }
+        Ok(WebDav {
+            root,
+            authorization,
+            created: HashSet::new(),
+            agent: ureq::Agent::new(),
+        })
+    }
+
+    /// Creates each of the directories that the file at the path goes in, that do not exist yet.
+    fn create_dirs(&mut self, key: &str) -> Result<(), String> {
+        let Some((dirs, _)) = key.rsplit_once('/') else {
+            return Ok(());
+        };
+        let mut dir = String::new();
+        for part in dirs.split('/') {
+            if !dir.is_empty() {
+                dir.push('/');
+            }
+            dir.push_str(part);
+            if self.created.contains(&dir) {
+                continue;
+            }
+            let url = format!("{}/{}/", self.root, uri_encode(&dir, false));
+            match self.request("MKCOL", &url).call() {
+                // A directory that already exists cannot be created again.
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(e) => return Err(failed("MKCOL", &url, e)),
+            }
+            self.created.insert(dir.clone());
+        }
+        Ok(())
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self.agent.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+}
+
+impl Upload for WebDav {
+    fn upload(&mut self, src: &path::Path, key: &str) -> Result<Option<String>, PlaceError> {
+        self.create_dirs(key)?;
+        let file = fs::File::open(src).map_err(|e| PlaceError::io("could not open file", e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| PlaceError::io("could not read file", e))?
+            .len();
+        let url = format!("{}/{}", self.root, uri_encode(key, false));
+        self.request("PUT", &url)
+            .set("Content-Length", &len.to_string())
+            .send(file)
+            .map_err(|e| failed("PUT", &url, e))?;
+        Ok(None)
+    }
+}
+
+/// Gets the Authorization header for HTTP basic authentication.
+fn basic(user: &str, password: &str) -> String {
+    let encoded =
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+    format!("Basic {}", encoded)
+}
+
+/// Describes the failed request.
+fn failed(method: &str, url: &str, error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => format!(
+            "{} {} failed with {} {}",
+            method,
+            url,
+            status,
+            response.status_text()
+        ),
+        e => format!("{} {} failed: {}", method, url, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{basic, WebDav};
+    use crate::remote::{Credentials, Destination};
+
+    #[test]
+    fn test_new() {
+        let destination =
+            Destination::parse("webdav://cloud.example/remote.php/dav/files/alex/Finance").unwrap();
+        let credentials = Credentials {
+            user: Some(String::from("alex")),
+            password: Some(String::from("secret")),
+            token: None,
+        };
+        let webdav = WebDav::new(&destination, &credentials).unwrap();
+        assert_eq!(
+            webdav.root,
+            "https://cloud.example/remote.php/dav/files/alex/Finance"
+        );
+        assert_eq!(webdav.authorization, Some(basic("alex", "secret")));
+        assert_eq!(basic("alex", "secret"), "Basic YWxleDpzZWNyZXQ=");
+
+        let token = Credentials {
+            token: Some(String::from("abc")),
+            ..credentials.clone()
+        };
+        let webdav = WebDav::new(&destination, &token).unwrap();
+        assert_eq!(webdav.authorization.as_deref(), Some("Bearer abc"));
+
+        // The user in the URL needs a password.
+        let destination = Destination::parse("webdav://sam@cloud.example/Finance").unwrap();
+        assert!(WebDav::new(&destination, &Credentials::default()).is_err());
+        let destination = Destination::parse("webdav://cloud.example/Finance").unwrap();
+        let anonymous = WebDav::new(&destination, &Credentials::default()).unwrap();
+        assert_eq!(anonymous.authorization, None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Posting a summary of each run to a webhook, so that the results can be passed on to a chat or
+//! a notification service. The summary is JSON, with a `text` field that services such as Slack
+//! show as the message.
+
+use std::path;
+
+use serde::Serialize;
+
+use crate::plan::{Outcome, Unclassified};
+use crate::stats::Stats;
+
+/// What happened in a run, as it is posted to the webhook.
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    /// A line describing the run.
+    text: String,
+    placed: usize,
+    up_to_date: usize,
+    unclassified: usize,
+    skipped: usize,
+    already_archived: usize,
+    duplicates: usize,
+    renamed: usize,
+    failures: Vec<FailureSummary<'a>>,
+    unclassified_files: &'a [Unclassified],
+    stats: &'a Stats,
+}
+
+/// A file that could not be placed, and why.
+#[derive(Debug, Serialize)]
+struct FailureSummary<'a> {
+    path: &'a path::Path,
+    error: &'a str,
+}
+
+impl<'a> Summary<'a> {
+    /// Summarises the outcome of the run, with the files that were left in place as they could
+    /// not be classified and the statistics of the run.
+    pub fn new(outcome: &'a Outcome, unclassified: &'a [Unclassified], stats: &'a Stats) -> Self {
+        let text = format!(
+            "Classified {} files, {} left unclassified, {} failed",
+            outcome.placed,
+            outcome.unclassified,
+            outcome.failures.len()
+        );
+        Summary {
+            text,
+            placed: outcome.placed,
+            up_to_date: outcome.up_to_date,
+            unclassified: outcome.unclassified,
+            skipped: outcome.skipped,
+            already_archived: outcome.already_archived,
+            duplicates: outcome.duplicates.len(),
+            renamed: outcome.renamed.len(),
+            failures: outcome
+                .failures
+                .iter()
+                .map(|failure| FailureSummary {
+                    path: &failure.path,
+                    error: &failure.error.message,
+                })
+                .collect(),
+            unclassified_files: unclassified,
+            stats,
+        }
+    }
+}
+
+/// Posts the summary to the webhook.
+#[cfg(feature = "webhook")]
+pub fn send(url: &str, summary: &Summary) -> Result<(), String> {
+    let body =
+        serde_json::to_string(summary).map_err(|e| format!("Could not send summary: {}", e))?;
+    match ureq::post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+    {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => Err(format!(
+            "POST {} failed with {} {}",
+            url,
+            status,
+            response.status_text()
+        )),
+        Err(e) => Err(format!("POST {} failed: {}", url, e)),
+    }
+}
+
+#[cfg(not(feature = "webhook"))]
+pub fn send(_url: &str, _summary: &Summary) -> Result<(), String> {
+    Err(String::from(
+        "classfy was built without the webhook feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path;
+    use std::time::Duration;
+
+    use super::Summary;
+    use crate::plan::{Outcome, PlaceError, Plan, Unclassified};
+    use crate::stats::Stats;
+
+    #[test]
+    fn test_summary() {
+        let mut outcome = Outcome {
+            placed: 4,
+            unclassified: 1,
+            ..Outcome::default()
+        };
+        outcome.fail(
+            path::Path::new("/scans/b_10JUL2022.pdf"),
+            PlaceError::io(
+                "could not move file",
+                io::Error::from(io::ErrorKind::PermissionDenied),
+            ),
+        );
+        let unclassified = [Unclassified {
+            path: path::PathBuf::from("/scans/notes.txt"),
+            reason: String::from("no date found"),
+        }];
+        let stats = Stats::new(&outcome, &Plan::default(), Duration::from_secs(2));
+        let summary = serde_json::to_value(Summary::new(&outcome, &unclassified, &stats)).unwrap();
+        assert_eq!(
+            summary["text"],
+            "Classified 4 files, 1 left unclassified, 1 failed"
+        );
+        assert_eq!(summary["placed"], 4);
+        assert_eq!(summary["failures"][0]["path"], "/scans/b_10JUL2022.pdf");
+        assert_eq!(summary["unclassified_files"][0]["reason"], "no date found");
+        assert_eq!(summary["stats"]["elapsed_secs"], 2.0);
+    }
+}
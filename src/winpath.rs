@@ -0,0 +1,135 @@
+//! Making destination paths that Windows can create. It refuses names that are reserved for
+//! devices, such as `CON` and `PRN`, and paths longer than `MAX_PATH` unless they have the `\\?\`
+//! prefix.
+
+use std::borrow::Cow;
+use std::path;
+
+/// The length, in UTF-16 units, from which Windows needs the `\\?\` prefix. This is less than
+/// `MAX_PATH` (260), as a directory has to leave room for the name of a file in it.
+pub const LONG_PATH: usize = 248;
+
+/// The names that Windows reserves for devices, whatever their extension or case.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Checks if Windows reserves the name for a device, as it does for `con.pdf` as well as `CON`.
+pub fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Gets a name that Windows does not reserve, by adding `_` to the stem of one that it does (such
+/// as `CON_.pdf` for `CON.pdf`).
+pub fn unreserved(name: &str) -> Cow<'_, str> {
+    if !is_reserved(name) {
+        return Cow::Borrowed(name);
+    }
+    match name.find('.') {
+        Some(dot) => Cow::Owned(format!("{}_{}", &name[..dot], &name[dot..])),
+        None => Cow::Owned(format!("{}_", name)),
+    }
+}
+
+/// Gets the relative path with each reserved name in it changed by `unreserved`.
+pub fn unreserved_path(relative: &path::Path) -> path::PathBuf {
+    relative
+        .components()
+        .map(|component| match component {
+            path::Component::Normal(name) => match name.to_str() {
+                Some(name) => path::PathBuf::from(unreserved(name).as_ref()),
+                None => path::PathBuf::from(name),
+            },
+            other => path::PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+/// Gets the `\\?\` form of an absolute Windows path that is too long to be used without it, or
+/// `None` if it can be used as it is.
+pub fn extended(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.encode_utf16().count() < LONG_PATH {
+        return None;
+    }
+    let path = path.replace('/', r"\");
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{}", unc));
+    }
+    let bytes = path.as_bytes();
+    let has_drive =
+        bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    has_drive.then(|| format!(r"\\?\{}", path))
+}
+
+/// Gets the path, relative to the base directory, that the file is placed at. On Windows, the
+/// reserved names in it are changed.
+pub fn destination(relative: &path::Path) -> Cow<'_, path::Path> {
+    if cfg!(windows) {
+        Cow::Owned(unreserved_path(relative))
+    } else {
+        Cow::Borrowed(relative)
+    }
+}
+
+/// Gets the path to use for the file or directory. On Windows, paths that are too long are given
+/// the `\\?\` prefix, so that files deep within nested directories can still be placed.
+pub fn long(path: &path::Path) -> Cow<'_, path::Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+    let absolute = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match absolute.to_str().and_then(extended) {
+        Some(extended) => Cow::Owned(path::PathBuf::from(extended)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+
+    use super::{extended, is_reserved, unreserved, unreserved_path};
+
+    #[test]
+    fn test_unreserved() {
+        assert!(is_reserved("CON"));
+        assert!(is_reserved("con.pdf"));
+        assert!(is_reserved("Lpt1.tar.gz"));
+        assert!(is_reserved("NUL .txt"));
+        assert!(!is_reserved("CONTRACT.pdf"));
+        assert!(!is_reserved("COM10"));
+        assert!(!is_reserved("a_CON.pdf"));
+
+        assert_eq!(unreserved("CON.pdf"), "CON_.pdf");
+        assert_eq!(unreserved("aux"), "aux_");
+        assert_eq!(unreserved("notes.txt"), "notes.txt");
+        assert_eq!(
+            unreserved_path(path::Path::new("2023FY/PRN/com1.tar.gz")),
+            path::Path::new("2023FY/PRN_/com1_.tar.gz")
+        );
+    }
+
+    #[test]
+    fn test_extended() {
+        let name = "a".repeat(250);
+        assert_eq!(
+            extended(&format!(r"C:\Archive\{}", name)),
+            Some(format!(r"\\?\C:\Archive\{}", name))
+        );
+        assert_eq!(
+            extended(&format!("C:/Archive/{}", name)),
+            Some(format!(r"\\?\C:\Archive\{}", name))
+        );
+        assert_eq!(
+            extended(&format!(r"\\server\share\{}", name)),
+            Some(format!(r"\\?\UNC\server\share\{}", name))
+        );
+        assert_eq!(extended(&format!(r"\\?\C:\{}", name)), None);
+        assert_eq!(extended(&format!(r"Archive\{}", name)), None);
+        assert_eq!(extended(r"C:\Archive\2023FY\a.pdf"), None);
+    }
+}
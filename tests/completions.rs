@@ -0,0 +1,66 @@
+//! The completions that classfy gives the shell through the script printed by `classfy
+//! completions`, which are asked for by running classfy with COMPLETE set.
+
+use std::fs;
+use std::path;
+use std::process;
+
+/// Gets the completions of the last of the words, as classfy gives them to fish, when run in the
+/// directory with the variables.
+fn complete(dir: &path::Path, words: &[&str], vars: &[(&str, &str)]) -> Vec<String> {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_classfy"))
+        .current_dir(dir)
+        .env_remove("CLASSFY_CONFIG")
+        .env("COMPLETE", "fish")
+        .envs(vars.iter().copied())
+        .arg("--")
+        .args(words)
+        .output()
+        .expect("could not run classfy");
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.split('\t').next().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[test]
+fn test_profiles() {
+    let tempdir = tempfile::tempdir().expect("could not create temp directory");
+    let dir = tempdir.path();
+    fs::write(
+        dir.join("classfy.toml"),
+        "[profile.work]\nfy-convention = \"uk\"\n\n[profile.personal]\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        complete(dir, &["classfy", "--profile", ""], &[]),
+        ["personal", "work"]
+    );
+    assert_eq!(
+        complete(dir, &["classfy", "tui", "--profile", "w"], &[]),
+        ["work"]
+    );
+
+    let other = dir.join("other.toml");
+    fs::write(&other, "[profile.home]\n").unwrap();
+    let other = other.to_str().unwrap();
+    assert_eq!(
+        complete(
+            dir,
+            &["classfy", "--profile", ""],
+            &[("CLASSFY_CONFIG", other)]
+        ),
+        ["home"]
+    );
+
+    let output = process::Command::new(env!("CARGO_BIN_EXE_classfy"))
+        .args(["completions", "fish"])
+        .output()
+        .expect("could not run classfy");
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("COMPLETE=fish"));
+}
@@ -0,0 +1,83 @@
+//! The options given by CLASSFY_* variables, which are layered between the configuration file and
+//! the command line. classfy is run for each case, so that the variables are only set for it and
+//! not for the other tests running alongside.
+
+use std::fs;
+use std::path;
+use std::process;
+
+/// The variables read by the cases, which are cleared so that those of whoever runs the tests are
+/// not used.
+const VARS: [&str; 5] = [
+    "CLASSFY_CONFIG",
+    "CLASSFY_FY_LABEL",
+    "CLASSFY_DRY_RUN",
+    "CLASSFY_DEST",
+    "CLASSFY_PROFILE",
+];
+
+/// Runs classfy in the directory with the arguments and variables.
+fn classfy(dir: &path::Path, args: &[&str], vars: &[(&str, &str)]) -> process::Output {
+    let mut command = process::Command::new(env!("CARGO_BIN_EXE_classfy"));
+    command.current_dir(dir).args(args);
+    for var in VARS {
+        command.env_remove(var);
+    }
+    command.envs(vars.iter().copied());
+    command.output().expect("could not run classfy")
+}
+
+/// Gets the bucket that test-rules gives the file name.
+fn bucket(dir: &path::Path, args: &[&str], vars: &[(&str, &str)]) -> String {
+    let output = classfy(
+        dir,
+        &[
+            &["test-rules", "--config", "classfy.toml"],
+            args,
+            &["a_10JUL2022.txt"],
+        ]
+        .concat(),
+        vars,
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("bucket: "))
+        .expect("no bucket was given")
+        .to_string()
+}
+
+#[test]
+fn test_layering() {
+    let tempdir = tempfile::tempdir().expect("could not create temp directory");
+    let dir = tempdir.path();
+    fs::write(dir.join("classfy.toml"), "fy-label = \"start\"\n").unwrap();
+
+    assert_eq!(bucket(dir, &[], &[]), "2022FY");
+    let end = [("CLASSFY_FY_LABEL", "end")];
+    assert_eq!(bucket(dir, &[], &end), "2023FY");
+    assert_eq!(bucket(dir, &["--fy-label", "start"], &end), "2022FY");
+
+    let output = classfy(dir, &["test-rules"], &[("CLASSFY_FY_LABEL", "middle")]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_dry_run() {
+    let tempdir = tempfile::tempdir().expect("could not create temp directory");
+    let dir = tempdir.path();
+    fs::write(dir.join("classfy.toml"), "").unwrap();
+    let docs = dir.join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(docs.join("a_10JUL2022.txt"), "").unwrap();
+
+    let output = classfy(dir, &["docs"], &[("CLASSFY_DRY_RUN", "1")]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(docs.join("a_10JUL2022.txt").is_file());
+    assert!(!docs.join("2023FY").exists());
+
+    let output = classfy(dir, &["docs"], &[("CLASSFY_DEST", "nowhere")]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(docs.join("a_10JUL2022.txt").is_file());
+}